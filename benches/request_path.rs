@@ -0,0 +1,112 @@
+//! What a request costs before and after the model's share: starting the
+//! binary, setting up the client, building the request and checking the
+//! answer. `--time-report` shows the same steps for a single run, along with
+//! gathering context for the system prompt, which is internal to the binary.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use llmwrap::api::{Client, KeySource, Provider, Turn};
+use llmwrap::fakeserver::FakeServer;
+use llmwrap::shell::Shell;
+use llmwrap::{SYSTEM_PROMPT, sanitize_command};
+use std::fs;
+use std::hint::black_box;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+const DESCRIPTION: &str = "find the ten largest files under here, skipping .git";
+const ANSWER: &str = "```bash\nfind . -path ./.git -prune -o -type f -printf '%s %p\\n' | sort -nr | head -n 10\n```";
+
+/// The binary run with `args` in a home of its own, as a user would run it.
+fn llmwrap(home: &Path, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_llmwrap"));
+    command
+        .args(args)
+        .env_clear()
+        .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("XDG_DATA_HOME", home.join("data"))
+        .env("XDG_CACHE_HOME", home.join("cache"))
+        .env("XDG_STATE_HOME", home.join("state"))
+        .env("LLMWRAP_OPENAI_API_KEY", "sk-bench")
+        .current_dir(home)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    command
+}
+
+fn cold_start(c: &mut Criterion) {
+    let home = std::env::temp_dir().join(format!("llmwrap-bench-{}", std::process::id()));
+    fs::create_dir_all(home.join("config/llmwrap")).unwrap();
+    fs::write(
+        home.join("config/llmwrap/config.toml"),
+        "[cache]\nttl_days = 0\n",
+    )
+    .unwrap();
+    let server = FakeServer::start(&[ANSWER]).unwrap();
+
+    let mut group = c.benchmark_group("cold start");
+    group.sample_size(20);
+    group.bench_function("parse arguments", |b| {
+        b.iter(|| llmwrap(&home, &["--version"]).status().unwrap())
+    });
+    group.bench_function("whole request", |b| {
+        b.iter(|| {
+            let status = llmwrap(
+                &home,
+                &["--api-base", server.base_url(), "--print", DESCRIPTION],
+            )
+            .status()
+            .unwrap();
+            assert!(status.success());
+        })
+    });
+    group.finish();
+    let _ = fs::remove_dir_all(&home);
+}
+
+fn request(c: &mut Criterion) {
+    let provider = Provider::Ollama;
+    let new_client = || {
+        Client::new(
+            provider,
+            &KeySource::Provider,
+            provider.default_base(),
+            provider.default_model(),
+        )
+        .unwrap()
+    };
+    c.bench_function("set up the client", |b| b.iter(new_client));
+
+    let system_prompt = format!("{} {}", SYSTEM_PROMPT, Shell::Bash.prompt_note().unwrap());
+    let turns = [
+        Turn::user("list the files here"),
+        Turn::assistant("ls -la"),
+        Turn::user(DESCRIPTION),
+    ];
+    for provider in [Provider::Openai, Provider::Anthropic, Provider::Ollama] {
+        let key = KeySource::Command("echo sk-bench".to_string());
+        let client = Client::new(provider, &key, provider.default_base(), "model").unwrap();
+        c.bench_function(&format!("serialize request/{}", provider.name()), |b| {
+            b.iter(|| {
+                client
+                    .request_body(black_box(&system_prompt), black_box(&turns))
+                    .unwrap()
+            })
+        });
+    }
+}
+
+fn answer(c: &mut Criterion) {
+    c.bench_function("sanitize the answer", |b| {
+        b.iter(|| sanitize_command(black_box(ANSWER)))
+    });
+    let command = sanitize_command(ANSWER);
+    c.bench_function("assess risk", |b| {
+        b.iter(|| llmwrap::assess(Shell::Bash, black_box(&command)))
+    });
+}
+
+criterion_group!(benches, cold_start, request, answer);
+criterion_main!(benches);
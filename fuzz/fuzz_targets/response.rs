@@ -0,0 +1,15 @@
+//! Reading the answer out of a response body from each provider.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use llmwrap::api::{self, Provider};
+
+fuzz_target!(|body: &str| {
+    let Ok(value) = serde_json::from_str(body) else {
+        return;
+    };
+    for provider in [Provider::Openai, Provider::Anthropic, Provider::Ollama] {
+        api::extract_text(provider, &value);
+    }
+});
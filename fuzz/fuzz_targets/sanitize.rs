@@ -0,0 +1,22 @@
+//! Cleaning up a reply: whatever the model sends, the command comes out
+//! trimmed, without fences, and the same when cleaned up again.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use llmwrap::{answer, is_script, sanitize_command};
+
+fuzz_target!(|raw: &str| {
+    let command = sanitize_command(raw);
+    assert_eq!(sanitize_command(&command), command);
+    assert_eq!(command.trim(), command);
+    assert_eq!(is_script(&command), command.contains('\n'));
+    assert!(
+        command
+            .lines()
+            .all(|line| !line.trim_start().starts_with("```"))
+    );
+    if let Some(answer) = answer::parse(raw) {
+        sanitize_command(&answer.command);
+    }
+});
@@ -0,0 +1,22 @@
+//! Reading a streamed reply from each provider, line by line, where the
+//! text handed on as it arrives must add up to the text gathered.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use llmwrap::api::{Provider, StreamParser};
+
+fuzz_target!(|stream: &str| {
+    for provider in [Provider::Openai, Provider::Anthropic, Provider::Ollama] {
+        let mut parser = StreamParser::new(provider);
+        let mut seen = String::new();
+        for line in stream.lines() {
+            match parser.line(line) {
+                Ok(delta) => seen.push_str(&delta.unwrap_or_default()),
+                Err(_) => break,
+            }
+        }
+        let (_, streamed) = parser.finish();
+        assert_eq!(seen, streamed);
+    }
+});
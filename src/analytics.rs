@@ -0,0 +1,315 @@
+use crate::history::{self, HistoryEntry};
+use crate::net;
+use crate::paths;
+use crate::risk::RiskLevel;
+use crate::store;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long to wait after a failed send before trying again, in seconds,
+/// doubling with each failure in a row up to the interval itself. Every
+/// start would otherwise wait on an endpoint that is down.
+const RETRY_AFTER: u64 = 60 * 60;
+
+/// `[analytics]` in `config.toml`: where to send usage counts, for deployments
+/// whose platform team collects them. Nothing is sent without an endpoint.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub endpoint: Option<String>,
+    /// A label for the team or fleet, passed along as is.
+    pub team: Option<String>,
+    /// Send at most once per this many hours.
+    pub interval_hours: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            endpoint: None,
+            team: None,
+            interval_hours: 24,
+        }
+    }
+}
+
+/// Counts over a stretch of history. Deliberately holds no prompts, commands,
+/// paths or hosts.
+#[derive(Serialize, Default)]
+pub struct Report {
+    pub version: &'static str,
+    pub os: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub team: Option<String>,
+    pub from: u64,
+    pub to: u64,
+    pub requests: usize,
+    pub run: usize,
+    pub failed: usize,
+    /// Share of requests whose command was run.
+    pub acceptance_rate: f64,
+    pub risk: RiskCounts,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+#[derive(Serialize, Default)]
+pub struct RiskCounts {
+    pub low: usize,
+    pub medium: usize,
+    pub high: usize,
+}
+
+impl Report {
+    pub fn new(entries: &[HistoryEntry], from: u64, to: u64, team: Option<String>) -> Self {
+        let mut report = Report {
+            version: env!("CARGO_PKG_VERSION"),
+            os: std::env::consts::OS,
+            team,
+            from,
+            to,
+            requests: entries.len(),
+            ..Report::default()
+        };
+        for entry in entries {
+            if entry.executed {
+                report.run += 1;
+            }
+            if entry.exit_code.is_some_and(|code| code != 0) {
+                report.failed += 1;
+            }
+            match entry.risk {
+                Some(RiskLevel::Low) => report.risk.low += 1,
+                Some(RiskLevel::Medium) => report.risk.medium += 1,
+                Some(RiskLevel::High) => report.risk.high += 1,
+                None => {}
+            }
+            if let Some(usage) = &entry.usage {
+                report.input_tokens += usage.input_tokens;
+                report.output_tokens += usage.output_tokens;
+            }
+        }
+        if report.requests > 0 {
+            report.acceptance_rate = report.run as f64 / report.requests as f64;
+        }
+        report
+    }
+}
+
+/// When the last report was sent, so the next one covers only what came after.
+#[derive(Serialize, Deserialize)]
+struct State {
+    sent_until: u64,
+    /// When sending last failed, if it has since the last report.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    failed_at: Option<u64>,
+    /// How many sends have failed in a row.
+    #[serde(default)]
+    failures: u32,
+}
+
+impl State {
+    fn sent(now: u64) -> Self {
+        State {
+            sent_until: now,
+            failed_at: None,
+            failures: 0,
+        }
+    }
+
+    /// Whether a report is due at `now`, `interval` seconds after the last.
+    fn due(&self, now: u64, interval: u64) -> bool {
+        if now < self.sent_until + interval {
+            return false;
+        }
+        let Some(failed_at) = self.failed_at else {
+            return true;
+        };
+        let wait = RETRY_AFTER << self.failures.saturating_sub(1).min(16);
+        now >= failed_at + wait.min(interval)
+    }
+}
+
+/// Send the counts since the last report when an endpoint is configured and
+/// the interval has passed. The first call only starts the clock, so history
+/// from before analytics were turned on is never sent.
+pub fn send_due(settings: &Settings) -> Result<()> {
+    let Some(endpoint) = &settings.endpoint else {
+        return Ok(());
+    };
+    let Some(path) = state_file() else {
+        return Ok(());
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create state directory {}", dir.display()))?;
+    }
+    // Another llmwrap sending now would send the same counts again.
+    let Some(_lock) =
+        store::try_lock(&path).with_context(|| format!("Failed to lock {}", path.display()))?
+    else {
+        return Ok(());
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let Some(state) = load_state() else {
+        return save_state(&State::sent(now));
+    };
+    if !state.due(now, settings.interval_hours * 60 * 60) {
+        return Ok(());
+    }
+
+    let entries: Vec<HistoryEntry> = history::load()
+        .into_iter()
+        .filter(|h| (state.sent_until..now).contains(&h.timestamp))
+        .collect();
+    let report = Report::new(&entries, state.sent_until, now, settings.team.clone());
+    let mut request = net::agent()?.timeout(SEND_TIMEOUT).build().post(endpoint);
+    if let Ok(token) = std::env::var("LLMWRAP_ANALYTICS_TOKEN") {
+        request = request.set("Authorization", &format!("Bearer {}", token));
+    }
+    let sent = request
+        .send_json(&report)
+        .with_context(|| format!("Failed to send usage counts to {}", endpoint));
+    if sent.is_err() {
+        save_state(&State {
+            failed_at: Some(now),
+            failures: state.failures + 1,
+            ..state
+        })?;
+    }
+    sent?;
+    save_state(&State::sent(now))
+}
+
+fn load_state() -> Option<State> {
+    let text = fs::read_to_string(state_file()?).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+fn save_state(state: &State) -> Result<()> {
+    let Some(path) = state_file() else {
+        return Ok(());
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create state directory {}", dir.display()))?;
+    }
+    let text = serde_json::to_string(state)?;
+    store::replace(&path, text.as_bytes())
+        .with_context(|| format!("Failed to write analytics state {}", path.display()))
+}
+
+fn state_file() -> Option<PathBuf> {
+    paths::state_dir().map(|dir| dir.join("analytics.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::Usage;
+
+    const HOUR: u64 = 60 * 60;
+
+    fn entry(executed: bool, exit_code: Option<i32>, risk: Option<RiskLevel>) -> HistoryEntry {
+        HistoryEntry {
+            shell: Some("fish".to_string()),
+            host: Some("db1.internal".to_string()),
+            executed,
+            exit_code,
+            model: Some("gpt-secret".to_string()),
+            usage: Some(Usage {
+                input_tokens: 100,
+                output_tokens: 20,
+            }),
+            risk,
+            ..HistoryEntry::new("delete /home/alice/secret.txt", "rm /home/alice/secret.txt")
+        }
+    }
+
+    #[test]
+    fn report_carries_counts_only() {
+        let entries = [
+            entry(true, Some(0), Some(RiskLevel::Low)),
+            entry(true, Some(1), Some(RiskLevel::High)),
+            entry(false, None, Some(RiskLevel::Medium)),
+            entry(false, None, None),
+        ];
+        let report = Report::new(&entries, 10, 20, Some("platform".to_string()));
+        assert_eq!((report.requests, report.run, report.failed), (4, 2, 1));
+        assert_eq!(report.acceptance_rate, 0.5);
+        assert_eq!(
+            (report.risk.low, report.risk.medium, report.risk.high),
+            (1, 1, 1)
+        );
+        assert_eq!((report.input_tokens, report.output_tokens), (400, 80));
+
+        let json = serde_json::to_value(&report).unwrap();
+        let mut keys: Vec<&str> = json
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(|k| k.as_str())
+            .collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            [
+                "acceptance_rate",
+                "failed",
+                "from",
+                "input_tokens",
+                "os",
+                "output_tokens",
+                "requests",
+                "risk",
+                "run",
+                "team",
+                "to",
+                "version"
+            ]
+        );
+        let text = json.to_string();
+        for secret in ["alice", "secret", "db1", "fish", "gpt"] {
+            assert!(!text.contains(secret), "{} in {}", secret, text);
+        }
+    }
+
+    #[test]
+    fn empty_report_has_no_rate() {
+        let report = Report::new(&[], 10, 20, None);
+        assert_eq!(report.acceptance_rate, 0.0);
+        let json = serde_json::to_value(&report).unwrap();
+        assert!(json.get("team").is_none());
+    }
+
+    #[test]
+    fn due_once_the_interval_passes() {
+        let state = State::sent(1000);
+        assert!(!state.due(1000 + 24 * HOUR - 1, 24 * HOUR));
+        assert!(state.due(1000 + 24 * HOUR, 24 * HOUR));
+    }
+
+    #[test]
+    fn backs_off_after_failed_sends() {
+        let day = 24 * HOUR;
+        let failed = |at, failures| State {
+            sent_until: 0,
+            failed_at: Some(at),
+            failures,
+        };
+        let at = 2 * day;
+        assert!(!failed(at, 1).due(at + HOUR - 1, day));
+        assert!(failed(at, 1).due(at + HOUR, day));
+        assert!(!failed(at, 3).due(at + 4 * HOUR - 1, day));
+        assert!(failed(at, 3).due(at + 4 * HOUR, day));
+        // Never longer than the interval, however many failed.
+        assert!(failed(at, 40).due(at + day, day));
+    }
+}
@@ -0,0 +1,112 @@
+use crate::api;
+use crate::exec;
+use anyhow::{Context, Result};
+use serde_yaml::Value;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// What to generate: a list of tasks to include, or complete plays.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Tasks,
+    Play,
+}
+
+pub fn generate(client: &api::Client, kind: Kind, description: &str) -> Result<String> {
+    let shape = match kind {
+        Kind::Tasks => "a YAML list of Ansible tasks (no play header)",
+        Kind::Play => "a complete Ansible playbook: a YAML list of plays with hosts and tasks",
+    };
+    let system_prompt = format!(
+        "You write Ansible. Respond with only {shape}, no explanations, no code fences. \
+Give every task a name, use fully qualified module names such as ansible.builtin.apt, \
+and keep tasks idempotent."
+    );
+    let raw = client.complete(&system_prompt, description)?;
+    let text = api::strip_code_fences(&raw);
+    Ok(text.trim_start_matches("---").trim_start().to_string())
+}
+
+/// Check the snippet parses and has the shape Ansible expects.
+pub fn validate(kind: Kind, text: &str) -> Result<()> {
+    let value: Value = serde_yaml::from_str(text).context("The snippet is not valid YAML")?;
+    let items = value
+        .as_sequence()
+        .context("The snippet is not a YAML list")?;
+    for (i, item) in items.iter().enumerate() {
+        let map = item
+            .as_mapping()
+            .with_context(|| format!("Item {} is not a mapping", i + 1))?;
+        if kind == Kind::Play && !map.contains_key("hosts") && !map.contains_key("import_playbook")
+        {
+            anyhow::bail!("Play {} has no `hosts`", i + 1);
+        }
+    }
+    Ok(())
+}
+
+/// Run ansible-lint over the snippet. Returns None when ansible-lint is not installed.
+pub fn lint(kind: Kind, text: &str) -> Option<Result<()>> {
+    // ansible-lint decides what a file is from its path, so lay it out like a role or playbook.
+    let dir = tempfile::Builder::new()
+        .prefix("llmwrap-ansible-")
+        .tempdir()
+        .ok()?;
+    let file = match kind {
+        Kind::Tasks => dir.path().join("roles/snippet/tasks/main.yml"),
+        Kind::Play => dir.path().join("playbook.yml"),
+    };
+    file.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&file, format!("---\n{}\n", text)))
+        .ok()?;
+    let output = Command::new("ansible-lint")
+        .args(["--nocolor", "-q"])
+        .arg(&file)
+        .current_dir(dir.path())
+        .output();
+    drop(dir);
+
+    let output = output.ok()?;
+    if output.status.success() {
+        return Some(Ok(()));
+    }
+    let report = exec::decode_output(&output.stdout).trim().to_string();
+    Some(Err(anyhow::anyhow!(
+        "ansible-lint found problems:\n{}",
+        report
+    )))
+}
+
+/// A file name derived from the description, e.g. `ensure-nginx-installed-running.yml`.
+pub fn default_output(description: &str) -> PathBuf {
+    let words: Vec<String> = description
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.len() > 2 && !matches!(*w, "and" | "the" | "for" | "that" | "with" | "all"))
+        .take(5)
+        .map(str::to_lowercase)
+        .collect();
+    let stem = if words.is_empty() {
+        "snippet".to_string()
+    } else {
+        words.join("-")
+    };
+    PathBuf::from(format!("{}.yml", stem))
+}
+
+/// The output file with the snippet appended. Both are YAML lists, so appending
+/// extends the existing tasks or plays.
+pub fn merge(existing: &str, snippet: &str) -> String {
+    let mut result = existing.trim_end().to_string();
+    if result.is_empty() {
+        result.push_str("---");
+    }
+    result.push('\n');
+    if existing.trim().len() > 3 {
+        result.push('\n');
+    }
+    result.push_str(snippet.trim_end());
+    result.push('\n');
+    result
+}
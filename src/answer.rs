@@ -0,0 +1,76 @@
+use crate::api;
+use crate::risk::RiskLevel;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+/// Added to the system prompt when the reply is held to `schema()`, which
+/// otherwise contradicts its "only the runnable command".
+pub const PROMPT_NOTE: &str = "Answer in the JSON format given: the command or script in \
+`command`, exactly as it would be typed; one short sentence on what it does in `explanation`; \
+and in `risk_level` low when it only reads, medium when it changes files or settings in a way \
+that can be undone, high when it deletes data or can't be undone. When the request is \
+impossible, leave `command` empty and give the reason as the explanation.";
+
+/// The model's answer to a request for a command.
+pub struct Answer {
+    /// The command, or the reason there is none when the model says so.
+    pub command: String,
+    /// What the command does, in the model's words.
+    pub explanation: Option<String>,
+    /// How risky the model rates the command.
+    pub rated: Option<RiskLevel>,
+}
+
+#[derive(Deserialize)]
+struct Structured {
+    command: String,
+    explanation: String,
+    risk_level: RiskLevel,
+}
+
+impl Answer {
+    /// A bare command, from a reply in free text, the cache or a snippet.
+    pub fn plain(command: String) -> Self {
+        Answer {
+            command,
+            explanation: None,
+            rated: None,
+        }
+    }
+}
+
+/// The JSON schema a reply is held to, for providers that enforce one.
+pub fn schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "command": {
+                "type": "string",
+                "description": "The command or script to run; empty when the request is impossible",
+            },
+            "explanation": {
+                "type": "string",
+                "description": "One short sentence on what the command does, or why there is none",
+            },
+            "risk_level": {"type": "string", "enum": ["low", "medium", "high"]},
+        },
+        "required": ["command", "explanation", "risk_level"],
+        "additionalProperties": false,
+    })
+}
+
+/// The answer in a reply held to `schema()`, with the command still to be
+/// cleaned up; None when the reply is not that JSON, e.g. from a server
+/// that ignored the schema.
+pub fn parse(raw: &str) -> Option<Answer> {
+    let structured: Structured = serde_json::from_str(&api::strip_code_fences(raw)).ok()?;
+    let explanation = structured.explanation.trim().to_string();
+    if structured.command.trim().is_empty() {
+        return Some(Answer::plain(explanation));
+    }
+    Some(Answer {
+        command: structured.command,
+        explanation: Some(explanation).filter(|e| !e.is_empty()),
+        rated: Some(structured.risk_level),
+    })
+}
@@ -0,0 +1,1328 @@
+use crate::dump;
+use crate::net;
+use crate::shell::Shell;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::io::BufRead;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How many times to ask again, with a larger output limit, when an answer is
+/// cut off.
+const TRUNCATION_RETRIES: usize = 2;
+/// The output limit to ask for after a cut-off answer that reported no usage.
+const MIN_RETRY_TOKENS: u64 = 4096;
+/// The output limit for APIs that require one.
+const DEFAULT_MAX_TOKENS: u64 = 4096;
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+/// The wait before the first retry, doubled for each one after.
+const RETRY_BASE: Duration = Duration::from_millis(500);
+/// The longest wait llmwrap sits through, even when the API asks for more.
+const MAX_RETRY_WAIT: Duration = Duration::from_secs(60);
+
+/// The API a client speaks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    /// The OpenAI Responses API
+    Openai,
+    /// The Anthropic Messages API
+    Anthropic,
+    /// A local Ollama server's chat API
+    Ollama,
+}
+
+impl Provider {
+    /// The provider an API base URL belongs to; OpenAI unless it is Anthropic's.
+    pub fn detect(api_base: &str) -> Self {
+        if api_base.contains("anthropic.com") {
+            Provider::Anthropic
+        } else if api_base.contains(":11434") {
+            Provider::Ollama
+        } else {
+            Provider::Openai
+        }
+    }
+
+    pub fn default_base(self) -> &'static str {
+        match self {
+            Provider::Openai => "https://api.openai.com/v1",
+            Provider::Anthropic => "https://api.anthropic.com/v1",
+            Provider::Ollama => "http://localhost:11434",
+        }
+    }
+
+    pub fn default_model(self) -> &'static str {
+        match self {
+            Provider::Openai => "gpt-5.1-codex-max",
+            Provider::Anthropic => "claude-sonnet-4-5",
+            Provider::Ollama => "llama3.2",
+        }
+    }
+
+    /// A cheaper, faster model for bulk work such as summarizing parts of a
+    /// long log.
+    pub fn small_model(self) -> &'static str {
+        match self {
+            Provider::Openai => "gpt-5-mini",
+            Provider::Anthropic => "claude-haiku-4-5",
+            Provider::Ollama => "llama3.2",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Provider::Openai => "OpenAI",
+            Provider::Anthropic => "Anthropic",
+            Provider::Ollama => "Ollama",
+        }
+    }
+
+    /// Where the API key is read from; None when the API takes no key.
+    fn key_variable(self) -> Option<&'static str> {
+        match self {
+            Provider::Openai => Some("LLMWRAP_OPENAI_API_KEY"),
+            Provider::Anthropic => Some("LLMWRAP_ANTHROPIC_API_KEY"),
+            Provider::Ollama => None,
+        }
+    }
+
+    fn endpoint(self) -> &'static str {
+        match self {
+            Provider::Openai => "responses",
+            Provider::Anthropic => "messages",
+            Provider::Ollama => "api/chat",
+        }
+    }
+}
+
+/// Where the API key comes from.
+#[derive(Clone, Debug, Default)]
+pub enum KeySource {
+    /// The provider's own variable, e.g. `LLMWRAP_OPENAI_API_KEY`.
+    #[default]
+    Provider,
+    /// Another environment variable.
+    Env(String),
+    /// What a command prints, e.g. `pass show openai/work`.
+    Command(String),
+}
+
+impl KeySource {
+    /// The key, or an empty one for providers that need none.
+    fn read(&self, provider: Provider) -> Result<String> {
+        match self {
+            KeySource::Provider => match provider.key_variable() {
+                Some(variable) => std::env::var(variable).with_context(|| {
+                    format!(
+                        "Set {} in your environment before running this tool",
+                        variable
+                    )
+                }),
+                None => Ok(String::new()),
+            },
+            KeySource::Env(variable) => std::env::var(variable).with_context(|| {
+                format!(
+                    "The profile reads the API key from {}, which is not set",
+                    variable
+                )
+            }),
+            KeySource::Command(command) => {
+                let shell = if cfg!(windows) {
+                    Shell::Pwsh
+                } else {
+                    Shell::Sh
+                };
+                let output = shell
+                    .command(command)
+                    .stdin(Stdio::null())
+                    .stderr(Stdio::inherit())
+                    .output()
+                    .with_context(|| format!("Failed to run API key command `{}`", command))?;
+                if !output.status.success() {
+                    anyhow::bail!(
+                        "API key command `{}` failed with {}",
+                        command,
+                        output.status
+                    );
+                }
+                let key = String::from_utf8_lossy(&output.stdout).trim().to_string();
+                if key.is_empty() {
+                    anyhow::bail!("API key command `{}` printed nothing", command);
+                }
+                Ok(key)
+            }
+        }
+    }
+
+    /// What to check when the key is refused.
+    fn hint(&self, provider: Provider) -> Option<String> {
+        match self {
+            KeySource::Provider => provider
+                .key_variable()
+                .map(|variable| format!("Check that {} holds a current key.", variable)),
+            KeySource::Env(variable) => {
+                Some(format!("Check that {} holds a current key.", variable))
+            }
+            KeySource::Command(command) => {
+                Some(format!("Check that `{}` prints a current key.", command))
+            }
+        }
+    }
+}
+
+/// A request body in the shape the provider expects.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Body {
+    Responses(ResponsesRequest),
+    Messages(MessagesRequest),
+    Chat(ChatRequest),
+}
+
+impl Body {
+    fn max_tokens(&self) -> Option<u64> {
+        match self {
+            Body::Responses(body) => body.max_output_tokens,
+            Body::Messages(body) => Some(body.max_tokens),
+            Body::Chat(body) => body.options.as_ref().map(|o| o.num_predict),
+        }
+    }
+
+    /// Double the room for the answer after `reply` ran out of it.
+    fn make_room(&mut self, reply: &Value) {
+        let used = reply
+            .pointer("/usage/output_tokens")
+            .or_else(|| reply.pointer("/eval_count"))
+            .and_then(Value::as_u64)
+            .unwrap_or(0);
+        let limit = self.max_tokens().unwrap_or(used).max(used);
+        self.set_max_tokens((limit * 2).max(MIN_RETRY_TOKENS));
+    }
+
+    fn set_max_tokens(&mut self, limit: u64) {
+        match self {
+            Body::Responses(body) => body.max_output_tokens = Some(limit),
+            Body::Messages(body) => body.max_tokens = limit,
+            Body::Chat(body) => body.options = Some(ChatOptions { num_predict: limit }),
+        }
+    }
+
+    fn set_stream(&mut self) {
+        match self {
+            Body::Responses(body) => body.stream = true,
+            Body::Messages(body) => body.stream = true,
+            Body::Chat(body) => body.stream = true,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ResponsesRequest {
+    model: String,
+    input: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u64>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    /// The JSON schema the answer must follow, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<Value>,
+}
+
+#[derive(Serialize)]
+struct Message {
+    role: String,
+    content: Vec<ContentPart>,
+}
+
+#[derive(Serialize)]
+struct ContentPart {
+    #[serde(rename = "type")]
+    part_type: String,
+    text: String,
+}
+
+#[derive(Serialize)]
+struct MessagesRequest {
+    model: String,
+    system: String,
+    messages: Vec<ChatMessage>,
+    max_tokens: u64,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[derive(Serialize)]
+struct ChatRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    /// Ollama streams unless told not to.
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<ChatOptions>,
+    /// The JSON schema the answer must follow, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<Value>,
+}
+
+#[derive(Serialize)]
+struct ChatOptions {
+    num_predict: u64,
+}
+
+#[derive(Serialize)]
+struct ChatMessage {
+    role: String,
+    content: String,
+}
+
+/// One request to the API and the reply exactly as it came back, kept so a
+/// strange answer can be traced to what was actually sent.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Exchange {
+    pub url: String,
+    /// The JSON body that was posted (the API key travels in a header and is
+    /// not part of it).
+    pub request: Value,
+    /// The response body, unparsed.
+    pub response: String,
+    /// The provider's ID for the request, to quote when reporting a problem.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Why the answer was cut off, if it was.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub incomplete: Option<String>,
+    /// How long the request took, from sending it to the end of the reply.
+    #[serde(default)]
+    pub elapsed_ms: u64,
+}
+
+/// Who wrote a turn of a conversation with the model.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+/// One message of a conversation, sent along with later requests so they
+/// can build on what came before.
+#[derive(Clone, Debug)]
+pub struct Turn {
+    pub role: Role,
+    pub text: String,
+}
+
+impl Turn {
+    pub fn user(text: &str) -> Self {
+        Turn {
+            role: Role::User,
+            text: text.to_string(),
+        }
+    }
+
+    pub fn assistant(text: &str) -> Self {
+        Turn {
+            role: Role::Assistant,
+            text: text.to_string(),
+        }
+    }
+}
+
+/// Tokens one request used, as the provider reported them.
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl Exchange {
+    /// The model the request asked for.
+    pub fn model(&self) -> Option<&str> {
+        self.request.get("model").and_then(Value::as_str)
+    }
+
+    /// The token counts in the response, when it reported them.
+    pub fn usage(&self) -> Option<Usage> {
+        let response: Value = serde_json::from_str(&self.response).ok()?;
+        let count = |pointer: &str| response.pointer(pointer).and_then(Value::as_u64);
+        Some(Usage {
+            input_tokens: count("/usage/input_tokens").or_else(|| count("/prompt_eval_count"))?,
+            output_tokens: count("/usage/output_tokens").or_else(|| count("/eval_count"))?,
+        })
+    }
+}
+
+/// Connection settings for the model's API.
+#[derive(Clone)]
+pub struct Client {
+    agent: ureq::Agent,
+    provider: Provider,
+    key: KeySource,
+    api_key: String,
+    api_base: String,
+    model: String,
+    /// A JSON schema to hold answers to, with its name.
+    schema: Option<(String, Value)>,
+    /// Clients to ask in turn when this one fails or answers with nothing.
+    fallbacks: Vec<Client>,
+}
+
+impl Client {
+    /// Build a client, reading the API key from `key`.
+    pub fn new(provider: Provider, key: &KeySource, api_base: &str, model: &str) -> Result<Self> {
+        let api_key = key.read(provider)?;
+        Ok(Client {
+            agent: net::agent()?.build(),
+            provider,
+            key: key.clone(),
+            api_key,
+            api_base: api_base.to_string(),
+            model: model.to_string(),
+            schema: None,
+            fallbacks: Vec::new(),
+        })
+    }
+
+    /// The same connection, asking another model.
+    pub fn with_model(&self, model: &str) -> Client {
+        Client {
+            model: model.to_string(),
+            ..self.clone()
+        }
+    }
+
+    /// The same connection, asking for answers that follow `schema`. Only
+    /// the OpenAI and Ollama APIs enforce it; Anthropic's ignores it.
+    pub fn with_schema(&self, name: &str, schema: Value) -> Client {
+        Client {
+            fallbacks: self
+                .fallbacks
+                .iter()
+                .map(|fallback| fallback.with_schema(name, schema.clone()))
+                .collect(),
+            schema: Some((name.to_string(), schema)),
+            ..self.clone()
+        }
+    }
+
+    /// The same client, asking `fallbacks` in turn when it fails or its
+    /// answer is empty.
+    pub fn with_fallbacks(self, fallbacks: Vec<Client>) -> Client {
+        Client { fallbacks, ..self }
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub fn api_base(&self) -> &str {
+        &self.api_base
+    }
+
+    pub fn key_source(&self) -> &KeySource {
+        &self.key
+    }
+
+    pub fn provider(&self) -> Provider {
+        self.provider
+    }
+
+    /// The JSON body a request for this conversation sends.
+    pub fn request_body(&self, system_prompt: &str, turns: &[Turn]) -> Result<Value> {
+        Ok(serde_json::to_value(self.request(system_prompt, turns))?)
+    }
+
+    /// Send one system + user exchange and return the model's raw text reply.
+    pub fn complete(&self, system_prompt: &str, user_request: &str) -> Result<String> {
+        self.complete_exchange(system_prompt, user_request)
+            .map(|(text, _)| text)
+    }
+
+    /// Like `complete`, also returning the request and response bodies.
+    pub fn complete_exchange(
+        &self,
+        system_prompt: &str,
+        user_request: &str,
+    ) -> Result<(String, Exchange)> {
+        self.converse(system_prompt, &[Turn::user(user_request)])
+    }
+
+    /// Like `complete_exchange`, sending the whole conversation so far; the
+    /// last turn is the user's new request.
+    pub fn converse(&self, system_prompt: &str, turns: &[Turn]) -> Result<(String, Exchange)> {
+        let stop = AtomicBool::new(false);
+        self.falling_back(&stop, |client| {
+            client.converse_one(client.request(system_prompt, turns), 0, &stop)
+        })
+    }
+
+    /// Send `body` to this client's model, and again with more room for the
+    /// answer while it runs out, `retries` times already. Nothing more is
+    /// asked once `stop` is set.
+    fn converse_one(
+        &self,
+        mut body: Body,
+        mut retries: usize,
+        stop: &AtomicBool,
+    ) -> Result<(String, Exchange)> {
+        let url = self.url();
+        loop {
+            if stop.load(Ordering::Relaxed) {
+                anyhow::bail!("Stopped asking for the response");
+            }
+            let request = serde_json::to_value(&body)?;
+            let started = Instant::now();
+            let response = self.post(&url, &body)?;
+            let request_id = request_id(&response);
+            let body_text = response.into_string()?;
+            let mut exchange = Exchange {
+                url: url.clone(),
+                request,
+                response: body_text,
+                request_id,
+                incomplete: None,
+                elapsed_ms: started.elapsed().as_millis() as u64,
+            };
+
+            // Parse as generic JSON to be resilient to minor schema changes and capture helpful errors
+            let parsed: Value = match serde_json::from_str(&exchange.response) {
+                Ok(parsed) => parsed,
+                Err(err) => {
+                    let err = anyhow::Error::new(err).context(format!(
+                        "The reply is not JSON; it starts `{}`",
+                        dump::preview(&exchange.response)
+                    ));
+                    return Err(dump::failure(&exchange, err));
+                }
+            };
+            let Some(reason) = self.incomplete_reason(&parsed) else {
+                let Some(text) = self.extract_text(&parsed) else {
+                    let err = anyhow::anyhow!(
+                        "No text output returned from model; the reply starts `{}`",
+                        dump::preview(&exchange.response)
+                    );
+                    return Err(dump::failure(&exchange, err));
+                };
+                dump::exchange(&exchange);
+                return Ok((text, exchange));
+            };
+            // Running out of tokens is worth another try with more room;
+            // anything else (a content filter, say) will only happen again.
+            if reason == "max_output_tokens" && retries < TRUNCATION_RETRIES {
+                retries += 1;
+                body.make_room(&parsed);
+                continue;
+            }
+            let text = self.extract_text(&parsed).with_context(|| {
+                format!(
+                    "The model's answer was cut off ({}) before it gave a command",
+                    reason
+                )
+            })?;
+            exchange.incomplete = Some(reason);
+            dump::exchange(&exchange);
+            return Ok((text, exchange));
+        }
+    }
+
+    /// Like `complete_exchange`, but streaming the answer to `on_text` as the
+    /// model writes it and hanging up once `stop` is set, which ends the
+    /// response on the provider's side too.
+    pub fn complete_stream_until(
+        &self,
+        system_prompt: &str,
+        user_request: &str,
+        on_text: &mut dyn FnMut(&str),
+        stop: &AtomicBool,
+    ) -> Result<(String, Exchange)> {
+        self.converse_stream_until(system_prompt, &[Turn::user(user_request)], on_text, stop)
+    }
+
+    /// Like `converse`, but passing each piece of text to `on_text` as the
+    /// model writes it.
+    pub fn converse_stream(
+        &self,
+        system_prompt: &str,
+        turns: &[Turn],
+        on_text: &mut dyn FnMut(&str),
+    ) -> Result<(String, Exchange)> {
+        self.converse_stream_until(system_prompt, turns, on_text, &AtomicBool::new(false))
+    }
+
+    fn converse_stream_until(
+        &self,
+        system_prompt: &str,
+        turns: &[Turn],
+        on_text: &mut dyn FnMut(&str),
+        stop: &AtomicBool,
+    ) -> Result<(String, Exchange)> {
+        self.falling_back(stop, |client| {
+            client.stream_one(system_prompt, turns, on_text, stop)
+        })
+    }
+
+    fn stream_one(
+        &self,
+        system_prompt: &str,
+        turns: &[Turn],
+        on_text: &mut dyn FnMut(&str),
+        stop: &AtomicBool,
+    ) -> Result<(String, Exchange)> {
+        let mut body = self.request(system_prompt, turns);
+        body.set_stream();
+        let url = self.url();
+        let request = serde_json::to_value(&body)?;
+        let started = Instant::now();
+        let response = self.post(&url, &body)?;
+        let request_id = request_id(&response);
+
+        let mut parser = StreamParser::new(self.provider);
+        // The stream as it came, for a bug report if it makes no sense.
+        let mut raw = String::new();
+        for line in std::io::BufReader::new(response.into_reader()).lines() {
+            let line = line.context("Failed to read the response stream")?;
+            if stop.load(Ordering::Relaxed) {
+                anyhow::bail!("Stopped reading the response");
+            }
+            raw.push_str(&line);
+            raw.push('\n');
+            if let Some(delta) = parser.line(&line)? {
+                on_text(&delta);
+            }
+        }
+        let (last, streamed) = parser.finish();
+
+        let mut exchange = Exchange {
+            url,
+            request,
+            response: raw,
+            request_id,
+            incomplete: None,
+            elapsed_ms: started.elapsed().as_millis() as u64,
+        };
+        // A provider that doesn't stream sends the whole response at once.
+        let last = last.or_else(|| {
+            serde_json::from_str::<Value>(&exchange.response)
+                .ok()
+                .filter(|value| self.extract_text(value).is_some())
+        });
+        let Some(last) = last else {
+            let err = anyhow::anyhow!("The response stream ended before the response was complete");
+            return Err(dump::failure(&exchange, err));
+        };
+        exchange.response = last.to_string();
+        exchange.incomplete = self.incomplete_reason(&last);
+        if exchange.incomplete.as_deref() == Some("max_output_tokens") {
+            // Out of room: ask the same model again with more, the usual way,
+            // since what was streamed so far has been shown already.
+            let mut body = self.request(system_prompt, turns);
+            body.make_room(&last);
+            return self.converse_one(body, 1, stop);
+        }
+        let Some(text) = self
+            .extract_text(&last)
+            .or_else(|| (!streamed.is_empty()).then_some(streamed))
+        else {
+            let err = anyhow::anyhow!(
+                "No text output returned from model; the reply ends `{}`",
+                dump::preview(&exchange.response)
+            );
+            return Err(dump::failure(&exchange, err));
+        };
+        dump::exchange(&exchange);
+        Ok((text, exchange))
+    }
+
+    /// Ask with `ask`, and while the answer is an error or empty, ask each
+    /// fallback in turn, saying why and which model answered in the end.
+    /// Nothing more is asked once `stop` is set.
+    fn falling_back(
+        &self,
+        stop: &AtomicBool,
+        mut ask: impl FnMut(&Client) -> Result<(String, Exchange)>,
+    ) -> Result<(String, Exchange)> {
+        let mut result = ask(self);
+        let mut asked = self;
+        for fallback in &self.fallbacks {
+            let why = match &result {
+                Ok((text, _)) if !text.trim().is_empty() => break,
+                _ if stop.load(Ordering::Relaxed) => break,
+                Ok(_) => "answered with nothing".to_string(),
+                Err(err) => {
+                    let err = format!("{:#}", err);
+                    format!("failed ({})", err.lines().next().unwrap_or_default())
+                }
+            };
+            eprintln!(
+                "{} {}; asking {} instead.",
+                asked.model, why, fallback.model
+            );
+            result = ask(fallback);
+            asked = fallback;
+        }
+        if result.is_ok() && !std::ptr::eq(asked, self) {
+            eprintln!("This answer is from {}.", asked.model);
+        }
+        result
+    }
+
+    fn url(&self) -> String {
+        format!(
+            "{}/{}",
+            self.api_base.trim_end_matches('/'),
+            self.provider.endpoint()
+        )
+    }
+
+    fn request(&self, system_prompt: &str, turns: &[Turn]) -> Body {
+        let role = |turn: &Turn| match turn.role {
+            Role::User => "user".to_string(),
+            Role::Assistant => "assistant".to_string(),
+        };
+        let message = |role: String, content: &str| ChatMessage {
+            role,
+            content: content.to_string(),
+        };
+        let conversation = turns.iter().map(|turn| message(role(turn), &turn.text));
+        match self.provider {
+            Provider::Anthropic => {
+                return Body::Messages(MessagesRequest {
+                    model: self.model.clone(),
+                    system: system_prompt.to_string(),
+                    messages: conversation.collect(),
+                    max_tokens: DEFAULT_MAX_TOKENS,
+                    stream: false,
+                });
+            }
+            Provider::Ollama => {
+                return Body::Chat(ChatRequest {
+                    model: self.model.clone(),
+                    messages: std::iter::once(message("system".to_string(), system_prompt))
+                        .chain(conversation)
+                        .collect(),
+                    stream: false,
+                    options: None,
+                    format: self.schema.as_ref().map(|(_, schema)| schema.clone()),
+                });
+            }
+            Provider::Openai => {}
+        }
+        // The Responses API takes the model's earlier answers as output text.
+        let message = |role: String, part_type: &str, text: &str| Message {
+            role,
+            content: vec![ContentPart {
+                part_type: part_type.to_string(),
+                text: text.to_string(),
+            }],
+        };
+        Body::Responses(ResponsesRequest {
+            model: self.model.clone(),
+            input: std::iter::once(message("system".to_string(), "input_text", system_prompt))
+                .chain(turns.iter().map(|turn| {
+                    let part_type = match turn.role {
+                        Role::User => "input_text",
+                        Role::Assistant => "output_text",
+                    };
+                    message(role(turn), part_type, &turn.text)
+                }))
+                .collect(),
+            max_output_tokens: None,
+            stream: false,
+            text: self.schema.as_ref().map(|(name, schema)| {
+                serde_json::json!({
+                    "format": {"type": "json_schema", "name": name, "schema": schema, "strict": true}
+                })
+            }),
+        })
+    }
+
+    /// Send `body`, again after a wait when the API is rate limiting or
+    /// overloaded or the connection drops, as many times as `net::retries()`.
+    /// A `Retry-After` from the API sets the wait; otherwise it doubles each
+    /// time, give or take a random part so clients don't retry in step.
+    fn post(&self, url: &str, body: &Body) -> Result<ureq::Response> {
+        let max_retries = net::retries();
+        let mut retries = 0;
+        loop {
+            let request = match self.provider {
+                Provider::Openai => self
+                    .agent
+                    .post(url)
+                    .set("Authorization", &format!("Bearer {}", self.api_key)),
+                Provider::Anthropic => self
+                    .agent
+                    .post(url)
+                    .set("x-api-key", &self.api_key)
+                    .set("anthropic-version", ANTHROPIC_VERSION),
+                Provider::Ollama => self.agent.post(url),
+            };
+            let (err, reason, wait) = match request.send_json(body) {
+                Ok(response) => return Ok(response),
+                Err(ureq::Error::Status(status, response)) => {
+                    let request_id = request_id(&response);
+                    let asked = retry_after(&response);
+                    let body = response.into_string().unwrap_or_default();
+                    // Out of credit is a 429 too, but waiting won't help.
+                    let retry = matches!(status, 429 | 500 | 502 | 503 | 504 | 529)
+                        && !body.contains("insufficient_quota");
+                    let err = provider_error(
+                        status,
+                        request_id,
+                        &body,
+                        &self.model,
+                        self.provider,
+                        &self.key,
+                    );
+                    let reason = format!("The {} API returned {}", self.provider.name(), status);
+                    (
+                        err,
+                        reason,
+                        retry.then(|| asked.unwrap_or(backoff(retries))),
+                    )
+                }
+                Err(err) => {
+                    // A local Ollama that isn't running won't be by the next try.
+                    let transient = matches!(
+                        err.kind(),
+                        ureq::ErrorKind::Io | ureq::ErrorKind::ConnectionFailed
+                    ) && self.provider != Provider::Ollama;
+                    let err = self.transport_error(err);
+                    let reason =
+                        format!("The connection to the {} API failed", self.provider.name());
+                    (err, reason, transient.then(|| backoff(retries)))
+                }
+            };
+            match wait {
+                Some(wait) if retries < max_retries && wait <= MAX_RETRY_WAIT => {
+                    retries += 1;
+                    eprintln!(
+                        "{}; retrying in {:.1}s ({} of {})",
+                        reason,
+                        wait.as_secs_f64(),
+                        retries,
+                        max_retries
+                    );
+                    thread::sleep(wait);
+                }
+                // Only a wait the API asked for can be this long.
+                Some(wait) if wait > MAX_RETRY_WAIT && max_retries > 0 => {
+                    return Err(err.context(format!(
+                        "The {} API asks to wait {}s before trying again, which is longer than llmwrap waits",
+                        self.provider.name(),
+                        wait.as_secs()
+                    )));
+                }
+                Some(_) if retries > 0 => {
+                    return Err(err.context(format!("Gave up after {} retries", retries)));
+                }
+                _ => return Err(err),
+            }
+        }
+    }
+
+    fn transport_error(&self, err: ureq::Error) -> anyhow::Error {
+        if self.provider == Provider::Ollama {
+            anyhow::Error::new(err).context(format!(
+                "Failed to reach Ollama at {}; is `ollama serve` running?",
+                self.api_base
+            ))
+        } else {
+            anyhow::Error::new(err).context(format!(
+                "Failed to get a response from the {} API",
+                self.provider.name()
+            ))
+        }
+    }
+
+    fn incomplete_reason(&self, value: &Value) -> Option<String> {
+        match self.provider {
+            Provider::Openai => incomplete_reason(value),
+            // Reported as the Responses API would, so retries treat both alike.
+            Provider::Anthropic => match value.get("stop_reason").and_then(Value::as_str)? {
+                "max_tokens" => Some("max_output_tokens".to_string()),
+                "refusal" => Some("refusal".to_string()),
+                _ => None,
+            },
+            Provider::Ollama => (value.get("done_reason").and_then(Value::as_str)? == "length")
+                .then(|| "max_output_tokens".to_string()),
+        }
+    }
+
+    fn extract_text(&self, value: &Value) -> Option<String> {
+        extract_text(self.provider, value)
+    }
+}
+
+/// Reads a streamed reply a line at a time, gathering the text as it arrives
+/// and the response object sent at the end. Server-sent events carry the
+/// text in deltas and the whole response once more at the end. Anthropic
+/// sends the message up front instead, and its stop reason and output tokens
+/// near the end. Ollama sends one JSON object per line, the last marked done.
+pub struct StreamParser {
+    provider: Provider,
+    streamed: String,
+    last: Option<Value>,
+    message: Option<Value>,
+}
+
+impl StreamParser {
+    pub fn new(provider: Provider) -> Self {
+        StreamParser {
+            provider,
+            streamed: String::new(),
+            last: None,
+            message: None,
+        }
+    }
+
+    /// Take one line of the stream, returning the text it adds, if any. Fails
+    /// when the provider reports an error in the stream.
+    pub fn line(&mut self, line: &str) -> Result<Option<String>> {
+        if self.provider == Provider::Ollama {
+            let Ok(mut event) = serde_json::from_str::<Value>(line) else {
+                return Ok(None);
+            };
+            if let Some(message) = event.get("error").and_then(Value::as_str) {
+                anyhow::bail!("The Ollama API stopped the response: {}", message);
+            }
+            let delta = event
+                .pointer("/message/content")
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            if let Some(delta) = &delta {
+                self.streamed.push_str(delta);
+            }
+            if event.get("done").and_then(Value::as_bool) == Some(true) {
+                event["message"] =
+                    serde_json::json!({"role": "assistant", "content": self.streamed});
+                self.last = Some(event);
+            }
+            return Ok(delta);
+        }
+        let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+            return Ok(None);
+        };
+        let Ok(event) = serde_json::from_str::<Value>(data) else {
+            return Ok(None);
+        };
+        let delta = match event.get("type").and_then(Value::as_str) {
+            Some("response.output_text.delta") => event.get("delta").and_then(Value::as_str),
+            Some("content_block_delta") => event.pointer("/delta/text").and_then(Value::as_str),
+            Some("response.completed" | "response.incomplete" | "response.failed") => {
+                self.last = event.get("response").cloned();
+                None
+            }
+            Some("message_start") => {
+                self.message = event.get("message").filter(|m| m.is_object()).cloned();
+                None
+            }
+            Some("message_delta") => {
+                if let Some(message) = self.message.as_mut() {
+                    if let Some(reason) = event.pointer("/delta/stop_reason") {
+                        message["stop_reason"] = reason.clone();
+                    }
+                    if let Some(tokens) = event.pointer("/usage/output_tokens") {
+                        if !message["usage"].is_object() {
+                            message["usage"] = serde_json::json!({});
+                        }
+                        message["usage"]["output_tokens"] = tokens.clone();
+                    }
+                }
+                None
+            }
+            Some("message_stop") => {
+                if let Some(mut message) = self.message.take() {
+                    message["content"] =
+                        serde_json::json!([{"type": "text", "text": self.streamed}]);
+                    self.last = Some(message);
+                }
+                None
+            }
+            Some("error") => {
+                let message = event
+                    .get("message")
+                    .or_else(|| event.pointer("/error/message"))
+                    .and_then(Value::as_str)
+                    .unwrap_or("no details given");
+                anyhow::bail!(
+                    "The {} API stopped the response: {}",
+                    self.provider.name(),
+                    message
+                );
+            }
+            _ => None,
+        };
+        let delta = delta.map(str::to_string);
+        if let Some(delta) = &delta {
+            self.streamed.push_str(delta);
+        }
+        Ok(delta)
+    }
+
+    /// The response object, if the stream got as far as sending it, and the
+    /// text that arrived.
+    pub fn finish(self) -> (Option<Value>, String) {
+        (self.last, self.streamed)
+    }
+}
+
+/// The provider's ID for a request, from whichever header it uses.
+fn request_id(response: &ureq::Response) -> Option<String> {
+    response
+        .header("x-request-id")
+        .or_else(|| response.header("request-id"))
+        .map(str::to_string)
+}
+
+/// How long the API asks to be left alone, from OpenAI's `retry-after-ms`
+/// or the standard `Retry-After` in seconds. A date there is ignored.
+fn retry_after(response: &ureq::Response) -> Option<Duration> {
+    let header = |name: &str| response.header(name)?.trim().parse::<f64>().ok();
+    let seconds = header("retry-after-ms")
+        .map(|ms| ms / 1000.0)
+        .or_else(|| header("retry-after"))?;
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// The wait before retry `retries + 1` when the API doesn't say: doubling
+/// from `RETRY_BASE`, plus up to half as much again at random, and never
+/// more than `MAX_RETRY_WAIT`.
+fn backoff(retries: u32) -> Duration {
+    let wait = RETRY_BASE * 2u32.saturating_pow(retries);
+    let random = RandomState::new().hash_one(retries);
+    (wait + wait.mul_f64((random % 1000) as f64 / 2000.0)).min(MAX_RETRY_WAIT)
+}
+
+/// Why the reply stopped short, when it did: `status: incomplete` with its
+/// `incomplete_details`, or a message whose own status says so.
+fn incomplete_reason(value: &Value) -> Option<String> {
+    let reason = |v: &Value| {
+        v.pointer("/incomplete_details/reason")
+            .and_then(Value::as_str)
+            .unwrap_or("incomplete")
+            .to_string()
+    };
+    if value.get("status").and_then(Value::as_str) == Some("incomplete") {
+        return Some(reason(value));
+    }
+    value
+        .get("output")
+        .and_then(Value::as_array)?
+        .iter()
+        .find(|item| item.get("status").and_then(Value::as_str) == Some("incomplete"))
+        .map(|_| reason(value))
+}
+
+/// Turn an error status into a message that says what went wrong and what to
+/// do about it, using the `error` object the API sends back.
+fn provider_error(
+    status: u16,
+    request_id: Option<String>,
+    body: &str,
+    model: &str,
+    provider: Provider,
+    key: &KeySource,
+) -> anyhow::Error {
+    let error = serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|v| v.get("error").cloned());
+    let field = |name: &str| {
+        error
+            .as_ref()
+            .and_then(|e| e.get(name))
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+    };
+    let kind = field("type");
+    let code = field("code");
+    // Ollama's `error` is the message itself.
+    let message = field("message")
+        .or_else(|| error.as_ref().and_then(Value::as_str).map(str::to_string))
+        .unwrap_or_else(|| {
+            let body = body.trim();
+            if body.is_empty() {
+                "no details given".to_string()
+            } else {
+                body.to_string()
+            }
+        });
+
+    let is = |name: &str| code.as_deref() == Some(name) || kind.as_deref() == Some(name);
+    let hint = if provider == Provider::Ollama && status == 404 {
+        Some(format!(
+            "Fetch the model with `ollama pull {}`, or pick another with --model.",
+            model
+        ))
+    } else if let Some(hint) = key.hint(provider)
+        && (status == 401 || is("invalid_api_key"))
+    {
+        Some(hint)
+    } else if is("insufficient_quota") {
+        Some("The account is out of credit; check its plan and billing.".to_string())
+    } else if status == 429 {
+        Some("Too many requests for now; wait a moment and try again.".to_string())
+    } else if is("model_not_found") || (status == 404 && message.contains(model)) {
+        Some(format!(
+            "The model `{}` is not available to this key; pick another with --model.",
+            model
+        ))
+    } else if status == 403 {
+        Some("This key is not allowed to use this API or this model.".to_string())
+    } else if status >= 500 {
+        Some("The API is having trouble; try again shortly.".to_string())
+    } else {
+        None
+    };
+
+    let mut text = format!(
+        "The {} API returned {}: {}",
+        provider.name(),
+        status,
+        message
+    );
+    if let Some(kind) = code.or(kind) {
+        text.push_str(&format!(" ({})", kind));
+    }
+    if let Some(hint) = hint {
+        text.push_str(&format!("\n{}", hint));
+    }
+    if let Some(id) = request_id {
+        text.push_str(&format!("\nRequest ID: {}", id));
+    }
+    anyhow::anyhow!(text)
+}
+
+/// The text of the model's answer in a response from `provider`; None when
+/// it has none.
+pub fn extract_text(provider: Provider, value: &Value) -> Option<String> {
+    match provider {
+        Provider::Openai => output_text(value),
+        Provider::Anthropic => {
+            let text: String = value
+                .get("content")?
+                .as_array()?
+                .iter()
+                .filter(|block| block.get("type").and_then(Value::as_str) == Some("text"))
+                .filter_map(|block| block.get("text").and_then(Value::as_str))
+                .collect();
+            (!text.is_empty()).then_some(text)
+        }
+        Provider::Ollama => value
+            .pointer("/message/content")
+            .and_then(Value::as_str)
+            .filter(|text| !text.is_empty())
+            .map(str::to_string),
+    }
+}
+
+/// The text in a Responses API response.
+fn output_text(value: &Value) -> Option<String> {
+    // Primary: output is an array of messages with content
+    if let Some(outputs) = value.get("output").and_then(|o| o.as_array()) {
+        for msg in outputs {
+            if let Some(contents) = msg.get("content").and_then(|c| c.as_array()) {
+                for c in contents {
+                    if let Some(text) = c.get("text").and_then(|t| t.as_str()) {
+                        return Some(text.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    // Some payloads may include a single object under "output"
+    if let Some(msg) = value.get("output").and_then(|o| o.as_object())
+        && let Some(contents) = msg.get("content").and_then(|c| c.as_array())
+    {
+        for c in contents {
+            if let Some(text) = c.get("text").and_then(|t| t.as_str()) {
+                return Some(text.to_string());
+            }
+        }
+    }
+
+    // Fallback: output_text as string or array
+    if let Some(text) = value.get("output_text").and_then(|t| t.as_str()) {
+        return Some(text.to_string());
+    }
+    if let Some(arr) = value.get("output_text").and_then(|t| t.as_array()) {
+        let joined: String = arr
+            .iter()
+            .filter_map(|v| v.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        if !joined.is_empty() {
+            return Some(joined);
+        }
+    }
+
+    None
+}
+
+/// Remove Markdown code fences that models wrap multi-line answers in.
+pub fn strip_code_fences(raw: &str) -> String {
+    raw.lines()
+        .filter(|line| !line.trim_start().starts_with("```"))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explains_provider_errors() {
+        let key = KeySource::Provider;
+        let cases: &[(Provider, u16, &str, &str)] = &[
+            (
+                Provider::Openai,
+                401,
+                r#"{"error": {"message": "Incorrect API key provided", "type": "invalid_request_error", "code": "invalid_api_key"}}"#,
+                "The OpenAI API returned 401: Incorrect API key provided (invalid_api_key)\nCheck that LLMWRAP_OPENAI_API_KEY holds a current key.",
+            ),
+            (
+                Provider::Openai,
+                429,
+                r#"{"error": {"message": "You exceeded your current quota", "type": "insufficient_quota", "code": null}}"#,
+                "The OpenAI API returned 429: You exceeded your current quota (insufficient_quota)\nThe account is out of credit; check its plan and billing.",
+            ),
+            (
+                Provider::Openai,
+                404,
+                r#"{"error": {"message": "The model `gpt-x` does not exist", "type": "invalid_request_error", "code": "model_not_found"}}"#,
+                "The OpenAI API returned 404: The model `gpt-x` does not exist (model_not_found)\nThe model `gpt-x` is not available to this key; pick another with --model.",
+            ),
+            (
+                Provider::Anthropic,
+                429,
+                r#"{"type": "error", "error": {"type": "rate_limit_error", "message": "Number of requests has exceeded your rate limit"}}"#,
+                "The Anthropic API returned 429: Number of requests has exceeded your rate limit (rate_limit_error)\nToo many requests for now; wait a moment and try again.",
+            ),
+            (
+                Provider::Anthropic,
+                529,
+                r#"{"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}"#,
+                "The Anthropic API returned 529: Overloaded (overloaded_error)\nThe API is having trouble; try again shortly.",
+            ),
+            (
+                Provider::Anthropic,
+                403,
+                r#"{"type": "error", "error": {"type": "permission_error", "message": "Your API key does not have permission"}}"#,
+                "The Anthropic API returned 403: Your API key does not have permission (permission_error)\nThis key is not allowed to use this API or this model.",
+            ),
+            (
+                Provider::Ollama,
+                404,
+                r#"{"error": "model \"gpt-x\" not found, try pulling it first"}"#,
+                "The Ollama API returned 404: model \"gpt-x\" not found, try pulling it first\nFetch the model with `ollama pull gpt-x`, or pick another with --model.",
+            ),
+            (
+                Provider::Ollama,
+                400,
+                "",
+                "The Ollama API returned 400: no details given",
+            ),
+            (
+                Provider::Openai,
+                502,
+                "<html>Bad Gateway</html>\n",
+                "The OpenAI API returned 502: <html>Bad Gateway</html>\nThe API is having trouble; try again shortly.",
+            ),
+        ];
+        for &(provider, status, body, expected) in cases {
+            let err = provider_error(status, None, body, "gpt-x", provider, &key);
+            assert_eq!(err.to_string(), expected, "{}", body);
+        }
+    }
+
+    #[test]
+    fn hints_where_the_key_came_from() {
+        let body = r#"{"error": {"message": "invalid x-api-key", "type": "authentication_error"}}"#;
+        let hint = |key: KeySource| {
+            let err = provider_error(401, None, body, "m", Provider::Anthropic, &key);
+            err.to_string().lines().nth(1).map(str::to_string)
+        };
+        assert_eq!(
+            hint(KeySource::Env("WORK_KEY".to_string())).unwrap(),
+            "Check that WORK_KEY holds a current key."
+        );
+        assert_eq!(
+            hint(KeySource::Command("pass show work".to_string())).unwrap(),
+            "Check that `pass show work` prints a current key."
+        );
+    }
+
+    #[test]
+    fn reads_request_ids() {
+        let response = |headers: &str| -> ureq::Response {
+            format!("HTTP/1.1 500 Internal Server Error\r\n{}\r\n", headers)
+                .parse()
+                .unwrap()
+        };
+        let cases = [
+            // OpenAI.
+            ("x-request-id: req_123\r\n", Some("req_123")),
+            // Anthropic.
+            ("request-id: req_011CS\r\n", Some("req_011CS")),
+            (
+                "X-Request-Id: req_456\r\nrequest-id: other\r\n",
+                Some("req_456"),
+            ),
+            // Ollama sends none.
+            ("content-type: application/json\r\n", None),
+        ];
+        for (headers, expected) in cases {
+            assert_eq!(
+                request_id(&response(headers)).as_deref(),
+                expected,
+                "{}",
+                headers
+            );
+        }
+
+        let err = provider_error(
+            500,
+            request_id(&response("x-request-id: req_123\r\n")),
+            "",
+            "m",
+            Provider::Openai,
+            &KeySource::Provider,
+        );
+        assert!(
+            err.to_string().ends_with("\nRequest ID: req_123"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn backoff_doubles_with_a_random_part() {
+        for retries in 0..5 {
+            let wait = RETRY_BASE * 2u32.pow(retries);
+            let backoff = backoff(retries);
+            assert!(
+                backoff >= wait && backoff <= wait.mul_f64(1.5),
+                "{:?}",
+                backoff
+            );
+        }
+    }
+
+    #[test]
+    fn backoff_stops_at_the_longest_wait() {
+        for retries in [7, 8, 20, 31, 32, 1000, u32::MAX] {
+            let backoff = backoff(retries);
+            assert!(backoff <= MAX_RETRY_WAIT, "{}: {:?}", retries, backoff);
+        }
+        assert_eq!(backoff(10), MAX_RETRY_WAIT);
+    }
+}
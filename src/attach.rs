@@ -0,0 +1,213 @@
+//! Files and piped input attached to a request with `--file` or
+//! `cat error.log | llmwrap ...`, so the model can see what the command is
+//! about. Long input is cut down to its beginning and end, where headers and
+//! the final errors are, before it goes into the prompt.
+
+use crate::ui;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{self, IsTerminal, Read};
+use std::path::PathBuf;
+
+/// `[attach]` in `config.toml`: how much of each attachment is sent.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// The most of one file or of stdin passed on, in bytes (about four to a
+    /// token). Longer input keeps its first quarter and last three quarters.
+    pub max_bytes: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings { max_bytes: 16_000 }
+    }
+}
+
+/// One file, or stdin, as it goes into the prompt.
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    /// The path as given, or `stdin`.
+    pub name: String,
+    pub text: String,
+    /// How many lines were cut from the middle to fit `max_bytes`.
+    pub left_out: usize,
+}
+
+/// Read `files`, then stdin when `stdin` is set and something is piped in.
+/// Empty input on stdin attaches nothing; a file that can't be read, or
+/// input that isn't text, is an error.
+pub fn gather(files: &[PathBuf], stdin: bool, settings: &Settings) -> Result<Vec<Attachment>> {
+    let limit = settings.max_bytes.max(100);
+    let mut attachments = Vec::new();
+    for path in files {
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let name = path.display().to_string();
+        let attachment =
+            read(&name, file, limit).with_context(|| format!("Failed to read {}", name))?;
+        attachments.push(attachment);
+    }
+    if stdin && !io::stdin().is_terminal() {
+        let attachment =
+            read("stdin", io::stdin().lock(), limit).context("Failed to read what was piped in")?;
+        if !attachment.text.trim().is_empty() {
+            attachments.push(attachment);
+        }
+    }
+    for attachment in &attachments {
+        if attachment.left_out > 0 {
+            ui::say!(
+                "Sending the start and end of {}; {} lines in between are left out.",
+                attachment.name,
+                attachment.left_out
+            );
+        }
+    }
+    Ok(attachments)
+}
+
+/// Read all of `reader`, keeping only the first quarter and the last three
+/// quarters of `limit` bytes, cut at line ends.
+pub fn read(name: &str, mut reader: impl Read, limit: usize) -> Result<Attachment> {
+    let head_limit = limit / 4;
+    let tail_limit = limit - head_limit;
+    let mut head = Vec::new();
+    let mut tail = Vec::new();
+    let mut total = 0;
+    let mut newlines = 0;
+    let mut last = None;
+    // The byte just before the tail, to tell whether it starts mid-line.
+    let mut before_tail = None;
+    let mut buffer = [0; 8192];
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        total += read;
+        newlines += count_lines(&buffer[..read]);
+        last = Some(buffer[read - 1]);
+        let mut chunk = &buffer[..read];
+        if head.len() < head_limit {
+            let take = chunk.len().min(head_limit - head.len());
+            head.extend_from_slice(&chunk[..take]);
+            chunk = &chunk[take..];
+        }
+        tail.extend_from_slice(chunk);
+        // Let the tail grow to twice its size before dropping the oldest part.
+        if tail.len() > 2 * tail_limit {
+            let excess = tail.len() - tail_limit;
+            before_tail = Some(tail[excess - 1]);
+            tail.drain(..excess);
+        }
+    }
+    if head.contains(&0) {
+        anyhow::bail!("{} looks like binary data; attach text", name);
+    }
+
+    if total <= limit {
+        head.append(&mut tail);
+        return Ok(Attachment {
+            name: name.to_string(),
+            text: String::from_utf8_lossy(&head).trim_end().to_string(),
+            left_out: 0,
+        });
+    }
+    let mut before_tail = before_tail.or(head.last().copied());
+    if tail.len() > tail_limit {
+        let excess = tail.len() - tail_limit;
+        before_tail = Some(tail[excess - 1]);
+        tail.drain(..excess);
+    }
+    if before_tail != Some(b'\n') {
+        cut_to_line_start(&mut tail);
+    }
+    // Whole lines only: the head ends and the tail starts at a line break.
+    match head.iter().rposition(|&b| b == b'\n') {
+        Some(end) => head.truncate(end),
+        None => head.clear(),
+    }
+    let lines = newlines + usize::from(last != Some(b'\n'));
+    let kept = if head.is_empty() {
+        0
+    } else {
+        count_lines(&head) + 1
+    } + count_lines(&tail)
+        + usize::from(tail.last().is_some_and(|&b| b != b'\n'));
+    let left_out = lines - kept;
+    let tail = tail.strip_suffix(b"\n").unwrap_or(&tail);
+    Ok(Attachment {
+        name: name.to_string(),
+        text: format!(
+            "{}\n[... {} lines left out ...]\n{}",
+            String::from_utf8_lossy(&head),
+            left_out,
+            String::from_utf8_lossy(tail)
+        ),
+        left_out,
+    })
+}
+
+/// Drop the partial line at the start of `tail`.
+fn cut_to_line_start(tail: &mut Vec<u8>) {
+    match tail.iter().position(|&b| b == b'\n') {
+        Some(start) => drop(tail.drain(..=start)),
+        None => tail.clear(),
+    }
+}
+
+fn count_lines(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|&&b| b == b'\n').count()
+}
+
+/// The attachments as a block for the system prompt, or None when there are
+/// none.
+pub fn prompt_block(attachments: &[Attachment]) -> Option<String> {
+    if attachments.is_empty() {
+        return None;
+    }
+    let sections: Vec<String> = attachments
+        .iter()
+        .map(|attachment| format!("[{}]\n{}", attachment.name, attachment.text))
+        .collect();
+    Some(format!(
+        "The user attached this input to the request so you can see what it looks like. \
+         Write the command to read it, from the file named or from stdin for piped input, \
+         rather than pasting it in:\n{}",
+        sections.join("\n\n")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn attachments_keep_whole_lines_within_the_limit(
+            lines in prop::collection::vec("[a-z0-9][a-z0-9 ]{0,40}", 0..200),
+            limit in 100usize..2000,
+        ) {
+            let input = lines.iter().map(|line| format!("{}\n", line)).collect::<String>();
+            let attachment = read("input", input.as_bytes(), limit).unwrap();
+            if input.len() <= limit {
+                prop_assert_eq!(attachment.text, input.trim_end());
+                prop_assert_eq!(attachment.left_out, 0);
+            } else {
+                let (head, tail) = attachment.text.split_once("\n[... ").unwrap();
+                let (_, tail) = tail.split_once(" ...]\n").unwrap_or(("", ""));
+                prop_assert!(head.len() + tail.len() <= limit);
+                prop_assert!(input.starts_with(head));
+                let ending = format!("{}\n", tail);
+                prop_assert!(tail.is_empty() || input.ends_with(&ending));
+                let kept = head.lines().count() + tail.lines().count();
+                prop_assert_eq!(kept + attachment.left_out, lines.len());
+            }
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use crate::safer;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// Which BSD this is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flavor {
+    FreeBsd,
+    OpenBsd,
+    NetBsd,
+    DragonFly,
+}
+
+/// A FreeBSD, OpenBSD, NetBSD or DragonFly host: what it installs packages
+/// and gains root with.
+pub struct Bsd {
+    pub flavor: Flavor,
+    /// `doas` or `sudo`, whichever is installed, preferring the one the
+    /// system ships.
+    pub escalate: Option<&'static str>,
+}
+
+static DETECTED: OnceLock<Option<Bsd>> = OnceLock::new();
+
+/// The BSD this is running on, if it is one. macOS has a BSD userland too but
+/// is a different system to administer.
+pub fn detect() -> Option<&'static Bsd> {
+    DETECTED
+        .get_or_init(|| {
+            let flavor = if cfg!(target_os = "freebsd") {
+                Flavor::FreeBsd
+            } else if cfg!(target_os = "openbsd") {
+                Flavor::OpenBsd
+            } else if cfg!(target_os = "netbsd") {
+                Flavor::NetBsd
+            } else if cfg!(target_os = "dragonfly") {
+                Flavor::DragonFly
+            } else {
+                return None;
+            };
+            let preferred: &[&'static str] = if flavor == Flavor::OpenBsd {
+                &["doas", "sudo"]
+            } else {
+                &["sudo", "doas"]
+            };
+            Some(Bsd {
+                flavor,
+                escalate: preferred.iter().copied().find(|p| safer::on_path(p)),
+            })
+        })
+        .as_ref()
+}
+
+impl Bsd {
+    /// The command that installs packages, e.g. `pkg install`.
+    pub fn install(&self) -> &'static str {
+        match self.flavor {
+            Flavor::FreeBsd | Flavor::DragonFly => "pkg install",
+            Flavor::OpenBsd => "pkg_add",
+            Flavor::NetBsd => "pkgin install",
+        }
+    }
+
+    /// The command that starts, stops or restarts `service`.
+    pub fn service(&self, action: &str, service: &str) -> String {
+        match self.flavor {
+            Flavor::OpenBsd => format!("rcctl {} {}", action, service),
+            _ => format!("service {} {}", service, action),
+        }
+    }
+
+    /// Sentences for the system prompt about administering this system.
+    pub fn prompt_note(&self) -> String {
+        let root = match self.escalate {
+            Some(program) => format!("Run commands as root with {}.", program),
+            None => {
+                "Neither sudo nor doas is installed; run commands as root with `su -m root -c`."
+                    .to_string()
+            }
+        };
+        let services = match self.flavor {
+            Flavor::OpenBsd => "`rcctl start NAME` and `rcctl enable NAME`",
+            _ => "`service NAME start` and `sysrc NAME_enable=YES`",
+        };
+        format!(
+            "The system is {}, not Linux. Install packages with `{}`. {} Manage services with {}; \
+there is no systemctl. There is no ip, free or ss: use ifconfig, route, netstat -rn, sockstat or \
+fstat, top and sysctl. ps takes BSD flags: `ps aux` works but there is no --sort (-m sorts by \
+memory, -r by CPU) and -e shows environments rather than every process.",
+            self,
+            self.install(),
+            root,
+            services
+        )
+    }
+}
+
+impl fmt::Display for Bsd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self.flavor {
+            Flavor::FreeBsd => "FreeBSD",
+            Flavor::OpenBsd => "OpenBSD",
+            Flavor::NetBsd => "NetBSD",
+            Flavor::DragonFly => "DragonFly BSD",
+        })
+    }
+}
@@ -0,0 +1,148 @@
+use regex::Regex;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{LazyLock, OnceLock};
+
+/// BusyBox standing in for the usual tools, as in Alpine and other minimal
+/// containers.
+pub struct Busybox {
+    /// The multi-call binary the applets link to.
+    binary: PathBuf,
+}
+
+static DETECTED: OnceLock<Option<Busybox>> = OnceLock::new();
+
+/// Options as a BusyBox help text lists them: `-x`, `[-HL]` or `--long`.
+static HELP_FLAG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?:^|[\s\[|,(])(--?[A-Za-z][\w-]*)").unwrap());
+
+/// BusyBox, if it provides this system's `sed` or `ls`.
+pub fn detect() -> Option<&'static Busybox> {
+    DETECTED
+        .get_or_init(|| {
+            let binary = ["sed", "ls"]
+                .into_iter()
+                .filter_map(|program| resolve(&locate(program)?))
+                .find(|binary| is_busybox(binary))?;
+            Some(Busybox { binary })
+        })
+        .as_ref()
+}
+
+impl Busybox {
+    /// Whether `program`, a name or a path, runs this BusyBox.
+    pub fn provides(&self, program: &str) -> bool {
+        let path = if program.contains('/') {
+            Some(PathBuf::from(program))
+        } else {
+            locate(program)
+        };
+        path.and_then(|path| resolve(&path)) == Some(self.binary.clone())
+    }
+
+    /// Options in `args` that the applet's own help does not list, stopping
+    /// at `--`. Values that merely start with a dash are taken for options
+    /// only when they are made of letters.
+    pub fn unsupported(&self, applet: &str, args: &[&str]) -> Vec<String> {
+        let Some(known) = self.flags(applet) else {
+            return Vec::new();
+        };
+        let mut unsupported: Vec<String> = Vec::new();
+        let mut report = |flag: String| {
+            if !unsupported.contains(&flag) {
+                unsupported.push(flag);
+            }
+        };
+        for arg in args.iter().take_while(|arg| **arg != "--") {
+            let Some(name) = arg.strip_prefix('-') else {
+                continue;
+            };
+            if let Some(long) = name.strip_prefix('-') {
+                let flag = format!("--{}", long.split('=').next().unwrap_or(long));
+                if !known.contains(&flag) {
+                    report(flag);
+                }
+                continue;
+            }
+            // `find -maxdepth` and the like are single words.
+            if known.contains(*arg) {
+                continue;
+            }
+            let letters: String = name
+                .chars()
+                .take_while(|c| c.is_ascii_alphabetic())
+                .collect();
+            if applet == "find" && letters.len() > 1 && letters.len() == name.len() {
+                report(arg.to_string());
+                continue;
+            }
+            // A cluster like `-rn`, up to a value attached as in `-n5`.
+            for letter in letters.chars() {
+                let flag = format!("-{}", letter);
+                if !known.contains(&flag) {
+                    report(flag);
+                }
+            }
+        }
+        unsupported
+    }
+
+    /// Every option the applet's `--help` mentions, or None when it has no
+    /// help to go by.
+    fn flags(&self, applet: &str) -> Option<HashSet<String>> {
+        let output = Command::new(&self.binary)
+            .args([applet, "--help"])
+            .stdin(Stdio::null())
+            .output()
+            .ok()?;
+        let help = format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        if !help.contains("Usage:") {
+            return None;
+        }
+        let mut flags = HashSet::new();
+        for caps in HELP_FLAG.captures_iter(&help) {
+            let flag = &caps[1];
+            flags.insert(flag.to_string());
+            // `[-HL]` is two options; `-maxdepth` adds a few too many, which
+            // only means missing a wrong one now and then.
+            if !flag.starts_with("--") {
+                for letter in flag[1..].chars().filter(char::is_ascii_alphabetic) {
+                    flags.insert(format!("-{}", letter));
+                }
+            }
+        }
+        Some(flags)
+    }
+}
+
+/// A sentence for the system prompt when this is Alpine.
+pub fn distro_note() -> Option<&'static str> {
+    Path::new("/etc/alpine-release").exists().then_some(
+        "The system is Alpine Linux: install packages with `apk add`. The shell is BusyBox ash; \
+bash is only there if it was added with apk.",
+    )
+}
+
+fn locate(program: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+}
+
+fn resolve(path: &Path) -> Option<PathBuf> {
+    fs::canonicalize(path).ok()
+}
+
+fn is_busybox(binary: &Path) -> bool {
+    binary
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with("busybox"))
+}
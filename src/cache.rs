@@ -0,0 +1,372 @@
+use crate::{fuzzy, paths, store};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Minimum similarity for a cached prompt to be offered for a new one.
+pub const FUZZY_THRESHOLD: f64 = 0.8;
+
+const MAX_ENTRIES: usize = 500;
+
+/// `[cache]` in `config.toml`: how long generated commands are reused.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// Reuse a cached command for this many days; 0 turns the cache off.
+    pub ttl_days: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings { ttl_days: 30 }
+    }
+}
+
+/// What a command is cached for: the request, and who wrote it for where.
+pub struct Key<'a> {
+    pub prompt: &'a str,
+    pub provider: &'a str,
+    pub model: &'a str,
+    /// Shell the command is written for; None for POSIX sh.
+    pub shell: Option<&'a str>,
+    /// Host and system the command is written for, when it runs over SSH.
+    pub host: Option<&'a str>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Cache {
+    entries: Vec<CacheEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CacheEntry {
+    pub prompt: String,
+    /// Provider the model belongs to; absent in entries from before there
+    /// was a choice, which were all OpenAI's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    pub model: String,
+    /// Shell the command was written for; absent for POSIX sh.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+    /// Host and system the command was written for, when it ran over SSH.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    pub command: String,
+    pub created: u64,
+}
+
+pub struct CacheHit {
+    pub entry: CacheEntry,
+    /// Similarity between the cached prompt and the new one; 1.0 for exact matches.
+    pub score: f64,
+    pub exact: bool,
+}
+
+impl Cache {
+    /// Load the cache from disk; a missing or unreadable cache is treated as empty.
+    pub fn load() -> Self {
+        cache_file()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    /// Save the cache, along with what other llmwraps saved since it was
+    /// loaded; for the same request the newer command wins.
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = cache_file() else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create cache directory {}", dir.display()))?;
+        }
+        store::update(&path, |current| {
+            let saved: Cache = current
+                .and_then(|text| serde_json::from_str(&text).ok())
+                .unwrap_or_default();
+            Ok(serde_json::to_string(&self.merged(saved))?)
+        })
+        .context("Failed to save the cache")
+    }
+
+    /// `saved` with our entries added, keeping the newest of each request.
+    fn merged(&self, saved: Cache) -> Cache {
+        let mut entries = saved.entries;
+        entries.extend(self.entries.iter().cloned());
+        entries.sort_by_key(|e| e.created);
+        let mut merged: Vec<CacheEntry> = Vec::new();
+        for entry in entries.into_iter().rev() {
+            if !merged.iter().any(|newer| newer.same_request(&entry)) {
+                merged.push(entry);
+            }
+        }
+        merged.truncate(MAX_ENTRIES);
+        merged.reverse();
+        Cache { entries: merged }
+    }
+
+    /// Find the best cached command for the key's prompt, either an exact
+    /// match or the most similar normalized prompt above `threshold` with the
+    /// same numbers and paths, among entries at most `max_age` seconds old.
+    pub fn lookup(&self, key: &Key, threshold: f64, max_age: Option<u64>) -> Option<CacheHit> {
+        let wanted = fuzzy::normalize(key.prompt);
+        let specifics = fuzzy::specifics(key.prompt);
+        let oldest = max_age.map_or(0, |age| now().saturating_sub(age));
+        let mut best: Option<CacheHit> = None;
+
+        for entry in self
+            .entries
+            .iter()
+            .filter(|e| e.is_for(key) && e.created >= oldest)
+        {
+            if entry.prompt.trim() == key.prompt.trim() {
+                return Some(CacheHit {
+                    entry: entry.clone(),
+                    score: 1.0,
+                    exact: true,
+                });
+            }
+            if fuzzy::specifics(&entry.prompt) != specifics {
+                continue;
+            }
+            let score = fuzzy::similarity(&wanted, &fuzzy::normalize(&entry.prompt));
+            if score >= threshold && best.as_ref().is_none_or(|b| score > b.score) {
+                best = Some(CacheHit {
+                    entry: entry.clone(),
+                    score,
+                    exact: false,
+                });
+            }
+        }
+
+        best
+    }
+
+    /// The command cached for exactly the key's prompt, however old: what
+    /// to fall back on when the API can't be reached.
+    pub fn stale(&self, key: &Key) -> Option<&CacheEntry> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|e| e.is_for(key) && e.prompt.trim() == key.prompt.trim())
+    }
+
+    /// Record a freshly generated command, replacing any entry for the same prompt.
+    pub fn insert(&mut self, key: &Key, command: &str) {
+        self.entries
+            .retain(|e| !(e.is_for(key) && e.prompt.trim() == key.prompt.trim()));
+        self.entries.push(CacheEntry {
+            prompt: key.prompt.trim().to_string(),
+            provider: Some(key.provider.to_string()),
+            model: key.model.to_string(),
+            shell: key.shell.map(str::to_string),
+            host: key.host.map(str::to_string),
+            command: command.to_string(),
+            created: now(),
+        });
+        if self.entries.len() > MAX_ENTRIES {
+            let excess = self.entries.len() - MAX_ENTRIES;
+            self.entries.drain(..excess);
+        }
+    }
+}
+
+impl CacheEntry {
+    /// Whether both entries answer the same request for the same model,
+    /// shell and host.
+    fn same_request(&self, other: &CacheEntry) -> bool {
+        self.prompt == other.prompt
+            && self.provider == other.provider
+            && self.model == other.model
+            && self.shell == other.shell
+            && self.host == other.host
+    }
+
+    /// Whole days since the command was cached.
+    pub fn age_days(&self) -> u64 {
+        now().saturating_sub(self.created) / (24 * 60 * 60)
+    }
+
+    /// Whether the entry was written by the key's model for its shell and host.
+    fn is_for(&self, key: &Key) -> bool {
+        self.provider.as_deref().unwrap_or("OpenAI") == key.provider
+            && self.model == key.model
+            && self.shell.as_deref() == key.shell
+            && self.host.as_deref() == key.host
+    }
+}
+
+fn cache_file() -> Option<PathBuf> {
+    paths::cache_dir().map(|dir| dir.join("responses.json"))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: Key = Key {
+        prompt: "",
+        provider: "OpenAI",
+        model: "gpt",
+        shell: None,
+        host: None,
+    };
+
+    fn entry(prompt: &str, command: &str, created: u64) -> CacheEntry {
+        CacheEntry {
+            prompt: prompt.to_string(),
+            provider: Some("OpenAI".to_string()),
+            model: "gpt".to_string(),
+            shell: None,
+            host: None,
+            command: command.to_string(),
+            created,
+        }
+    }
+
+    fn cache(entries: Vec<CacheEntry>) -> Cache {
+        Cache { entries }
+    }
+
+    fn lookup(cache: &Cache, prompt: &str, max_age: Option<u64>) -> Option<CacheHit> {
+        let key = Key { prompt, ..KEY };
+        cache.lookup(&key, FUZZY_THRESHOLD, max_age)
+    }
+
+    #[test]
+    fn exact_prompts_match_first() {
+        let cache = cache(vec![
+            entry("gif from video.mp4", "fuzzy", now()),
+            entry("make a gif from video.mp4", "exact", now()),
+        ]);
+        let hit = lookup(&cache, " make a gif from video.mp4 ", None).unwrap();
+        assert!(hit.exact);
+        assert_eq!(hit.entry.command, "exact");
+    }
+
+    #[test]
+    fn similar_prompts_match_from_the_threshold() {
+        let cache = cache(vec![entry("compress old log file here", "gzip", now())]);
+        let hit = lookup(&cache, "please compress the old log files here", None).unwrap();
+        assert!(!hit.exact);
+        assert_eq!(hit.score, 1.0);
+        // Four of five words shared is just enough; four of six is not.
+        assert_eq!(
+            lookup(&cache, "compress old log file", None).unwrap().score,
+            0.8
+        );
+        assert!(lookup(&cache, "compress old log file there", None).is_none());
+        assert!(lookup(&cache, "compress old file", None).is_none());
+    }
+
+    #[test]
+    fn differing_numbers_or_paths_never_match() {
+        let cache = cache(vec![entry(
+            "find and delete all log files older than 7 days in /var/log",
+            "find /var/log -mtime +7 -delete",
+            now(),
+        )]);
+        let prompt = "find and delete all log files older than 30 days in /var/log";
+        assert!(lookup(&cache, prompt, None).is_none());
+        let prompt = "find and delete all log files older than 7 days in /tmp";
+        assert!(lookup(&cache, prompt, None).is_none());
+        let prompt = "find and delete every log file older than 7 days in /var/log";
+        assert!(lookup(&cache, prompt, None).is_some());
+    }
+
+    #[test]
+    fn old_entries_are_skipped() {
+        let day = 24 * 60 * 60;
+        let cache = cache(vec![entry("list files", "ls", now() - 3 * day)]);
+        assert!(lookup(&cache, "list files", Some(2 * day)).is_none());
+        assert!(lookup(&cache, "list files", Some(4 * day)).is_some());
+        assert!(lookup(&cache, "list files", None).is_some());
+        assert_eq!(cache.entries[0].age_days(), 3);
+        // Too old to reuse, but still there for when the API can't be reached.
+        let key = Key {
+            prompt: "list files",
+            ..KEY
+        };
+        assert_eq!(cache.stale(&key).unwrap().command, "ls");
+    }
+
+    #[test]
+    fn entries_are_kept_apart_by_model_shell_and_host() {
+        let cache = cache(vec![entry("list files", "ls", now())]);
+        let other = |key: Key| cache.lookup(&key, FUZZY_THRESHOLD, None).is_none();
+        let prompt = "list files";
+        assert!(other(Key {
+            prompt,
+            model: "claude",
+            ..KEY
+        }));
+        assert!(other(Key {
+            prompt,
+            shell: Some("fish"),
+            ..KEY
+        }));
+        assert!(other(Key {
+            prompt,
+            host: Some("web1"),
+            ..KEY
+        }));
+        assert!(other(Key {
+            prompt,
+            provider: "Anthropic",
+            ..KEY
+        }));
+    }
+
+    #[test]
+    fn insert_replaces_the_same_prompt() {
+        let mut cache = cache(vec![entry("list files", "ls", 1)]);
+        let key = Key {
+            prompt: "list files ",
+            ..KEY
+        };
+        cache.insert(&key, "ls -la");
+        assert_eq!(cache.entries.len(), 1);
+        assert_eq!(cache.entries[0].command, "ls -la");
+        assert_eq!(cache.entries[0].prompt, "list files");
+    }
+
+    #[test]
+    fn save_keeps_the_newest_of_each_request() {
+        let saved = cache(vec![
+            entry("list files", "ls from elsewhere", 30),
+            entry("disk usage", "du", 10),
+        ]);
+        let ours = cache(vec![
+            entry("list files", "ls", 20),
+            entry("show date", "date", 40),
+        ]);
+        let merged = ours.merged(saved);
+        let commands: Vec<&str> = merged.entries.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, ["du", "ls from elsewhere", "date"]);
+    }
+
+    #[test]
+    fn save_keeps_the_newest_entries_when_full() {
+        let saved = cache(
+            (0..MAX_ENTRIES as u64)
+                .map(|i| entry(&format!("prompt {}", i), "true", i))
+                .collect(),
+        );
+        let ours = cache(vec![entry("latest", "date", MAX_ENTRIES as u64)]);
+        let merged = ours.merged(saved);
+        assert_eq!(merged.entries.len(), MAX_ENTRIES);
+        assert_eq!(merged.entries[0].prompt, "prompt 1");
+        assert_eq!(merged.entries.last().unwrap().prompt, "latest");
+    }
+}
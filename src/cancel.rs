@@ -0,0 +1,346 @@
+//! Stopping a run part way through without leaving a mess. Ctrl-C, SIGTERM
+//! and SIGHUP (a closed terminal, a service manager shutting down), their
+//! Windows console counterparts, and `--timeout` all cancel the one token the
+//! whole run shares.
+//!
+//! What happens then depends on where the run is. Waiting for the model or at
+//! a prompt there is nothing to finish: hooks registered with `on_cancel` put
+//! the terminal back and the process exits. Writes to history, the cache and
+//! the session run `shield`ed and are finished first, so none is left half
+//! written. A running command is `handling` cancellation itself: it is
+//! stopped, the run is recorded, and the error from `check` ends the run.
+
+use anyhow::Result;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+/// How often a running command is checked on once the run is cancelled.
+pub const POLL: Duration = Duration::from_millis(50);
+
+/// Why the run was cancelled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reason {
+    /// Ctrl-C.
+    Interrupted,
+    /// `--timeout` ran out.
+    TimedOut(Duration),
+    /// Told to stop by another signal, such as SIGTERM.
+    Signal(i32),
+}
+
+/// The error a cancelled run ends with.
+#[derive(Debug)]
+pub struct Cancelled(pub Reason);
+
+impl Cancelled {
+    /// The exit status shells use for the same: 130 for Ctrl-C, 124 for a
+    /// timeout as with `timeout(1)`, and 128 + N for signal N.
+    pub fn exit_code(&self) -> u8 {
+        match self.0 {
+            Reason::Interrupted => 130,
+            Reason::TimedOut(_) => 124,
+            Reason::Signal(signal) => 128u8.wrapping_add(signal as u8),
+        }
+    }
+}
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Reason::Interrupted => write!(f, "Interrupted"),
+            Reason::TimedOut(after) => write!(f, "Timed out after {}s", after.as_secs()),
+            Reason::Signal(signal) => write!(f, "Stopped by signal {}", signal),
+        }
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+struct State {
+    reason: Option<Reason>,
+    /// Cleanup to run on cancelling, by id.
+    hooks: Vec<(u64, Box<dyn FnOnce() + Send>)>,
+    next_hook: u64,
+    /// How many writes are under way that must not be cut short.
+    shielded: usize,
+    /// How many steps will see the cancellation and end the run themselves.
+    handling: usize,
+    /// How many commands are running in our process group, which Ctrl-C
+    /// reaches directly.
+    foreground: usize,
+}
+
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+static STATE: Mutex<State> = Mutex::new(State {
+    reason: None,
+    hooks: Vec::new(),
+    next_hook: 0,
+    shielded: 0,
+    handling: 0,
+    foreground: 0,
+});
+/// Signalled when a shielded write finishes.
+static FINISHED: Condvar = Condvar::new();
+
+fn state() -> MutexGuard<'static, State> {
+    STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Cancel the run on Ctrl-C, SIGTERM and SIGHUP, and after `timeout` if
+/// there is one. Call once, early. A second Ctrl-C leaves at once.
+pub fn install(timeout: Option<Duration>) {
+    #[cfg(unix)]
+    signals::install();
+    #[cfg(windows)]
+    console::install();
+    if let Some(timeout) = timeout {
+        thread::spawn(move || {
+            thread::sleep(timeout);
+            cancel(Reason::TimedOut(timeout));
+        });
+    }
+}
+
+/// Whether the run was cancelled.
+pub fn requested() -> bool {
+    CANCELLED.load(Ordering::Relaxed)
+}
+
+/// Fail with `Cancelled` once the run was cancelled.
+pub fn check() -> Result<()> {
+    match state().reason {
+        Some(reason) => Err(Cancelled(reason).into()),
+        None => Ok(()),
+    }
+}
+
+/// Cancel the run: run the hooks, then, unless a step is handling it, wait
+/// for shielded writes and exit. Only the first call does anything.
+pub fn cancel(reason: Reason) {
+    let hooks = {
+        let mut state = state();
+        if state.reason.is_some() {
+            return;
+        }
+        state.reason = Some(reason);
+        CANCELLED.store(true, Ordering::Relaxed);
+        std::mem::take(&mut state.hooks)
+    };
+    for (_, hook) in hooks {
+        hook();
+    }
+    let mut state = state();
+    if state.handling > 0 {
+        return;
+    }
+    while state.shielded > 0 {
+        state = FINISHED.wait(state).unwrap_or_else(|e| e.into_inner());
+    }
+    eprintln!("{}", Cancelled(reason));
+    // The lock is held so that nothing starts another write.
+    std::process::exit(Cancelled(reason).exit_code().into());
+}
+
+/// Cleanup registered with `on_cancel`, dropped once it's no longer needed.
+pub struct Hook(Option<u64>);
+
+/// Run `hook` if the run is cancelled before the returned `Hook` is dropped,
+/// e.g. to put the terminal back the way it was. Right away if it already
+/// was.
+pub fn on_cancel(hook: impl FnOnce() + Send + 'static) -> Hook {
+    let mut state = state();
+    if state.reason.is_some() {
+        drop(state);
+        hook();
+        return Hook(None);
+    }
+    let id = state.next_hook;
+    state.next_hook += 1;
+    state.hooks.push((id, Box::new(hook)));
+    Hook(Some(id))
+}
+
+impl Drop for Hook {
+    fn drop(&mut self) {
+        if let Some(id) = self.0 {
+            state().hooks.retain(|(hook, _)| *hook != id);
+        }
+    }
+}
+
+/// Run `write` to the end even if the run is cancelled meanwhile.
+pub fn shield<T>(write: impl FnOnce() -> T) -> T {
+    state().shielded += 1;
+    let value = write();
+    state().shielded -= 1;
+    FINISHED.notify_all();
+    value
+}
+
+/// Held by a step that stops on its own when the run is cancelled, leaving
+/// it to end the run through `check` rather than being cut short.
+pub struct Handling(());
+
+/// Start a step that handles cancellation itself, failing if the run was
+/// already cancelled.
+pub fn handling() -> Result<Handling> {
+    let mut state = state();
+    if let Some(reason) = state.reason {
+        return Err(Cancelled(reason).into());
+    }
+    state.handling += 1;
+    Ok(Handling(()))
+}
+
+impl Drop for Handling {
+    fn drop(&mut self) {
+        state().handling -= 1;
+    }
+}
+
+/// Held while a command shares our terminal and process group. Ctrl-C
+/// reaches it directly and is its to act on, as in a shell: llmwrap waits to
+/// see what it does rather than cancelling the run.
+pub struct Foreground(());
+
+pub fn foreground() -> Foreground {
+    state().foreground += 1;
+    Foreground(())
+}
+
+impl Drop for Foreground {
+    fn drop(&mut self) {
+        state().foreground -= 1;
+    }
+}
+
+/// The signal to pass on to a running command for `reason`.
+#[cfg(unix)]
+pub fn signal_for(reason: Reason) -> i32 {
+    match reason {
+        Reason::Interrupted => libc::SIGINT,
+        Reason::TimedOut(_) => libc::SIGTERM,
+        Reason::Signal(signal) => signal,
+    }
+}
+
+/// The reason the run was cancelled, if it was.
+pub fn reason() -> Option<Reason> {
+    state().reason
+}
+
+/// Signals become bytes on a pipe that a thread reads, since a signal
+/// handler can do little more than write.
+#[cfg(unix)]
+mod signals {
+    use super::{Reason, cancel, requested, state};
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::thread;
+
+    static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+    extern "C" fn on_signal(signal: libc::c_int) {
+        let byte = signal as u8;
+        // SAFETY: write is async-signal-safe and the byte outlives the call.
+        unsafe { libc::write(PIPE.load(Ordering::Relaxed), (&raw const byte).cast(), 1) };
+    }
+
+    pub fn install() {
+        let mut fds = [-1; 2];
+        // SAFETY: fds has room for the two descriptors pipe returns.
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return;
+        }
+        let [read, write] = fds;
+        PIPE.store(write, Ordering::Relaxed);
+        for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+            // SAFETY: sigaction is plain data, filled in before the call; the
+            // handler only writes to the pipe.
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = on_signal as *const () as libc::sighandler_t;
+                action.sa_flags = libc::SA_RESTART;
+                libc::sigemptyset(&mut action.sa_mask);
+                libc::sigaction(signal, &action, std::ptr::null_mut());
+            }
+        }
+        thread::spawn(move || {
+            let mut byte = 0u8;
+            loop {
+                // SAFETY: byte is valid for a one-byte write.
+                let n = unsafe { libc::read(read, (&raw mut byte).cast(), 1) };
+                if n < 0 {
+                    continue;
+                }
+                if n == 0 {
+                    return;
+                }
+                let signal = byte as i32;
+                if signal == libc::SIGINT && state().foreground > 0 {
+                    continue;
+                }
+                let reason = if signal == libc::SIGINT {
+                    Reason::Interrupted
+                } else {
+                    Reason::Signal(signal)
+                };
+                if requested() {
+                    // Asked again while winding down: don't wait any longer.
+                    eprintln!();
+                    std::process::exit(super::Cancelled(reason).exit_code().into());
+                }
+                thread::spawn(move || cancel(reason));
+            }
+        });
+    }
+}
+
+/// Windows has no signals, only console control events, each handled on a
+/// thread of its own.
+#[cfg(windows)]
+mod console {
+    use super::{Cancelled, POLL, Reason, cancel, requested, state};
+    use std::thread;
+    use windows_sys::Win32::System::Console::{
+        CTRL_BREAK_EVENT, CTRL_C_EVENT, CTRL_CLOSE_EVENT, CTRL_LOGOFF_EVENT, CTRL_SHUTDOWN_EVENT,
+        SetConsoleCtrlHandler,
+    };
+    use windows_sys::core::BOOL;
+
+    /// The C runtime's numbers for the signals these events stand for.
+    const SIGTERM: i32 = 15;
+    const SIGBREAK: i32 = 21;
+
+    unsafe extern "system" fn on_event(event: u32) -> BOOL {
+        let reason = match event {
+            CTRL_C_EVENT if state().foreground > 0 => return 1,
+            CTRL_C_EVENT => Reason::Interrupted,
+            CTRL_BREAK_EVENT => Reason::Signal(SIGBREAK),
+            CTRL_CLOSE_EVENT | CTRL_LOGOFF_EVENT | CTRL_SHUTDOWN_EVENT => Reason::Signal(SIGTERM),
+            _ => return 0,
+        };
+        if requested() {
+            // Asked again while winding down: don't wait any longer.
+            eprintln!();
+            std::process::exit(Cancelled(reason).exit_code().into());
+        }
+        cancel(reason);
+        if event != CTRL_C_EVENT && event != CTRL_BREAK_EVENT {
+            // Windows ends the process as soon as this returns, so give the
+            // step handling the cancellation the time the system allows.
+            loop {
+                thread::sleep(POLL);
+            }
+        }
+        1
+    }
+
+    pub fn install() {
+        // SAFETY: on_event has the signature of a handler routine and lives
+        // as long as the process.
+        unsafe { SetConsoleCtrlHandler(Some(on_event), 1) };
+    }
+}
@@ -0,0 +1,49 @@
+/// The line the model puts between alternatives.
+const SEPARATOR: &str = "---";
+
+/// A note for the system prompt asking for `count` different commands
+/// instead of one.
+pub fn prompt_note(count: u8) -> String {
+    format!(
+        "Give {count} different commands for the request instead of one, each taking another \
+approach where there is a choice (find or fd, sed or awk, a loop or xargs), best first. Put a \
+line with only {SEPARATOR} between them and nothing else around them."
+    )
+}
+
+/// The alternatives in an answer to a request with the note above, still to
+/// be cleaned up like any single answer.
+pub fn split(answer: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    for line in answer.lines() {
+        if line.trim() == SEPARATOR {
+            parts.push(String::new());
+            continue;
+        }
+        let part = parts.last_mut().unwrap();
+        part.push_str(line);
+        part.push('\n');
+    }
+    parts.retain(|part| !part.trim().is_empty());
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sanitize_command;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn candidates_split_on_separators(
+            commands in prop::collection::vec("[a-z][a-zA-Z0-9 ./'\"|&;<>=$*_-]{0,60}", 1..6),
+        ) {
+            let commands: Vec<String> = commands.iter().map(|c| c.trim().to_string()).collect();
+            prop_assume!(commands.iter().all(|c| !c.is_empty() && c != SEPARATOR));
+            let parts = split(&commands.join(&format!("\n{}\n", SEPARATOR)));
+            let parts: Vec<String> = parts.iter().map(|part| sanitize_command(part)).collect();
+            prop_assert_eq!(parts, commands);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use crate::cmdline;
+use crate::remote;
+use crate::safer::{on_path, quote};
+
+/// Programs whose presence means the command already checks what it downloads.
+const VERIFIERS: &[&str] = &[
+    "sha256sum",
+    "sha512sum",
+    "shasum",
+    "gpg",
+    "gpgv",
+    "cosign",
+    "minisign",
+    "signify",
+];
+/// Names projects publish checksum lists under, next to their downloads.
+const SUM_LISTS: &[&str] = &["SHA256SUMS", "sha256sums.txt", "checksums.txt"];
+
+/// A file the command downloads to disk.
+pub struct Download {
+    pub url: String,
+    /// Where it is saved, as written in the command.
+    pub file: String,
+    /// Byte offset just past the downloading segment, where a check can go.
+    end: usize,
+}
+
+/// The first download in `command` saved to a file and used after an `&&` or
+/// `;`, unless the command already verifies something.
+pub fn unverified_download(command: &str) -> Option<Download> {
+    let segments = cmdline::segments(command);
+    let verifies = segments.iter().any(|s| {
+        s.program()
+            .is_some_and(|p| VERIFIERS.contains(&p.rsplit('/').next().unwrap_or(p)))
+    });
+    if verifies {
+        return None;
+    }
+    segments.iter().find_map(|segment| {
+        // Piped straight into the next program, there is no file to check.
+        let followed = matches!(
+            segment.op,
+            Some(cmdline::Operator::And | cmdline::Operator::Seq)
+        );
+        if !followed {
+            return None;
+        }
+        let args = segment.args_from_program();
+        let program = args.first()?.rsplit('/').next()?;
+        let url = args[1..]
+            .iter()
+            .find(|a| a.starts_with("https://") || a.starts_with("http://"))?;
+        let remote_name = || {
+            let path = url.split(['?', '#']).next().unwrap_or(url);
+            path.rsplit('/').next().filter(|n| !n.is_empty())
+        };
+        let value_of = |flags: &[&str]| {
+            args.iter()
+                .position(|a| flags.contains(&a.as_str()))
+                .and_then(|i| args.get(i + 1))
+        };
+        let file = match program {
+            "curl" => match value_of(&["-o", "--output"]) {
+                Some(file) => file.clone(),
+                None if args.iter().any(|a| a == "-O" || a == "--remote-name") => {
+                    remote_name()?.to_string()
+                }
+                None => return None,
+            },
+            "wget" => match value_of(&["-O", "--output-document"]) {
+                Some(file) if file != "-" => file.clone(),
+                Some(_) => return None,
+                None => remote_name()?.to_string(),
+            },
+            _ => return None,
+        };
+        Some(Download {
+            url: url.clone(),
+            file,
+            end: segment.span.end,
+        })
+    })
+}
+
+/// Look for a checksum the project publishes for `url`: a `.sha256` file next
+/// to it or a checksum list in the same directory.
+pub fn find_published(url: &str) -> Option<String> {
+    let (dir, _) = url.rsplit_once('/')?;
+    [format!("{}.sha256", url), format!("{}.sha256sum", url)]
+        .into_iter()
+        .chain(SUM_LISTS.iter().map(|name| format!("{}/{}", dir, name)))
+        .find(|candidate| remote::exists(candidate))
+}
+
+/// `command` with a step after the download that fails unless the file
+/// matches the checksum published at `sums_url`.
+pub fn add_verification(command: &str, download: &Download, sums_url: &str) -> String {
+    let name = download.url.rsplit('/').next().unwrap_or_default();
+    let name = name.split(['?', '#']).next().unwrap_or(name);
+    let check = if on_path("sha256sum") {
+        "sha256sum -c -"
+    } else {
+        "shasum -a 256 -c -"
+    };
+    // A `.sha256` file may hold just the hash; a list has one "hash  name"
+    // line per file.
+    let verify = format!(
+        "expected=$(curl -fsSL {} | awk -v n={} 'NF == 1 || $2 == n || $2 == \"*\" n {{ print $1; exit }}') && echo \"$expected  {}\" | {}",
+        quote(sums_url),
+        quote(name),
+        download.file,
+        check
+    );
+    format!(
+        "{} && {}{}",
+        command[..download.end].trim_end(),
+        verify,
+        &command[download.end..]
+    )
+}
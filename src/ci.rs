@@ -0,0 +1,177 @@
+use crate::api;
+use anyhow::{Context, Result};
+use serde_yaml::Value;
+use std::path::Path;
+
+/// CI system to generate configuration for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Provider {
+    Github,
+    Gitlab,
+}
+
+impl Provider {
+    /// GitLab when the repository has only a `.gitlab-ci.yml`, otherwise GitHub Actions.
+    pub fn detect() -> Self {
+        if Path::new(".gitlab-ci.yml").exists() && !Path::new(".github").exists() {
+            Provider::Gitlab
+        } else {
+            Provider::Github
+        }
+    }
+}
+
+pub fn generate(client: &api::Client, provider: Provider, description: &str) -> Result<String> {
+    let shape = match provider {
+        Provider::Github => {
+            "a YAML list of GitHub Actions steps (each starting with `- name:`), without the \
+workflow or job around them. Pin actions to a major version such as actions/checkout@v4"
+        }
+        Provider::Gitlab => {
+            "one GitLab CI job as a YAML mapping from the job name to its definition, \
+with a stage and a script"
+        }
+    };
+    let system_prompt = format!(
+        "You write CI configuration. Respond with only {shape}; no explanations, no code fences."
+    );
+    let raw = client.complete(&system_prompt, description)?;
+    Ok(api::strip_code_fences(&raw)
+        .trim_start_matches("---")
+        .trim_start()
+        .to_string())
+}
+
+/// Check the snippet is YAML of the shape `insert` expects.
+pub fn validate(provider: Provider, snippet: &str) -> Result<()> {
+    let value: Value = serde_yaml::from_str(snippet).context("The snippet is not valid YAML")?;
+    match provider {
+        Provider::Github if !value.is_sequence() => anyhow::bail!("Expected a list of steps"),
+        Provider::Gitlab if !value.is_mapping() => anyhow::bail!("Expected a job mapping"),
+        _ => Ok(()),
+    }
+}
+
+/// Insert the snippet into an existing workflow: GitHub steps go at the end of
+/// the job's `steps:` list, GitLab jobs at the end of the file. Edits the text
+/// so comments and formatting elsewhere survive.
+pub fn insert(
+    provider: Provider,
+    existing: &str,
+    snippet: &str,
+    job: Option<&str>,
+) -> Result<String> {
+    let lines: Vec<&str> = existing.lines().collect();
+    let (at, indent) = match provider {
+        Provider::Gitlab => (lines.len(), 0),
+        Provider::Github => steps_end(&lines, job)?,
+    };
+
+    // Keep blank lines that separate the steps list from what follows.
+    let mut at = at;
+    while at > 0 && lines[at - 1].trim().is_empty() {
+        at -= 1;
+    }
+
+    let mut result: Vec<String> = lines[..at].iter().map(|l| l.to_string()).collect();
+    if provider == Provider::Gitlab && !result.is_empty() {
+        result.push(String::new());
+    }
+    result.extend(reindent(snippet, indent));
+    result.extend(lines[at..].iter().map(|l| l.to_string()));
+    let text = result.join("\n") + "\n";
+
+    serde_yaml::from_str::<Value>(&text).context("Inserting the snippet would break the file")?;
+    Ok(text)
+}
+
+/// Line index just past the job's steps list, and the indentation of its items.
+fn steps_end(lines: &[&str], job: Option<&str>) -> Result<(usize, usize)> {
+    let jobs = lines
+        .iter()
+        .position(|l| l.trim_end() == "jobs:")
+        .context("No top-level `jobs:` in the workflow")?;
+
+    let mut job_indent = None;
+    let mut job_start = None;
+    for (i, line) in lines.iter().enumerate().skip(jobs + 1) {
+        if is_blank(line) {
+            continue;
+        }
+        let ind = indent_of(line);
+        if ind == 0 {
+            break;
+        }
+        let job_indent = *job_indent.get_or_insert(ind);
+        if ind == job_indent {
+            let name = line.trim().trim_end_matches(':');
+            if job.is_none_or(|j| j == name) {
+                job_start = Some(i);
+                break;
+            }
+        }
+    }
+    let job_start = job_start.with_context(|| match job {
+        Some(name) => format!("No job named `{}` in the workflow", name),
+        None => "The workflow has no jobs".to_string(),
+    })?;
+    let job_indent = indent_of(lines[job_start]);
+
+    let steps = lines
+        .iter()
+        .enumerate()
+        .skip(job_start + 1)
+        .take_while(|(_, l)| is_blank(l) || indent_of(l) > job_indent)
+        .find(|(_, l)| l.trim() == "steps:")
+        .map(|(i, _)| i)
+        .context("The job has no `steps:` list")?;
+    let steps_indent = indent_of(lines[steps]);
+
+    let mut item_indent = None;
+    let mut end = steps + 1;
+    for (i, line) in lines.iter().enumerate().skip(steps + 1) {
+        if is_blank(line) {
+            continue;
+        }
+        let ind = indent_of(line);
+        let item = *item_indent.get_or_insert(ind);
+        let is_item = ind == item && line.trim_start().starts_with('-');
+        if ind < item || ind == item && !is_item {
+            break;
+        }
+        end = i + 1;
+    }
+    Ok((end, item_indent.unwrap_or(steps_indent + 2)))
+}
+
+fn reindent(snippet: &str, indent: usize) -> Vec<String> {
+    let base = snippet
+        .lines()
+        .filter(|l| !is_blank(l))
+        .map(indent_of)
+        .min()
+        .unwrap_or(0);
+    snippet
+        .lines()
+        .map(|line| {
+            if is_blank(line) {
+                String::new()
+            } else {
+                format!(
+                    "{}{}",
+                    " ".repeat(indent),
+                    &line[base.min(indent_of(line))..]
+                )
+            }
+        })
+        .collect()
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_blank(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.is_empty() || trimmed.starts_with('#')
+}
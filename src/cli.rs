@@ -0,0 +1,3060 @@
+//! The `llmwrap` command line: its arguments, subcommands and the session
+//! that proposes, checks and runs a command.
+
+use crate::{SYSTEM_PROMPT, assess, is_script, sanitize_command};
+use crate::{
+    analytics, ansible, answer, api, attach, bsd, busybox, cache, cancel, candidates, checksum, ci,
+    clipboard, cmdline, config, container, context, digest, dotenv, dump, envchange, environment,
+    exec, explain, fix, followup, functions, history, init, intents, inventory, latency,
+    logpatterns, macos, makefile, net, novelty, patch, pathcheck, postprocess, preflight, probes,
+    project, quoting, readonly, remote, risk, safer, session, shell, snippets, ssh, suggest,
+    summarize, targets, template, termux, timing, tutor, ui, userland, winpath, workspace, wsl,
+};
+use anyhow::{Context, Result};
+use clap::error::{ContextKind, ErrorKind};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::ffi::OsString;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::process::{ExitCode, ExitStatus};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+#[derive(Parser, Clone, Debug)]
+#[command(
+    version,
+    about = "Describe a shell task in plain English and get a runnable command back"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    /// Natural language description of the shell task, e.g. "convert input.mp4 to gif"
+    prompt: Vec<String>,
+
+    /// Model to use for the Responses API
+    #[arg(long, global = true, default_value = "gpt-5.1-codex-max")]
+    model: String,
+
+    /// API to send requests to; detected from --api-base when not given
+    #[arg(long, global = true, value_enum, env = "LLMWRAP_PROVIDER")]
+    provider: Option<api::Provider>,
+
+    /// Base URL for the API (defaults to api.openai.com, or the provider's own)
+    #[arg(
+        long,
+        global = true,
+        env = "LLMWRAP_OPENAI_BASE_URL",
+        default_value = "https://api.openai.com/v1"
+    )]
+    api_base: String,
+
+    /// Shell to write and run commands for (defaults to sh; `function` defaults to $SHELL)
+    #[arg(long, global = true, value_enum, env = "LLMWRAP_SHELL")]
+    shell: Option<shell::Shell>,
+
+    /// Where the command will run: `local`, `windows-host` for Windows PowerShell on the Windows side of WSL, or `ssh:HOST` for another machine, with several hosts, inventory groups or patterns like `web*` separated by commas
+    #[arg(long, global = true, default_value = "local")]
+    target: Target,
+
+    /// Print the proposed command and its risk assessment as JSON; never runs it
+    #[arg(long)]
+    json: bool,
+
+    /// Print only the command on stdout, never asking or running it, even at a terminal
+    #[arg(long, conflicts_with_all = ["json", "interactive"])]
+    print: bool,
+
+    /// Print the command for `eval "$(llmwrap --print-eval ...)"`, so cd, export and the like change your shell
+    #[arg(long, conflicts_with = "json")]
+    print_eval: bool,
+
+    /// Hand the confirmed command to the shell function from `llmwrap init` to run in your shell, so cd and export stick
+    #[arg(long, conflicts_with_all = ["json", "print", "print_eval"])]
+    eval: bool,
+
+    /// Copy the command to the clipboard instead of running it, e.g. to paste into an SSH session
+    #[arg(long, conflicts_with_all = ["json", "print", "print_eval", "eval"])]
+    copy: bool,
+
+    /// Prompt for confirmation even when stdin or stdout is not a terminal
+    #[arg(long, global = true)]
+    interactive: bool,
+
+    /// Keep a conversation open, so follow-ups like "also skip node_modules" refine the last command
+    #[arg(long, short = 'i')]
+    repl: bool,
+
+    /// Ask for N different commands (2 to 9) and pick one, e.g. to compare find with fd; skips the cache
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(2..=9), conflicts_with = "repl")]
+    candidates: Option<u8>,
+
+    /// Run the command without asking when Enter would run it (low risk, by default), even with no terminal
+    #[arg(long, short, global = true)]
+    yes: bool,
+
+    /// Whether `--yes` was given on the command line, which also runs
+    /// commands with no terminal; a profile's `yes` only skips the prompt.
+    #[arg(skip)]
+    unattended: bool,
+
+    /// Ask the model even when the answer is cached; the new answer replaces the cached one
+    #[arg(long, global = true)]
+    no_cache: bool,
+
+    /// Take defaults from this `[profiles.NAME]` table in config.toml
+    #[arg(long, global = true, env = "LLMWRAP_PROFILE")]
+    profile: Option<String>,
+
+    /// Ask only for commands that change nothing, and refuse any that would
+    #[arg(long, global = true)]
+    read_only: bool,
+
+    /// Allow commands to modify files outside the trees listed in `workspace`
+    #[arg(long, global = true)]
+    outside_workspace: bool,
+
+    /// Show the model this file along with the request (repeatable); piped input is shown too
+    #[arg(long = "file", short = 'f', value_name = "PATH")]
+    files: Vec<PathBuf>,
+
+    /// The files given with --file and what was piped in, read once the
+    /// config says how much of each to keep.
+    #[arg(skip)]
+    attachments: Vec<attach::Attachment>,
+
+    /// Use this text as the system prompt instead of the built-in one (or `system_prompt` in config.toml)
+    #[arg(long, global = true, value_name = "TEXT")]
+    system_prompt: Option<String>,
+
+    /// Write the command with this prompt template, built in (git, docker) or from `[templates.NAME]`
+    #[arg(long, global = true, value_name = "NAME")]
+    template: Option<String>,
+
+    /// Tell the model about your environment with these `[contexts.NAME]` bundles
+    #[arg(long, global = true, value_delimiter = ',')]
+    context: Vec<String>,
+
+    /// Connect to HOST at IP instead of looking it up, e.g. api.openai.com:10.1.2.3
+    #[arg(long, global = true, value_name = "HOST:IP")]
+    resolve: Vec<String>,
+
+    /// Connect only over IPv4
+    #[arg(long, global = true, conflicts_with = "ipv6")]
+    ipv4: bool,
+
+    /// Connect only over IPv6
+    #[arg(long, global = true)]
+    ipv6: bool,
+
+    /// Look hosts up with this DNS server instead of the system resolver
+    #[arg(long, global = true, value_name = "IP[:PORT]")]
+    dns: Option<String>,
+
+    /// Write each API request and reply, with credentials removed, to a file in DIR
+    #[arg(long, global = true, value_name = "DIR")]
+    dump_dir: Option<PathBuf>,
+
+    /// When done, print how long each step took (arguments, config, context, the model...) to stderr
+    #[arg(long, global = true)]
+    time_report: bool,
+
+    /// Give up after this many seconds, stopping the command if it is running
+    #[arg(long, global = true, value_name = "SECONDS")]
+    timeout: Option<u64>,
+
+    /// Where the API key comes from; only a profile changes it.
+    #[arg(skip)]
+    api_key: api::KeySource,
+
+    /// Models to ask when the one asked fails, from `config.toml`.
+    #[arg(skip)]
+    fallbacks: Vec<config::Fallback>,
+
+    /// What the command line gave for the settings `config.toml` fills in.
+    #[arg(skip)]
+    given: Given,
+}
+
+/// The settings a profile or `config.toml` can fill in, as the command line
+/// gave them, so the file can be applied again once it changes.
+#[derive(Clone, Debug, Default)]
+struct Given {
+    matches: ArgMatches,
+    model: String,
+    api_base: String,
+    provider: Option<api::Provider>,
+    system_prompt: Option<String>,
+    shell: Option<shell::Shell>,
+    json: bool,
+    yes: bool,
+    read_only: bool,
+    context: Vec<String>,
+}
+
+impl Cli {
+    fn target_shell(&self) -> shell::Shell {
+        if self.target == Target::WindowsHost {
+            return shell::Shell::Pwsh;
+        }
+        // Windows has no sh to run commands with; PowerShell is always there.
+        let default = if cfg!(windows) {
+            shell::Shell::Pwsh
+        } else {
+            shell::Shell::Sh
+        };
+        self.shell.unwrap_or(default)
+    }
+
+    /// Fill in settings the command line left at their defaults from a profile.
+    fn apply_profile(&mut self, profile: config::Profile) {
+        let defaulted = |id: &str| {
+            matches!(
+                self.given.matches.value_source(id),
+                None | Some(ValueSource::DefaultValue)
+            )
+        };
+        if let Some(model) = profile.model.filter(|_| defaulted("model")) {
+            self.model = model;
+        }
+        if let Some(api_base) = profile.api_base.filter(|_| defaulted("api_base")) {
+            self.api_base = api_base;
+        }
+        self.provider = self.provider.or(profile.provider);
+        if let Some(variable) = profile.api_key_env {
+            self.api_key = api::KeySource::Env(variable);
+        } else if let Some(command) = profile.api_key_command {
+            self.api_key = api::KeySource::Command(command);
+        }
+        self.system_prompt = self.system_prompt.take().or(profile.system_prompt);
+        self.shell = self.shell.or(profile.shell);
+        if let Some(json) = profile.json.filter(|_| defaulted("json")) {
+            self.json = json;
+        }
+        if let Some(yes) = profile.yes.filter(|_| defaulted("yes")) {
+            self.yes = yes;
+        }
+        if let Some(read_only) = profile.read_only.filter(|_| defaulted("read_only")) {
+            self.read_only = read_only;
+        }
+        if let Some(context) = profile.context.filter(|_| self.context.is_empty()) {
+            self.context = context;
+        }
+    }
+
+    /// Remember what the command line gave for each setting `config.toml`
+    /// fills in.
+    fn remember_given(&mut self, matches: ArgMatches) {
+        self.given = Given {
+            matches,
+            model: self.model.clone(),
+            api_base: self.api_base.clone(),
+            provider: self.provider,
+            system_prompt: self.system_prompt.clone(),
+            shell: self.shell,
+            json: self.json,
+            yes: self.yes,
+            read_only: self.read_only,
+            context: self.context.clone(),
+        };
+    }
+
+    /// Put back what the command line gave for each setting `config.toml`
+    /// fills in, undoing what the file settled before.
+    fn restore_given(&mut self) {
+        let given = &self.given;
+        self.model = given.model.clone();
+        self.api_base = given.api_base.clone();
+        self.provider = given.provider;
+        self.system_prompt = given.system_prompt.clone();
+        self.shell = given.shell;
+        self.json = given.json;
+        self.yes = given.yes;
+        self.read_only = given.read_only;
+        self.context = given.context.clone();
+        self.api_key = api::KeySource::default();
+    }
+
+    /// Settle which provider to use, and switch the base URL and model to its
+    /// own when they were left at OpenAI's defaults.
+    fn settle_provider(&mut self) {
+        let provider = self
+            .provider
+            .unwrap_or_else(|| api::Provider::detect(&self.api_base));
+        self.provider = Some(provider);
+        let openai = api::Provider::Openai;
+        if self.api_base == openai.default_base() {
+            self.api_base = provider.default_base().to_string();
+        }
+        if self.model == openai.default_model() {
+            self.model = provider.default_model().to_string();
+        }
+    }
+
+    fn client(&self) -> Result<api::Client> {
+        let provider = self.provider.unwrap_or(api::Provider::Openai);
+        timing::time("set up the client", || {
+            let client = api::Client::new(provider, &self.api_key, &self.api_base, &self.model)?;
+            let mut fallbacks = Vec::new();
+            for fallback in &self.fallbacks {
+                match fallback.client(&client) {
+                    Ok(fallback) => fallbacks.push(fallback),
+                    Err(err) => eprintln!(
+                        "Warning: leaving out the fallback {}: {:#}",
+                        fallback.model, err
+                    ),
+                }
+            }
+            Ok(client.with_fallbacks(fallbacks))
+        })
+    }
+
+    /// Paths the command would modify outside the configured workspace, unless
+    /// `--outside-workspace` allows them.
+    fn outside_workspace(&self, config: &config::Config, command: &str) -> Vec<String> {
+        if config.workspace.is_empty()
+            || self.outside_workspace
+            || !self.target_shell().is_posix()
+            || ssh::target().is_some()
+        {
+            return Vec::new();
+        }
+        workspace::outside(command, &config.workspace)
+    }
+
+    /// Why the command is not read-only, when `--read-only` is on.
+    fn read_only_violations(&self, command: &str) -> Vec<String> {
+        if self.read_only {
+            readonly::violations(command)
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// The `[network]` settings with the command-line options applied on top.
+    fn network(&self, config: &config::Config) -> net::NetworkSettings {
+        let mut network = config.network.clone();
+        network.resolve.extend(self.resolve.iter().cloned());
+        if self.ipv4 {
+            network.family = Some(net::Family::Ipv4);
+        } else if self.ipv6 {
+            network.family = Some(net::Family::Ipv6);
+        }
+        if let Some(dns) = &self.dns {
+            network.dns = Some(dns.clone());
+        }
+        network
+    }
+
+    /// How cache and history entries record the shell; None for plain sh.
+    fn shell_key(&self) -> Option<&'static str> {
+        Some(self.target_shell())
+            .filter(|s| *s != shell::Shell::Sh)
+            .map(shell::Shell::binary)
+    }
+
+    /// How cache entries record the hosts commands run on; None for this
+    /// machine.
+    fn host_key(&self) -> Option<String> {
+        let hosts = ssh::targets();
+        (!hosts.is_empty()).then(|| {
+            let keys: Vec<String> = hosts.iter().map(ssh::Host::cache_key).collect();
+            keys.join(", ")
+        })
+    }
+
+    /// The container being debugged, in `llmwrap container-debug`.
+    fn container_debug(&self) -> Option<&'static container::Container> {
+        matches!(self.command, Some(Commands::ContainerDebug(_)))
+            .then(container::detect)
+            .flatten()
+    }
+}
+
+/// Where a command is meant to run.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+enum Target {
+    /// Here, in the current shell
+    #[default]
+    Local,
+    /// On the Windows side of WSL, in Windows PowerShell
+    WindowsHost,
+    /// On another machine over SSH: an alias from ~/.ssh/config or `[user@]host[:port]`
+    Ssh(String),
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "local" => Ok(Target::Local),
+            "windows-host" => Ok(Target::WindowsHost),
+            _ => match value.strip_prefix("ssh:") {
+                Some(host) => Ok(Target::Ssh(host.to_string())),
+                None => Err("expected local, windows-host or ssh:HOST".to_string()),
+            },
+        }
+    }
+}
+
+#[derive(Subcommand, Clone, Debug)]
+enum Commands {
+    /// Generate a reusable shell function and install it into your rc file
+    Function(FunctionArgs),
+    /// Add a target to the Makefile or justfile in the current directory
+    Make(MakeArgs),
+    /// Write an Ansible task list or playbook to a file
+    Ansible(AnsibleArgs),
+    /// Generate a CI step or job, optionally inserting it into a workflow file
+    Ci(CiArgs),
+    /// Return to an earlier variant of the last command you refined
+    Back(BackArgs),
+    /// Run a command from history again, optionally changed, e.g. `llmwrap rerun same but for *.png`
+    Rerun(RerunArgs),
+    /// List past requests and commands, e.g. `llmwrap history --search ffmpeg`, and pick one to run again
+    History(HistoryArgs),
+    /// Ask for a corrected version of the last command that failed, or of one given with -c
+    Fix(FixArgs),
+    /// Follow up on the last command run, with what it printed, e.g. `llmwrap then delete the ones older than 30 days`
+    Then(ThenArgs),
+    /// Break a command down part by part, e.g. `llmwrap explain 'tar -xzvf foo.tgz -C /tmp'`
+    Explain(ExplainArgs),
+    /// Summarize output piped in, however long, e.g. `journalctl -b | llmwrap summarize why did nginx stop`
+    Summarize(SummarizeArgs),
+    /// Group the errors in a log piped in by kind, with counts and when each was first and last seen, e.g. `journalctl -u app | llmwrap errors`
+    Errors(ErrorsArgs),
+    /// Describe tasks one after another, each command streamed in and acted on with one key
+    Repl,
+    /// Manage what llmwrap remembers about remote hosts
+    Context {
+        #[command(subcommand)]
+        action: ContextAction,
+    },
+    /// Investigate from inside a container, told its image, tools and limits, e.g. `llmwrap container-debug why is port 8080 not answering`
+    ContainerDebug(ContainerDebugArgs),
+    /// Run a command, then go through what each part did and answer a question on it
+    Tutor(TutorArgs),
+    /// Summarize recent use as Markdown: requests, most-run commands, failures and cost
+    Digest(DigestArgs),
+    /// Print the integration script for a shell, e.g. `eval "$(llmwrap init bash)"`
+    Init {
+        #[arg(value_enum)]
+        shell: shell::Shell,
+        /// Add the line that loads it to the shell's startup file instead
+        #[arg(long)]
+        install: bool,
+    },
+}
+
+#[derive(Args, Clone, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
+struct FunctionArgs {
+    #[command(subcommand)]
+    action: Option<FunctionAction>,
+
+    /// What the function should do, e.g. "extract any archive by extension"
+    description: Vec<String>,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+enum FunctionAction {
+    /// List the functions llmwrap has installed
+    List,
+    /// Remove a function llmwrap has installed
+    Remove { name: String },
+}
+
+#[derive(Subcommand, Clone, Debug)]
+enum ContextAction {
+    /// Ask a host what it runs again, e.g. `llmwrap context refresh web01` or a group; without one, forget every host
+    Refresh { host: Option<String> },
+}
+
+#[derive(Args, Clone, Debug)]
+struct MakeArgs {
+    /// What the target should do, e.g. "build the docker image and push it"
+    description: Vec<String>,
+
+    /// Build file to edit instead of the justfile or Makefile found here
+    #[arg(long, short)]
+    file: Option<PathBuf>,
+}
+
+#[derive(Args, Clone, Debug)]
+struct AnsibleArgs {
+    /// What the tasks should do, e.g. "ensure nginx is installed and running on debian hosts"
+    description: Vec<String>,
+
+    /// Generate a whole play instead of a list of tasks
+    #[arg(long)]
+    play: bool,
+
+    /// File to write or append to (defaults to a name based on the description)
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args, Clone, Debug)]
+struct CiArgs {
+    /// What the step should do, e.g. "run clippy and fail on warnings"
+    description: Vec<String>,
+
+    /// CI system (detected from the repository when omitted)
+    #[arg(long, value_enum)]
+    provider: Option<ci::Provider>,
+
+    /// Workflow file to insert the snippet into; without it the snippet is only printed
+    #[arg(long, short)]
+    file: Option<PathBuf>,
+
+    /// Job whose steps to extend (GitHub Actions; defaults to the first job)
+    #[arg(long)]
+    job: Option<String>,
+}
+
+#[derive(Args, Clone, Debug)]
+struct BackArgs {
+    /// Variant number to return to (defaults to the one the latest variant came from)
+    args: Vec<String>,
+}
+
+#[derive(Args, Clone, Debug)]
+struct TutorArgs {
+    /// The task, e.g. "archive the logs directory"; without one, list the concepts met so far
+    description: Vec<String>,
+}
+
+#[derive(Args, Clone, Debug)]
+struct DigestArgs {
+    /// How many days back to cover
+    #[arg(long, default_value_t = 7)]
+    days: u64,
+}
+
+#[derive(Args, Clone, Debug)]
+struct HistoryArgs {
+    /// Show only entries whose request or command contains these words
+    #[arg(long, short)]
+    search: Option<String>,
+
+    /// How many entries to show, the most recent last
+    #[arg(long, short = 'n', default_value_t = 20)]
+    limit: usize,
+}
+
+#[derive(Args, Clone, Debug)]
+struct FixArgs {
+    /// Command to repair instead of the last one run; it is run first to see how it fails
+    #[arg(long, short)]
+    command: Option<String>,
+
+    /// Anything else is a request, e.g. `llmwrap fix the permissions on ~/.ssh`
+    words: Vec<String>,
+}
+
+#[derive(Args, Clone, Debug)]
+struct ThenArgs {
+    /// What to do next, e.g. "delete the ones older than 30 days"
+    followup: Vec<String>,
+}
+
+#[derive(Args, Clone, Debug)]
+struct ExplainArgs {
+    /// The command to explain; read from stdin when not given
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    command: Vec<String>,
+}
+
+#[derive(Args, Clone, Debug)]
+struct SummarizeArgs {
+    /// What to find out from the output; without it, a summary of what happened
+    question: Vec<String>,
+}
+
+#[derive(Args, Clone, Debug)]
+struct ErrorsArgs {
+    /// Count warnings as well as errors
+    #[arg(long)]
+    warnings: bool,
+
+    /// Leave the kinds unnamed instead of asking the model to name them
+    #[arg(long)]
+    local: bool,
+
+    /// How many kinds to show, most frequent first
+    #[arg(long, short = 'n', default_value_t = 20)]
+    limit: usize,
+}
+
+#[derive(Args, Clone, Debug)]
+struct ContainerDebugArgs {
+    /// What to look into, e.g. "what is using the memory"
+    description: Vec<String>,
+}
+
+#[derive(Args, Clone, Debug)]
+struct RerunArgs {
+    /// Which run to repeat, counting back from the most recent (1)
+    #[arg(long, short = 'n', default_value_t = 1)]
+    entry: usize,
+
+    /// How the command should differ this time, e.g. "same but for *.png"
+    change: Vec<String>,
+}
+
+/// Added to the system prompt in the REPL, where requests build on each other.
+const REPL_NOTE: &str = "Later requests may refer to earlier ones, e.g. \"also exclude \
+node_modules\"; answer each with the complete new command.";
+
+/// How many requests and answers the REPL sends along with a new request.
+const REPL_TURNS: usize = 20;
+
+/// Parse the command line. Requests can start with a subcommand's name, as in
+/// `llmwrap history of the shell` or `llmwrap init a git repository`: when
+/// the words after it don't fit that subcommand, they are taken as a request
+/// instead, as if given after `--`.
+fn parse_args() -> Result<ArgMatches, clap::Error> {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let err = match Cli::command().try_get_matches_from(&args) {
+        Ok(matches) => return Ok(matches),
+        Err(err) => err,
+    };
+    // A mistyped option is reported rather than sent to the model.
+    let misfit = match err.kind() {
+        ErrorKind::UnknownArgument | ErrorKind::InvalidValue | ErrorKind::InvalidSubcommand => err
+            .get(ContextKind::InvalidArg)
+            .or(err.get(ContextKind::InvalidValue))
+            .or(err.get(ContextKind::InvalidSubcommand))
+            .is_some_and(|arg| !arg.to_string().starts_with('-')),
+        _ => false,
+    };
+    let command = Cli::command();
+    let subcommand = args.iter().skip(1).position(|arg| {
+        arg.to_str()
+            .is_some_and(|arg| command.find_subcommand(arg).is_some())
+    });
+    let (true, Some(at)) = (misfit, subcommand) else {
+        return Err(err);
+    };
+    let mut request = args;
+    request.insert(at + 1, OsString::from("--"));
+    Cli::command().try_get_matches_from(request).or(Err(err))
+}
+
+/// The `llmwrap` binary: parse the arguments and do what they ask.
+pub fn main() -> Result<ExitCode> {
+    let started = Instant::now();
+    let (matches, cli) = timing::time("parse arguments", || {
+        let matches = parse_args().unwrap_or_else(|err| err.exit());
+        let cli = Cli::from_arg_matches(&matches);
+        (matches, cli)
+    });
+    let mut cli = cli?;
+    cli.remember_given(matches);
+    cancel::install(cli.timeout.map(Duration::from_secs));
+    cli.unattended = cli.yes;
+    let config = timing::time("load config", || configure(&mut cli))?;
+    if cli.target == Target::WindowsHost {
+        if wsl::detect().is_none() {
+            anyhow::bail!("--target windows-host only works inside WSL");
+        }
+        if cli.shell.is_some_and(|shell| shell != shell::Shell::Pwsh) {
+            anyhow::bail!("--target windows-host runs commands in PowerShell; drop --shell");
+        }
+    }
+    wsl::init(cli.target == Target::WindowsHost);
+    if cli.eval {
+        if cli.target != Target::Local {
+            anyhow::bail!("--eval runs commands in your own shell; drop --target");
+        }
+        ui::divert_stdout()?;
+    }
+    ui::init(if cli.json || cli.print || cli.print_eval {
+        Some(false)
+    } else {
+        cli.interactive.then_some(true)
+    });
+    // The REPL reads its requests from stdin, so only a single request takes it.
+    if cli.command.is_none() {
+        cli.attachments = attach::gather(&cli.files, !cli.repl, &config.attach)?;
+    }
+    let inventory = inventory::load()?;
+    // "restart the exporter on the metrics group" needs no --target.
+    if cli.target == Target::Local && cli.command.is_none() {
+        let groups = inventory.mentioned(&cli.prompt.join(" "));
+        if !groups.is_empty() {
+            let plural = if groups.len() > 1 { "s" } else { "" };
+            ui::say!("Running on the {} group{}.", groups.join(" and "), plural);
+            cli.target = Target::Ssh(groups.join(","));
+        }
+    }
+    ssh::init(match &cli.target {
+        Target::Ssh(spec) => ssh::resolve_all(spec, &inventory)?,
+        _ => Vec::new(),
+    });
+    // Found out now rather than once the model has written the command.
+    if cli.target == Target::Local && ui::interactive() && !cli.target_shell().installed() {
+        eprintln!(
+            "Warning: {} is not installed here, so commands for it can be shown but not run",
+            cli.target_shell()
+        );
+    }
+    net::init(&config.proxy, &cli.network(&config))?;
+    dump::init(cli.dump_dir.as_deref());
+    risk::init(&config.risk)?;
+    if let Err(err) = analytics::send_due(&config.analytics) {
+        eprintln!("Warning: {:#}", err);
+    }
+    let result = run(&cli, &config);
+    if cli.time_report {
+        eprint!("{}", timing::report(started.elapsed()));
+    }
+    if let Err(err) = &result
+        && let Some(cancelled) = err.downcast_ref::<cancel::Cancelled>()
+    {
+        eprintln!("{}", cancelled);
+        return Ok(ExitCode::from(cancelled.exit_code()));
+    }
+    if let Err(err) = &result
+        && ui::interactive()
+        && let Some(unreadable) = err.downcast_ref::<dump::Unreadable>()
+    {
+        eprintln!("Error: {:#}", err);
+        if ui::ask_yes_no("Show the raw reply? [Y/n]: ")? {
+            let raw = serde_json::from_str::<serde_json::Value>(&unreadable.raw)
+                .and_then(|value| serde_json::to_string_pretty(&value))
+                .unwrap_or_else(|_| unreadable.raw.clone());
+            println!("{}", raw);
+        }
+        return Ok(ExitCode::FAILURE);
+    }
+    result
+}
+
+/// Load `config.toml` and settle `cli` against it: the profile's defaults,
+/// the system prompt, the fallbacks and the provider. Settings the file
+/// filled in before are first put back as the command line gave them.
+fn configure(cli: &mut Cli) -> Result<config::Config> {
+    let mut config = config::Config::load()?;
+    cli.restore_given();
+    if let Some(name) = &cli.profile {
+        let profile = config.select_profile(name)?;
+        cli.apply_profile(profile);
+    }
+    cli.system_prompt = cli.system_prompt.take().or(config.system_prompt.take());
+    cli.fallbacks = config.fallbacks.clone();
+    cli.settle_provider();
+    Ok(config)
+}
+
+/// Carry out the subcommand, or turn the prompt into a command.
+fn run(cli: &Cli, config: &config::Config) -> Result<ExitCode> {
+    match &cli.command {
+        Some(Commands::Function(args)) => run_function(cli, args)?,
+        // With no build file to add to, `llmwrap make a gif out of video.mp4`
+        // is a request.
+        Some(Commands::Make(args)) if args.file.is_none() && makefile::existing().is_none() => {
+            return run_prompt(cli, config, &format!("make {}", args.description.join(" ")));
+        }
+        Some(Commands::Make(args)) => run_make(cli, args)?,
+        Some(Commands::Ansible(args)) => run_ansible(cli, args)?,
+        Some(Commands::Ci(args)) => run_ci(cli, args)?,
+        Some(Commands::Back(args)) => return run_back(cli, config, args),
+        Some(Commands::Rerun(args)) => return run_rerun(cli, config, args),
+        Some(Commands::History(args)) => return run_history(cli, config, args),
+        Some(Commands::Fix(args)) => return run_fix(cli, config, args),
+        Some(Commands::Then(args)) => return run_then(cli, config, args),
+        Some(Commands::Explain(args)) => run_explain(cli, args)?,
+        // Nothing piped in to summarize: `llmwrap summarize the disk usage`.
+        Some(Commands::Summarize(args)) if io::stdin().is_terminal() => {
+            return run_prompt(
+                cli,
+                config,
+                &format!("summarize {}", args.question.join(" ")),
+            );
+        }
+        Some(Commands::Summarize(args)) => run_summarize(cli, config, args)?,
+        Some(Commands::Errors(args)) => run_errors(cli, args)?,
+        Some(Commands::Repl) => run_repl(cli, config, "")?,
+        Some(Commands::Context { action }) => run_context(action)?,
+        Some(Commands::ContainerDebug(args)) => return run_container_debug(cli, config, args),
+        Some(Commands::Tutor(args)) => return run_tutor(cli, config, args),
+        Some(Commands::Digest(args)) => print!(
+            "{}",
+            digest::render(&history::load(), args.days, &config.prices)
+        ),
+        Some(Commands::Init {
+            shell,
+            install: false,
+        }) => print!("{}", init::script(*shell)?),
+        Some(Commands::Init {
+            shell,
+            install: true,
+        }) => {
+            init::script(*shell)?;
+            let (file, added) = init::install(*shell)?;
+            if added {
+                println!(
+                    "Added llmwrap to {}; it loads in new shells.",
+                    file.display()
+                );
+            } else {
+                println!("{} already loads llmwrap.", file.display());
+            }
+        }
+        None if cli.repl => run_repl(cli, config, &cli.prompt.join(" "))?,
+        None => return run_prompt(cli, config, &cli.prompt.join(" ")),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Turn a description into a command, confirm it and run it, exiting with the
+/// command's own status.
+fn run_prompt(cli: &Cli, config: &config::Config, description: &str) -> Result<ExitCode> {
+    if description.trim().is_empty() {
+        anyhow::bail!("Please provide a description, e.g. `llmwrap convert video.mp4 to gif`");
+    }
+    let description = &match wsl::detect() {
+        Some(wsl) if !wsl::windows_host() => wsl.linux_paths(description),
+        _ => description.to_string(),
+    };
+    let description = &clarify_target(description)?;
+    if let Some(count) = cli.candidates {
+        return run_candidates(cli, config, description, count);
+    }
+
+    // Answers in a container, or about attached input, depend on what is in
+    // it, not just the request; a template asks for answers of its own.
+    let debugging =
+        cli.container_debug().is_some() || !cli.attachments.is_empty() || cli.template.is_some();
+    let caching = !debugging && config.cache.ttl_days > 0;
+    let mut cache = cache::Cache::load();
+    let host = cli.host_key();
+    let key = cache::Key {
+        prompt: description,
+        provider: cli.provider.unwrap_or(api::Provider::Openai).name(),
+        model: &cli.model,
+        shell: cli.shell_key(),
+        host: host.as_deref(),
+    };
+    let local = if debugging {
+        None
+    } else {
+        let max_age = (caching && !cli.no_cache).then_some(config.cache.ttl_days * 24 * 60 * 60);
+        local_command(cli, config, &key, max_age.map(|age| (&cache, age)))?
+    };
+    let (answer, exchange) = match local {
+        Some(command) => (answer::Answer::plain(command), None),
+        None => {
+            let client = cli.client()?;
+            let system_prompt = system_prompt(cli, config)?;
+            match fetch_command(&client, config, &system_prompt, description) {
+                Ok((answer, exchange)) => {
+                    // A cut-off answer shouldn't be served again as if it were whole.
+                    if exchange.incomplete.is_none() && caching {
+                        cache.insert(&key, &answer.command);
+                        if let Err(err) = cache.save() {
+                            eprintln!("Warning: {:#}", err);
+                        }
+                    }
+                    (answer, Some(exchange))
+                }
+                // Better an old answer than none when the API is out of reach.
+                Err(err) => {
+                    let Some(entry) = cache.stale(&key).filter(|_| caching) else {
+                        return Err(err);
+                    };
+                    eprintln!("Warning: {:#}", err);
+                    let when = match entry.age_days() {
+                        0 => "today".to_string(),
+                        1 => "yesterday".to_string(),
+                        days => format!("{} days ago", days),
+                    };
+                    ui::say!("Using the answer cached {} instead.", when);
+                    (answer::Answer::plain(entry.command.clone()), None)
+                }
+            }
+        }
+    };
+
+    let command_text =
+        postprocess::apply(&config.postprocess, cli.target_shell(), &answer.command)?;
+    let mut session = session::Session::new(description, &command_text);
+    if let Some(exchange) = exchange {
+        session.set_exchange(exchange);
+    }
+    session.describe(answer.explanation, answer.rated);
+    run_session(cli, config, session)
+}
+
+/// Ask for `count` different commands at once and let the user pick the one
+/// to go on with. All of them stay in the session as variants, so `g`, `b`
+/// and `llmwrap back` reach the others.
+fn run_candidates(
+    cli: &Cli,
+    config: &config::Config,
+    description: &str,
+    count: u8,
+) -> Result<ExitCode> {
+    let client = cli.client()?;
+    let system_prompt = format!(
+        "{} {}",
+        system_prompt(cli, config)?,
+        candidates::prompt_note(count)
+    );
+    let (answer, exchange) = fetch_text(&client, config, &system_prompt, description)?;
+    let mut commands: Vec<String> = Vec::new();
+    for part in candidates::split(&answer) {
+        let command = postprocess::apply(
+            &config.postprocess,
+            cli.target_shell(),
+            &sanitize_command(&part),
+        )?;
+        if !command.is_empty() && !commands.contains(&command) {
+            commands.push(command);
+        }
+    }
+    // A refusal or a single answer goes through as it is.
+    if commands.len() < 2 {
+        let command = commands.pop().unwrap_or_else(|| sanitize_command(&answer));
+        let mut session = session::Session::new(description, &command);
+        session.set_exchange(exchange);
+        return run_session(cli, config, session);
+    }
+
+    let mut session = session::Session::new(description, &commands[0]);
+    session.set_exchange(exchange.clone());
+    for command in &commands[1..] {
+        session.regenerate(command);
+        session.set_exchange(exchange.clone());
+    }
+    session.select(0);
+    if !ui::interactive() {
+        ui::say!("Taking the first of {} candidates.", commands.len());
+        return run_session(cli, config, session);
+    }
+    match ui::pick(&commands)? {
+        Some(chosen) => {
+            session.select(chosen);
+            run_session(cli, config, session)
+        }
+        None => {
+            session.save()?;
+            print_variants(&session);
+            ui::say!("Nothing run; `llmwrap back N` reopens variant N.");
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
+
+/// Settle which file a phrase like "the video" means before asking the model,
+/// which cannot see the directory and would only guess.
+fn clarify_target(description: &str) -> Result<String> {
+    let Some(ambiguity) = targets::find(description) else {
+        return Ok(description.to_string());
+    };
+    let phrase = &description[ambiguity.phrase.clone()];
+    let candidates = &ambiguity.candidates;
+    let chosen = if let [only] = candidates.as_slice() {
+        ui::say!("Using {} for \"{}\".", only, phrase);
+        only
+    } else if ui::interactive() && candidates.len() <= targets::PICK_LIMIT {
+        ui::say!("Which {} do you mean?", ambiguity.noun);
+        for (i, name) in candidates.iter().enumerate() {
+            ui::say!("  [{}] {}", i + 1, name);
+        }
+        let input = ui::read_line("Number (Enter to leave it to the model): ")?;
+        match input.parse::<usize>() {
+            Ok(n) if (1..=candidates.len()).contains(&n) => &candidates[n - 1],
+            _ => return Ok(description.to_string()),
+        }
+    } else {
+        return Ok(description.to_string());
+    };
+    let name = shlex::try_quote(chosen).map_or_else(|_| chosen.clone(), |q| q.into_owned());
+    let mut clarified = description.to_string();
+    clarified.replace_range(ambiguity.phrase, &name);
+    Ok(clarified)
+}
+
+/// Reopen the last session at an earlier variant.
+fn run_back(cli: &Cli, config: &config::Config, args: &BackArgs) -> Result<ExitCode> {
+    let variant = match args.args.as_slice() {
+        [] => None,
+        [n] if n.parse::<usize>().is_ok() => n.parse().ok(),
+        // `llmwrap back up my photos` is a request, not navigation.
+        words => return run_prompt(cli, config, &format!("back {}", words.join(" "))),
+    };
+
+    let Some(mut session) = session::Session::load() else {
+        anyhow::bail!("There is no previous session to go back to");
+    };
+    match variant {
+        Some(n) if !session.select(n) => anyhow::bail!(
+            "The last session has no variant {} (it has {})",
+            n,
+            session.nodes.len()
+        ),
+        Some(_) => {}
+        None => {
+            session.back();
+        }
+    }
+    print_variants(&session);
+    run_session(cli, config, session)
+}
+
+/// Start a session from a command that ran before, refined by the requested
+/// change if there is one.
+fn run_rerun(cli: &Cli, config: &config::Config, args: &RerunArgs) -> Result<ExitCode> {
+    let shell_key = cli.shell_key();
+    let runs: Vec<history::HistoryEntry> = history::load()
+        .into_iter()
+        .filter(|h| h.executed && h.shell.as_deref() == shell_key)
+        .collect();
+    if runs.is_empty() {
+        anyhow::bail!("No command has been run yet");
+    }
+    let Some(entry) = args
+        .entry
+        .checked_sub(1)
+        .and_then(|back| runs.iter().rev().nth(back))
+    else {
+        anyhow::bail!(
+            "There is no run {} back (history has {})",
+            args.entry,
+            runs.len()
+        );
+    };
+
+    let mut session = session::Session::new(&entry.prompt, &entry.command);
+    let change = args.change.join(" ");
+    if !change.trim().is_empty() {
+        ui::say!("Previous command:\n{}", entry.command);
+        refine(cli, config, &mut session, change.trim())?;
+    }
+    run_session(cli, config, session)
+}
+
+/// List past commands, most recent last and numbered back from it, and at a
+/// terminal offer to start a session from one of them.
+fn run_history(cli: &Cli, config: &config::Config, args: &HistoryArgs) -> Result<ExitCode> {
+    let found = history::search(args.search.as_deref().unwrap_or_default());
+    if found.is_empty() {
+        match &args.search {
+            Some(query) => anyhow::bail!("No history entry matches \"{}\"", query),
+            None => anyhow::bail!("History is empty"),
+        }
+    }
+    let shown = &found[found.len().saturating_sub(args.limit)..];
+    let width = shown.len().to_string().len();
+    for (i, entry) in shown.iter().enumerate() {
+        let status = match (entry.executed, entry.exit_code) {
+            (false, _) => "not run".to_string(),
+            (true, Some(0) | None) => "ok".to_string(),
+            (true, Some(code)) => format!("exit {}", code),
+        };
+        let mut place = String::new();
+        if let Some(shell) = &entry.shell {
+            place.push_str(&format!(" [{}]", shell));
+        }
+        if let Some(host) = &entry.host {
+            place.push_str(&format!(" on {}", host));
+        }
+        println!(
+            "{:>width$}  {}  {:<7}  {}{}",
+            shown.len() - i,
+            digest::date(entry.timestamp),
+            status,
+            entry.prompt,
+            place,
+            width = width
+        );
+        for line in entry.command.lines() {
+            println!("{:width$}  {}", "", line, width = width + 22);
+        }
+    }
+    if !ui::interactive() {
+        return Ok(ExitCode::SUCCESS);
+    }
+    let choice = ui::read_line("\nRun which? (number, Enter for none): ")?;
+    if choice.is_empty() {
+        return Ok(ExitCode::SUCCESS);
+    }
+    let Some(entry) = choice
+        .parse::<usize>()
+        .ok()
+        .filter(|n| (1..=shown.len()).contains(n))
+        .map(|n| &shown[shown.len() - n])
+    else {
+        anyhow::bail!("Pick a number from 1 to {}", shown.len());
+    };
+    if entry.shell.as_deref() != cli.shell_key() {
+        anyhow::bail!(
+            "That command was written for {}; run `llmwrap --shell {} history` to use it",
+            entry.shell.as_deref().unwrap_or("sh"),
+            entry.shell.as_deref().unwrap_or("sh")
+        );
+    }
+    let here = ssh::targets().first().map(|host| host.destination.as_str());
+    if let Some(host) = entry.host.as_deref().filter(|host| Some(*host) != here) {
+        anyhow::bail!(
+            "That command was written for {}; run `llmwrap --target ssh:{} history` to use it",
+            host,
+            host
+        );
+    }
+    run_session(
+        cli,
+        config,
+        session::Session::new(&entry.prompt, &entry.command),
+    )
+}
+
+/// Ask for a command that carries on from the last one run, with what it
+/// printed, so "the ones" in the request can mean what it listed.
+fn run_then(cli: &Cli, config: &config::Config, args: &ThenArgs) -> Result<ExitCode> {
+    let followup = args.followup.join(" ");
+    if followup.trim().is_empty() {
+        anyhow::bail!(
+            "Say what to do next, e.g. `llmwrap then delete the ones older than 30 days`"
+        );
+    }
+    let Some(ran) = followup::last_run() else {
+        anyhow::bail!("No command has been run yet");
+    };
+    ui::say!("Following on from: {}", ran.command);
+    if ran.output.is_none() {
+        ui::say!("Its output wasn't kept, so the model only sees the command.");
+    }
+    let client = cli.client()?;
+    let (answer, exchange) = fetch_command(
+        &client,
+        config,
+        &system_prompt(cli, config)?,
+        &ran.request(&followup),
+    )?;
+    let command = postprocess::apply(&config.postprocess, cli.target_shell(), &answer.command)?;
+    let mut session = session::Session::new(&followup, &command);
+    session.set_exchange(exchange);
+    session.describe(answer.explanation, answer.rated);
+    run_session(cli, config, session)
+}
+
+/// Ask for a corrected version of a command that failed: the last one llmwrap
+/// ran, or one given with `-c`, which runs first to show how it fails.
+fn run_fix(cli: &Cli, config: &config::Config, args: &FixArgs) -> Result<ExitCode> {
+    if !args.words.is_empty() {
+        // `llmwrap fix the permissions` is a request, not a repair.
+        return run_prompt(cli, config, &format!("fix {}", args.words.join(" ")));
+    }
+    let failure = match &args.command {
+        Some(command) => {
+            let assessment = assess(cli.target_shell(), command);
+            if assessment.level > risk::RiskLevel::Low {
+                ui::say!("Risk: {}", assessment.level.painted());
+                for finding in &assessment.findings {
+                    ui::say!("  - {}", finding);
+                }
+                if !ui::interactive() || !ui::ask_yes_no("Run it to see how it fails? [Y/n]: ")? {
+                    anyhow::bail!("Not run, so there is no failure to repair");
+                }
+            }
+            ui::say!("Running it to see how it fails: {}", command);
+            match fix::capture(cli.target_shell(), command)? {
+                Some(failure) => failure,
+                None => {
+                    ui::say!("It succeeded; there is nothing to fix.");
+                    return Ok(ExitCode::SUCCESS);
+                }
+            }
+        }
+        None => match fix::last_failure() {
+            Some(failure) => {
+                ui::say!("Fixing: {}", failure.command);
+                failure
+            }
+            None if !history::load().iter().any(|h| h.executed) => anyhow::bail!(
+                "No command has been run yet; use `llmwrap fix -c COMMAND` for one run elsewhere"
+            ),
+            None => anyhow::bail!(
+                "The last command llmwrap ran did not fail; use `llmwrap fix -c COMMAND` for another"
+            ),
+        },
+    };
+    fix_failure(cli, config, &failure)
+}
+
+/// Show the model how a command failed and go through the corrected command
+/// it proposes as usual.
+fn fix_failure(cli: &Cli, config: &config::Config, failure: &fix::Failure) -> Result<ExitCode> {
+    let client = cli.client()?;
+    let (answer, exchange) = fetch_command(
+        &client,
+        config,
+        &system_prompt(cli, config)?,
+        &failure.request(),
+    )?;
+    let command = postprocess::apply(&config.postprocess, cli.target_shell(), &answer.command)?;
+    let prompt = match &failure.prompt {
+        Some(prompt) => prompt.clone(),
+        None => format!("fix {}", failure.command),
+    };
+    let mut session = session::Session::new(&prompt, &command);
+    session.set_exchange(exchange);
+    session.describe(answer.explanation, answer.rated);
+    run_session(cli, config, session)
+}
+
+/// Explain a command given on the command line or piped in, without running it.
+fn run_explain(cli: &Cli, args: &ExplainArgs) -> Result<()> {
+    let command = if args.command.is_empty() && !io::stdin().is_terminal() {
+        io::read_to_string(io::stdin()).context("Failed to read the command from stdin")?
+    } else {
+        args.command.join(" ")
+    };
+    if command.trim().is_empty() {
+        anyhow::bail!(
+            "Give a command to explain, e.g. `llmwrap explain 'tar -xzvf foo.tgz -C /tmp'`"
+        );
+    }
+    explain::explain(&cli.client()?, cli.target_shell(), command.trim())
+}
+
+/// Summarize what is piped in, or answer a question about it.
+fn run_summarize(cli: &Cli, config: &config::Config, args: &SummarizeArgs) -> Result<()> {
+    let output = read_piped("make 2>&1 | llmwrap summarize")?;
+    let question = args.question.join(" ");
+    let question = Some(question.trim()).filter(|q| !q.is_empty());
+    let answer = summarize::summarize(&cli.client()?, &config.summarize, &output, question)?;
+    println!("{}", answer.trim());
+    Ok(())
+}
+
+/// List the kinds of error in a log piped in, most frequent first.
+fn run_errors(cli: &Cli, args: &ErrorsArgs) -> Result<()> {
+    let log = read_piped("journalctl -u app | llmwrap errors")?;
+    let mut clusters = logpatterns::cluster(&log, args.warnings);
+    if clusters.is_empty() {
+        ui::say!("No errors found.");
+        return Ok(());
+    }
+    let total = clusters.len();
+    clusters.truncate(args.limit);
+    if !args.local
+        && let Err(err) = cli
+            .client()
+            .and_then(|client| logpatterns::name(&client, &mut clusters))
+    {
+        eprintln!("Warning: could not name the errors: {:#}", err);
+    }
+    let width = clusters[0].count.to_string().len();
+    for cluster in &clusters {
+        let heading = cluster.name.as_deref().unwrap_or(&cluster.pattern);
+        println!("{:>width$}  {}", cluster.count, heading);
+        let indent = " ".repeat(width + 2);
+        match (&cluster.first, &cluster.last) {
+            (Some(first), Some(last)) if cluster.count > 1 && first != last => {
+                println!("{}first {}, last {}", indent, first, last)
+            }
+            (Some(first), _) => println!("{}at {}", indent, first),
+            _ => {}
+        }
+        if cluster.name.is_some() {
+            println!("{}{}", indent, cluster.pattern);
+        }
+    }
+    if total > clusters.len() {
+        ui::say!(
+            "\n{} more kinds; -n {} shows them all.",
+            total - clusters.len(),
+            total
+        );
+    }
+    Ok(())
+}
+
+/// Everything piped in on stdin, failing when nothing is, with `example`
+/// showing how to pipe it.
+fn read_piped(example: &str) -> Result<String> {
+    if io::stdin().is_terminal() {
+        anyhow::bail!("Pipe the output in, e.g. `{}`", example);
+    }
+    let mut bytes = Vec::new();
+    io::stdin()
+        .read_to_end(&mut bytes)
+        .context("Failed to read from stdin")?;
+    let text = String::from_utf8_lossy(&bytes).into_owned();
+    if text.trim().is_empty() {
+        anyhow::bail!("Nothing was piped in");
+    }
+    Ok(text)
+}
+
+fn run_context(action: &ContextAction) -> Result<()> {
+    let ContextAction::Refresh { host } = action;
+    let Some(spec) = host else {
+        ssh::forget_all()?;
+        ui::say!("Forgot what every host runs; each will be asked again.");
+        return Ok(());
+    };
+    for host in ssh::resolve_all(spec, &inventory::load()?)? {
+        print_fingerprint(&host)?;
+    }
+    Ok(())
+}
+
+/// Ask `host` what it runs afresh and show the answer.
+fn print_fingerprint(host: &ssh::Host) -> Result<()> {
+    let fingerprint = host.refresh()?;
+    println!(
+        "{}: {} ({})",
+        host.destination, fingerprint.os, fingerprint.arch
+    );
+    if let Some(shell) = &fingerprint.login_shell {
+        println!("  login shell: {}", shell);
+    }
+    if !fingerprint.package_managers.is_empty() {
+        println!(
+            "  package managers: {}",
+            fingerprint.package_managers.join(", ")
+        );
+    }
+    if !fingerprint.tools.is_empty() {
+        println!("  tools: {}", fingerprint.tools.join(", "));
+    }
+    Ok(())
+}
+
+/// Turn a description into an investigation of the container llmwrap runs
+/// in, with the model told what the image has to work with.
+fn run_container_debug(
+    cli: &Cli,
+    config: &config::Config,
+    args: &ContainerDebugArgs,
+) -> Result<ExitCode> {
+    if ssh::target().is_some() {
+        anyhow::bail!("llmwrap container-debug runs inside the container; drop --target ssh:");
+    }
+    let Some(container) = container::detect() else {
+        anyhow::bail!("llmwrap container-debug must run inside a container");
+    };
+    ui::say!("Debugging inside {}.", container);
+    run_prompt(cli, config, &args.description.join(" "))
+}
+
+/// Run a command as usual, then turn it into a short lesson: what each part
+/// did, which concepts are new, and a question to answer.
+fn run_tutor(cli: &Cli, config: &config::Config, args: &TutorArgs) -> Result<ExitCode> {
+    let description = args.description.join(" ");
+    if description.trim().is_empty() {
+        let progress = tutor::Progress::load();
+        if progress.concepts.is_empty() {
+            ui::say!("No lessons yet; try `llmwrap tutor archive the logs directory`.");
+        }
+        for (concept, count) in &progress.concepts {
+            println!("{} (seen {})", concept, count);
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    if !ui::interactive() {
+        anyhow::bail!("llmwrap tutor needs a terminal");
+    }
+
+    let code = run_prompt(cli, config, &description)?;
+    let Some(entry) = history::load().pop().filter(|h| h.executed) else {
+        return Ok(code);
+    };
+    let lesson = cli
+        .client()
+        .and_then(|client| tutor::lesson(&client, &entry.prompt, &entry.command));
+    let lesson = match lesson {
+        Ok(lesson) => lesson,
+        Err(err) => {
+            eprintln!("Warning: no lesson this time: {:#}", err);
+            return Ok(code);
+        }
+    };
+
+    let mut progress = tutor::Progress::load();
+    ui::say!("\nWhat each part did:");
+    for part in &lesson.parts {
+        let new = if progress.record(&part.concept) {
+            format!(" [new: {}]", part.concept)
+        } else {
+            String::new()
+        };
+        ui::say!("  {}: {}{}", part.text, part.explanation, new);
+    }
+    if let Err(err) = progress.save() {
+        eprintln!("Warning: {:#}", err);
+    }
+    ui::say!("\nQuestion: {}", lesson.question);
+    ui::read_line("Your answer (Enter to skip): ")?;
+    ui::say!("Answer: {}", lesson.answer);
+    Ok(code)
+}
+
+/// Read task descriptions until EOF or `exit`, streaming each command in as
+/// the model writes it and acting on it with a single key. Earlier requests
+/// and commands go along with each new one, so a follow-up can refine the
+/// last command; `new` forgets them.
+fn run_repl(cli: &Cli, config: &config::Config, first: &str) -> Result<()> {
+    if !ui::interactive() {
+        anyhow::bail!("llmwrap repl needs a terminal");
+    }
+    let mut client = cli.client()?;
+    let mut system_prompt = format!("{} {}", system_prompt(cli, config)?, REPL_NOTE);
+    let mut watch = config::Watch::new();
+    let mut reloaded: Option<(Cli, config::Config)> = None;
+    let mut conversation = Vec::new();
+    let mut first = Some(first.trim().to_string()).filter(|first| !first.is_empty());
+    // What the last command printed, sent along with the next request.
+    let mut output = None;
+    ui::say!(
+        "Describe a task, follow up to change the last command, `new` to start over, or press Ctrl-D to quit."
+    );
+    loop {
+        let description = match first.take() {
+            Some(first) => first,
+            None => {
+                print!("\n> ");
+                io::stdout().flush()?;
+                let mut line = String::new();
+                if io::stdin().read_line(&mut line)? == 0 {
+                    println!();
+                    return Ok(());
+                }
+                line.trim().to_string()
+            }
+        };
+        if description.is_empty() {
+            continue;
+        }
+        match description.as_str() {
+            "exit" | "quit" => return Ok(()),
+            "new" => {
+                conversation.clear();
+                ui::say!("Starting over.");
+                continue;
+            }
+            _ => {}
+        }
+        if watch.changed() {
+            let current = reloaded.as_ref().map_or(cli, |(cli, _)| cli);
+            match reload(current) {
+                Ok(settings) => {
+                    client = settings.client;
+                    system_prompt = settings.system_prompt;
+                    reloaded = Some(settings.settled);
+                    ui::say!("Applied the changes to config.toml.");
+                }
+                Err(err) => eprintln!("Warning: kept the settings from before: {:#}", err),
+            }
+        }
+        let (cli, config) = match &reloaded {
+            Some((cli, config)) => (cli, config),
+            None => (cli, config),
+        };
+        // A failed request shouldn't end the session.
+        match repl_task(
+            cli,
+            config,
+            &client,
+            &system_prompt,
+            &mut conversation,
+            &description,
+            output.take(),
+        ) {
+            Ok(printed) => output = printed,
+            Err(err) => eprintln!("Error: {:#}", err),
+        }
+        if conversation.len() > REPL_TURNS {
+            conversation.drain(..conversation.len() - REPL_TURNS);
+        }
+    }
+}
+
+/// What the REPL goes on with after `config.toml` changes.
+struct Reloaded {
+    settled: (Cli, config::Config),
+    client: api::Client,
+    system_prompt: String,
+}
+
+/// Read `config.toml` again and settle the settings it fills in against it,
+/// as at start-up. Everything else the session settled, such as the target,
+/// carries over. Nothing is applied unless all of it is valid.
+fn reload(current: &Cli) -> Result<Reloaded> {
+    let mut cli = current.clone();
+    let config = configure(&mut cli)?;
+    let client = cli.client()?;
+    let system_prompt = format!("{} {}", system_prompt(&cli, &config)?, REPL_NOTE);
+    // Last, as it takes effect at once.
+    risk::init(&config.risk)?;
+    Ok(Reloaded {
+        settled: (cli, config),
+        client,
+        system_prompt,
+    })
+}
+
+/// Get a command for one REPL description and run, edit, regenerate or copy
+/// it, adding the description and the final command to `conversation`. The
+/// request goes with `output`, what the last command printed, and what this
+/// one printed comes back.
+fn repl_task(
+    cli: &Cli,
+    config: &config::Config,
+    client: &api::Client,
+    system_prompt: &str,
+    conversation: &mut Vec<api::Turn>,
+    description: &str,
+    output: Option<String>,
+) -> Result<Option<String>> {
+    let shell = cli.target_shell();
+    let request = match &output {
+        Some(output) => followup::with_output(output, description),
+        None => description.to_string(),
+    };
+    conversation.push(api::Turn::user(&request));
+    let mut command = match stream_command(client, config, shell, system_prompt, conversation) {
+        Ok(command) => command,
+        Err(err) => {
+            conversation.pop();
+            return Err(err);
+        }
+    };
+    loop {
+        if command.is_empty() {
+            conversation.pop();
+            return Ok(None);
+        }
+        let assessment = assess(shell, &command);
+        if assessment.level > risk::RiskLevel::Low {
+            ui::say!("Risk: {}", assessment.level.painted());
+            for finding in &assessment.findings {
+                ui::say!("  - {}", finding);
+            }
+        }
+        // Enter runs only what would run on Enter at the usual prompt.
+        let policy = config.confirm.policy(assessment.level);
+        let on_enter =
+            policy.style == config::PromptStyle::YesNo && policy.default == config::Answer::Yes;
+        let keys = if on_enter {
+            "[Enter] run  [e] edit  [r] regenerate  [c] copy  [q] skip "
+        } else {
+            "[y] run  [e] edit  [r] regenerate  [c] copy  [q] skip "
+        };
+        match ui::read_key(keys)? {
+            '\n' if on_enter => {}
+            'y' | 'Y' => {
+                if policy.style == config::PromptStyle::Typed
+                    && ui::read_line("Type \"yes\" to run it: ")?.to_lowercase() != "yes"
+                {
+                    continue;
+                }
+            }
+            'e' => {
+                match ui::edit(&command) {
+                    Ok(edited) => command = edited,
+                    Err(err) => eprintln!("{:#}", err),
+                }
+                ui::say!("{}", command);
+                continue;
+            }
+            'r' => {
+                command = match stream_command(client, config, shell, system_prompt, conversation) {
+                    Ok(command) => command,
+                    Err(err) => {
+                        conversation.pop();
+                        return Err(err);
+                    }
+                };
+                continue;
+            }
+            'c' => {
+                conversation.push(api::Turn::assistant(&command));
+                clipboard::copy(&command)?;
+                ui::say!("Copied to the clipboard.");
+                return Ok(None);
+            }
+            _ => {
+                conversation.push(api::Turn::assistant(&command));
+                return Ok(None);
+            }
+        }
+        conversation.push(api::Turn::assistant(&command));
+
+        let outside = cli.outside_workspace(config, &command);
+        if !outside.is_empty() {
+            ui::say!(
+                "Not run: it modifies {} outside the workspace; start the REPL with --outside-workspace to allow that.",
+                outside.join(", ")
+            );
+            return Ok(None);
+        }
+        let violations = cli.read_only_violations(&command);
+        if !violations.is_empty() {
+            ui::say!("Not run in read-only mode: {}.", violations.join("; "));
+            return Ok(None);
+        }
+        let mut entry = history::HistoryEntry::new(description, &command);
+        entry.shell = cli.shell_key().map(str::to_string);
+        entry.host = ssh::target().map(|host| host.destination.clone());
+        entry.risk = Some(assessment.level);
+        if let Some(status) = execute(config, shell, &mut entry, assessment.level)? {
+            // Reports a failure the same way a one-off run does.
+            exit_code(status);
+        }
+        return Ok(session::output_since(entry.timestamp));
+    }
+}
+
+/// Ask for a command, printing its first line as it arrives and then the
+/// cleaned-up, post-processed command in its place.
+fn stream_command(
+    client: &api::Client,
+    config: &config::Config,
+    shell: shell::Shell,
+    system_prompt: &str,
+    conversation: &[api::Turn],
+) -> Result<String> {
+    let mut shown = String::new();
+    let mut line_done = false;
+    let mut on_text = |delta: &str| {
+        if line_done {
+            return;
+        }
+        let part = match delta.find('\n') {
+            Some(end) => {
+                line_done = true;
+                &delta[..end]
+            }
+            None => delta,
+        };
+        shown.push_str(part);
+        print!("{}", part);
+        let _ = io::stdout().flush();
+    };
+    let (raw, exchange) = client.converse_stream(system_prompt, conversation, &mut on_text)?;
+    let command = postprocess::apply(&config.postprocess, shell, &sanitize_command(&raw))?;
+    if command != shown.trim() {
+        print!("\r\x1b[2K{}", command);
+    }
+    println!();
+    if let Some(reason) = &exchange.incomplete {
+        eprintln!(
+            "Warning: the model's answer was cut off ({}); this command may be incomplete.",
+            reason
+        );
+    }
+    Ok(command)
+}
+
+/// Confirm and run the session's current variant, refining, regenerating or
+/// stepping back through variants until the user runs one or gives up.
+fn run_session(
+    cli: &Cli,
+    config: &config::Config,
+    mut session: session::Session,
+) -> Result<ExitCode> {
+    let userland = userland::Userland::detect();
+    // Checks against this machine's tools and files don't apply to another.
+    let here = ssh::target().is_none();
+    let mac = macos::detect().filter(|_| here);
+    let shell = cli.target_shell();
+    // The command last explained, so going round the loop doesn't ask again.
+    let mut explained = String::new();
+    loop {
+        if let Err(err) = session.save() {
+            eprintln!("Warning: {:#}", err);
+        }
+        let mut command_text = session.command().to_string();
+        let repair = shell
+            .is_posix()
+            .then(|| quoting::repair(&command_text, &session.prompt))
+            .flatten();
+        if let Some(repair) = &repair {
+            command_text = repair.command.clone();
+        }
+        let windows = (shell == shell::Shell::Pwsh)
+            .then(|| winpath::repair(&command_text))
+            .flatten();
+        if let Some(windows) = &windows {
+            command_text = windows.command.clone();
+        }
+        let android = termux::detect()
+            .filter(|_| !shell.is_windows() && here)
+            .and_then(|termux| termux.repair(&command_text));
+        if let Some(android) = &android {
+            command_text = android.command.clone();
+        }
+        if ui::interactive() {
+            let kind = if is_script(&command_text) {
+                "script"
+            } else {
+                "command"
+            };
+            println!("\nProposed {}:\n{}\n", kind, command_text);
+            if let Some(explanation) = session.explanation() {
+                ui::say!("{}\n", explanation);
+            }
+        }
+        if let Some(repair) = &repair {
+            ui::say!("Fixed quoting of {}\n", repair.names.join(", "));
+        }
+        if let Some(windows) = &windows {
+            ui::say!("Adjusted for PowerShell: {}\n", windows.changes.join(", "));
+        }
+        if let Some(android) = &android {
+            ui::say!("Adjusted for Termux: {}\n", android.changes.join(", "));
+        }
+
+        if !matches!(shell, shell::Shell::Nu | shell::Shell::Cmd)
+            && here
+            && let Some(lint) = userland::lint(&command_text, userland)
+        {
+            ui::say!("Not available with {} tools:", userland);
+            for problem in &lint.problems {
+                ui::say!("  - {}", problem);
+            }
+            ui::say!();
+            if let Some(corrected) = lint.corrected {
+                ui::say!("Corrected for {}:\n{}\n", userland, corrected);
+                if ui::interactive() && ui::ask_yes_no("Use the corrected command? [Y/n]: ")? {
+                    session.refine(&corrected, &format!("adjusted for {} tools", userland));
+                    continue;
+                }
+            }
+        }
+        if let Some(mac) = &mac {
+            let problems = mac.sip_problems(&command_text);
+            if !problems.is_empty() {
+                ui::say!("Blocked by System Integrity Protection:");
+                for problem in &problems {
+                    ui::say!("  - {}", problem);
+                }
+                ui::say!();
+            }
+        }
+
+        // Paths are read the POSIX way, which would take backslashes in
+        // Windows paths for escapes.
+        let missing = if shell.is_windows() || !here {
+            Vec::new()
+        } else {
+            pathcheck::check(&command_text)
+        };
+        for path in &missing {
+            match &path.suggestion {
+                Some(suggestion) => {
+                    ui::say!("{} not found; did you mean {}?", path.path, suggestion)
+                }
+                None => ui::say!("{} not found", path.path),
+            }
+        }
+        let corrections: Vec<String> = missing
+            .iter()
+            .filter_map(|m| {
+                let suggestion = m.suggestion.as_ref()?;
+                Some(format!("use {} instead of {}", suggestion, m.path))
+            })
+            .collect();
+        if !missing.is_empty() {
+            ui::say!();
+        }
+        if !corrections.is_empty()
+            && ui::interactive()
+            && ui::ask_yes_no("Regenerate with the suggested paths? [Y/n]: ")?
+        {
+            refine(cli, config, &mut session, &corrections.join("; "))?;
+            continue;
+        }
+        let command_text = match cli.container_debug() {
+            Some(container) => offer_debug_tools(container, command_text)?,
+            None => command_text,
+        };
+        let command_text = if shell.is_posix() && here {
+            apply_preflight(command_text)?
+        } else {
+            command_text
+        };
+
+        let mut assessment = timing::time("assess risk", || assess(shell, &command_text));
+        // The model may know what a command does better than the patterns,
+        // but is never trusted to lower the tier.
+        let rated = session.rated().filter(|rated| *rated > assessment.level);
+        if let Some(rated) = rated {
+            assessment.level = rated;
+        }
+        if assessment.level > risk::RiskLevel::Low && !cli.json {
+            ui::say!("Risk: {}", assessment.level.painted());
+            for finding in &assessment.findings {
+                ui::say!("  - {}", finding);
+            }
+            if let Some(rated) = rated {
+                ui::say!("  - the model rates it {} risk", rated);
+            }
+            if !assessment.destinations.is_empty() {
+                ui::say!(
+                    "Data leaves this machine for: {}",
+                    assessment.destinations.join(", ")
+                );
+            }
+            ui::say!();
+        }
+
+        // Checked on the command as it will run, after any sudo rewrite.
+        let outside = cli.outside_workspace(config, &command_text);
+        if !outside.is_empty() {
+            ui::say!(
+                "Outside the workspace ({}): {}\n",
+                config.workspace.join(", "),
+                outside.join(", ")
+            );
+        }
+        let violations = cli.read_only_violations(&command_text);
+        if !violations.is_empty() {
+            ui::say!("Not read-only:");
+            for violation in &violations {
+                ui::say!("  - {}", violation);
+            }
+            ui::say!();
+        }
+        // A command run from here can't change the shell llmwrap was run in.
+        let shell_changes = if shell.is_posix() && here {
+            envchange::changes(&command_text)
+        } else {
+            Vec::new()
+        };
+        let only_shell_changes = !shell_changes.is_empty() && envchange::only_shell(&command_text);
+        if !shell_changes.is_empty() && ui::interactive() && cli.eval {
+            ui::say!("This runs in your shell, so it changes:");
+            for change in &shell_changes {
+                ui::say!("  {}", change);
+            }
+            ui::say!();
+        } else if !shell_changes.is_empty() && ui::interactive() {
+            if only_shell_changes {
+                ui::say!("Run from here this does nothing: it only changes the shell it runs in.");
+            } else {
+                ui::say!(
+                    "This also changes the shell it runs in, which isn't the one you typed in:"
+                );
+            }
+            for change in &shell_changes {
+                ui::say!("  {}", change);
+            }
+            ui::say!(
+                "To change your own shell, run: eval \"$(llmwrap --print-eval {})\"\n",
+                safer::quote(&session.prompt)
+            );
+        }
+        let refusal = if !violations.is_empty() {
+            Some("Not run: read-only mode refuses commands that change anything")
+        } else if !outside.is_empty() {
+            Some(
+                "Not run: the command modifies files outside the workspace; run again with --outside-workspace to allow that",
+            )
+        } else {
+            None
+        };
+
+        // Resolving makes network requests, worth it only when someone will
+        // read the result before deciding.
+        let remote_urls = if ui::interactive() && shell.is_posix() {
+            remote::executed_urls(&command_text)
+        } else {
+            Vec::new()
+        };
+        for url in &remote_urls {
+            show_remote(url);
+        }
+        if ui::interactive()
+            && shell.is_posix()
+            && let Some(verified) = offer_verification(&command_text)?
+        {
+            session.refine(&verified, "verifies the published checksum");
+            continue;
+        }
+
+        let incomplete = session.exchange().and_then(|e| e.incomplete.clone());
+        if let Some(reason) = &incomplete {
+            // Printed even for scripts: a cut-off command may do something else
+            // entirely.
+            eprintln!(
+                "Warning: the model's answer was cut off ({}); this command may be incomplete.\n",
+                reason
+            );
+        }
+
+        let mut entry = history::HistoryEntry::new(&session.prompt, &command_text);
+        entry.shell = cli.shell_key().map(str::to_string);
+        entry.host = ssh::target().map(|host| host.destination.clone());
+        entry.risk = Some(assessment.level);
+        if let Some(exchange) = session.exchange() {
+            entry.model = exchange.model().map(str::to_string);
+            entry.usage = exchange.usage();
+        }
+        let mut policy = config.confirm.policy(assessment.level);
+        if incomplete.is_some() || (only_shell_changes && !cli.eval) {
+            policy.default = config::Answer::No;
+        }
+        if !remote_urls.is_empty() {
+            policy.style = config::PromptStyle::Typed;
+        }
+        let on_enter =
+            policy.style == config::PromptStyle::YesNo && policy.default == config::Answer::Yes;
+        if cli.copy {
+            record_history(&entry);
+            clipboard::copy(&command_text)?;
+            ui::say!("Copied to the clipboard; not run.");
+            return Ok(ExitCode::SUCCESS);
+        }
+        if !ui::interactive() {
+            // Whatever reads our output may well run it.
+            if !violations.is_empty() {
+                record_history(&entry);
+                anyhow::bail!("Read-only mode refuses commands that change anything");
+            }
+            // `--yes` with no terminal, as in CI: run what Enter would run.
+            if cli.unattended && !(cli.print || cli.json || cli.print_eval) {
+                if let Some(refusal) = refusal {
+                    record_history(&entry);
+                    anyhow::bail!("{}", refusal);
+                }
+                if !on_enter {
+                    record_history(&entry);
+                    anyhow::bail!(
+                        "Not run: the command is rated {} risk and needs confirming, which --yes doesn't do:\n{}",
+                        assessment.level,
+                        command_text
+                    );
+                }
+                if ssh::targets().len() > 1 {
+                    return fan_out(config, shell, &entry, assessment.level, true);
+                }
+                let Some(status) = execute(config, shell, &mut entry, assessment.level)? else {
+                    return Ok(ExitCode::FAILURE);
+                };
+                return Ok(exit_code(status));
+            }
+            if cli.print_eval {
+                record_history(&entry);
+                // Unlike a printed command, this one is certain to be run, unseen.
+                if assessment.level > risk::RiskLevel::Low {
+                    anyhow::bail!(
+                        "The command is rated {} risk; run it through llmwrap to confirm it first:\n{}",
+                        assessment.level,
+                        command_text
+                    );
+                }
+                for change in &shell_changes {
+                    eprintln!("{}", change);
+                }
+                println!("{}", command_text);
+                return Ok(ExitCode::SUCCESS);
+            }
+            // Print-only: hand the command to whatever reads our output, never run it.
+            // Nushell gets a record it can parse with `from nuon` (or `from json`).
+            record_history(&entry);
+            if cli.json || shell == shell::Shell::Nu {
+                let findings: Vec<String> =
+                    assessment.findings.iter().map(|f| f.to_string()).collect();
+                let output = serde_json::json!({
+                    "command": command_text,
+                    "explanation": session.explanation(),
+                    "risk": assessment.level.to_string(),
+                    "findings": findings,
+                    "destinations": assessment.destinations,
+                    "incomplete": incomplete.is_some(),
+                });
+                println!("{}", output);
+            } else {
+                println!("{}", command_text);
+            }
+            return Ok(ExitCode::SUCCESS);
+        }
+        if config.explain_new && shell.is_posix() && explained != command_text {
+            explain_unfamiliar(cli, &command_text);
+            explained = command_text.clone();
+        }
+        let safer = safer::rewrite(&command_text).filter(|_| shell.is_posix());
+        if let Some(safer) = &safer {
+            ui::say!("Safer variant:\n{}", safer.command);
+            for change in &safer.changes {
+                ui::say!("  - {}", change);
+            }
+            ui::say!();
+        }
+        let offers = Offers {
+            strike: cmdline::segments(&command_text).len() > 1,
+            safer: safer.is_some(),
+            inspect: session.exchange().is_some(),
+        };
+        let decision = if cli.yes && on_enter && refusal.is_none() {
+            Decision::Run
+        } else {
+            confirm_run(&policy, offers)?
+        };
+        match decision {
+            Decision::Run => {
+                if let Some(refusal) = refusal {
+                    record_history(&entry);
+                    anyhow::bail!("{}", refusal);
+                }
+                if cli.eval {
+                    return hand_to_shell(config, shell, &mut entry, assessment.level);
+                }
+                if ssh::targets().len() > 1 {
+                    let unattended = cli.yes && on_enter;
+                    return fan_out(config, shell, &entry, assessment.level, unattended);
+                }
+                let Some(status) = execute(config, shell, &mut entry, assessment.level)? else {
+                    return Ok(ExitCode::FAILURE);
+                };
+                let code = exit_code(status);
+                // 130 is the shell reporting Ctrl-C, which needs no fixing.
+                if status.code().is_some_and(|code| code != 0 && code != 130)
+                    && ui::interactive()
+                    && ui::ask_yes_no("Ask for a fix? [Y/n]: ")?
+                    && let Some(failure) = fix::last_failure()
+                {
+                    return fix_failure(cli, config, &failure);
+                }
+                return Ok(code);
+            }
+            Decision::Abort => {
+                record_history(&entry);
+                ui::say!("Aborted by user; command not executed.");
+                return Ok(ExitCode::SUCCESS);
+            }
+            Decision::Copy => {
+                record_history(&entry);
+                clipboard::copy(&command_text)?;
+                ui::say!("Copied to the clipboard; not run.");
+                return Ok(ExitCode::SUCCESS);
+            }
+            Decision::Edit => match ui::edit(&command_text) {
+                Ok(edited) if !edited.is_empty() && edited != command_text => {
+                    session.refine(&edited, "edited by hand");
+                }
+                Ok(_) => {}
+                Err(err) => eprintln!("{:#}", err),
+            },
+            Decision::Refine => {
+                let note = ui::read_line("How should it change? ")?;
+                if !note.is_empty() {
+                    refine(cli, config, &mut session, &note)?;
+                }
+            }
+            Decision::Regenerate => {
+                let client = cli.client()?;
+                let request = format!(
+                    "{}\n\nSuggest a different command from these:\n{}",
+                    session.prompt,
+                    session.siblings().join("\n")
+                );
+                let (answer, exchange) =
+                    fetch_command(&client, config, &system_prompt(cli, config)?, &request)?;
+                let command = postprocess::apply(&config.postprocess, shell, &answer.command)?;
+                session.regenerate(&command);
+                session.set_exchange(exchange);
+                session.describe(answer.explanation, answer.rated);
+            }
+            Decision::Safer => {
+                if let Some(safer) = safer {
+                    session.refine(&safer.command, "safer variant");
+                }
+            }
+            Decision::Inspect => {
+                if let Some(exchange) = session.exchange() {
+                    print_exchange(exchange);
+                }
+            }
+            Decision::Explain => {
+                println!();
+                let explained = cli
+                    .client()
+                    .and_then(|client| explain::explain(&client, shell, &command_text));
+                if let Err(err) = explained {
+                    eprintln!("Warning: could not explain the command: {:#}", err);
+                }
+            }
+            Decision::Strike => {
+                if let Some((command, note)) = strike_parts(&command_text)? {
+                    session.refine(&command, &note);
+                }
+            }
+            Decision::Back => {
+                if session.back() {
+                    print_variants(&session);
+                } else {
+                    ui::say!("This is the first variant.");
+                }
+            }
+        }
+    }
+}
+
+/// Explain, one line each, the programs and flags in the command that you
+/// have not run before. Failing to is not worth stopping for.
+fn explain_unfamiliar(cli: &Cli, command: &str) {
+    let runs: Vec<history::HistoryEntry> = history::load()
+        .into_iter()
+        .filter(|h| h.shell.as_deref() == cli.shell_key())
+        .collect();
+    let unfamiliar = novelty::find(command, &runs);
+    if unfamiliar.is_empty() {
+        return;
+    }
+    let explained = cli
+        .client()
+        .and_then(|client| novelty::explain(&client, command, &unfamiliar));
+    match explained {
+        Ok(lines) if !lines.is_empty() => {
+            ui::say!("New to you:");
+            for (item, text) in lines {
+                ui::say!("  {}: {}", item, text);
+            }
+            ui::say!();
+        }
+        Ok(_) => {}
+        Err(err) => eprintln!("Warning: could not explain new flags: {:#}", err),
+    }
+}
+
+/// Show where a URL the command downloads and runs code from really leads.
+fn show_remote(url: &str) {
+    ui::say!("Runs code downloaded from {}", url);
+    match remote::resolve(url) {
+        Ok(resolved) => {
+            if !resolved.redirects.is_empty() {
+                ui::say!("  redirects to {}", resolved.final_url);
+            }
+            ui::say!(
+                "  content type: {}",
+                resolved.content_type.as_deref().unwrap_or("not given")
+            );
+            if resolved.looks_like_html() {
+                ui::say!("  This is a web page, not a script; the URL is probably wrong.");
+            }
+        }
+        Err(err) => ui::say!("  It could not be checked: {}", err),
+    }
+    ui::say!();
+}
+
+/// When the command downloads a file without checking it and the project
+/// publishes a checksum, offer a version that verifies the file before using it.
+fn offer_verification(command: &str) -> Result<Option<String>> {
+    let Some(download) = checksum::unverified_download(command) else {
+        return Ok(None);
+    };
+    let Some(sums_url) = checksum::find_published(&download.url) else {
+        return Ok(None);
+    };
+    let verified = checksum::add_verification(command, &download, &sums_url);
+    ui::say!(
+        "{} is not checked after downloading, but a checksum is published at {}.\n\nVerified:\n{}\n",
+        download.file,
+        sums_url,
+        verified
+    );
+    if ui::ask_yes_no("Verify the download before using it? [Y/n]: ")? {
+        return Ok(Some(verified));
+    }
+    ui::say!();
+    Ok(None)
+}
+
+/// Ask the model to change the current variant as described, adding the result
+/// to the session.
+fn refine(
+    cli: &Cli,
+    config: &config::Config,
+    session: &mut session::Session,
+    note: &str,
+) -> Result<()> {
+    let client = cli.client()?;
+    let request = format!(
+        "{}\n\nYour previous command was:\n{}\n\nChange it as follows: {}",
+        session.prompt,
+        session.command(),
+        note
+    );
+    let (answer, exchange) =
+        fetch_command(&client, config, &system_prompt(cli, config)?, &request)?;
+    let command = postprocess::apply(&config.postprocess, cli.target_shell(), &answer.command)?;
+    session.refine(&command, note);
+    session.set_exchange(exchange);
+    session.describe(answer.explanation, answer.rated);
+    Ok(())
+}
+
+/// Show exactly what was sent to the model for this variant and what came back.
+fn print_exchange(exchange: &api::Exchange) {
+    let request = serde_json::to_string_pretty(&exchange.request).unwrap_or_default();
+    let response = serde_json::from_str::<serde_json::Value>(&exchange.response)
+        .ok()
+        .and_then(|value| serde_json::to_string_pretty(&value).ok())
+        .unwrap_or_else(|| exchange.response.clone());
+    ui::say!("\nRequest to {}:\n{}\n", exchange.url, request);
+    match &exchange.request_id {
+        Some(id) => ui::say!("Response (request ID {}):\n{}\n", id, response),
+        None => ui::say!("Response:\n{}\n", response),
+    }
+}
+
+fn print_variants(session: &session::Session) {
+    ui::say!("\nVariants for \"{}\":", session.prompt);
+    for line in session.tree() {
+        ui::say!("{}", line);
+    }
+}
+
+/// Exit the way the command did so wrapping scripts see its real status. Like
+/// shells, a command killed by signal N is reported as 128 + N.
+fn exit_code(status: ExitStatus) -> ExitCode {
+    if let Some(code) = status.code() {
+        if code != 0 {
+            eprintln!("Command exited with status {}", code);
+        }
+        return ExitCode::from(clamp(code));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            let core = if status.core_dumped() {
+                " (core dumped)"
+            } else {
+                ""
+            };
+            eprintln!(
+                "Command was killed by signal {} ({}){}",
+                signal,
+                signal_name(signal),
+                core
+            );
+            return ExitCode::from(128u8.wrapping_add(signal as u8));
+        }
+    }
+    ExitCode::FAILURE
+}
+
+/// An exit code as one byte. Statuses past 255, as Windows has, would wrap
+/// and 256 read as success, so anything that doesn't fit is reported as 255.
+fn clamp(code: i32) -> u8 {
+    u8::try_from(code).unwrap_or(u8::MAX)
+}
+
+#[cfg(unix)]
+fn signal_name(signal: i32) -> String {
+    // SAFETY: strsignal returns a pointer to a NUL-terminated string that stays
+    // valid until the next call; it is copied out immediately.
+    let name = unsafe { libc::strsignal(signal) };
+    if name.is_null() {
+        return format!("signal {}", signal);
+    }
+    // SAFETY: checked non-null above; strsignal always NUL-terminates.
+    unsafe { std::ffi::CStr::from_ptr(name) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn run_function(cli: &Cli, args: &FunctionArgs) -> Result<()> {
+    let shell = cli.shell.unwrap_or_else(shell::Shell::detect);
+    let rc_file = functions::rc_file(shell)?;
+
+    match &args.action {
+        Some(FunctionAction::List) => {
+            let names = functions::list(shell)?;
+            if names.is_empty() {
+                ui::say!("No llmwrap functions in {}", rc_file.display());
+            }
+            for name in names {
+                println!("{}", name);
+            }
+            return Ok(());
+        }
+        Some(FunctionAction::Remove { name }) => {
+            if !functions::remove(shell, name)? {
+                anyhow::bail!(
+                    "No llmwrap function named {} in {}",
+                    name,
+                    rc_file.display()
+                );
+            }
+            ui::say!("Removed {} from {}", name, rc_file.display());
+            return Ok(());
+        }
+        None => {}
+    }
+
+    let description = args.description.join(" ");
+    if description.trim().is_empty() {
+        anyhow::bail!(
+            "Please describe the function, e.g. `llmwrap function \"extract any archive by extension\"`"
+        );
+    }
+
+    let client = cli.client()?;
+    let function = functions::generate(&client, shell, &description)?;
+    ui::say!("\nProposed {} function:", shell);
+    println!("{}", function.body);
+    ui::say!();
+    if let Err(err) = functions::check_syntax(shell, &function) {
+        ui::say!("Warning: {:#}\n", err);
+    }
+    if !ui::interactive() {
+        return Ok(());
+    }
+
+    let question = if functions::is_installed(shell, &function.name)? {
+        format!(
+            "Replace the existing {} in {}? [Y/n]: ",
+            function.name,
+            rc_file.display()
+        )
+    } else {
+        format!("Add {} to {}? [Y/n]: ", function.name, rc_file.display())
+    };
+    if !ui::ask_yes_no(&question)? {
+        ui::say!("Aborted by user; rc file not changed.");
+        return Ok(());
+    }
+
+    let path = functions::install(shell, &function)?;
+    ui::say!(
+        "Added {} to {}. Open a new shell or run `source {}` to use it.",
+        function.name,
+        path.display(),
+        path.display()
+    );
+    Ok(())
+}
+
+fn run_make(cli: &Cli, args: &MakeArgs) -> Result<()> {
+    let description = args.description.join(" ");
+    if description.trim().is_empty() {
+        anyhow::bail!(
+            "Please describe the target, e.g. `llmwrap make \"build the docker image and push it\"`"
+        );
+    }
+
+    let (kind, path) = makefile::locate(args.file.as_deref());
+    let existing = patch::read_or_empty(&path)?;
+    let client = cli.client()?;
+    let target = makefile::generate(&client, kind, &existing, &description)?;
+    let updated = makefile::merge(kind, &existing, &target);
+
+    ui::say!("\nProposed change to {}:\n", path.display());
+    if ui::interactive() {
+        patch::print_diff(&path, &existing, &updated);
+    } else {
+        println!("{}", target.text);
+    }
+    ui::say!();
+    if let Err(err) = makefile::check(kind, &updated) {
+        ui::say!("Warning: {:#}\n", err);
+    }
+    if !ui::interactive() {
+        return Ok(());
+    }
+    if !ui::ask_yes_no("Apply this change? [Y/n]: ")? {
+        ui::say!("Aborted by user; {} not changed.", path.display());
+        return Ok(());
+    }
+
+    patch::write(&path, &updated)?;
+    ui::say!(
+        "Updated {}. Run it with `{} {}`.",
+        path.display(),
+        kind.runner(),
+        target.name
+    );
+    Ok(())
+}
+
+fn run_ansible(cli: &Cli, args: &AnsibleArgs) -> Result<()> {
+    let description = args.description.join(" ");
+    if description.trim().is_empty() {
+        anyhow::bail!(
+            "Please describe the tasks, e.g. `llmwrap ansible \"ensure nginx is installed and running\"`"
+        );
+    }
+
+    let kind = if args.play {
+        ansible::Kind::Play
+    } else {
+        ansible::Kind::Tasks
+    };
+    let path = args
+        .output
+        .clone()
+        .unwrap_or_else(|| ansible::default_output(&description));
+    let client = cli.client()?;
+    let snippet = ansible::generate(&client, kind, &description)?;
+
+    ui::say!(
+        "\nProposed Ansible {}:",
+        if args.play { "play" } else { "tasks" }
+    );
+    println!("{}", snippet);
+    ui::say!();
+    if let Err(err) = ansible::validate(kind, &snippet) {
+        ui::say!("Warning: {:#}\n", err);
+    } else if let Some(Err(err)) = ansible::lint(kind, &snippet) {
+        ui::say!("Warning: {:#}\n", err);
+    }
+    if !ui::interactive() {
+        return Ok(());
+    }
+
+    let existing = patch::read_or_empty(&path)?;
+    let updated = ansible::merge(&existing, &snippet);
+    let question = if existing.trim().is_empty() {
+        format!("Write to {}? [Y/n]: ", path.display())
+    } else {
+        patch::print_diff(&path, &existing, &updated);
+        ui::say!();
+        format!("Append to {}? [Y/n]: ", path.display())
+    };
+    if !ui::ask_yes_no(&question)? {
+        ui::say!("Aborted by user; nothing written.");
+        return Ok(());
+    }
+
+    patch::write(&path, &updated)?;
+    ui::say!("Wrote {}. Nothing was executed.", path.display());
+    Ok(())
+}
+
+fn run_ci(cli: &Cli, args: &CiArgs) -> Result<()> {
+    let description = args.description.join(" ");
+    if description.trim().is_empty() {
+        anyhow::bail!(
+            "Please describe the step, e.g. `llmwrap ci \"run clippy on pull requests\"`"
+        );
+    }
+
+    let provider = args.provider.unwrap_or_else(ci::Provider::detect);
+    let client = cli.client()?;
+    let snippet = ci::generate(&client, provider, &description)?;
+    ui::say!("\nProposed snippet:");
+    println!("{}", snippet);
+    ui::say!();
+    ci::validate(provider, &snippet)?;
+
+    let Some(path) = args.file.as_ref().filter(|_| ui::interactive()) else {
+        return Ok(());
+    };
+    let existing = patch::read_or_empty(path)?;
+    let updated = ci::insert(provider, &existing, &snippet, args.job.as_deref())?;
+    patch::print_diff(path, &existing, &updated);
+    ui::say!();
+    if !ui::ask_yes_no("Apply this change? [Y/n]: ")? {
+        ui::say!("Aborted by user; {} not changed.", path.display());
+        return Ok(());
+    }
+    patch::write(path, &updated)?;
+    ui::say!("Updated {}.", path.display());
+    Ok(())
+}
+
+/// Look for a command that can be offered without calling the API: a cached
+/// response first, then matching snippets and history entries, then the
+/// built-in intent templates.
+fn local_command(
+    cli: &Cli,
+    config: &config::Config,
+    key: &cache::Key,
+    cache: Option<(&cache::Cache, u64)>,
+) -> Result<Option<String>> {
+    let description = key.prompt;
+    let hit =
+        cache.and_then(|(cache, max_age)| cache.lookup(key, cache::FUZZY_THRESHOLD, Some(max_age)));
+    match hit {
+        Some(hit) if hit.exact => return Ok(Some(hit.entry.command)),
+        // Without anyone to confirm it, a command for a similar prompt may do
+        // something else entirely, so only an exact hit is used.
+        Some(_) if !ui::interactive() => return Ok(None),
+        Some(hit) => {
+            ui::say!(
+                "\nCached command for a similar prompt ({:.0}% match): \"{}\"\n{}\n",
+                hit.score * 100.0,
+                hit.entry.prompt,
+                hit.entry.command
+            );
+            let reuse = ui::ask_no_yes("Use the cached command? [y/N] (n regenerates): ")?;
+            return Ok(reuse.then_some(hit.entry.command));
+        }
+        None => {}
+    }
+
+    // Snippets, history and templates are all POSIX shell commands.
+    if !cli.target_shell().is_posix() {
+        return Ok(None);
+    }
+    let snippets = snippets::load().unwrap_or_else(|err| {
+        eprintln!("Warning: {:#}", err);
+        Vec::new()
+    });
+    let suggestions = if ui::interactive() {
+        suggest::local_matches(description, &snippets, &history::load())
+    } else {
+        Vec::new()
+    };
+    if suggestions.is_empty() {
+        if config.local_intents
+            && let Some(intent) = intents::classify(description)
+        {
+            ui::say!(
+                "\nMatched built-in template \"{}\"; no API call needed.",
+                intent.name
+            );
+            return Ok(Some(intent.command));
+        }
+        return Ok(None);
+    }
+
+    ui::say!("\nDid you mean one of these?");
+    for (i, suggestion) in suggestions.iter().enumerate() {
+        ui::say!(
+            "  [{}] {}\n      ({}, {:.0}% match)",
+            i,
+            suggestion.command,
+            suggestion.source,
+            suggestion.score * 100.0
+        );
+    }
+    ui::say!("  [g] generate fresh");
+    let choice = ui::read_line("Choose [0]: ")?.to_lowercase();
+    if choice.is_empty() {
+        return Ok(Some(suggestions[0].command.clone()));
+    }
+    Ok(choice
+        .parse::<usize>()
+        .ok()
+        .and_then(|i| suggestions.get(i))
+        .map(|s| s.command.clone()))
+}
+
+/// Point out debugging tools the command needs that the container lacks, and
+/// offer to install them first or show how to bring them in from the host.
+fn offer_debug_tools(container: &container::Container, command: String) -> Result<String> {
+    let missing = container.missing_tools(&command);
+    if missing.is_empty() {
+        return Ok(command);
+    }
+    ui::say!("Not in this container: {}", missing.join(", "));
+    if let Some(sidecar) = container.sidecar() {
+        ui::say!(
+            "To leave the image alone, run a toolbox from the host:\n  {}",
+            sidecar
+        );
+    }
+    let install = container.install(&missing);
+    ui::say!();
+    if let Some(install) = install
+        && ui::interactive()
+        && ui::ask_yes_no("Install them in the container first? [Y/n]: ")?
+    {
+        return Ok(format!("{} && {}", install, command));
+    }
+    Ok(command)
+}
+
+/// Warn about steps the current user lacks permission for, offering a sudo
+/// version of the command when one can be built.
+fn apply_preflight(command: String) -> Result<String> {
+    let Some(report) = timing::time("check the command", || preflight::check(&command)) else {
+        return Ok(command);
+    };
+    ui::say!("Permissions:");
+    for problem in &report.problems {
+        ui::say!("  - {}", problem);
+    }
+    match report.adapted {
+        Some(adapted) => {
+            ui::say!("\nWith sudo:\n{}\n", adapted);
+            if ui::interactive() && ui::ask_yes_no("Use the sudo version? [Y/n]: ")? {
+                return Ok(adapted);
+            }
+            ui::say!();
+        }
+        None => ui::say!("This will probably fail: sudo is not available to you.\n"),
+    }
+    Ok(command)
+}
+
+fn record_history(entry: &history::HistoryEntry) {
+    if let Err(err) = history::append(entry) {
+        eprintln!("Warning: {:#}", err);
+    }
+}
+
+/// Ask the model for a command, returning it along with the exchange that
+/// produced it. Where the API can hold the reply to a JSON schema, the model
+/// also says what the command does and how risky it is.
+fn fetch_command(
+    client: &api::Client,
+    config: &config::Config,
+    system_prompt: &str,
+    user_request: &str,
+) -> Result<(answer::Answer, api::Exchange)> {
+    if !config.structured_output || client.provider() == api::Provider::Anthropic {
+        let (raw_text, exchange) = fetch_text(client, config, system_prompt, user_request)?;
+        return Ok((answer::Answer::plain(sanitize_command(&raw_text)), exchange));
+    }
+    let client = client.with_schema("shell_command", answer::schema());
+    let system_prompt = format!("{} {}", system_prompt, answer::PROMPT_NOTE);
+    let (raw_text, exchange) = fetch_text(&client, config, &system_prompt, user_request)?;
+    // A server that ignores the schema answers as it always did.
+    let mut answer =
+        answer::parse(&raw_text).unwrap_or_else(|| answer::Answer::plain(raw_text.clone()));
+    answer.command = sanitize_command(&answer.command);
+    Ok((answer, exchange))
+}
+
+/// Ask the model, returning its answer as it came along with the exchange
+/// that produced it. The answer is shown on stderr as it streams in.
+fn fetch_text(
+    client: &api::Client,
+    config: &config::Config,
+    system_prompt: &str,
+    user_request: &str,
+) -> Result<(String, api::Exchange)> {
+    let progress = ui::Progress::start(client.model());
+    let mut on_text = |delta: &str| progress.push(delta);
+    let answer = timing::time("wait for the model", || {
+        latency::complete(
+            client,
+            &config.latency,
+            system_prompt,
+            user_request,
+            &mut on_text,
+        )
+    });
+    drop(progress);
+    answer
+}
+
+/// The system prompt for commands in the target shell, with notes on its
+/// syntax, the local tools and any context bundles attached.
+fn system_prompt(cli: &Cli, config: &config::Config) -> Result<String> {
+    timing::time("gather context", || build_system_prompt(cli, config))
+}
+
+fn build_system_prompt(cli: &Cli, config: &config::Config) -> Result<String> {
+    let shell = cli.target_shell();
+    let base = cli.system_prompt.as_deref().unwrap_or(SYSTEM_PROMPT);
+    let mut system_prompt = match &cli.template {
+        Some(name) => template::render(
+            template::find(&config.templates, name)?,
+            base,
+            &shell.to_string(),
+        ),
+        None => base.to_string(),
+    };
+    // Over SSH nothing about this machine applies.
+    let remote = ssh::target();
+    let here = remote.is_none();
+    let local_tools =
+        !matches!(shell, shell::Shell::Nu | shell::Shell::Cmd) && !wsl::windows_host() && here;
+    let mut notes: Vec<String> = shell
+        .prompt_note()
+        .map(str::to_string)
+        .into_iter()
+        .collect();
+    if cli.read_only {
+        notes.push(readonly::PROMPT_NOTE.to_string());
+    }
+    if let Some(host) = remote {
+        ssh::probe_all();
+        notes.push(host.prompt_note());
+        notes.extend(ssh::fan_out_note(ssh::targets()));
+        notes.extend(ssh::vars_note(ssh::targets()));
+    }
+    if let Some(container) = cli.container_debug() {
+        notes.push(container.prompt_note());
+    }
+
+    // Only what applies here is looked into, all at once.
+    let mut probes = Vec::new();
+    if local_tools {
+        probes.push(probes::Probe::new("userland", || {
+            Some(userland::Userland::detect().prompt_note().to_string())
+        }));
+        probes.push(probes::Probe::new("BusyBox", || {
+            busybox::distro_note().map(str::to_string)
+        }));
+    }
+    if here {
+        probes.push(probes::Probe::new("WSL", || {
+            wsl::detect().map(|wsl| wsl.prompt_note())
+        }));
+        probes.push(probes::Probe::new("macOS", || {
+            macos::detect().map(|mac| mac.prompt_note())
+        }));
+        probes.push(probes::Probe::new("BSD", || {
+            bsd::detect().map(|bsd| bsd.prompt_note())
+        }));
+        probes.push(probes::Probe::new("Termux", || {
+            termux::detect().map(|termux| termux.prompt_note())
+        }));
+    }
+    if config.environment_context && !wsl::windows_host() && here {
+        probes.push(probes::Probe::new("environment", || {
+            Some(environment::prompt_note())
+        }));
+    }
+    if config.project_context && here {
+        probes.push(probes::Probe::new("project", || {
+            project::Project::detect().map(|project| project.prompt_note())
+        }));
+        probes.push(probes::Probe::new(".env", dotenv::prompt_note));
+    }
+    notes.extend(probes::run(
+        probes,
+        Duration::from_millis(config.latency.context_timeout_ms),
+    ));
+
+    for note in notes {
+        system_prompt.push(' ');
+        system_prompt.push_str(&note);
+    }
+    if let Some(context) = context::gather(&config.contexts, &cli.context)? {
+        system_prompt.push_str("\n\n");
+        system_prompt.push_str(&context);
+    }
+    if let Some(attached) = attach::prompt_block(&cli.attachments) {
+        system_prompt.push_str("\n\n");
+        system_prompt.push_str(&attached);
+    }
+    Ok(system_prompt)
+}
+
+/// What to do with the proposed command.
+enum Decision {
+    Run,
+    Abort,
+    Copy,
+    Edit,
+    Refine,
+    Regenerate,
+    Back,
+    Strike,
+    Safer,
+    Inspect,
+    Explain,
+}
+
+/// Actions the confirmation prompt offers only for some commands.
+#[derive(Clone, Copy)]
+struct Offers {
+    /// Striking parts of a command chained with `&&`, `;` or pipes.
+    strike: bool,
+    /// Switching to the safer variant.
+    safer: bool,
+    /// Showing the request and raw response behind the command.
+    inspect: bool,
+}
+
+fn confirm_run(policy: &config::ConfirmPolicy, offers: Offers) -> Result<Decision> {
+    let mut actions = vec![
+        "e edit",
+        "r refine",
+        "g regenerate",
+        "b back",
+        "x explain",
+        "c copy",
+    ];
+    if offers.strike {
+        actions.push("s strike parts");
+    }
+    if offers.safer {
+        actions.push("v safer variant");
+    }
+    if offers.inspect {
+        actions.push("i inspect response");
+    }
+    let actions = format!("({})", actions.join(", "));
+    let prompt = match (policy.style, policy.default) {
+        (config::PromptStyle::Typed, _) => {
+            format!("Type \"yes\" to run this command {}: ", actions)
+        }
+        (config::PromptStyle::YesNo, config::Answer::Yes) => {
+            format!("Run this command? [Y/n] {}: ", actions)
+        }
+        (config::PromptStyle::YesNo, config::Answer::No) => {
+            format!("Run this command? [y/N] {}: ", actions)
+        }
+    };
+    let input = ui::read_line(&prompt)?.to_lowercase();
+    let run = match policy.style {
+        config::PromptStyle::Typed => input == "yes",
+        config::PromptStyle::YesNo => {
+            input == "y"
+                || input == "yes"
+                || input.is_empty() && policy.default == config::Answer::Yes
+        }
+    };
+    Ok(match input.as_str() {
+        _ if run => Decision::Run,
+        "e" => Decision::Edit,
+        "r" => Decision::Refine,
+        "g" => Decision::Regenerate,
+        "b" => Decision::Back,
+        "x" => Decision::Explain,
+        "c" => Decision::Copy,
+        "s" if offers.strike => Decision::Strike,
+        "v" if offers.safer => Decision::Safer,
+        "i" if offers.inspect => Decision::Inspect,
+        _ => Decision::Abort,
+    })
+}
+
+/// List the parts of a chained command and let the user strike some, returning
+/// the command rebuilt from the rest along with a note for the session.
+fn strike_parts(command: &str) -> Result<Option<(String, String)>> {
+    let segments = cmdline::segments(command);
+    ui::say!("\nParts:");
+    for (i, segment) in segments.iter().enumerate() {
+        let op = segment.op.map(|op| format!("  {}", op)).unwrap_or_default();
+        ui::say!("  [{}] {}{}", i + 1, &command[segment.span.clone()], op);
+    }
+    let input = ui::read_line("Parts to strike (e.g. 2 3): ")?;
+    let mut struck = Vec::new();
+    for word in input.split([' ', ',']).filter(|w| !w.is_empty()) {
+        match word.parse::<usize>() {
+            Ok(n) if (1..=segments.len()).contains(&n) => struck.push(n - 1),
+            _ => {
+                ui::say!("There is no part {}.", word);
+                return Ok(None);
+            }
+        }
+    }
+    let keep: Vec<usize> = (0..segments.len())
+        .filter(|i| !struck.contains(i))
+        .collect();
+    if struck.is_empty() {
+        return Ok(None);
+    }
+    if keep.is_empty() {
+        ui::say!("That strikes every part; nothing would be left to run.");
+        return Ok(None);
+    }
+    struck.sort_unstable();
+    struck.dedup();
+    let numbers: Vec<String> = struck.iter().map(|i| (i + 1).to_string()).collect();
+    let noun = if numbers.len() == 1 { "part" } else { "parts" };
+    let note = format!("struck {} {}", noun, numbers.join(", "));
+    Ok(Some((cmdline::join(command, &segments, &keep), note)))
+}
+
+/// With `--eval`, give the confirmed command to the shell function that
+/// called us to run in the user's own shell. Its exit status never comes
+/// back, so post-exec hooks don't run.
+fn hand_to_shell(
+    config: &config::Config,
+    shell: shell::Shell,
+    entry: &mut history::HistoryEntry,
+    risk: risk::RiskLevel,
+) -> Result<ExitCode> {
+    if pre_exec(config, shell, entry, risk)?.is_none() {
+        return Ok(ExitCode::FAILURE);
+    }
+    entry.executed = true;
+    record_history(entry);
+    ui::emit(&entry.command)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Ask the pre-exec hook about the command in `entry`, returning the event
+/// it was sent, or `None`, with the command recorded as not run, when it
+/// vetoes it.
+fn pre_exec(
+    config: &config::Config,
+    shell: shell::Shell,
+    entry: &history::HistoryEntry,
+    risk: risk::RiskLevel,
+) -> Result<Option<serde_json::Value>> {
+    let event = serde_json::json!({
+        "event": "pre_exec",
+        "prompt": entry.prompt,
+        "command": entry.command,
+        "shell": shell.binary(),
+        "risk": risk.to_string(),
+        "cwd": std::env::current_dir().ok(),
+    });
+    if !config.hooks.pre_exec(&event)? {
+        record_history(entry);
+        ui::say!("The pre-exec hook vetoed this command; not executed.");
+        return Ok(None);
+    }
+    Ok(Some(event))
+}
+
+/// Run the command in `entry` between the configured hooks, recording it in
+/// history. Returns `None` when the pre-exec hook vetoes it.
+fn execute(
+    config: &config::Config,
+    shell: shell::Shell,
+    entry: &mut history::HistoryEntry,
+    risk: risk::RiskLevel,
+) -> Result<Option<ExitStatus>> {
+    let Some(mut event) = pre_exec(config, shell, entry, risk)? else {
+        return Ok(None);
+    };
+
+    let outcome = run_command(shell, &entry.command)?;
+    let status = outcome.status;
+    let output_path = match outcome.output.as_deref().map(session::save_output) {
+        Some(Ok(path)) => path,
+        Some(Err(err)) => {
+            eprintln!("Warning: {:#}", err);
+            None
+        }
+        None => None,
+    };
+    entry.executed = true;
+    entry.exit_code = status.code();
+    record_history(entry);
+    // A command stopped because the run was cancelled ends the run here.
+    cancel::check()?;
+
+    event["event"] = "post_exec".into();
+    event["exit_code"] = status.code().into();
+    event["output_path"] = serde_json::json!(output_path);
+    if let Err(err) = config.hooks.post_exec(&event) {
+        eprintln!("Warning: {:#}", err);
+    }
+    Ok(Some(status))
+}
+
+/// Run the command on each `--target ssh:` host in turn, asking before each
+/// one unless `unattended`, and finish with a table of how it went on each.
+fn fan_out(
+    config: &config::Config,
+    shell: shell::Shell,
+    entry: &history::HistoryEntry,
+    risk: risk::RiskLevel,
+    unattended: bool,
+) -> Result<ExitCode> {
+    let hosts = ssh::targets();
+    let mut all = unattended;
+    let mut results: Vec<(&str, String)> = Vec::new();
+    let mut failed = false;
+    for (i, host) in hosts.iter().enumerate() {
+        ssh::select(i);
+        ui::say!("\n{}:", host.destination);
+        for mismatch in host.mismatches(&hosts[0], &entry.command) {
+            ui::say!("  - {}", mismatch);
+        }
+        if !all {
+            let key = ui::read_key("Run here? [Y/n/a/q] (a all the rest, q stop): ")?;
+            match key.to_ascii_lowercase() {
+                'n' => {
+                    results.push((&host.destination, "skipped".to_string()));
+                    continue;
+                }
+                'q' => break,
+                'a' => all = true,
+                _ => {}
+            }
+        }
+        let mut entry = entry.clone();
+        entry.host = Some(host.destination.clone());
+        let result = match execute(config, shell, &mut entry, risk)? {
+            Some(status) if status.success() => "ok".to_string(),
+            Some(status) => {
+                failed = true;
+                match status.code() {
+                    Some(code) => format!("failed (exit {})", code),
+                    None => "killed".to_string(),
+                }
+            }
+            None => "vetoed".to_string(),
+        };
+        results.push((&host.destination, result));
+    }
+    for host in &hosts[results.len()..] {
+        results.push((&host.destination, "not run".to_string()));
+    }
+    let width = hosts.iter().map(|h| h.destination.len()).max().unwrap_or(0);
+    ui::say!();
+    for (host, result) in &results {
+        ui::say!("{:width$}  {}", host, result, width = width);
+    }
+    Ok(if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+fn run_command(shell: shell::Shell, command: &str) -> Result<exec::Outcome> {
+    let place = match ssh::target() {
+        Some(host) => {
+            if !host.known {
+                ui::say!(
+                    "{} is not in known_hosts yet; ssh will ask you to check its key.",
+                    host.hostname
+                );
+            }
+            format!(" on {}", host.destination)
+        }
+        None => String::new(),
+    };
+    if is_script(command) {
+        ui::say!("Executing the script{} with {}", place, shell.binary());
+    } else {
+        ui::say!("Executing{}: {}", place, command);
+    }
+    exec::run(shell, command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes_that_dont_fit_become_255() {
+        assert_eq!(clamp(0), 0);
+        assert_eq!(clamp(1), 1);
+        assert_eq!(clamp(255), 255);
+        assert_eq!(clamp(256), 255);
+        assert_eq!(clamp(-1073741819), 255);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn exit_codes_follow_the_command() {
+        use std::os::unix::process::ExitStatusExt;
+        // Wait statuses: the exit code in the second byte, or the signal.
+        let code = |raw| exit_code(ExitStatus::from_raw(raw));
+        assert_eq!(code(0), ExitCode::SUCCESS);
+        assert_eq!(code(1 << 8), ExitCode::from(1));
+        assert_eq!(code(255 << 8), ExitCode::from(255));
+        assert_eq!(code(libc::SIGKILL), ExitCode::from(137));
+        assert_eq!(code(libc::SIGTERM), ExitCode::from(143));
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn exit_codes_follow_the_command() {
+        use std::os::windows::process::ExitStatusExt;
+        let code = |raw| exit_code(ExitStatus::from_raw(raw));
+        assert_eq!(code(0), ExitCode::SUCCESS);
+        assert_eq!(code(1), ExitCode::from(1));
+        assert_eq!(code(255), ExitCode::from(255));
+        // Too large for an exit code, and 256 must not wrap to success.
+        assert_eq!(code(256), ExitCode::from(255));
+        assert_eq!(code(0xC000_0005), ExitCode::from(255));
+    }
+}
@@ -0,0 +1,106 @@
+use crate::wsl;
+use anyhow::{Context, Result};
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// Clipboard programs to try, in order, with their arguments.
+const PROGRAMS: &[(&str, &[&str])] = &[
+    ("pbcopy", &[]),
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+    ("clip.exe", &[]),
+    ("termux-clipboard-set", &[]),
+];
+
+/// Put `text` on the system clipboard. Without a clipboard program the
+/// terminal is asked to do it with an OSC 52 escape, which also reaches the
+/// local clipboard from an SSH session in terminals that support it.
+pub fn copy(text: &str) -> Result<()> {
+    let mut programs: Vec<&(&str, &[&str])> = PROGRAMS.iter().collect();
+    if wsl::detect().is_some() {
+        // The Windows clipboard rather than WSLg's, which few Windows
+        // programs see.
+        programs.sort_by_key(|(program, _)| *program != "clip.exe");
+    }
+    for (program, args) in programs {
+        let Ok(mut child) = Command::new(program)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        else {
+            continue;
+        };
+        // A program with nothing to talk to, as wl-copy outside Wayland or
+        // xclip without a display, exits before reading: try the next.
+        if let Some(mut stdin) = child.stdin.take()
+            && stdin.write_all(text.as_bytes()).is_err()
+        {
+            let _ = child.wait();
+            continue;
+        }
+        if child
+            .wait()
+            .with_context(|| format!("Failed to wait for {}", program))?
+            .success()
+        {
+            return Ok(());
+        }
+    }
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", base64(text.as_bytes()))?;
+    stdout.flush()?;
+    Ok(())
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_base64_with_padding() {
+        // The test vectors from RFC 4648.
+        let cases = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (text, encoded) in cases {
+            assert_eq!(base64(text.as_bytes()), encoded, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn encodes_base64_of_any_byte() {
+        assert_eq!(base64(&[0xff, 0xfe, 0x00]), "//4A");
+        assert_eq!(
+            base64("ls -la | grep é".as_bytes()),
+            "bHMgLWxhIHwgZ3JlcCDDqQ=="
+        );
+    }
+}
@@ -0,0 +1,426 @@
+use std::ops::Range;
+
+/// How a segment is joined to the one after it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operator {
+    And,
+    Or,
+    Seq,
+    Pipe,
+    Background,
+}
+
+impl std::fmt::Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Operator::And => "&&",
+            Operator::Or => "||",
+            Operator::Seq => ";",
+            Operator::Pipe => "|",
+            Operator::Background => "&",
+        })
+    }
+}
+
+/// A redirection such as `> out.txt` or `2>> err.log`.
+#[derive(Clone, Debug)]
+pub struct Redirect {
+    pub op: String,
+    pub target: String,
+}
+
+impl Redirect {
+    /// Whether the redirection writes to its target.
+    pub fn writes(&self) -> bool {
+        self.op.contains('>') && !self.op.ends_with('&')
+    }
+}
+
+/// One simple command of a command line, split at `&&`, `||`, `;`, `|` and `&`.
+#[derive(Clone, Debug)]
+pub struct Segment {
+    /// Byte range of the segment in the original command line.
+    pub span: Range<usize>,
+    /// Words with quotes removed and redirections taken out.
+    pub words: Vec<String>,
+    pub redirects: Vec<Redirect>,
+    /// Operator joining this segment to the next one, if any.
+    pub op: Option<Operator>,
+}
+
+/// One word of a segment: where it sits in the command line and what the shell sees.
+pub struct Word {
+    pub span: Range<usize>,
+    pub value: String,
+}
+
+impl Segment {
+    /// The segment's words as written in `command`, wrappers and redirections
+    /// included, with their byte ranges so they can be edited in place.
+    pub fn raw_words(&self, command: &str) -> Vec<Word> {
+        let start = self.span.start;
+        word_spans(&command[self.span.clone()])
+            .into_iter()
+            .map(|span| {
+                let span = span.start + start..span.end + start;
+                let raw = &command[span.clone()];
+                let value = shlex::split(raw)
+                    .and_then(|w| w.into_iter().next())
+                    .unwrap_or_else(|| raw.to_string());
+                Word { span, value }
+            })
+            .collect()
+    }
+
+    /// The program being run, skipping `sudo`, `env` and variable assignments.
+    pub fn program(&self) -> Option<&str> {
+        self.args_from_program().first().map(String::as_str)
+    }
+
+    /// Words starting at the program, with any wrapper prefixes removed.
+    pub fn args_from_program(&self) -> &[String] {
+        let mut rest = self.words.as_slice();
+        while let Some(first) = rest.first() {
+            let is_assignment = first.split_once('=').is_some_and(|(name, _)| {
+                !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+            });
+            let is_wrapper = matches!(
+                first.as_str(),
+                "sudo" | "doas" | "env" | "nice" | "nohup" | "time" | "command" | "exec"
+            );
+            if is_assignment || is_wrapper {
+                rest = &rest[1..];
+                // Skip the wrapper's own flags such as `sudo -u root` or `nice -n 19`.
+                while let Some(flag) = rest.first().filter(|w| is_wrapper && w.starts_with('-')) {
+                    let takes_value = matches!(flag.as_str(), "-u" | "-g" | "-n");
+                    rest = &rest[1..];
+                    if takes_value && !rest.is_empty() {
+                        rest = &rest[1..];
+                    }
+                }
+            } else {
+                break;
+            }
+        }
+        rest
+    }
+
+    /// Whether the segment runs with elevated privileges.
+    pub fn elevated(&self) -> bool {
+        self.words
+            .first()
+            .is_some_and(|w| w == "sudo" || w == "doas")
+    }
+}
+
+/// Split a command line into simple commands, respecting quotes, escapes and
+/// `$(...)` substitutions.
+pub fn segments(command: &str) -> Vec<Segment> {
+    let mut result = Vec::new();
+    let mut chars = command.char_indices().peekable();
+    let mut seg_start = 0;
+    let mut prev = None;
+    let mut single = false;
+    let mut double = false;
+    let mut depth = 0usize;
+
+    while let Some((i, c)) = chars.next() {
+        let last = prev.replace(c);
+        if single {
+            single = c != '\'';
+            continue;
+        }
+        match c {
+            '\\' => {
+                chars.next();
+                prev = None;
+                continue;
+            }
+            '\'' if !double => single = true,
+            '"' => double = !double,
+            '(' if last == Some('$') || depth > 0 => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            _ => {}
+        }
+        if double || depth > 0 {
+            continue;
+        }
+
+        let next = chars.peek().map(|&(_, n)| n);
+        let op = match c {
+            '&' if next == Some('&') => Some(Operator::And),
+            '|' if next == Some('|') => Some(Operator::Or),
+            '|' => Some(Operator::Pipe),
+            // `>&` and `&>` are redirections, not background jobs.
+            '&' if last == Some('>') || next == Some('>') => None,
+            '&' => Some(Operator::Background),
+            ';' | '\n' => Some(Operator::Seq),
+            _ => None,
+        };
+        if let Some(op) = op {
+            if matches!(op, Operator::And | Operator::Or) {
+                chars.next();
+            }
+            push_segment(&mut result, command, seg_start..i, Some(op));
+            seg_start = chars.peek().map_or(command.len(), |&(j, _)| j);
+            prev = None;
+        }
+    }
+    push_segment(&mut result, command, seg_start..command.len(), None);
+
+    // A trailing operator has nothing to join; keep only real separators.
+    if let Some(last) = result.last_mut()
+        && last.op != Some(Operator::Background)
+    {
+        last.op = None;
+    }
+    result
+}
+
+/// Rebuild `command` from the segments at `keep` (in order). Each kept segment
+/// joins the next one with the operator that came right before that one, so a
+/// later part keeps its relation to what ran before: dropping `b` from
+/// `a && b; c` leaves `a; c`.
+pub fn join(command: &str, segments: &[Segment], keep: &[usize]) -> String {
+    let mut joined = String::new();
+    for (n, &i) in keep.iter().enumerate() {
+        joined.push_str(&command[segments[i].span.clone()]);
+        match keep.get(n + 1) {
+            Some(&next) => {
+                let op = segments[next - 1].op.unwrap_or(Operator::Seq);
+                if op == Operator::Seq {
+                    joined.push_str("; ");
+                } else {
+                    joined.push_str(&format!(" {} ", op));
+                }
+            }
+            None if segments[i].op == Some(Operator::Background) => joined.push_str(" &"),
+            None => {}
+        }
+    }
+    joined
+}
+
+/// Byte ranges of the words in `text`, split on whitespace outside quotes, with
+/// their quoting left in place so they can be edited without re-quoting.
+pub fn word_spans(text: &str) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    let mut single = false;
+    let mut double = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' if !single => escaped = true,
+            '\'' if !double => single = !single,
+            '"' if !single => double = !double,
+            c if c.is_whitespace() && !single && !double => {
+                if let Some(s) = start.take() {
+                    spans.push(s..i);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        start.get_or_insert(i);
+    }
+    if let Some(s) = start {
+        spans.push(s..text.len());
+    }
+    spans
+}
+
+fn push_segment(
+    result: &mut Vec<Segment>,
+    command: &str,
+    span: Range<usize>,
+    op: Option<Operator>,
+) {
+    let raw = &command[span.clone()];
+    let text = raw.trim();
+    if text.is_empty() {
+        // `a ;; b` or a leading separator: fold the operator into the previous segment.
+        if let (Some(last), Some(op)) = (result.last_mut(), op) {
+            last.op = Some(op);
+        }
+        return;
+    }
+    let start = span.start + (raw.len() - raw.trim_start().len());
+    let (words, redirects) = split_words(text);
+    result.push(Segment {
+        span: start..start + text.len(),
+        words,
+        redirects,
+        op,
+    });
+}
+
+fn split_words(text: &str) -> (Vec<String>, Vec<Redirect>) {
+    let raw =
+        shlex::split(text).unwrap_or_else(|| text.split_whitespace().map(str::to_string).collect());
+    let mut words = Vec::new();
+    let mut redirects = Vec::new();
+    let mut iter = raw.into_iter();
+
+    while let Some(word) = iter.next() {
+        // A file descriptor, then the operator: `2>&1` duplicates onto 1
+        // rather than taking the next word as its target.
+        let fd_len = word.chars().take_while(char::is_ascii_digit).count();
+        let op_len = fd_len
+            + word[fd_len..]
+                .chars()
+                .take_while(|c| matches!(c, '>' | '<' | '&'))
+                .count();
+        let (op, target) = word.split_at(op_len);
+        if op.contains('>') || op.contains('<') {
+            let target = if target.is_empty() {
+                iter.next().unwrap_or_default()
+            } else {
+                target.to_string()
+            };
+            redirects.push(Redirect {
+                op: op.to_string(),
+                target,
+            });
+        } else {
+            words.push(word);
+        }
+    }
+    (words, redirects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(command: &str) -> Vec<Vec<String>> {
+        segments(command).into_iter().map(|s| s.words).collect()
+    }
+
+    fn ops(command: &str) -> Vec<Option<Operator>> {
+        segments(command).iter().map(|s| s.op).collect()
+    }
+
+    #[test]
+    fn splits_at_each_operator() {
+        let command = "a && b || c; d | e & f\ng";
+        assert_eq!(
+            words(command),
+            [["a"], ["b"], ["c"], ["d"], ["e"], ["f"], ["g"]]
+        );
+        assert_eq!(
+            ops(command),
+            [
+                Some(Operator::And),
+                Some(Operator::Or),
+                Some(Operator::Seq),
+                Some(Operator::Pipe),
+                Some(Operator::Background),
+                Some(Operator::Seq),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn operators_in_quotes_escapes_and_substitutions_are_words() {
+        let split = segments(r#"echo 'a && b' "c; d | e" $(f || g) h\;i"#);
+        assert_eq!(split.len(), 1);
+        assert_eq!(split[0].words[1], "a && b");
+        assert_eq!(split[0].words[2], "c; d | e");
+        assert_eq!(split[0].words.last().unwrap(), "h;i");
+        assert_eq!(segments("echo $(a; $(b && c)) && d").len(), 2);
+    }
+
+    #[test]
+    fn only_a_trailing_background_is_kept() {
+        assert_eq!(ops("sleep 10 &"), [Some(Operator::Background)]);
+        assert_eq!(ops("ls;"), [None]);
+        assert_eq!(ops("ls &&"), [None]);
+        assert_eq!(words("a ;; b"), [["a"], ["b"]]);
+        assert!(segments("  ").is_empty());
+    }
+
+    #[test]
+    fn spans_point_at_the_text_of_each_segment() {
+        let command = "  ls -l  &&  pwd ";
+        let split = segments(command);
+        assert_eq!(&command[split[0].span.clone()], "ls -l");
+        assert_eq!(&command[split[1].span.clone()], "pwd");
+    }
+
+    #[test]
+    fn redirects_are_taken_out_of_the_words() {
+        let split = segments("sort < in.txt > out.txt 2>> err.log 2>&1 &> all.log");
+        assert_eq!(split.len(), 1);
+        assert_eq!(split[0].words, ["sort"]);
+        let redirects: Vec<(&str, &str, bool)> = split[0]
+            .redirects
+            .iter()
+            .map(|r| (r.op.as_str(), r.target.as_str(), r.writes()))
+            .collect();
+        assert_eq!(
+            redirects,
+            [
+                ("<", "in.txt", false),
+                (">", "out.txt", true),
+                ("2>>", "err.log", true),
+                ("2>&", "1", false),
+                ("&>", "all.log", true),
+            ]
+        );
+        assert_eq!(
+            segments(">out.txt echo hi")[0].redirects[0].target,
+            "out.txt"
+        );
+    }
+
+    #[test]
+    fn the_program_comes_after_wrappers_and_assignments() {
+        let split = segments("sudo -u postgres env PGUSER=app nice -n 19 psql -c 'select 1'");
+        assert_eq!(split[0].program(), Some("psql"));
+        assert_eq!(split[0].args_from_program(), ["psql", "-c", "select 1"]);
+        assert_eq!(segments("LANG=C sort f")[0].program(), Some("sort"));
+        assert_eq!(segments("FOO=1")[0].program(), None);
+        assert_eq!(segments("exec nohup ./run")[0].program(), Some("./run"));
+    }
+
+    #[test]
+    fn only_sudo_and_doas_elevate() {
+        assert!(segments("sudo rm x")[0].elevated());
+        assert!(segments("doas rm x")[0].elevated());
+        assert!(!segments("echo sudo")[0].elevated());
+        assert!(!segments("env sudo=1 rm x")[0].elevated());
+    }
+
+    #[test]
+    fn join_keeps_the_operator_before_each_kept_segment() {
+        let command = "a && b; c | d &";
+        let split = segments(command);
+        assert_eq!(join(command, &split, &[0, 2]), "a; c");
+        assert_eq!(join(command, &split, &[0, 1]), "a && b");
+        assert_eq!(join(command, &split, &[2, 3]), "c | d &");
+        assert_eq!(join(command, &split, &[1, 3]), "b | d &");
+    }
+
+    #[test]
+    fn word_spans_keep_the_quoting() {
+        let text = r#"echo  "a b" 'c d' e\ f"#;
+        let spans: Vec<&str> = word_spans(text).into_iter().map(|s| &text[s]).collect();
+        assert_eq!(spans, ["echo", r#""a b""#, "'c d'", r"e\ f"]);
+    }
+
+    #[test]
+    fn raw_words_carry_their_place_and_value() {
+        let command = "ls; sudo rm -rf 'my dir'";
+        let split = segments(command);
+        let raw = split[1].raw_words(command);
+        let values: Vec<&str> = raw.iter().map(|w| w.value.as_str()).collect();
+        assert_eq!(values, ["sudo", "rm", "-rf", "my dir"]);
+        assert_eq!(&command[raw[3].span.clone()], "'my dir'");
+    }
+}
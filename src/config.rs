@@ -0,0 +1,332 @@
+use crate::analytics;
+use crate::api::{self, Provider};
+use crate::attach;
+use crate::cache;
+use crate::context;
+use crate::hooks::Hooks;
+use crate::latency;
+use crate::net;
+use crate::paths;
+use crate::postprocess;
+use crate::risk::{self, RiskLevel};
+use crate::shell::Shell;
+use crate::summarize;
+use crate::template;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// Settings read from `config.toml` in the config directory.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Answer very common requests from built-in templates without calling the API.
+    pub local_intents: bool,
+    /// Tell the model what kind of project the current directory holds and
+    /// which tasks it defines.
+    pub project_context: bool,
+    /// Tell the model the OS, architecture, shell, working directory and which
+    /// common tools are installed.
+    pub environment_context: bool,
+    /// Explain programs and flags that no command you ran has used before.
+    pub explain_new: bool,
+    /// Have the model answer in JSON with the command, what it does and how
+    /// risky it is, where the API can hold it to a schema.
+    pub structured_output: bool,
+    /// How to ask before running a command, per risk tier.
+    pub confirm: ConfirmConfig,
+    /// Rewrites applied to every proposed command, in order.
+    pub postprocess: Vec<postprocess::Step>,
+    /// Commands to run before and after executing a command.
+    pub hooks: Hooks,
+    /// Directory trees commands may modify files in; empty allows any.
+    pub workspace: Vec<String>,
+    /// Models to ask in turn when the one asked fails or answers with nothing.
+    pub fallbacks: Vec<Fallback>,
+    /// Replace the built-in system prompt. Notes on the shell, local tools and
+    /// context bundles are still added after it.
+    pub system_prompt: Option<String>,
+    /// Named system prompts picked with `--template`, added to the built-in ones.
+    pub templates: BTreeMap<String, template::Template>,
+    /// Named sets of defaults picked with `--profile`.
+    pub profiles: BTreeMap<String, Profile>,
+    /// Named bundles of context attached with `--context`.
+    pub contexts: BTreeMap<String, context::Bundle>,
+    /// What each model costs, for `llmwrap digest`.
+    pub prices: BTreeMap<String, Price>,
+    /// Where to send anonymous usage counts, if anywhere.
+    pub analytics: analytics::Settings,
+    /// The proxy to reach the API and downloads through.
+    pub proxy: net::ProxySettings,
+    /// How to look up and connect to hosts.
+    pub network: net::NetworkSettings,
+    /// What to do when the model is slow to answer.
+    pub latency: latency::Settings,
+    /// How long generated commands are reused.
+    pub cache: cache::Settings,
+    /// Extra patterns that raise a command's risk tier.
+    pub risk: risk::Settings,
+    /// How `llmwrap summarize` splits output too long for one request.
+    pub summarize: summarize::Settings,
+    /// How much of an attached file or piped input is sent.
+    pub attach: attach::Settings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            local_intents: true,
+            project_context: true,
+            environment_context: true,
+            explain_new: true,
+            structured_output: true,
+            confirm: ConfirmConfig::default(),
+            postprocess: Vec::new(),
+            hooks: Hooks::default(),
+            workspace: Vec::new(),
+            fallbacks: Vec::new(),
+            system_prompt: None,
+            templates: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            contexts: BTreeMap::new(),
+            prices: BTreeMap::new(),
+            analytics: analytics::Settings::default(),
+            proxy: net::ProxySettings::default(),
+            network: net::NetworkSettings::default(),
+            latency: latency::Settings::default(),
+            cache: cache::Settings::default(),
+            risk: risk::Settings::default(),
+            summarize: summarize::Settings::default(),
+            attach: attach::Settings::default(),
+        }
+    }
+}
+
+/// A `[profiles.NAME]` table. Settings it leaves out keep the top-level value,
+/// and command-line flags still win over it.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub provider: Option<Provider>,
+    pub model: Option<String>,
+    pub api_base: Option<String>,
+    /// Read the API key from this environment variable instead of the
+    /// provider's usual one.
+    pub api_key_env: Option<String>,
+    /// Run this command and use what it prints as the API key, e.g.
+    /// `op read op://work/openai/key`.
+    pub api_key_command: Option<String>,
+    /// Replace the built-in system prompt. Notes on the shell, local tools and
+    /// context bundles are still added after it.
+    pub system_prompt: Option<String>,
+    pub shell: Option<Shell>,
+    /// Print commands as JSON instead of running them, as with `--json`.
+    pub json: Option<bool>,
+    /// Run commands that would run on Enter without asking, as with `--yes`.
+    pub yes: Option<bool>,
+    /// Refuse commands that change anything, as with `--read-only`.
+    pub read_only: Option<bool>,
+    /// Context bundles to attach when `--context` is not given.
+    pub context: Option<Vec<String>>,
+    pub local_intents: Option<bool>,
+    pub confirm: Option<ConfirmConfig>,
+    pub postprocess: Option<Vec<postprocess::Step>>,
+    pub hooks: Option<Hooks>,
+    pub workspace: Option<Vec<String>>,
+    pub fallbacks: Option<Vec<Fallback>>,
+}
+
+/// A `[[fallbacks]]` table. The provider defaults to the one asked first,
+/// and so do its API base and key when the provider is the same.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Fallback {
+    pub provider: Option<Provider>,
+    pub model: String,
+    pub api_base: Option<String>,
+    pub api_key_env: Option<String>,
+    pub api_key_command: Option<String>,
+}
+
+impl Fallback {
+    /// A client for this fallback to the model `primary` asks.
+    pub fn client(&self, primary: &api::Client) -> Result<api::Client> {
+        let provider = self.provider.unwrap_or(primary.provider());
+        let same = provider == primary.provider();
+        let key = match (&self.api_key_env, &self.api_key_command) {
+            (Some(_), Some(_)) => {
+                anyhow::bail!("it sets both api_key_env and api_key_command; keep one")
+            }
+            (Some(variable), None) => api::KeySource::Env(variable.clone()),
+            (None, Some(command)) => api::KeySource::Command(command.clone()),
+            (None, None) if same => primary.key_source().clone(),
+            (None, None) => api::KeySource::Provider,
+        };
+        let api_base = match &self.api_base {
+            Some(base) => base.as_str(),
+            None if same => primary.api_base(),
+            None => provider.default_base(),
+        };
+        api::Client::new(provider, &key, api_base, &self.model)
+    }
+}
+
+/// A `[prices.MODEL]` table, in US dollars per million tokens.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct Price {
+    pub input: f64,
+    pub output: f64,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfirmConfig {
+    pub low: ConfirmOverride,
+    pub medium: ConfirmOverride,
+    pub high: ConfirmOverride,
+}
+
+/// Per-tier settings; anything left out keeps the built-in default for that tier.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfirmOverride {
+    pub default: Option<Answer>,
+    pub style: Option<PromptStyle>,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Answer {
+    Yes,
+    No,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PromptStyle {
+    /// A `[Y/n]` or `[y/N]` prompt where Enter picks the default.
+    YesNo,
+    /// The full word "yes" must be typed.
+    Typed,
+}
+
+pub struct ConfirmPolicy {
+    pub default: Answer,
+    pub style: PromptStyle,
+}
+
+impl ConfirmConfig {
+    pub fn policy(&self, level: RiskLevel) -> ConfirmPolicy {
+        let (overrides, default, style) = match level {
+            RiskLevel::Low => (&self.low, Answer::Yes, PromptStyle::YesNo),
+            RiskLevel::Medium => (&self.medium, Answer::No, PromptStyle::YesNo),
+            RiskLevel::High => (&self.high, Answer::No, PromptStyle::Typed),
+        };
+        ConfirmPolicy {
+            default: overrides.default.unwrap_or(default),
+            style: overrides.style.unwrap_or(style),
+        }
+    }
+}
+
+/// Where `config.toml` is, when there is a config directory.
+pub fn path() -> Option<PathBuf> {
+    paths::config_dir().map(|dir| dir.join("config.toml"))
+}
+
+/// Notices when `config.toml` is written, created or removed, for sessions
+/// that pick up changes without a restart.
+pub struct Watch {
+    seen: Option<(SystemTime, u64)>,
+}
+
+impl Watch {
+    pub fn new() -> Watch {
+        Watch { seen: stamp() }
+    }
+
+    /// Whether the file changed since the last call, or since `new`.
+    pub fn changed(&mut self) -> bool {
+        let now = stamp();
+        let changed = now != self.seen;
+        self.seen = now;
+        changed
+    }
+}
+
+impl Default for Watch {
+    fn default() -> Self {
+        Watch::new()
+    }
+}
+
+/// When the file was last written and how long it is; the length catches two
+/// writes within the file system's clock resolution.
+fn stamp() -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path()?).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+impl Config {
+    /// Load the config file, falling back to defaults when it does not exist.
+    pub fn load() -> Result<Self> {
+        let Some(path) = path() else {
+            return Ok(Config::default());
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Config::default());
+            }
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", path.display()));
+            }
+        };
+        toml::from_str(&text).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    /// Apply the named profile's settings over the top-level ones, returning
+    /// the profile for its command-line defaults.
+    pub fn select_profile(&mut self, name: &str) -> Result<Profile> {
+        let Some(mut profile) = self.profiles.remove(name) else {
+            let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            if known.is_empty() {
+                anyhow::bail!("No profile named {}; config.toml defines none", name);
+            }
+            anyhow::bail!(
+                "No profile named {}; config.toml defines {}",
+                name,
+                known.join(", ")
+            );
+        };
+        if profile.api_key_env.is_some() && profile.api_key_command.is_some() {
+            anyhow::bail!(
+                "Profile {} sets both api_key_env and api_key_command; keep one",
+                name
+            );
+        }
+        if let Some(local_intents) = profile.local_intents {
+            self.local_intents = local_intents;
+        }
+        if let Some(confirm) = profile.confirm.take() {
+            self.confirm = confirm;
+        }
+        if let Some(postprocess) = profile.postprocess.take() {
+            self.postprocess = postprocess;
+        }
+        if let Some(hooks) = profile.hooks.take() {
+            self.hooks = hooks;
+        }
+        if let Some(fallbacks) = profile.fallbacks.take() {
+            self.fallbacks = fallbacks;
+        }
+        if let Some(workspace) = profile.workspace.take() {
+            self.workspace = workspace;
+        }
+        Ok(profile)
+    }
+}
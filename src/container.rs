@@ -0,0 +1,271 @@
+use crate::cmdline;
+use crate::environment;
+use crate::safer;
+use regex::Regex;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::{LazyLock, OnceLock};
+
+/// The image used to debug from outside, which has every tool in `TOOLS`.
+const TOOLBOX_IMAGE: &str = "nicolaka/netshoot";
+
+/// Tools for looking into a running service, with the package that provides
+/// each under apt, apk and dnf.
+const TOOLS: &[(&str, &str, &str, &str)] = &[
+    ("ps", "procps", "procps", "procps-ng"),
+    ("top", "procps", "procps", "procps-ng"),
+    ("ss", "iproute2", "iproute2", "iproute"),
+    ("ip", "iproute2", "iproute2", "iproute"),
+    ("netstat", "net-tools", "net-tools", "net-tools"),
+    ("curl", "curl", "curl", "curl"),
+    ("wget", "wget", "wget", "wget"),
+    ("nc", "netcat-openbsd", "netcat-openbsd", "nmap-ncat"),
+    ("dig", "dnsutils", "bind-tools", "bind-utils"),
+    ("nslookup", "dnsutils", "bind-tools", "bind-utils"),
+    ("strace", "strace", "strace", "strace"),
+    ("lsof", "lsof", "lsof", "lsof"),
+    ("tcpdump", "tcpdump", "tcpdump", "tcpdump"),
+    ("jq", "jq", "jq", "jq"),
+];
+
+/// What runs the container.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Runtime {
+    Docker,
+    Podman,
+    Kubernetes,
+    Other,
+}
+
+/// The container llmwrap runs in.
+pub struct Container {
+    pub runtime: Runtime,
+    /// The container's id as the runtime knows it, when it can be found.
+    pub id: Option<String>,
+    /// The pod, in Kubernetes, which is also the hostname there.
+    pub pod: Option<String>,
+    pub namespace: Option<String>,
+}
+
+static DETECTED: OnceLock<Option<Container>> = OnceLock::new();
+
+/// A 64-digit container id in a cgroup path or mount source.
+static CONTAINER_ID: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b[0-9a-f]{64}\b").unwrap());
+
+/// The container this is running in, if it is one.
+pub fn detect() -> Option<&'static Container> {
+    DETECTED
+        .get_or_init(|| {
+            let cgroup = fs::read_to_string("/proc/self/cgroup").unwrap_or_default();
+            let runtime = if std::env::var_os("KUBERNETES_SERVICE_HOST").is_some()
+                || cgroup.contains("kubepods")
+            {
+                Runtime::Kubernetes
+            } else if Path::new("/run/.containerenv").exists() || cgroup.contains("libpod") {
+                Runtime::Podman
+            } else if Path::new("/.dockerenv").exists() || cgroup.contains("docker") {
+                Runtime::Docker
+            } else if cgroup.contains("containerd") || std::env::var_os("container").is_some() {
+                Runtime::Other
+            } else {
+                return None;
+            };
+            // Under cgroup v2 the path is just `/`, but the runtime still
+            // mounts files from the container's own directory.
+            let mounts = fs::read_to_string("/proc/self/mountinfo").unwrap_or_default();
+            let id = [cgroup.as_str(), mounts.as_str()]
+                .into_iter()
+                .find_map(|text| CONTAINER_ID.find(text))
+                .map(|id| id.as_str()[..12].to_string());
+            let hostname = fs::read_to_string("/etc/hostname")
+                .ok()
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty());
+            let (pod, namespace) = if runtime == Runtime::Kubernetes {
+                let namespace =
+                    fs::read_to_string("/var/run/secrets/kubernetes.io/serviceaccount/namespace")
+                        .ok()
+                        .map(|ns| ns.trim().to_string());
+                (hostname.clone(), namespace)
+            } else {
+                (None, None)
+            };
+            Some(Container {
+                runtime,
+                // Docker names the host after the short id unless told otherwise.
+                id: id.or(hostname.filter(|_| runtime == Runtime::Docker)),
+                pod,
+                namespace,
+            })
+        })
+        .as_ref()
+}
+
+impl Container {
+    /// A paragraph for the system prompt in `llmwrap container-debug`: the
+    /// image's distribution, which debugging tools it has, its resource
+    /// limits, and a preference for looking before changing anything.
+    pub fn prompt_note(&self) -> String {
+        let mut facts = vec![format!("running under {}", self)];
+        if let Some(distribution) = environment::distribution() {
+            facts.push(format!("image: {}", distribution));
+        }
+        let (installed, missing): (Vec<&str>, Vec<&str>) = TOOLS
+            .iter()
+            .map(|(tool, ..)| *tool)
+            .partition(|tool| safer::on_path(tool));
+        if !installed.is_empty() {
+            facts.push(format!("debugging tools: {}", installed.join(", ")));
+        }
+        if !missing.is_empty() {
+            facts.push(format!("missing: {}", missing.join(", ")));
+        }
+        let limits = limits();
+        if !limits.is_empty() {
+            facts.push(format!("cgroup limits: {}", limits.join(", ")));
+        }
+        format!(
+            "The command runs inside a container being debugged: {}. Investigate with commands \
+that only read and report, such as /proc, ps, ss, env, cat and logs; do not restart processes, \
+change files or install anything unless the request asks for it. Where a missing tool would be \
+needed, prefer what /proc and the shell provide.",
+            facts.join("; ")
+        )
+    }
+
+    /// Debugging tools `command` runs that this container lacks.
+    pub fn missing_tools(&self, command: &str) -> Vec<&'static str> {
+        let mut missing = Vec::new();
+        for segment in cmdline::segments(command) {
+            let Some(program) = segment.program() else {
+                continue;
+            };
+            let Some((tool, ..)) = TOOLS.iter().find(|(tool, ..)| *tool == program) else {
+                continue;
+            };
+            if !safer::on_path(tool) && !missing.contains(tool) {
+                missing.push(*tool);
+            }
+        }
+        missing
+    }
+
+    /// The command that installs `tools` inside the container, for the
+    /// package manager it has. Whatever it adds is gone when the container
+    /// is recreated.
+    pub fn install(&self, tools: &[&str]) -> Option<String> {
+        let (manager, column) = if safer::on_path("apk") {
+            ("apk add", 1)
+        } else if safer::on_path("apt-get") {
+            ("apt-get update && apt-get install -y", 0)
+        } else if safer::on_path("dnf") {
+            ("dnf install -y", 2)
+        } else if safer::on_path("microdnf") {
+            ("microdnf install -y", 2)
+        } else {
+            return None;
+        };
+        let mut packages: Vec<&str> = Vec::new();
+        for (tool, apt, apk, dnf) in TOOLS {
+            let package = [*apt, *apk, *dnf][column];
+            if tools.contains(tool) && !packages.contains(&package) {
+                packages.push(package);
+            }
+        }
+        Some(format!("{} {}", manager, packages.join(" ")))
+    }
+
+    /// The command to run on the host for a throwaway toolbox that shares
+    /// this container's processes and network, leaving the image untouched.
+    pub fn sidecar(&self) -> Option<String> {
+        match self.runtime {
+            Runtime::Docker | Runtime::Podman => {
+                let program = if self.runtime == Runtime::Podman {
+                    "podman"
+                } else {
+                    "docker"
+                };
+                let id = self.id.as_ref()?;
+                Some(format!(
+                    "{program} run --rm -it --pid=container:{id} --network=container:{id} {TOOLBOX_IMAGE}"
+                ))
+            }
+            Runtime::Kubernetes => {
+                let pod = self.pod.as_ref()?;
+                let namespace = self
+                    .namespace
+                    .as_ref()
+                    .map(|ns| format!(" -n {}", ns))
+                    .unwrap_or_default();
+                Some(format!(
+                    "kubectl debug -it {pod}{namespace} --image={TOOLBOX_IMAGE} --target=CONTAINER"
+                ))
+            }
+            Runtime::Other => None,
+        }
+    }
+}
+
+impl fmt::Display for Container {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self.runtime {
+            Runtime::Docker => "Docker",
+            Runtime::Podman => "Podman",
+            Runtime::Kubernetes => "Kubernetes",
+            Runtime::Other => "a container runtime",
+        })?;
+        if let Some(pod) = &self.pod {
+            write!(f, " (pod {})", pod)?;
+        } else if let Some(id) = &self.id {
+            write!(f, " (container {})", id)?;
+        }
+        Ok(())
+    }
+}
+
+/// Memory, CPU and process limits from cgroup v2, or v1 where that is
+/// what's mounted, leaving out the ones that aren't set.
+fn limits() -> Vec<String> {
+    let read = |path: &str| {
+        fs::read_to_string(Path::new("/sys/fs/cgroup").join(path))
+            .ok()
+            .map(|value| value.trim().to_string())
+    };
+    let mut limits = Vec::new();
+    if let Some(max) = read("memory.max").or_else(|| read("memory/memory.limit_in_bytes"))
+        && let Ok(bytes) = max.parse::<u64>()
+        // v1 reports no limit as a number near u64::MAX.
+        && bytes < 1 << 62
+    {
+        let used = read("memory.current")
+            .or_else(|| read("memory/memory.usage_in_bytes"))
+            .and_then(|used| used.parse::<u64>().ok())
+            .map(|used| format!(" ({} in use)", mebibytes(used)))
+            .unwrap_or_default();
+        limits.push(format!("memory {}{}", mebibytes(bytes), used));
+    }
+    let cpu = read("cpu.max")
+        .and_then(|max| {
+            let (quota, period) = max.split_once(' ')?;
+            Some((quota.parse::<f64>().ok()?, period.parse::<f64>().ok()?))
+        })
+        .or_else(|| {
+            let quota = read("cpu/cpu.cfs_quota_us")?.parse::<f64>().ok()?;
+            let period = read("cpu/cpu.cfs_period_us")?.parse::<f64>().ok()?;
+            Some((quota, period)).filter(|(quota, _)| *quota > 0.0)
+        });
+    if let Some((quota, period)) = cpu.filter(|(_, period)| *period > 0.0) {
+        limits.push(format!("{:.2} CPUs", quota / period));
+    }
+    if let Some(max) = read("pids.max").or_else(|| read("pids/pids.max"))
+        && max != "max"
+    {
+        limits.push(format!("{} processes", max));
+    }
+    limits
+}
+
+fn mebibytes(bytes: u64) -> String {
+    format!("{} MiB", bytes / (1024 * 1024))
+}
@@ -0,0 +1,84 @@
+use crate::shell::Shell;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::process::Stdio;
+
+/// How much of a context command's output is passed on; a runaway command
+/// shouldn't fill the prompt.
+const OUTPUT_LIMIT: usize = 2000;
+
+/// A `[contexts.NAME]` table in `config.toml`: what to tell the model when the
+/// bundle is attached with `--context NAME`.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Bundle {
+    /// Text passed on as written, e.g. which clusters may be touched.
+    pub notes: Option<String>,
+    /// Commands run when the bundle is attached, whose output is passed on,
+    /// e.g. `kubectl config current-context`.
+    pub commands: Vec<String>,
+}
+
+/// Collect the named bundles into a block for the system prompt. Commands that
+/// fail are reported and left out.
+pub fn gather(bundles: &BTreeMap<String, Bundle>, names: &[String]) -> Result<Option<String>> {
+    let mut sections = Vec::new();
+    for name in names {
+        let Some(bundle) = bundles.get(name) else {
+            let known: Vec<&str> = bundles.keys().map(String::as_str).collect();
+            if known.is_empty() {
+                anyhow::bail!("No context named {}; config.toml defines none", name);
+            }
+            anyhow::bail!(
+                "No context named {}; config.toml defines {}",
+                name,
+                known.join(", ")
+            );
+        };
+        let mut section = format!("[{}]", name);
+        if let Some(notes) = &bundle.notes {
+            section.push('\n');
+            section.push_str(notes.trim());
+        }
+        for command in &bundle.commands {
+            match run(command) {
+                Ok(output) => section.push_str(&format!("\n$ {}\n{}", command, output)),
+                Err(err) => eprintln!("Warning: context {}: {:#}", name, err),
+            }
+        }
+        sections.push(section);
+    }
+    if sections.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(format!(
+        "Context the user attached about their environment:\n{}",
+        sections.join("\n\n")
+    )))
+}
+
+/// Run a context command with the same shell hooks use, returning its trimmed
+/// standard output.
+fn run(command: &str) -> Result<String> {
+    let shell = if cfg!(windows) {
+        Shell::Pwsh
+    } else {
+        Shell::Sh
+    };
+    let output = shell
+        .command(command)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .with_context(|| format!("Failed to run `{}`", command))?;
+    if !output.status.success() {
+        anyhow::bail!("`{}` failed ({})", command, output.status);
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    let text = text.trim();
+    Ok(match text.char_indices().nth(OUTPUT_LIMIT) {
+        Some((end, _)) => format!("{}\n[output truncated]", &text[..end]),
+        None => text.to_string(),
+    })
+}
@@ -0,0 +1,138 @@
+use crate::config::Price;
+use crate::history::HistoryEntry;
+use crate::novelty;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// List at most this many commands, failures and new programs.
+const TOP: usize = 10;
+const DAY: u64 = 24 * 60 * 60;
+
+/// A Markdown summary of the history from the last `days` days: how much was
+/// asked and run, the commands run most, what failed, which programs were new
+/// and what the API calls cost.
+pub fn render(history: &[HistoryEntry], days: u64, prices: &BTreeMap<String, Price>) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let since = now.saturating_sub(days * DAY);
+    let start = history.partition_point(|h| h.timestamp < since);
+    let recent = &history[start..];
+
+    let mut out = String::new();
+    let _ = writeln!(out, "# llmwrap digest, {} to {}\n", date(since), date(now));
+    let run: Vec<&HistoryEntry> = recent.iter().filter(|h| h.executed).collect();
+    let failed: Vec<&HistoryEntry> = run
+        .iter()
+        .copied()
+        .filter(|h| h.exit_code.is_some_and(|code| code != 0))
+        .collect();
+    let _ = writeln!(
+        out,
+        "{} requests, {} commands run, {} failed.",
+        recent.len(),
+        run.len(),
+        failed.len()
+    );
+    if recent.is_empty() {
+        return out;
+    }
+
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for entry in &run {
+        *counts.entry(entry.command.as_str()).or_default() += 1;
+    }
+    let mut most: Vec<(&str, usize)> = counts.into_iter().collect();
+    most.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    if !most.is_empty() {
+        let _ = writeln!(out, "\n## Most run\n");
+        for (command, count) in most.iter().take(TOP) {
+            let _ = writeln!(out, "- `{}` ({}x)", command, count);
+        }
+    }
+
+    if !failed.is_empty() {
+        let _ = writeln!(out, "\n## Failures\n");
+        for entry in failed.iter().rev().take(TOP) {
+            let _ = writeln!(
+                out,
+                "- {}: `{}` exited {} (asked: {})",
+                date(entry.timestamp),
+                entry.command,
+                entry.exit_code.unwrap_or_default(),
+                entry.prompt
+            );
+        }
+    }
+
+    // Programs run this period that no earlier command ran.
+    let mut learned: Vec<(u64, String)> = Vec::new();
+    for (i, entry) in history.iter().enumerate().skip(start) {
+        if !entry.executed {
+            continue;
+        }
+        for item in novelty::find(&entry.command, &history[..i]) {
+            if item.flag.is_none() && !learned.iter().any(|(_, p)| *p == item.program) {
+                learned.push((entry.timestamp, item.program));
+            }
+        }
+    }
+    if !learned.is_empty() {
+        let _ = writeln!(out, "\n## New to you\n");
+        for (timestamp, program) in learned.iter().take(TOP) {
+            let _ = writeln!(out, "- `{}`, first run {}", program, date(*timestamp));
+        }
+    }
+
+    let mut by_model: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+    for entry in recent {
+        if let (Some(model), Some(usage)) = (&entry.model, &entry.usage) {
+            let tokens = by_model.entry(model.as_str()).or_default();
+            tokens.0 += usage.input_tokens;
+            tokens.1 += usage.output_tokens;
+        }
+    }
+    if !by_model.is_empty() {
+        let _ = writeln!(out, "\n## Cost\n");
+        let mut total = 0.0;
+        for (model, (input, output)) in &by_model {
+            let _ = write!(
+                out,
+                "- {}: {} input and {} output tokens",
+                model, input, output
+            );
+            match prices.get(*model) {
+                Some(price) => {
+                    let cost = (*input as f64 * price.input + *output as f64 * price.output) / 1e6;
+                    total += cost;
+                    let _ = writeln!(out, ", ${:.2}", cost);
+                }
+                None => {
+                    let _ = writeln!(out, ", no price set in config.toml");
+                }
+            }
+        }
+        if by_model.len() > 1 {
+            let _ = writeln!(out, "\nTotal: ${:.2}", total);
+        }
+    }
+    out
+}
+
+/// The UTC date of a Unix timestamp as YYYY-MM-DD.
+pub fn date(timestamp: u64) -> String {
+    // Howard Hinnant's days-to-civil algorithm.
+    let days = (timestamp / DAY) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
@@ -0,0 +1,237 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const TIMEOUT: Duration = Duration::from_secs(3);
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+
+/// Ask `server` for the A or AAAA records of `host` with one UDP query,
+/// bypassing the system resolver.
+pub fn lookup(server: SocketAddr, host: &str, ipv6: bool) -> io::Result<Vec<IpAddr>> {
+    let qtype = if ipv6 { TYPE_AAAA } else { TYPE_A };
+    let id = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u16)
+        .unwrap_or(0);
+
+    // Header: ID, recursion desired, one question.
+    let mut query = Vec::with_capacity(32 + host.len());
+    query.extend_from_slice(&id.to_be_bytes());
+    query.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(invalid(format!("{} is not a valid host name", host)));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&qtype.to_be_bytes());
+    query.extend_from_slice(&1u16.to_be_bytes());
+
+    let bind: SocketAddr = if server.is_ipv6() {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(bind)?;
+    socket.set_read_timeout(Some(TIMEOUT))?;
+    socket.connect(server)?;
+    socket.send(&query)?;
+    let mut reply = [0u8; 1500];
+    let len = socket.recv(&mut reply)?;
+    parse(&reply[..len], id, qtype)
+}
+
+/// The addresses of type `qtype` in the answer section of a reply.
+fn parse(reply: &[u8], id: u16, qtype: u16) -> io::Result<Vec<IpAddr>> {
+    let short = || invalid("DNS reply is cut short".to_string());
+    let u16_at = |at: usize| {
+        reply
+            .get(at..at + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or_else(short)
+    };
+    if u16_at(0)? != id {
+        return Err(invalid("DNS reply does not match the query".to_string()));
+    }
+    let flags = u16_at(2)?;
+    let rcode = flags & 0x000f;
+    if rcode != 0 {
+        return Err(invalid(format!(
+            "DNS server answered with error code {}",
+            rcode
+        )));
+    }
+    let questions = u16_at(4)?;
+    let answers = u16_at(6)?;
+
+    let mut at = 12;
+    for _ in 0..questions {
+        at = skip_name(reply, at).ok_or_else(short)? + 4;
+    }
+    let mut found = Vec::new();
+    for _ in 0..answers {
+        at = skip_name(reply, at).ok_or_else(short)?;
+        let rtype = u16_at(at)?;
+        let rdlength = u16_at(at + 8)? as usize;
+        let data = reply.get(at + 10..at + 10 + rdlength).ok_or_else(short)?;
+        match (rtype, data.len()) {
+            (TYPE_A, 4) if qtype == TYPE_A => {
+                found.push(IpAddr::from(<[u8; 4]>::try_from(data).unwrap()));
+            }
+            (TYPE_AAAA, 16) if qtype == TYPE_AAAA => {
+                found.push(IpAddr::from(<[u8; 16]>::try_from(data).unwrap()));
+            }
+            // CNAMEs on the way to the address.
+            _ => {}
+        }
+        at += 10 + rdlength;
+    }
+    // Truncated replies are not retried over TCP; what fit may be enough.
+    if found.is_empty() && flags & 0x0200 != 0 {
+        return Err(invalid("DNS reply was truncated".to_string()));
+    }
+    Ok(found)
+}
+
+/// The offset just past the (possibly compressed) name starting at `at`.
+fn skip_name(reply: &[u8], mut at: usize) -> Option<usize> {
+    loop {
+        let len = *reply.get(at)?;
+        match len {
+            0 => return Some(at + 1),
+            len if len & 0xc0 == 0xc0 => return Some(at + 2),
+            len => at += 1 + len as usize,
+        }
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: u16 = 0x1234;
+
+    /// A reply to a query for example.com, its name at offset 12, with
+    /// `answers` already encoded.
+    fn reply(id: u16, flags: u16, count: u16, qtype: u16, answers: &[u8]) -> Vec<u8> {
+        let mut reply = Vec::new();
+        reply.extend_from_slice(&id.to_be_bytes());
+        reply.extend_from_slice(&flags.to_be_bytes());
+        reply.extend_from_slice(&[0, 1]);
+        reply.extend_from_slice(&count.to_be_bytes());
+        reply.extend_from_slice(&[0, 0, 0, 0]);
+        reply.extend_from_slice(b"\x07example\x03com\x00");
+        reply.extend_from_slice(&qtype.to_be_bytes());
+        reply.extend_from_slice(&[0, 1]);
+        reply.extend_from_slice(answers);
+        reply
+    }
+
+    /// One answer record for `name`, already encoded.
+    fn record(name: &[u8], rtype: u16, data: &[u8]) -> Vec<u8> {
+        let mut record = name.to_vec();
+        record.extend_from_slice(&rtype.to_be_bytes());
+        record.extend_from_slice(&[0, 1, 0, 0, 0x0e, 0x10]);
+        record.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        record.extend_from_slice(data);
+        record
+    }
+
+    /// A pointer to the question's name.
+    const QUESTION: &[u8] = &[0xc0, 12];
+
+    #[test]
+    fn reads_a_records_through_compression_pointers() {
+        let answers = [
+            record(QUESTION, TYPE_A, &[93, 184, 216, 34]),
+            record(QUESTION, TYPE_A, &[93, 184, 216, 35]),
+        ]
+        .concat();
+        let found = parse(&reply(ID, 0x8180, 2, TYPE_A, &answers), ID, TYPE_A).unwrap();
+        assert_eq!(
+            found,
+            [
+                IpAddr::from([93, 184, 216, 34]),
+                IpAddr::from([93, 184, 216, 35])
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_uncompressed_names() {
+        let answers = record(b"\x07example\x03com\x00", TYPE_A, &[10, 0, 0, 1]);
+        let found = parse(&reply(ID, 0x8180, 1, TYPE_A, &answers), ID, TYPE_A).unwrap();
+        assert_eq!(found, [IpAddr::from([10, 0, 0, 1])]);
+    }
+
+    #[test]
+    fn follows_cname_chains() {
+        // example.com -> www.example.net (at offset 41) -> edge.example.net (at 70).
+        const TYPE_CNAME: u16 = 5;
+        let answers = [
+            record(QUESTION, TYPE_CNAME, b"\x03www\x07example\x03net\x00"),
+            record(&[0xc0, 41], TYPE_CNAME, b"\x04edge\xc0\x2d"),
+            record(
+                &[0xc0, 70],
+                TYPE_AAAA,
+                &[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+            ),
+        ]
+        .concat();
+        let found = parse(&reply(ID, 0x8180, 3, TYPE_AAAA, &answers), ID, TYPE_AAAA).unwrap();
+        assert_eq!(found, ["2001:db8::1".parse::<IpAddr>().unwrap()]);
+    }
+
+    #[test]
+    fn skips_records_of_the_other_family() {
+        let answers = [
+            record(QUESTION, TYPE_A, &[10, 0, 0, 1]),
+            record(QUESTION, TYPE_AAAA, &[0; 16]),
+        ]
+        .concat();
+        let found = parse(&reply(ID, 0x8180, 2, TYPE_A, &answers), ID, TYPE_A).unwrap();
+        assert_eq!(found, [IpAddr::from([10, 0, 0, 1])]);
+        let empty = parse(&reply(ID, 0x8180, 0, TYPE_AAAA, &[]), ID, TYPE_AAAA).unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn rejects_a_reply_to_another_query() {
+        let answers = record(QUESTION, TYPE_A, &[10, 0, 0, 1]);
+        let err = parse(&reply(ID + 1, 0x8180, 1, TYPE_A, &answers), ID, TYPE_A).unwrap_err();
+        assert!(err.to_string().contains("does not match"), "{}", err);
+    }
+
+    #[test]
+    fn reports_server_errors() {
+        // NXDOMAIN.
+        let err = parse(&reply(ID, 0x8183, 0, TYPE_A, &[]), ID, TYPE_A).unwrap_err();
+        assert!(err.to_string().contains("error code 3"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_replies_cut_short() {
+        let answers = record(QUESTION, TYPE_A, &[10, 0, 0, 1]);
+        let full = reply(ID, 0x8180, 1, TYPE_A, &answers);
+        for len in [0, 1, 11, 20, full.len() - 1] {
+            let err = parse(&full[..len], ID, TYPE_A).unwrap_err();
+            assert!(err.to_string().contains("cut short"), "{}: {}", len, err);
+        }
+    }
+
+    #[test]
+    fn truncated_replies_count_only_with_addresses() {
+        let err = parse(&reply(ID, 0x8380, 0, TYPE_A, &[]), ID, TYPE_A).unwrap_err();
+        assert!(err.to_string().contains("truncated"), "{}", err);
+        let answers = record(QUESTION, TYPE_A, &[10, 0, 0, 1]);
+        let found = parse(&reply(ID, 0x8380, 1, TYPE_A, &answers), ID, TYPE_A).unwrap();
+        assert_eq!(found, [IpAddr::from([10, 0, 0, 1])]);
+    }
+}
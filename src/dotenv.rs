@@ -0,0 +1,61 @@
+use std::fs;
+use std::path::Path;
+
+/// Whether `path` names a dotenv file such as `.env` or `config/.env.local`.
+pub fn is_dotenv(path: &str) -> bool {
+    let name = Path::new(path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default();
+    name == ".env" || name.starts_with(".env.")
+}
+
+/// A note for the system prompt naming the variables the dotenv files in the
+/// current directory define. Their values never leave the machine.
+pub fn prompt_note() -> Option<String> {
+    let mut files: Vec<(String, Vec<String>)> = fs::read_dir(".")
+        .ok()?
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !is_dotenv(&name) || !entry.path().is_file() {
+                return None;
+            }
+            let text = fs::read_to_string(entry.path()).ok()?;
+            Some((name, variable_names(&text)))
+        })
+        .collect();
+    if files.is_empty() {
+        return None;
+    }
+    files.sort();
+    let listed: Vec<String> = files
+        .iter()
+        .map(|(name, vars)| match vars.as_slice() {
+            [] => format!("{} (no variables)", name),
+            vars => format!("{} ({})", name, vars.join(", ")),
+        })
+        .collect();
+    Some(format!(
+        "The current directory has dotenv files defining these variables (values withheld): \
+{}. Refer to them by name and never print or send a dotenv file's contents.",
+        listed.join("; ")
+    ))
+}
+
+/// The names assigned in a dotenv file, in order, with `export ` prefixes,
+/// comments and values left out.
+fn variable_names(text: &str) -> Vec<String> {
+    text.lines()
+        .filter_map(|line| {
+            let line = line.trim_start();
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (name, _) = line.split_once('=')?;
+            let name = name.trim();
+            let valid = !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            valid.then(|| name.to_string())
+        })
+        .collect()
+}
@@ -0,0 +1,187 @@
+use crate::api::Exchange;
+use crate::cancel;
+use crate::paths;
+use crate::ui;
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde_json::Value;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{LazyLock, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where `--dump-dir` asked every exchange to be written.
+static DIR: OnceLock<PathBuf> = OnceLock::new();
+/// Bundles written by this process, to keep their names apart.
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Environment variables worth knowing about in a bug report; only whether
+/// they are set is recorded, never their values.
+const NOTED_VARIABLES: &[&str] = &[
+    "LLMWRAP_OPENAI_API_KEY",
+    "LLMWRAP_ANTHROPIC_API_KEY",
+    "LLMWRAP_OPENAI_BASE_URL",
+    "LLMWRAP_PROVIDER",
+    "LLMWRAP_PROFILE",
+    "HTTPS_PROXY",
+    "HTTP_PROXY",
+    "ALL_PROXY",
+    "NO_PROXY",
+];
+
+/// Things that look like credentials, replaced before anything is written.
+static SECRETS: LazyLock<Vec<(Regex, &str)>> = LazyLock::new(|| {
+    [
+        (r"sk-[A-Za-z0-9_-]{16,}", "[redacted]"),
+        (r"AKIA[0-9A-Z]{16}", "[redacted]"),
+        (r"gh[pousr]_[A-Za-z0-9]{20,}", "[redacted]"),
+        (r"xox[abprs]-[A-Za-z0-9-]{10,}", "[redacted]"),
+        (r"(?i)bearer [A-Za-z0-9._~+/=-]{8,}", "Bearer [redacted]"),
+        (r"://[^/\s:@]+:[^/\s@]+@", "://[redacted]@"),
+        (
+            r#"(?i)\b(password|passwd|secret|token|api[_-]?key)(\\?["']?\s*[:=]\s*\\?["']?)[^\s"'\\,]+"#,
+            "$1$2[redacted]",
+        ),
+    ]
+    .into_iter()
+    .map(|(pattern, replacement)| (Regex::new(pattern).unwrap(), replacement))
+    .collect()
+});
+
+pub fn init(dir: Option<&Path>) {
+    if let Some(dir) = dir {
+        let _ = DIR.set(dir.to_path_buf());
+    }
+}
+
+/// Write `exchange` to the `--dump-dir` directory, if one was given.
+pub fn exchange(exchange: &Exchange) {
+    let Some(dir) = DIR.get() else {
+        return;
+    };
+    if let Err(err) = write(dir, exchange, None) {
+        eprintln!("Warning: {:#}", err);
+    }
+}
+
+/// A reply that could not be understood. It carries the raw text so it can
+/// still be shown, and where the whole exchange was saved.
+#[derive(Debug)]
+pub struct Unreadable {
+    pub reason: String,
+    pub raw: String,
+    pub path: Option<PathBuf>,
+}
+
+impl fmt::Display for Unreadable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.reason)?;
+        if let Some(path) = &self.path {
+            write!(
+                f,
+                "\nThe request and reply are saved in {} to attach to a bug report.",
+                path.display()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Unreadable {}
+
+/// Write a bundle for a reply that could not be understood, to the
+/// `--dump-dir` directory or else the state directory, and return an
+/// `Unreadable` error saying why and where.
+pub fn failure(exchange: &Exchange, error: anyhow::Error) -> anyhow::Error {
+    let dir = DIR.get().cloned().unwrap_or_else(|| {
+        paths::state_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("dumps")
+    });
+    anyhow::Error::new(Unreadable {
+        reason: format!("{:#}", error),
+        raw: exchange.response.clone(),
+        path: write(&dir, exchange, Some(&error)).ok(),
+    })
+}
+
+/// The start of `raw` on one line, for a short diagnostic.
+pub fn preview(raw: &str) -> String {
+    const LENGTH: usize = 80;
+    let line: String = raw.split_whitespace().collect::<Vec<_>>().join(" ");
+    if line.chars().count() > LENGTH {
+        format!("{}...", line.chars().take(LENGTH).collect::<String>())
+    } else {
+        line
+    }
+}
+
+fn write(dir: &Path, exchange: &Exchange, error: Option<&anyhow::Error>) -> Result<PathBuf> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create dump directory {}", dir.display()))?;
+    let response = serde_json::from_str::<Value>(&exchange.response)
+        .unwrap_or_else(|_| Value::String(exchange.response.clone()));
+    let bundle = serde_json::json!({
+        "url": exchange.url,
+        "request_id": exchange.request_id,
+        "elapsed_ms": exchange.elapsed_ms,
+        "incomplete": exchange.incomplete,
+        "error": error.map(|err| format!("{:#}", err)),
+        "request": exchange.request,
+        "response": response,
+        "environment": environment(),
+    });
+    let text = redact(&serde_json::to_string_pretty(&bundle)?);
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let path = dir.join(format!(
+        "llmwrap-{}-{}-{}.json",
+        timestamp,
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    cancel::shield(|| fs::write(&path, text))
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+fn environment() -> Value {
+    let variable = |name: &str| std::env::var(name).ok();
+    let set: Vec<&str> = NOTED_VARIABLES
+        .iter()
+        .copied()
+        .filter(|name| std::env::var_os(name).is_some())
+        .collect();
+    serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+        "shell": variable("SHELL"),
+        "term": variable("TERM"),
+        "lang": variable("LANG"),
+        "interactive": ui::interactive(),
+        "variables_set": set,
+    })
+}
+
+/// `text` with the API keys and anything else that looks like a credential
+/// replaced.
+fn redact(text: &str) -> String {
+    let mut text = text.to_string();
+    for variable in ["LLMWRAP_OPENAI_API_KEY", "LLMWRAP_ANTHROPIC_API_KEY"] {
+        if let Ok(key) = std::env::var(variable)
+            && !key.is_empty()
+        {
+            text = text.replace(&key, "[redacted]");
+        }
+    }
+    for (pattern, replacement) in SECRETS.iter() {
+        text = pattern.replace_all(&text, *replacement).into_owned();
+    }
+    text
+}
@@ -0,0 +1,366 @@
+use crate::cmdline::{self, Segment};
+use regex::Regex;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::LazyLock;
+
+/// `$NAME` or `${NAME}` in a value.
+static REFERENCE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\$(?:\{([A-Za-z_]\w*)\}|([A-Za-z_]\w*))").unwrap());
+
+/// Builtins whose whole effect is on the shell that runs them.
+const SHELL_BUILTINS: &[&str] = &[
+    "cd", "pushd", "popd", "export", "unset", "ulimit", "umask", "alias", "unalias", "source", ".",
+    "set", "shopt", "hash",
+];
+
+/// Something a command changes in the shell running it, which a command
+/// llmwrap runs can't change in the shell it was started from.
+pub struct Change {
+    /// What changes, e.g. "working directory" or "$PATH".
+    pub what: String,
+    /// The value now; None when unset or unknown.
+    pub from: Option<String>,
+    /// The value afterwards, with the variables it uses filled in; None
+    /// when unset.
+    pub to: Option<String>,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // A list like PATH that only grows is shown by what is added.
+        if let (Some(from), Some(to)) = (&self.from, &self.to)
+            && !from.is_empty()
+            && to != from
+        {
+            if let Some(added) = to.strip_suffix(from.as_str()) {
+                return write!(f, "{}: {} added in front", self.what, added);
+            }
+            if let Some(added) = to.strip_prefix(from.as_str()) {
+                return write!(f, "{}: {} added at the end", self.what, added);
+            }
+        }
+        let from = self.from.as_deref().unwrap_or("(unset)");
+        let to = self.to.as_deref().unwrap_or("(unset)");
+        write!(f, "{}: {} -> {}", self.what, from, to)
+    }
+}
+
+/// What `command` changes in the shell itself: its working directory,
+/// variables, limits, aliases and options.
+pub fn changes(command: &str) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let mut cwd = std::env::current_dir().ok();
+    for segment in cmdline::segments(command) {
+        // `FOO=bar` on its own sets a shell variable.
+        if !segment.words.is_empty() && segment.words.iter().all(|word| assignment(word).is_some())
+        {
+            for word in &segment.words {
+                let (name, value) = assignment(word).unwrap_or_default();
+                changes.push(variable(name, Some(value), cwd.as_deref()));
+            }
+            continue;
+        }
+        let Some((builtin, args)) = segment.words.split_first() else {
+            continue;
+        };
+        match builtin.as_str() {
+            "cd" | "pushd" => {
+                let target = directory(cwd.as_deref(), args.iter().find(|a| !a.starts_with('-')));
+                changes.push(Change {
+                    what: "working directory".to_string(),
+                    from: cwd.as_ref().map(|dir| dir.display().to_string()),
+                    to: target.as_ref().map(|dir| dir.display().to_string()),
+                });
+                cwd = target;
+            }
+            "popd" => changes.push(Change {
+                what: "working directory".to_string(),
+                from: cwd.take().map(|dir| dir.display().to_string()),
+                to: Some("the one before the last pushd".to_string()),
+            }),
+            "export" => {
+                for arg in args.iter().filter(|arg| !arg.starts_with('-')) {
+                    if let Some((name, value)) = assignment(arg) {
+                        changes.push(variable(name, Some(value), cwd.as_deref()));
+                    }
+                }
+            }
+            "unset" => {
+                for name in args.iter().filter(|arg| !arg.starts_with('-')) {
+                    changes.push(variable(name, None, cwd.as_deref()));
+                }
+            }
+            "ulimit" => changes.extend(limit(&segment)),
+            "umask" => {
+                if let Some(mask) = args.iter().find(|arg| !arg.starts_with('-')) {
+                    changes.push(Change {
+                        what: "umask".to_string(),
+                        from: current("umask"),
+                        to: Some(mask.clone()),
+                    });
+                }
+            }
+            "alias" => {
+                for arg in args {
+                    if let Some((name, value)) = arg.split_once('=') {
+                        changes.push(Change {
+                            what: format!("alias {}", name),
+                            from: None,
+                            to: Some(value.to_string()),
+                        });
+                    }
+                }
+            }
+            "unalias" => {
+                for name in args.iter().filter(|arg| !arg.starts_with('-')) {
+                    changes.push(Change {
+                        what: format!("alias {}", name),
+                        from: Some("defined".to_string()),
+                        to: None,
+                    });
+                }
+            }
+            "source" | "." => {
+                if let Some(file) = args.first() {
+                    changes.push(Change {
+                        what: "whatever it sets".to_string(),
+                        from: None,
+                        to: Some(format!("read from {}", file)),
+                    });
+                }
+            }
+            "set" | "shopt" if !args.is_empty() => changes.push(Change {
+                what: "shell options".to_string(),
+                from: None,
+                to: Some(format!("{} {}", builtin, args.join(" "))),
+            }),
+            _ => {}
+        }
+    }
+    changes
+}
+
+/// Whether all `command` does is change the shell running it, so running
+/// it from llmwrap achieves nothing.
+pub fn only_shell(command: &str) -> bool {
+    let segments = cmdline::segments(command);
+    !segments.is_empty()
+        && segments.iter().all(|segment| {
+            segment.words.iter().all(|word| assignment(word).is_some())
+                || segment
+                    .words
+                    .first()
+                    .is_some_and(|word| SHELL_BUILTINS.contains(&word.as_str()))
+        })
+}
+
+/// `NAME=value`, split, when `word` assigns a variable.
+fn assignment(word: &str) -> Option<(&str, &str)> {
+    let (name, value) = word.split_once('=')?;
+    let valid = name.chars().next().is_some_and(|c| !c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid.then_some((name, value))
+}
+
+/// A change to the variable `name`, with the variables `to` refers to
+/// filled in and `$PWD` being `cwd`.
+fn variable(name: &str, to: Option<&str>, cwd: Option<&Path>) -> Change {
+    let to = to.map(|value| {
+        REFERENCE
+            .replace_all(value, |caps: &regex::Captures| {
+                let reference = caps.get(1).or(caps.get(2)).map_or("", |m| m.as_str());
+                match (reference, cwd) {
+                    ("PWD", Some(cwd)) => cwd.display().to_string(),
+                    _ => std::env::var(reference).unwrap_or_default(),
+                }
+            })
+            .into_owned()
+    });
+    Change {
+        what: format!("${}", name),
+        from: std::env::var(name).ok(),
+        to,
+    }
+}
+
+/// Where `cd` with `arg` goes from `cwd`: home without one, the previous
+/// directory with `-`.
+fn directory(cwd: Option<&Path>, arg: Option<&String>) -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let Some(arg) = arg else {
+        return home;
+    };
+    if arg == "-" {
+        return std::env::var_os("OLDPWD").map(PathBuf::from);
+    }
+    let path = match (arg.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) => home.join(rest.trim_start_matches('/')),
+        _ => PathBuf::from(arg),
+    };
+    Some(match cwd {
+        Some(cwd) if path.is_relative() => cwd.join(path),
+        _ => path,
+    })
+}
+
+/// The limits a `ulimit` segment sets, with their current values.
+fn limit(segment: &Segment) -> Vec<Change> {
+    let args = &segment.words[1..];
+    let Some(value) = args.last().filter(|arg| !arg.starts_with('-')) else {
+        return Vec::new();
+    };
+    let flags: Vec<&String> = args.iter().filter(|arg| arg.starts_with('-')).collect();
+    // Without a resource flag ulimit sets the file size limit.
+    let flag = flags
+        .iter()
+        .flat_map(|flag| flag[1..].chars())
+        .find(|c| !matches!(c, 'H' | 'S'))
+        .unwrap_or('f');
+    let what = match flag {
+        'n' => "open files limit",
+        's' => "stack size limit",
+        'c' => "core file size limit",
+        'u' => "process limit",
+        'v' => "virtual memory limit",
+        'f' => "file size limit",
+        't' => "CPU time limit",
+        _ => "limit",
+    };
+    vec![Change {
+        what: format!("{} (ulimit -{})", what, flag),
+        from: current(&format!("ulimit -{}", flag)),
+        to: Some(value.clone()),
+    }]
+}
+
+/// What `builtin` prints in a fresh sh, which inherits our limits and umask.
+fn current(builtin: &str) -> Option<String> {
+    let output = Command::new("sh")
+        .args(["-c", builtin])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !value.is_empty()).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shown(command: &str) -> Vec<String> {
+        changes(command)
+            .iter()
+            .map(|change| change.to_string())
+            .collect()
+    }
+
+    fn cwd() -> PathBuf {
+        std::env::current_dir().unwrap()
+    }
+
+    #[test]
+    fn cd_follows_the_directory() {
+        let here = cwd();
+        let moves = changes("cd build && cd ../dist");
+        assert_eq!(moves.len(), 2);
+        assert_eq!(moves[0].what, "working directory");
+        assert_eq!(moves[0].from, Some(here.display().to_string()));
+        assert_eq!(moves[0].to, Some(here.join("build").display().to_string()));
+        assert_eq!(moves[1].from, moves[0].to);
+        assert_eq!(
+            moves[1].to,
+            Some(here.join("build/../dist").display().to_string())
+        );
+        assert_eq!(changes("cd /tmp")[0].to.as_deref(), Some("/tmp"));
+        if let Some(home) = std::env::var_os("HOME").map(PathBuf::from) {
+            assert_eq!(changes("cd")[0].to, Some(home.display().to_string()));
+            assert_eq!(
+                changes("cd ~/src")[0].to,
+                Some(home.join("src").display().to_string())
+            );
+        }
+        assert_eq!(
+            changes("pushd /tmp && popd")[1].to.as_deref(),
+            Some("the one before the last pushd")
+        );
+    }
+
+    #[test]
+    fn variables_are_filled_in() {
+        let change =
+            &changes("export GOPATH=$PWD/go LLMWRAP_TEST_EMPTY=${LLMWRAP_TEST_UNSET}x")[..];
+        assert_eq!(change.len(), 2);
+        assert_eq!(change[0].what, "$GOPATH");
+        assert_eq!(change[0].to, Some(format!("{}/go", cwd().display())));
+        assert_eq!(change[1].to.as_deref(), Some("x"));
+        assert_eq!(change[1].from, None);
+        // $PWD is where the earlier cd went.
+        let change = &changes("cd /tmp; GOBIN=$PWD/bin")[1];
+        assert_eq!(change.to.as_deref(), Some("/tmp/bin"));
+        assert_eq!(changes("unset LLMWRAP_TEST_UNSET")[0].to, None);
+        // Names that can't be variables are arguments.
+        assert!(changes("export -n 1X=y").is_empty());
+    }
+
+    #[test]
+    fn lists_are_shown_by_what_is_added() {
+        let change = |from: &str, to: &str| Change {
+            what: "$PATH".to_string(),
+            from: Some(from.to_string()),
+            to: Some(to.to_string()),
+        };
+        assert_eq!(
+            change("/usr/bin", "/opt/bin:/usr/bin").to_string(),
+            "$PATH: /opt/bin: added in front"
+        );
+        assert_eq!(
+            change("/usr/bin", "/usr/bin:/opt/bin").to_string(),
+            "$PATH: :/opt/bin added at the end"
+        );
+        assert_eq!(
+            change("/usr/bin", "/bin").to_string(),
+            "$PATH: /usr/bin -> /bin"
+        );
+        assert_eq!(
+            Change {
+                what: "$EDITOR".to_string(),
+                from: None,
+                to: None
+            }
+            .to_string(),
+            "$EDITOR: (unset) -> (unset)"
+        );
+    }
+
+    #[test]
+    fn other_builtins_are_named() {
+        assert_eq!(shown("alias ll='ls -l'"), ["alias ll: (unset) -> ls -l"]);
+        assert_eq!(shown("unalias ll"), ["alias ll: defined -> (unset)"]);
+        assert_eq!(
+            shown("source venv/bin/activate"),
+            ["whatever it sets: (unset) -> read from venv/bin/activate"]
+        );
+        assert_eq!(shown("set -o vi"), ["shell options: (unset) -> set -o vi"]);
+        assert!(shown("set").is_empty());
+        let limits = changes("ulimit -Sn 4096; ulimit 100");
+        assert_eq!(limits[0].what, "open files limit (ulimit -n)");
+        assert_eq!(limits[0].to.as_deref(), Some("4096"));
+        assert_eq!(limits[1].what, "file size limit (ulimit -f)");
+        assert!(changes("ulimit -n").is_empty());
+        assert_eq!(changes("umask 077")[0].to.as_deref(), Some("077"));
+        assert!(changes("ls -la && make").is_empty());
+    }
+
+    #[test]
+    fn only_shell_needs_every_part() {
+        assert!(only_shell("cd /tmp"));
+        assert!(only_shell("export A=1 && FOO=bar; source .env"));
+        assert!(!only_shell("cd /tmp && ls"));
+        assert!(!only_shell("FOO=bar make"));
+        assert!(!only_shell(""));
+    }
+}
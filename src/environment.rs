@@ -0,0 +1,88 @@
+use crate::safer;
+use std::fs;
+use std::process::{Command, Stdio};
+
+/// Programs requests often call for, so the model knows whether it can use
+/// them or has to make do without.
+pub const TOOLS: &[&str] = &[
+    "ffmpeg", "convert", "magick", "jq", "yq", "rg", "fd", "gsed", "gawk", "curl", "wget", "git",
+    "docker", "podman", "python3", "node", "pandoc", "rsync", "zip", "unzip", "7z", "zstd",
+    "parallel", "trash",
+];
+
+/// Package managers, in the order to name them when several are installed.
+pub const PACKAGE_MANAGERS: &[&str] = &[
+    "apt-get",
+    "dnf",
+    "yum",
+    "pacman",
+    "zypper",
+    "apk",
+    "emerge",
+    "xbps-install",
+    "brew",
+    "port",
+    "nix-env",
+    "pkg",
+    "winget",
+    "scoop",
+    "choco",
+];
+
+/// A paragraph for the system prompt on the machine the command runs on: the
+/// OS and distribution, architecture, login shell, working directory, package
+/// manager and which common tools are installed.
+pub fn prompt_note() -> String {
+    let mut facts = vec![format!(
+        "OS: {}",
+        distribution().unwrap_or_else(|| std::env::consts::OS.to_string())
+    )];
+    facts.push(format!("architecture: {}", std::env::consts::ARCH));
+    if let Some(login) = std::env::var("SHELL").ok().filter(|s| !s.is_empty()) {
+        facts.push(format!("login shell: {}", login));
+    }
+    if let Ok(cwd) = std::env::current_dir() {
+        facts.push(format!("working directory: {}", cwd.display()));
+    }
+    let managers: Vec<&str> = PACKAGE_MANAGERS
+        .iter()
+        .copied()
+        .filter(|manager| safer::on_path(manager))
+        .collect();
+    if !managers.is_empty() {
+        facts.push(format!("package manager: {}", managers.join(", ")));
+    }
+    let (installed, missing): (Vec<&str>, Vec<&str>) =
+        TOOLS.iter().partition(|tool| safer::on_path(tool));
+    if !installed.is_empty() {
+        facts.push(format!("installed: {}", installed.join(", ")));
+    }
+    if !missing.is_empty() {
+        facts.push(format!("not installed: {}", missing.join(", ")));
+    }
+    format!(
+        "The machine the command runs on: {}. Prefer tools that are installed; when the task \
+can't be done without a missing one, install it with the package manager first.",
+        facts.join("; ")
+    )
+}
+
+/// The distribution and version, e.g. "Fedora Linux 40 (Workstation
+/// Edition)" or "macOS 14.5".
+pub fn distribution() -> Option<String> {
+    if cfg!(target_os = "macos") {
+        let output = Command::new("sw_vers")
+            .arg("-productVersion")
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        return Some(format!("macOS {}", version).trim().to_string());
+    }
+    let release = fs::read_to_string("/etc/os-release").ok()?;
+    release.lines().find_map(|line| {
+        let value = line.strip_prefix("PRETTY_NAME=")?;
+        Some(value.trim_matches('"').to_string())
+    })
+}
@@ -0,0 +1,915 @@
+use crate::shell::Shell;
+use crate::{cancel, tty, ui};
+use anyhow::{Context, Result};
+use std::io::IsTerminal;
+use std::process::{Child, ExitStatus};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Keep at most this much of a command's output, dropping the oldest first.
+const CAPTURE_LIMIT: usize = 1 << 20;
+
+/// How a command ran.
+pub struct Outcome {
+    pub status: ExitStatus,
+    /// What the command printed, when it was captured.
+    pub output: Option<String>,
+}
+
+/// Run `command` with `shell`, attached to the user's terminal.
+///
+/// When we have a terminal the command runs inside a pseudo terminal (a
+/// pseudo console on Windows) that we relay, so it still sees a terminal while
+/// we keep a copy of its output. Editors, pagers, REPLs and remote shells are
+/// not captured: their screen is not output worth keeping, and on Unix they
+/// get the real terminal rather than a relayed one.
+pub fn run(shell: Shell, command: &str) -> Result<Outcome> {
+    let command = &*shell.runnable(command);
+    let terminal = std::io::stdin().is_terminal() && std::io::stdout().is_terminal();
+    let capture = match tty::interactive_program(command) {
+        Some(program) if terminal => {
+            ui::say!(
+                "`{}` is interactive, so its output will not be captured.",
+                program
+            );
+            false
+        }
+        Some(_) => false,
+        None => terminal,
+    };
+
+    // The pseudo console starts the shell itself, so it can't go over SSH.
+    #[cfg(windows)]
+    if crate::ssh::target().is_none()
+        && let Some((status, output)) = conpty::run(shell, command)?
+    {
+        let output = capture.then(|| output.into_text());
+        return Ok(Outcome { status, output });
+    }
+    #[cfg(unix)]
+    if capture && let Some((status, output)) = pty::run(shell, command)? {
+        let output = Some(output.into_text());
+        return Ok(Outcome { status, output });
+    }
+    let _handling = cancel::handling()?;
+    let mut process = shell.target_command(command);
+    // With no terminal at all, as under CI or a service manager, the command
+    // gets a process group of its own, so stopping it stops everything it
+    // started. At a terminal it stays in ours, where Ctrl-C reaches it.
+    let attached = [
+        std::io::stdin().is_terminal(),
+        std::io::stdout().is_terminal(),
+        std::io::stderr().is_terminal(),
+    ]
+    .contains(&true);
+    #[cfg(unix)]
+    if !attached {
+        std::os::unix::process::CommandExt::process_group(&mut process, 0);
+    }
+    let _foreground = attached.then(cancel::foreground);
+    let mut child = process
+        .spawn()
+        .with_context(|| format!("Failed to spawn {}", shell))?;
+    let status = wait(&mut child, !attached)?;
+    Ok(Outcome {
+        status,
+        output: None,
+    })
+}
+
+/// How long a command has to exit once asked to stop before it is killed.
+const GRACE: Duration = Duration::from_secs(5);
+
+/// Wait for `child` to exit. Once the run is cancelled the command is sent
+/// the signal that cancelled it, to its whole process group when `group`,
+/// and killed if it hasn't exited after `GRACE`.
+fn wait(child: &mut Child, group: bool) -> Result<ExitStatus> {
+    let mut asked: Option<Instant> = None;
+    // Short at first so quick commands don't wait on the loop.
+    let mut pause = Duration::from_millis(1);
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if let Some(reason) = cancel::reason() {
+            match asked {
+                None => {
+                    stop(child, reason, group);
+                    asked = Some(Instant::now());
+                }
+                Some(when) if when.elapsed() > GRACE => {
+                    kill(child, group);
+                }
+                Some(_) => {}
+            }
+        }
+        thread::sleep(pause);
+        pause = (pause * 2).min(cancel::POLL);
+    }
+}
+
+/// Pass the signal that cancelled the run on to `child`.
+#[cfg(unix)]
+fn stop(child: &Child, reason: cancel::Reason, group: bool) {
+    let pid = child.id() as libc::pid_t;
+    // SAFETY: the child hasn't been waited for, so the id is still its own.
+    unsafe { libc::kill(if group { -pid } else { pid }, cancel::signal_for(reason)) };
+}
+
+#[cfg(not(unix))]
+fn stop(child: &mut Child, _reason: cancel::Reason, _group: bool) {
+    let _ = child.kill();
+}
+
+#[cfg(unix)]
+fn kill(child: &mut Child, group: bool) {
+    if group {
+        // SAFETY: as in `stop`.
+        unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) };
+    } else {
+        let _ = child.kill();
+    }
+}
+
+#[cfg(not(unix))]
+fn kill(child: &mut Child, _group: bool) {
+    let _ = child.kill();
+}
+
+/// The tail of what a command wrote to its terminal.
+#[derive(Default)]
+struct Capture {
+    bytes: Vec<u8>,
+    truncated: bool,
+}
+
+impl Capture {
+    fn push(&mut self, data: &[u8]) {
+        self.bytes.extend_from_slice(data);
+        if self.bytes.len() > 2 * CAPTURE_LIMIT {
+            let excess = self.bytes.len() - CAPTURE_LIMIT;
+            self.bytes.drain(..excess);
+            self.truncated = true;
+        }
+    }
+
+    /// Pseudo terminals always carry UTF-8, so no code page guessing is needed.
+    fn into_text(self) -> String {
+        let text = plain_text(&String::from_utf8_lossy(&self.bytes));
+        if self.truncated {
+            format!("[earlier output dropped]\n{}", text)
+        } else {
+            text
+        }
+    }
+}
+
+/// Terminal output as plain text: escape sequences removed and carriage
+/// returns applied, so a progress bar leaves only its last state.
+fn plain_text(raw: &str) -> String {
+    let mut stripped = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                // Control sequences end with a byte in @..~.
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // Operating system commands (window titles, links) end with
+                // BEL or ESC \.
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' {
+                            break;
+                        }
+                        if c == '\x1b' {
+                            chars.next();
+                            break;
+                        }
+                    }
+                }
+                // Character set selection takes one more byte.
+                Some('(' | ')') => {
+                    chars.next();
+                }
+                _ => {}
+            },
+            '\n' | '\r' | '\t' => stripped.push(c),
+            c if c.is_control() => {}
+            c => stripped.push(c),
+        }
+    }
+    let mut lines: Vec<&str> = stripped
+        .lines()
+        .map(|line| {
+            line.rsplit('\r')
+                .find(|part| !part.is_empty())
+                .unwrap_or("")
+        })
+        .collect();
+    while lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+/// Decode output captured from a child process.
+///
+/// Windows programs write UTF-16 (Windows PowerShell when redirected) or the
+/// console code page rather than UTF-8, so trying UTF-8 alone would mangle
+/// anything outside ASCII.
+pub fn decode_output(bytes: &[u8]) -> String {
+    if let Some(rest) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        return decode_utf16le(rest);
+    }
+    // UTF-16 without a BOM: text never contains NUL, but ASCII in UTF-16 has
+    // one in every other byte.
+    if bytes.len() >= 4
+        && bytes.len().is_multiple_of(2)
+        && bytes.iter().skip(1).step_by(2).all(|&b| b == 0)
+    {
+        return decode_utf16le(bytes);
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.trim_start_matches('\u{feff}').to_string(),
+        Err(_) => decode_legacy(bytes),
+    }
+}
+
+fn decode_utf16le(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(windows)]
+fn decode_legacy(bytes: &[u8]) -> String {
+    conpty::from_code_page(bytes)
+}
+
+#[cfg(not(windows))]
+fn decode_legacy(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+#[cfg(windows)]
+mod conpty {
+    use super::Capture;
+    use crate::cancel;
+    use crate::shell::Shell;
+    use anyhow::{Result, bail};
+    use std::ffi::c_void;
+    use std::os::windows::process::ExitStatusExt;
+    use std::process::ExitStatus;
+    use std::ptr::{null, null_mut};
+    use windows_sys::Win32::Foundation::{
+        CloseHandle, ERROR_FILE_NOT_FOUND, GetLastError, HANDLE, INVALID_HANDLE_VALUE,
+        WAIT_OBJECT_0,
+    };
+    use windows_sys::Win32::Globalization::{CP_UTF8, GetOEMCP, MultiByteToWideChar};
+    use windows_sys::Win32::Storage::FileSystem::{ReadFile, WriteFile};
+    use windows_sys::Win32::System::Console::{
+        CONSOLE_MODE, CONSOLE_SCREEN_BUFFER_INFO, COORD, ClosePseudoConsole, CreatePseudoConsole,
+        DISABLE_NEWLINE_AUTO_RETURN, ENABLE_ECHO_INPUT, ENABLE_LINE_INPUT, ENABLE_PROCESSED_INPUT,
+        ENABLE_VIRTUAL_TERMINAL_INPUT, ENABLE_VIRTUAL_TERMINAL_PROCESSING, GetConsoleCP,
+        GetConsoleMode, GetConsoleOutputCP, GetConsoleScreenBufferInfo, GetStdHandle, HPCON,
+        STD_INPUT_HANDLE, STD_OUTPUT_HANDLE, SetConsoleCP, SetConsoleMode, SetConsoleOutputCP,
+    };
+    use windows_sys::Win32::System::Pipes::CreatePipe;
+    use windows_sys::Win32::System::Threading::{
+        CreateEventW, CreateProcessW, DeleteProcThreadAttributeList, EXTENDED_STARTUPINFO_PRESENT,
+        GetExitCodeProcess, INFINITE, InitializeProcThreadAttributeList,
+        LPPROC_THREAD_ATTRIBUTE_LIST, PROC_THREAD_ATTRIBUTE_PSEUDOCONSOLE, PROCESS_INFORMATION,
+        STARTF_USESTDHANDLES, STARTUPINFOEXW, SetEvent, TerminateProcess,
+        UpdateProcThreadAttribute, WaitForMultipleObjects,
+    };
+
+    /// A handle moved to a relay thread.
+    struct Handle(HANDLE);
+
+    // SAFETY: kernel handles may be used from any thread.
+    unsafe impl Send for Handle {}
+
+    /// Run `command` in a pseudo console, or return None when stdin and stdout
+    /// are not a console and the plain spawn path should be used instead.
+    pub fn run(shell: Shell, command: &str) -> Result<Option<(ExitStatus, Capture)>> {
+        let _handling = cancel::handling()?;
+        // SAFETY: GetStdHandle has no preconditions.
+        let (input, output) = unsafe {
+            (
+                GetStdHandle(STD_INPUT_HANDLE),
+                GetStdHandle(STD_OUTPUT_HANDLE),
+            )
+        };
+        let Some(size) = console_size(output) else {
+            return Ok(None);
+        };
+        let Some(_console) = ConsoleState::enter(input, output) else {
+            return Ok(None);
+        };
+
+        let (pty_input, our_input) = pipe()?;
+        let (our_output, pty_output) = pipe()?;
+        let mut pty: HPCON = 0;
+        // SAFETY: both pipe ends are valid and pty outlives the call.
+        let hr = unsafe { CreatePseudoConsole(size, pty_input, pty_output, 0, &mut pty) };
+        if hr < 0 {
+            close(&[pty_input, our_input, our_output, pty_output]);
+            bail!("Could not create a pseudo console (HRESULT {:#010x})", hr);
+        }
+
+        let spawned = spawn(pty, shell, command);
+        // The pseudo console holds its own references to its ends of the pipes.
+        close(&[pty_input, pty_output]);
+        let process = match spawned {
+            Ok(process) => process,
+            Err(err) => {
+                // SAFETY: pty was created above and is closed exactly once.
+                unsafe { ClosePseudoConsole(pty) };
+                close(&[our_input, our_output]);
+                return Err(err);
+            }
+        };
+
+        let (from_pty, to_user) = (Handle(our_output), Handle(output));
+        let relay_output = std::thread::spawn(move || {
+            let mut capture = Capture::default();
+            relay(from_pty, to_user, Some(&mut capture));
+            capture
+        });
+        // Nothing wakes a thread blocked reading the console, so the input relay
+        // is left to end on its own the next time a key is pressed.
+        let (from_user, to_pty) = (Handle(input), Handle(our_input));
+        std::thread::spawn(move || relay(from_user, to_pty, None));
+
+        // Set once the run is cancelled, to wake the wait below.
+        // SAFETY: default security, manual reset, unset, unnamed.
+        let cancelled = unsafe { CreateEventW(null(), 1, 0, null()) };
+        let hook = (!cancelled.is_null()).then(|| {
+            let event = Handle(cancelled);
+            cancel::on_cancel(move || {
+                let event = &event;
+                // SAFETY: the event is closed only after the hook is dropped.
+                unsafe { SetEvent(event.0) };
+            })
+        });
+        let waits = [process.hProcess, cancelled];
+        let count = if cancelled.is_null() { 1 } else { 2 };
+        let mut code = 0u32;
+        // SAFETY: the process handle is valid until closed below, and so is the
+        // event when there is one.
+        unsafe {
+            if WaitForMultipleObjects(count, waits.as_ptr(), 0, INFINITE) == WAIT_OBJECT_0 + 1 {
+                // There is no signal to pass on through a pseudo console, so the
+                // command is stopped outright; closing the console below ends
+                // whatever it started.
+                let reason = cancel::reason().unwrap_or(cancel::Reason::Interrupted);
+                TerminateProcess(
+                    process.hProcess,
+                    cancel::Cancelled(reason).exit_code().into(),
+                );
+                WaitForMultipleObjects(1, waits.as_ptr(), 0, INFINITE);
+            }
+            GetExitCodeProcess(process.hProcess, &mut code);
+        }
+        drop(hook);
+        if !cancelled.is_null() {
+            close(&[cancelled]);
+        }
+        // Closing the pseudo console flushes what the child wrote and ends the
+        // output relay with a broken pipe.
+        // SAFETY: pty was created above and is closed exactly once.
+        unsafe { ClosePseudoConsole(pty) };
+        let capture = relay_output.join().unwrap_or_default();
+        close(&[process.hProcess, process.hThread, our_output]);
+        Ok(Some((ExitStatus::from_raw(code), capture)))
+    }
+
+    /// Start the shell attached to the pseudo console. `pwsh` is PowerShell 7;
+    /// machines without it still have Windows PowerShell.
+    fn spawn(pty: HPCON, shell: Shell, command: &str) -> Result<PROCESS_INFORMATION> {
+        let mut programs = vec![shell.binary()];
+        if shell == Shell::Pwsh {
+            programs.push("powershell");
+        }
+
+        let mut size = 0usize;
+        // SAFETY: a null list asks for the required size; the call fails by design.
+        unsafe { InitializeProcThreadAttributeList(null_mut(), 1, 0, &mut size) };
+        // usize elements keep the list pointer-aligned.
+        let mut storage = vec![0usize; size.div_ceil(size_of::<usize>())];
+        let list = storage.as_mut_ptr() as LPPROC_THREAD_ATTRIBUTE_LIST;
+        // SAFETY: storage holds at least `size` bytes and outlives the list.
+        unsafe {
+            if InitializeProcThreadAttributeList(list, 1, 0, &mut size) == 0 {
+                bail!("Could not set up the process attributes");
+            }
+            if UpdateProcThreadAttribute(
+                list,
+                0,
+                PROC_THREAD_ATTRIBUTE_PSEUDOCONSOLE as usize,
+                pty as *const c_void,
+                size_of::<HPCON>(),
+                null_mut(),
+                null(),
+            ) == 0
+            {
+                DeleteProcThreadAttributeList(list);
+                bail!("Could not attach the pseudo console");
+            }
+        }
+
+        // SAFETY: STARTUPINFOEXW is plain data; zero is its documented default.
+        let mut startup: STARTUPINFOEXW = unsafe { std::mem::zeroed() };
+        startup.StartupInfo.cb = size_of::<STARTUPINFOEXW>() as u32;
+        // Without this the child inherits our redirected handles, if any, instead
+        // of the pseudo console's.
+        startup.StartupInfo.dwFlags = STARTF_USESTDHANDLES;
+        startup.lpAttributeList = list;
+
+        let mut result = None;
+        for program in &programs {
+            let mut line = command_line(program, shell, command);
+            // SAFETY: zero is a valid PROCESS_INFORMATION to be filled in.
+            let mut process: PROCESS_INFORMATION = unsafe { std::mem::zeroed() };
+            // SAFETY: line is a mutable NUL-terminated buffer and startup points
+            // at a valid attribute list for the duration of the call.
+            let ok = unsafe {
+                CreateProcessW(
+                    null(),
+                    line.as_mut_ptr(),
+                    null(),
+                    null(),
+                    0,
+                    EXTENDED_STARTUPINFO_PRESENT,
+                    null(),
+                    null(),
+                    &startup.StartupInfo,
+                    &mut process,
+                )
+            };
+            if ok != 0 {
+                result = Some(Ok(process));
+                break;
+            }
+            // SAFETY: GetLastError has no preconditions.
+            let error = unsafe { GetLastError() };
+            result = Some(Err(anyhow::anyhow!(
+                "Failed to spawn {}: {}",
+                program,
+                std::io::Error::from_raw_os_error(error as i32)
+            )));
+            if error != ERROR_FILE_NOT_FOUND {
+                break;
+            }
+        }
+        // SAFETY: the list was initialized above and is no longer needed.
+        unsafe { DeleteProcThreadAttributeList(list) };
+        result.expect("at least one program to try")
+    }
+
+    /// Copy bytes from one handle to another until either side closes, keeping
+    /// a copy in `capture` if given.
+    fn relay(from: Handle, to: Handle, mut capture: Option<&mut Capture>) {
+        let mut buf = [0u8; 4096];
+        loop {
+            let mut read = 0u32;
+            // SAFETY: buf is valid for its length and `read` receives the count.
+            let ok = unsafe {
+                ReadFile(
+                    from.0,
+                    buf.as_mut_ptr(),
+                    buf.len() as u32,
+                    &mut read,
+                    null_mut(),
+                )
+            };
+            if ok == 0 || read == 0 {
+                return;
+            }
+            if let Some(capture) = capture.as_deref_mut() {
+                capture.push(&buf[..read as usize]);
+            }
+            let mut written = 0;
+            while written < read {
+                let mut n = 0u32;
+                // SAFETY: the written range lies within the bytes just read.
+                let ok = unsafe {
+                    WriteFile(
+                        to.0,
+                        buf[written as usize..].as_ptr(),
+                        read - written,
+                        &mut n,
+                        null_mut(),
+                    )
+                };
+                if ok == 0 {
+                    return;
+                }
+                written += n;
+            }
+        }
+    }
+
+    /// The console window size, or None when `output` is not a console.
+    fn console_size(output: HANDLE) -> Option<COORD> {
+        // SAFETY: zero is a valid CONSOLE_SCREEN_BUFFER_INFO to be filled in.
+        let mut info: CONSOLE_SCREEN_BUFFER_INFO = unsafe { std::mem::zeroed() };
+        // SAFETY: info is valid for writes; a non-console handle makes the call fail.
+        if output == INVALID_HANDLE_VALUE
+            || unsafe { GetConsoleScreenBufferInfo(output, &mut info) } == 0
+        {
+            return None;
+        }
+        let window = info.srWindow;
+        Some(COORD {
+            X: window.Right - window.Left + 1,
+            Y: window.Bottom - window.Top + 1,
+        })
+    }
+
+    /// Console settings changed while the pseudo console is in use, restored on drop.
+    struct ConsoleState {
+        input: HANDLE,
+        output: HANDLE,
+        input_mode: CONSOLE_MODE,
+        output_mode: CONSOLE_MODE,
+        input_cp: u32,
+        output_cp: u32,
+    }
+
+    impl ConsoleState {
+        /// Switch the console to raw VT input and output in UTF-8, which is what
+        /// the pseudo console reads and writes.
+        fn enter(input: HANDLE, output: HANDLE) -> Option<ConsoleState> {
+            let (mut input_mode, mut output_mode) = (0, 0);
+            // SAFETY: both handles come from GetStdHandle; the modes are valid for writes.
+            unsafe {
+                if GetConsoleMode(input, &mut input_mode) == 0
+                    || GetConsoleMode(output, &mut output_mode) == 0
+                {
+                    return None;
+                }
+            }
+            // SAFETY: the code page getters have no preconditions.
+            let state = unsafe {
+                ConsoleState {
+                    input,
+                    output,
+                    input_mode,
+                    output_mode,
+                    input_cp: GetConsoleCP(),
+                    output_cp: GetConsoleOutputCP(),
+                }
+            };
+            // Ctrl+C is passed through as a keystroke so it reaches the child
+            // rather than interrupting us.
+            let raw_input = (input_mode | ENABLE_VIRTUAL_TERMINAL_INPUT)
+                & !(ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT | ENABLE_PROCESSED_INPUT);
+            // SAFETY: plain console setters on our own console handles.
+            unsafe {
+                SetConsoleMode(input, raw_input);
+                SetConsoleMode(
+                    output,
+                    output_mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING | DISABLE_NEWLINE_AUTO_RETURN,
+                );
+                SetConsoleCP(CP_UTF8);
+                SetConsoleOutputCP(CP_UTF8);
+            }
+            Some(state)
+        }
+    }
+
+    impl Drop for ConsoleState {
+        fn drop(&mut self) {
+            // SAFETY: restores the values read in `enter` on the same handles.
+            unsafe {
+                SetConsoleMode(self.input, self.input_mode);
+                SetConsoleMode(self.output, self.output_mode);
+                SetConsoleCP(self.input_cp);
+                SetConsoleOutputCP(self.output_cp);
+            }
+        }
+    }
+
+    /// Decode bytes in the console's code page (the OEM one when detached),
+    /// which is what console programs write when their output is captured.
+    pub fn from_code_page(bytes: &[u8]) -> String {
+        if bytes.is_empty() {
+            return String::new();
+        }
+        // SAFETY: the code page getters have no preconditions.
+        let code_page = match unsafe { GetConsoleOutputCP() } {
+            0 => unsafe { GetOEMCP() },
+            cp => cp,
+        };
+        let len = bytes.len().min(i32::MAX as usize) as i32;
+        // SAFETY: a null output buffer asks for the required length.
+        let wide_len =
+            unsafe { MultiByteToWideChar(code_page, 0, bytes.as_ptr(), len, null_mut(), 0) };
+        if wide_len <= 0 {
+            return String::from_utf8_lossy(bytes).into_owned();
+        }
+        let mut wide = vec![0u16; wide_len as usize];
+        // SAFETY: wide has room for the length reported above.
+        let written = unsafe {
+            MultiByteToWideChar(
+                code_page,
+                0,
+                bytes.as_ptr(),
+                len,
+                wide.as_mut_ptr(),
+                wide_len,
+            )
+        };
+        wide.truncate(written.max(0) as usize);
+        String::from_utf16_lossy(&wide)
+    }
+
+    fn pipe() -> Result<(HANDLE, HANDLE)> {
+        let (mut read, mut write) = (null_mut(), null_mut());
+        // SAFETY: both out-pointers are valid; default security, default size.
+        if unsafe { CreatePipe(&mut read, &mut write, null(), 0) } == 0 {
+            bail!(
+                "Could not create a pipe: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        Ok((read, write))
+    }
+
+    fn close(handles: &[HANDLE]) {
+        for &handle in handles {
+            // SAFETY: each handle is owned here and closed once.
+            unsafe { CloseHandle(handle) };
+        }
+    }
+
+    /// A NUL-terminated command line that CommandLineToArgvW (and so the C
+    /// runtime of the child) splits back into `program`, `flags` and `command`.
+    fn command_line(program: &str, shell: Shell, command: &str) -> Vec<u16> {
+        let mut line = String::from(program);
+        for flag in shell.command_flags() {
+            line.push(' ');
+            line.push_str(&quote_arg(flag));
+        }
+        line.push(' ');
+        // cmd takes the rest of the line as written, quotes and all.
+        if shell == Shell::Cmd {
+            line.push_str(&format!("\"{}\"", command));
+        } else {
+            line.push_str(&quote_arg(command));
+        }
+        line.encode_utf16().chain([0]).collect()
+    }
+
+    fn quote_arg(arg: &str) -> String {
+        if !arg.is_empty() && !arg.contains([' ', '\t', '\n', '"']) {
+            return arg.to_string();
+        }
+        let mut quoted = String::from('"');
+        let mut backslashes = 0;
+        for c in arg.chars() {
+            match c {
+                '\\' => backslashes += 1,
+                '"' => {
+                    // Backslashes before a quote are escaped, and so is the quote.
+                    quoted.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                    quoted.push('"');
+                    backslashes = 0;
+                    continue;
+                }
+                _ => {}
+            }
+            if c != '\\' {
+                quoted.extend(std::iter::repeat_n('\\', backslashes));
+                backslashes = 0;
+                quoted.push(c);
+            }
+        }
+        // Backslashes before the closing quote are doubled so they stay literal.
+        quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+        quoted.push('"');
+        quoted
+    }
+}
+
+#[cfg(unix)]
+mod pty {
+    use super::Capture;
+    use crate::cancel;
+    use crate::shell::Shell;
+    use anyhow::{Context, Result, bail};
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+    use std::os::unix::process::CommandExt;
+    use std::process::{Child, ExitStatus, Stdio};
+
+    const STDIN: RawFd = 0;
+    const STDOUT: RawFd = 1;
+    /// How often to check for window size changes and a command that exited
+    /// while something it started still holds the terminal open.
+    const POLL_MS: i32 = 100;
+
+    /// Run `command` on a new pseudo terminal, relaying it to ours, or return
+    /// None when stdin and stdout are not a terminal.
+    pub fn run(shell: Shell, command: &str) -> Result<Option<(ExitStatus, Capture)>> {
+        // SAFETY: isatty has no preconditions.
+        if unsafe { libc::isatty(STDIN) == 0 || libc::isatty(STDOUT) == 0 } {
+            return Ok(None);
+        }
+        let Some(saved) = get_termios(STDIN) else {
+            return Ok(None);
+        };
+        let mut size = window_size();
+        let mut termios = saved;
+        let (mut master, mut slave) = (-1, -1);
+        // SAFETY: all pointers are valid for the call; the new terminal starts
+        // with our settings and window size.
+        let opened = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                std::ptr::null_mut(),
+                // macOS declares these as mutable, Linux as const.
+                &raw mut termios,
+                &raw mut size,
+            )
+        };
+        if opened != 0 {
+            bail!(
+                "Could not open a pseudo terminal: {}",
+                io::Error::last_os_error()
+            );
+        }
+        // SAFETY: openpty returned two new descriptors that we now own.
+        let (master, slave) =
+            unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+        let _handling = cancel::handling()?;
+
+        let mut process = shell.target_command(command);
+        process
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave));
+        // SAFETY: only async-signal-safe calls run between fork and exec.
+        unsafe {
+            process.pre_exec(|| {
+                // A session of its own with the new terminal as the controlling
+                // one, so job control and Ctrl+C reach the command.
+                if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let mut child = process
+            .spawn()
+            .with_context(|| format!("Failed to spawn {}", shell))?;
+        // The command keeps our copies of the terminal side open; until they
+        // are closed reading the master never sees the command exit.
+        drop(process);
+
+        let raw = RawMode::enter(saved);
+        let capture = relay(master.as_raw_fd(), &mut child, size);
+        drop(raw);
+        // The command has its own session, so its group is everything it started.
+        let status = super::wait(&mut child, true)?;
+        Ok(Some((status, capture?)))
+    }
+
+    /// Copy keystrokes to the command and its output to our terminal until the
+    /// command closes its terminal or exits.
+    fn relay(master: RawFd, child: &mut Child, mut size: libc::winsize) -> Result<Capture> {
+        let mut capture = Capture::default();
+        let mut buf = [0u8; 4096];
+        let mut stdin_open = true;
+        loop {
+            // Stopping the command is left to `wait`.
+            if cancel::requested() {
+                return Ok(capture);
+            }
+            let mut fds = [
+                libc::pollfd {
+                    fd: master,
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+                libc::pollfd {
+                    // Negative descriptors are ignored by poll.
+                    fd: if stdin_open { STDIN } else { -1 },
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+            ];
+            // SAFETY: fds is a valid array of two pollfd structs.
+            let ready = unsafe { libc::poll(fds.as_mut_ptr(), 2, POLL_MS) };
+            if ready < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err.into());
+            }
+            if ready == 0 {
+                resize(master, &mut size);
+                if child.try_wait()?.is_some() {
+                    return Ok(capture);
+                }
+                continue;
+            }
+            if fds[0].revents != 0 {
+                // SAFETY: buf is valid for writes of its length.
+                let n = unsafe { libc::read(master, buf.as_mut_ptr().cast(), buf.len()) };
+                // Linux reports EIO once the command side is closed.
+                if n <= 0 {
+                    return Ok(capture);
+                }
+                let data = &buf[..n as usize];
+                write_all(STDOUT, data);
+                capture.push(data);
+            }
+            if fds[1].revents != 0 {
+                // SAFETY: buf is valid for writes of its length.
+                let n = unsafe { libc::read(STDIN, buf.as_mut_ptr().cast(), buf.len()) };
+                if n <= 0 {
+                    stdin_open = false;
+                } else {
+                    write_all(master, &buf[..n as usize]);
+                }
+            }
+        }
+    }
+
+    fn write_all(fd: RawFd, mut data: &[u8]) {
+        while !data.is_empty() {
+            // SAFETY: data is valid for reads of its length.
+            let n = unsafe { libc::write(fd, data.as_ptr().cast(), data.len()) };
+            if n < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            if n <= 0 {
+                return;
+            }
+            data = &data[n as usize..];
+        }
+    }
+
+    fn window_size() -> libc::winsize {
+        // SAFETY: winsize is plain data and TIOCGWINSZ fills it in; on failure
+        // it stays zeroed, which terminals treat as unknown.
+        unsafe {
+            let mut size: libc::winsize = std::mem::zeroed();
+            libc::ioctl(STDOUT, libc::TIOCGWINSZ, &mut size);
+            size
+        }
+    }
+
+    /// Pass a change in our window size on to the command's terminal.
+    fn resize(master: RawFd, size: &mut libc::winsize) {
+        let now = window_size();
+        if (now.ws_row, now.ws_col) != (size.ws_row, size.ws_col) {
+            // SAFETY: now is a valid winsize for the duration of the call.
+            unsafe { libc::ioctl(master, libc::TIOCSWINSZ, &now) };
+            *size = now;
+        }
+    }
+
+    fn get_termios(fd: RawFd) -> Option<libc::termios> {
+        // SAFETY: termios is plain data that tcgetattr fills in.
+        unsafe {
+            let mut termios: libc::termios = std::mem::zeroed();
+            (libc::tcgetattr(fd, &mut termios) == 0).then_some(termios)
+        }
+    }
+
+    /// Our terminal in raw mode, so every keystroke (Ctrl+C included) goes to
+    /// the command's terminal as typed. Restored on drop.
+    struct RawMode(libc::termios);
+
+    impl RawMode {
+        fn enter(saved: libc::termios) -> RawMode {
+            let mut raw = saved;
+            // SAFETY: raw is a valid termios copied from the current settings.
+            unsafe {
+                libc::cfmakeraw(&mut raw);
+                libc::tcsetattr(STDIN, libc::TCSANOW, &raw);
+            }
+            RawMode(saved)
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            // SAFETY: restores the settings read before entering raw mode.
+            unsafe { libc::tcsetattr(STDIN, libc::TCSANOW, &self.0) };
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use crate::api::{Client, Turn};
+use crate::shell::Shell;
+use anyhow::Result;
+use std::io::{self, Write};
+
+const SYSTEM_PROMPT: &str = "You explain shell commands to someone deciding whether to run one. \
+Go through the command in order, one line per program, flag, argument, operator and redirection: \
+`PART: what it does here`, grouping a flag with its value. Then one line `Overall: ` saying what \
+the whole command does and what it changes or deletes, if anything. Plain text only, no Markdown \
+and no code fences.";
+
+/// Print a part-by-part breakdown of `command` as the model writes it. The
+/// answer is prose, so it is shown as it comes rather than cleaned up like
+/// a command.
+pub fn explain(client: &Client, shell: Shell, command: &str) -> Result<()> {
+    let request = format!("The command, for {}:\n{}", shell, command);
+    let mut printed = String::new();
+    let mut on_text = |delta: &str| {
+        print!("{}", delta);
+        let _ = io::stdout().flush();
+        printed.push_str(delta);
+    };
+    let (text, _) = client.converse_stream(SYSTEM_PROMPT, &[Turn::user(&request)], &mut on_text)?;
+    // An answer that had to be asked for again arrives all at once.
+    if printed.is_empty() {
+        print!("{}", text);
+        printed = text;
+    }
+    if !printed.ends_with('\n') {
+        println!();
+    }
+    Ok(())
+}
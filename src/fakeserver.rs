@@ -0,0 +1,169 @@
+//! A stand-in for the OpenAI Responses API on a local port, answering every
+//! request with canned text, plain or streamed as the request asks. The test
+//! suite runs the binary against it so no request leaves the machine.
+
+use serde_json::{Value, json};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub struct FakeServer {
+    base: String,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    requests: Vec<Value>,
+    answered: usize,
+    /// Statuses to turn the next requests away with, soonest first.
+    failures: VecDeque<u16>,
+}
+
+impl FakeServer {
+    /// Start answering requests with `answers` in turn, repeating the last
+    /// once they run out.
+    pub fn start(answers: &[&str]) -> std::io::Result<FakeServer> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let base = format!("http://{}/v1", listener.local_addr()?);
+        let answers: Vec<String> = answers.iter().map(|a| a.to_string()).collect();
+        let state = Arc::new(Mutex::new(State::default()));
+        let shared = Arc::clone(&state);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { continue };
+                let Some(body) = read_request(&stream) else {
+                    continue;
+                };
+                let mut state = shared.lock().unwrap_or_else(|e| e.into_inner());
+                state.requests.push(body.clone());
+                if let Some(status) = state.failures.pop_front() {
+                    let _ = write_failure(stream, status);
+                    continue;
+                }
+                let answer = answers
+                    .get(state.answered)
+                    .or(answers.last())
+                    .cloned()
+                    .unwrap_or_default();
+                state.answered += 1;
+                let streamed = body.get("stream").and_then(Value::as_bool) == Some(true);
+                let model = body.get("model").and_then(Value::as_str).unwrap_or("");
+                let _ = if streamed {
+                    write_stream(stream, model, &answer)
+                } else {
+                    write_json(stream, model, &answer)
+                };
+            }
+        });
+        Ok(FakeServer { base, state })
+    }
+
+    /// Turn the next `times` requests away with `status`, e.g. 429 to see
+    /// that llmwrap retries. They ask for no wait before trying again.
+    pub fn fail_next(&self, status: u16, times: usize) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.failures.extend(std::iter::repeat_n(status, times));
+    }
+
+    /// The URL to pass as `--api-base`.
+    pub fn base_url(&self) -> &str {
+        &self.base
+    }
+
+    /// The bodies of the requests received so far, oldest first.
+    pub fn requests(&self) -> Vec<Value> {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .requests
+            .clone()
+    }
+}
+
+/// The JSON body of one HTTP request; None when it isn't one.
+fn read_request(stream: &TcpStream) -> Option<Value> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).ok()?;
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = value.trim().parse().unwrap_or(0);
+        }
+    }
+    let mut body = Vec::new();
+    reader.take(length).read_to_end(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+/// A completed response whose only output is `text`.
+fn response(model: &str, text: &str) -> Value {
+    json!({
+        "id": "resp_fake",
+        "object": "response",
+        "status": "completed",
+        "model": model,
+        "output": [{
+            "id": "msg_fake",
+            "type": "message",
+            "status": "completed",
+            "role": "assistant",
+            "content": [{"type": "output_text", "annotations": [], "text": text}],
+        }],
+        "usage": {"input_tokens": 100, "output_tokens": 10, "total_tokens": 110},
+    })
+}
+
+fn write_json(mut stream: TcpStream, model: &str, text: &str) -> std::io::Result<()> {
+    let body = response(model, text).to_string();
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )
+}
+
+fn write_failure(mut stream: TcpStream, status: u16) -> std::io::Result<()> {
+    let body =
+        json!({"error": {"message": "Turned away by the fake server", "type": "fake_error"}})
+            .to_string();
+    write!(
+        stream,
+        "HTTP/1.1 {} Fake\r\nContent-Type: application/json\r\nContent-Length: {}\r\nRetry-After: 0\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+/// Send `text` as server-sent events a few characters at a time, then the
+/// whole response, as the API does when asked to stream.
+fn write_stream(mut stream: TcpStream, model: &str, text: &str) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\n"
+    )?;
+    let chars: Vec<char> = text.chars().collect();
+    for chunk in chars.chunks(8) {
+        let delta: String = chunk.iter().collect();
+        let event = json!({"type": "response.output_text.delta", "delta": delta});
+        write!(
+            stream,
+            "event: response.output_text.delta\ndata: {}\n\n",
+            event
+        )?;
+        stream.flush()?;
+    }
+    let event = json!({"type": "response.completed", "response": response(model, text)});
+    write!(stream, "event: response.completed\ndata: {}\n\n", event)
+}
@@ -0,0 +1,101 @@
+use crate::exec;
+use crate::history;
+use crate::session;
+use crate::shell::Shell;
+use anyhow::{Context, Result};
+use std::process::Stdio;
+
+/// How much of the end of a failed command's output goes to the model.
+const OUTPUT_LIMIT: usize = 4000;
+
+/// A command that failed and what is known about how.
+pub struct Failure {
+    /// What the command was meant to do, when llmwrap wrote it.
+    pub prompt: Option<String>,
+    pub command: String,
+    pub exit_code: Option<i32>,
+    /// What it printed, or at least its errors.
+    pub output: Option<String>,
+}
+
+impl Failure {
+    /// The request asking the model for a corrected command.
+    pub fn request(&self) -> String {
+        let mut request = String::new();
+        if let Some(prompt) = &self.prompt {
+            request.push_str(&format!("The task: {}\n\n", prompt));
+        }
+        let status = match self.exit_code {
+            Some(code) => format!("exit status {}", code),
+            None => "a signal".to_string(),
+        };
+        request.push_str(&format!(
+            "This command failed with {}:\n{}\n",
+            status, self.command
+        ));
+        match self
+            .output
+            .as_deref()
+            .map(str::trim)
+            .filter(|o| !o.is_empty())
+        {
+            Some(output) => request.push_str(&format!("\nIt printed:\n{}\n", tail(output))),
+            None => request.push_str("\nIts output was not captured.\n"),
+        }
+        request.push_str(
+            "\nWork out why it failed and give a corrected command that does what it was meant \
+to do.",
+        );
+        request
+    }
+}
+
+/// The last command llmwrap ran, if it failed, with the output kept from it.
+pub fn last_failure() -> Option<Failure> {
+    let entry = history::load().into_iter().rev().find(|h| h.executed)?;
+    if entry.exit_code == Some(0) {
+        return None;
+    }
+    let output = session::output_since(entry.timestamp);
+    Some(Failure {
+        prompt: Some(entry.prompt).filter(|p| !p.is_empty()),
+        command: entry.command,
+        exit_code: entry.exit_code,
+        output,
+    })
+}
+
+/// Run `command` to see how it fails, showing its output as usual while
+/// keeping what it writes to stderr. None when it succeeds.
+pub fn capture(shell: Shell, command: &str) -> Result<Option<Failure>> {
+    let output = shell
+        .target_command(&shell.runnable(command))
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::piped())
+        .output()
+        .with_context(|| format!("Failed to spawn {}", shell))?;
+    let errors = exec::decode_output(&output.stderr);
+    eprint!("{}", errors);
+    if output.status.success() {
+        return Ok(None);
+    }
+    Ok(Some(Failure {
+        prompt: None,
+        command: command.to_string(),
+        exit_code: output.status.code(),
+        output: Some(errors),
+    }))
+}
+
+/// The end of `output`, where the error usually is.
+fn tail(output: &str) -> &str {
+    if output.len() <= OUTPUT_LIMIT {
+        return output;
+    }
+    let mut start = output.len() - OUTPUT_LIMIT;
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+    &output[start..]
+}
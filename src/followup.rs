@@ -0,0 +1,94 @@
+use crate::history;
+use crate::session;
+
+/// How much of a command's output goes to the model with a follow-up, split
+/// between its start and its end.
+const OUTPUT_LIMIT: usize = 8000;
+
+/// A command that ran and what it printed, to follow up on.
+pub struct Ran {
+    /// What the command was meant to do.
+    pub prompt: String,
+    pub command: String,
+    pub exit_code: Option<i32>,
+    pub output: Option<String>,
+}
+
+impl Ran {
+    /// The request for a new command doing `followup`, which may refer to
+    /// what this one printed, e.g. "now delete the ones older than 30 days".
+    pub fn request(&self, followup: &str) -> String {
+        let mut request = format!(
+            "The previous request: {}\n\nThe command run for it:\n{}\n",
+            self.prompt, self.command
+        );
+        let status = match self.exit_code {
+            Some(0) => "It succeeded".to_string(),
+            Some(code) => format!("It exited with status {}", code),
+            None => "It was killed by a signal".to_string(),
+        };
+        match self.output.as_deref().filter(|o| !o.trim().is_empty()) {
+            Some(output) => request.push_str(&format!(
+                "\n{} and printed:\n{}\n",
+                status,
+                clip(trim_blank_lines(output))
+            )),
+            None => request.push_str(&format!("\n{}; its output was not captured.\n", status)),
+        }
+        request.push_str(&format!(
+            "\nNow: {}\n\nGive a new command for this, using what the previous one printed where \
+the request refers to it.",
+            followup
+        ));
+        request
+    }
+}
+
+/// The last command llmwrap ran, with the output kept from it.
+pub fn last_run() -> Option<Ran> {
+    let entry = history::load().into_iter().rev().find(|h| h.executed)?;
+    Some(Ran {
+        output: session::output_since(entry.timestamp),
+        prompt: entry.prompt,
+        command: entry.command,
+        exit_code: entry.exit_code,
+    })
+}
+
+/// `request` with what the last command printed in front, for the REPL,
+/// where the command itself is already in the conversation.
+pub fn with_output(output: &str, request: &str) -> String {
+    format!(
+        "That command printed:\n{}\n\nNext request, which may refer to that output: {}",
+        clip(trim_blank_lines(output)),
+        request
+    )
+}
+
+/// `output` without the blank lines around it.
+fn trim_blank_lines(output: &str) -> &str {
+    output.trim_matches(|c| c == '\n' || c == '\r')
+}
+
+/// `output`, with its middle left out when it is long: a listing matters
+/// from the start, an error at the end.
+fn clip(output: &str) -> String {
+    if output.len() <= OUTPUT_LIMIT {
+        return output.to_string();
+    }
+    let mut head = OUTPUT_LIMIT / 2;
+    while !output.is_char_boundary(head) {
+        head -= 1;
+    }
+    let mut tail = output.len() - OUTPUT_LIMIT / 2;
+    while !output.is_char_boundary(tail) {
+        tail += 1;
+    }
+    let skipped = output[head..tail].lines().count();
+    format!(
+        "{}\n[... {} lines left out ...]\n{}",
+        &output[..head],
+        skipped,
+        &output[tail..]
+    )
+}
@@ -0,0 +1,158 @@
+use crate::api;
+use crate::exec;
+use crate::patch;
+use crate::shell::Shell;
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+const BEGIN_MARKER: &str = "# >>> llmwrap function: ";
+const END_MARKER: &str = "# <<< llmwrap function: ";
+
+/// A generated shell function, ready to be appended to an rc file.
+pub struct ShellFunction {
+    pub name: String,
+    pub body: String,
+}
+
+pub fn generate(client: &api::Client, shell: Shell, description: &str) -> Result<ShellFunction> {
+    let system_prompt = format!(
+        "You write reusable {shell} shell functions. Respond with only one complete function \
+definition for {shell}, no explanations, no code fences. Give it a short, descriptive \
+snake_case name that does not shadow common commands. Quote variables safely and print a \
+usage message when required arguments are missing."
+    );
+    let raw = client.complete(&system_prompt, description)?;
+    let body = api::strip_code_fences(&raw);
+    let name = function_name(shell, &body)
+        .with_context(|| format!("The model did not return a {} function:\n{}", shell, body))?;
+    Ok(ShellFunction { name, body })
+}
+
+/// Run the shell's syntax checker over the function, when the shell is installed.
+pub fn check_syntax(shell: Shell, function: &ShellFunction) -> Result<()> {
+    let flag = match shell {
+        Shell::Fish => "--no-execute",
+        // nu, PowerShell and cmd have no way to parse a script without running it.
+        Shell::Nu | Shell::Pwsh | Shell::Cmd => return Ok(()),
+        _ => "-n",
+    };
+    let Ok(mut child) = Command::new(shell.binary())
+        .arg(flag)
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    else {
+        return Ok(());
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(function.body.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} reports a syntax error: {}",
+            shell,
+            exec::decode_output(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Add (or replace) the function inside its managed block in the rc file.
+pub fn install(shell: Shell, function: &ShellFunction) -> Result<PathBuf> {
+    let path = rc_file(shell)?;
+    let existing = patch::read_or_empty(&path)?;
+    let mut text = remove_block(&existing, &function.name).unwrap_or(existing);
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text.push_str(&format!(
+        "{BEGIN_MARKER}{name} >>>\n{body}\n{END_MARKER}{name} <<<\n",
+        name = function.name,
+        body = function.body.trim_end()
+    ));
+    patch::write(&path, &text)?;
+    Ok(path)
+}
+
+/// Names of the functions llmwrap has installed in the rc file.
+pub fn list(shell: Shell) -> Result<Vec<String>> {
+    let text = patch::read_or_empty(&rc_file(shell)?)?;
+    Ok(text
+        .lines()
+        .filter_map(|line| line.strip_prefix(BEGIN_MARKER))
+        .map(|rest| rest.trim_end_matches(" >>>").to_string())
+        .collect())
+}
+
+pub fn is_installed(shell: Shell, name: &str) -> Result<bool> {
+    Ok(list(shell)?.iter().any(|n| n == name))
+}
+
+/// Remove a managed function; returns false when no such function was installed.
+pub fn remove(shell: Shell, name: &str) -> Result<bool> {
+    let path = rc_file(shell)?;
+    let text = patch::read_or_empty(&path)?;
+    match remove_block(&text, name) {
+        Some(updated) => {
+            patch::write(&path, &updated)?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+pub fn rc_file(shell: Shell) -> Result<PathBuf> {
+    if shell == Shell::Cmd {
+        anyhow::bail!("cmd has no startup file to install functions into; use --shell pwsh");
+    }
+    shell
+        .rc_file()
+        .context("Cannot locate your shell rc file because HOME is not set")
+}
+
+fn remove_block(text: &str, name: &str) -> Option<String> {
+    let begin = format!("{BEGIN_MARKER}{name} >>>");
+    let end = format!("{END_MARKER}{name} <<<");
+    let mut result = String::new();
+    let mut inside = false;
+    let mut found = false;
+    for line in text.lines() {
+        if line == begin {
+            inside = true;
+            found = true;
+        } else if inside && line == end {
+            inside = false;
+        } else if !inside {
+            result.push_str(line);
+            result.push('\n');
+        }
+    }
+    found.then_some(result)
+}
+
+/// Find the name of the first function defined in `body`.
+fn function_name(shell: Shell, body: &str) -> Option<String> {
+    let line = body.lines().map(str::trim).find(|l| !l.is_empty())?;
+    let name = if shell == Shell::Fish {
+        line.strip_prefix("function ")?.split_whitespace().next()?
+    } else if shell == Shell::Nu {
+        // `def name [args] { ... }`, possibly with flags such as `def --env`.
+        line.strip_prefix("def ")?
+            .split_whitespace()
+            .find(|w| !w.starts_with("--"))?
+            .trim_matches(|c| c == '"' || c == '\'')
+    } else if let Some(rest) = line.strip_prefix("function ") {
+        rest.split(|c: char| c.is_whitespace() || c == '(' || c == '{')
+            .next()?
+    } else {
+        line.split_once("()")?.0.trim()
+    };
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+    valid.then(|| name.to_string())
+}
@@ -0,0 +1,153 @@
+use std::collections::BTreeSet;
+
+// Filler words that change the phrasing of a request but not what it asks for,
+// e.g. "gif from video.mp4" vs "make a gif out of video.mp4".
+const STOPWORDS: &[&str] = &[
+    "a", "an", "the", "of", "out", "from", "into", "to", "in", "for", "with", "using", "make",
+    "create", "please", "me", "my", "i", "want", "how", "do", "can", "you", "some", "this", "that",
+    "and",
+];
+
+/// Reduce a prompt to a canonical set of meaningful tokens.
+pub fn normalize(prompt: &str) -> BTreeSet<String> {
+    prompt
+        .split_whitespace()
+        .filter_map(normalize_word)
+        .collect()
+}
+
+/// Canonical form of a single word, or `None` for punctuation and filler words.
+pub fn normalize_word(word: &str) -> Option<String> {
+    canonical(word).filter(|w| !STOPWORDS.contains(&w.as_str()))
+}
+
+/// Lowercased, punctuation-trimmed and plural-folded form of a word.
+pub fn canonical(word: &str) -> Option<String> {
+    let word = word
+        .trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase();
+    (!word.is_empty()).then(|| stem(&word))
+}
+
+/// Numbers and paths in a prompt, which a similar prompt has to share to be
+/// given the same command: files older than 7 days are not those older than 30.
+pub fn specifics(prompt: &str) -> BTreeSet<String> {
+    prompt
+        .split_whitespace()
+        .map(|word| {
+            word.trim_matches(|c: char| !c.is_alphanumeric() && !"/~._-".contains(c))
+                .trim_end_matches('.')
+        })
+        .filter(|word| {
+            word.chars()
+                .any(|c| c.is_ascii_digit() || c == '/' || c == '~')
+                || word
+                    .rsplit_once('.')
+                    .is_some_and(|(stem, ext)| !stem.is_empty() && !ext.is_empty())
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+/// Jaccard similarity between two normalized prompts, from 0.0 to 1.0.
+pub fn similarity(a: &BTreeSet<String>, b: &BTreeSet<String>) -> f64 {
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    let shared = a.intersection(b).count();
+    let total = a.union(b).count();
+    shared as f64 / total as f64
+}
+
+/// How alike two strings are by Levenshtein distance, from 0.0 to 1.0.
+pub fn edit_similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    1.0 - row[b.len()] as f64 / longest as f64
+}
+
+// Crude plural folding so "files" and "file" compare equal; filenames keep
+// their exact spelling.
+fn stem(word: &str) -> String {
+    if word.len() > 3 && word.ends_with('s') && !word.ends_with("ss") && !word.contains('.') {
+        word[..word.len() - 1].to_string()
+    } else {
+        word.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(words: &[&str]) -> BTreeSet<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn normalize_drops_filler_and_punctuation() {
+        assert_eq!(normalize("gif from video.mp4"), set(&["gif", "video.mp4"]));
+        assert_eq!(
+            normalize("Make a GIF out of video.mp4, please!"),
+            set(&["gif", "video.mp4"])
+        );
+        assert!(normalize("the of -- ?").is_empty());
+    }
+
+    #[test]
+    fn stem_folds_plurals_only() {
+        assert_eq!(stem("files"), "file");
+        assert_eq!(stem("logs"), "log");
+        // Too short, a double s, or a file name.
+        assert_eq!(stem("ls"), "ls");
+        assert_eq!(stem("bus"), "bus");
+        assert_eq!(stem("process"), "process");
+        assert_eq!(stem("notes.rs"), "notes.rs");
+    }
+
+    #[test]
+    fn similarity_is_jaccard() {
+        assert_eq!(similarity(&set(&[]), &set(&[])), 1.0);
+        assert_eq!(similarity(&set(&["a", "b"]), &set(&["a", "b"])), 1.0);
+        assert_eq!(similarity(&set(&["a", "b"]), &set(&["c"])), 0.0);
+        assert_eq!(
+            similarity(&set(&["a", "b", "c"]), &set(&["a", "b"])),
+            2.0 / 3.0
+        );
+    }
+
+    #[test]
+    fn specifics_are_numbers_and_paths() {
+        assert_eq!(
+            specifics("delete logs older than 7 days in /var/log."),
+            set(&["7", "/var/log"])
+        );
+        assert_eq!(
+            specifics("convert \"video.mp4\" to ~/out.gif, at 2x"),
+            set(&["video.mp4", "~/out.gif", "2x"])
+        );
+        assert!(specifics("list the files here.").is_empty());
+    }
+
+    #[test]
+    fn edit_similarity_counts_edits() {
+        assert_eq!(edit_similarity("", ""), 1.0);
+        assert_eq!(edit_similarity("kitten", "kitten"), 1.0);
+        assert_eq!(edit_similarity("abcd", "abce"), 0.75);
+        assert_eq!(edit_similarity("abc", ""), 0.0);
+    }
+}
@@ -0,0 +1,98 @@
+use crate::api::Usage;
+use crate::paths;
+use crate::risk::RiskLevel;
+use crate::store;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HistoryEntry {
+    pub timestamp: u64,
+    pub prompt: String,
+    pub command: String,
+    /// Shell the command was written for; absent for POSIX sh.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+    /// Host the command was meant to run on over SSH; absent for this machine.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    pub executed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// Model that wrote the command; absent when it came from the cache or a
+    /// local rewrite.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub risk: Option<RiskLevel>,
+}
+
+impl HistoryEntry {
+    pub fn new(prompt: &str, command: &str) -> Self {
+        HistoryEntry {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            prompt: prompt.trim().to_string(),
+            command: command.to_string(),
+            shell: None,
+            host: None,
+            executed: false,
+            exit_code: None,
+            model: None,
+            usage: None,
+            risk: None,
+        }
+    }
+}
+
+/// Read every recorded entry, oldest first. Lines that fail to parse are skipped.
+pub fn load() -> Vec<HistoryEntry> {
+    history_file()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|text| {
+            text.lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Entries whose request or command contains every word of `query`, in any
+/// case, oldest first, with only the latest of commands that repeat.
+pub fn search(query: &str) -> Vec<HistoryEntry> {
+    let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+    let mut found: Vec<HistoryEntry> = Vec::new();
+    for entry in load() {
+        let text = format!("{}\n{}", entry.prompt, entry.command).to_lowercase();
+        if !words.iter().all(|word| text.contains(word)) {
+            continue;
+        }
+        found.retain(|earlier| earlier.command != entry.command);
+        found.push(entry);
+    }
+    found
+}
+
+pub fn append(entry: &HistoryEntry) -> Result<()> {
+    let Some(path) = history_file() else {
+        return Ok(());
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create data directory {}", dir.display()))?;
+    }
+    let line = serde_json::to_string(entry)?;
+    store::append_line(&path, &line)
+        .with_context(|| format!("Failed to write history file {}", path.display()))
+}
+
+fn history_file() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join("history.jsonl"))
+}
@@ -0,0 +1,60 @@
+use crate::shell::Shell;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::io::Write;
+use std::process::Stdio;
+
+/// Commands from `[hooks]` in `config.toml`, run with the shell around each
+/// command llmwrap executes. Each gets a JSON description of the command on
+/// stdin.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hooks {
+    /// Runs before the command; a non-zero exit stops the command from running.
+    pub pre_exec: Option<String>,
+    /// Runs after the command with its exit status and where its output was saved.
+    pub post_exec: Option<String>,
+}
+
+impl Hooks {
+    /// Run the pre-exec hook, returning whether the command may run.
+    pub fn pre_exec(&self, event: &Value) -> Result<bool> {
+        match &self.pre_exec {
+            Some(hook) => run(hook, event),
+            None => Ok(true),
+        }
+    }
+
+    /// Run the post-exec hook. Its exit status is ignored: the command has
+    /// already run.
+    pub fn post_exec(&self, event: &Value) -> Result<()> {
+        if let Some(hook) = &self.post_exec {
+            run(hook, event)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run `hook` with `event` on stdin, returning whether it succeeded. Its own
+/// output goes straight to the terminal so it can explain a veto.
+fn run(hook: &str, event: &Value) -> Result<bool> {
+    let shell = if cfg!(windows) {
+        Shell::Pwsh
+    } else {
+        Shell::Sh
+    };
+    let mut child = shell
+        .command(hook)
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run hook `{}`", hook))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that exits without reading its input is fine.
+        let _ = writeln!(stdin, "{}", event);
+    }
+    let status = child
+        .wait()
+        .with_context(|| format!("Failed to run hook `{}`", hook))?;
+    Ok(status.success())
+}
@@ -0,0 +1,86 @@
+use crate::cancel;
+use crate::shell::Shell;
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+const BASH: &str = include_str!("init/llmwrap.bash");
+const ZSH: &str = include_str!("init/llmwrap.zsh");
+const FISH: &str = include_str!("init/llmwrap.fish");
+const POWERSHELL: &str = include_str!("init/llmwrap.ps1");
+
+/// The integration script to load from the given shell's startup file.
+pub fn script(shell: Shell) -> Result<&'static str> {
+    match shell {
+        Shell::Bash => Ok(BASH),
+        Shell::Zsh => Ok(ZSH),
+        Shell::Fish => Ok(FISH),
+        Shell::Pwsh => Ok(POWERSHELL),
+        other => anyhow::bail!("`llmwrap init {}` is not supported yet", other),
+    }
+}
+
+/// Add the line that loads the script to the shell's startup file, returning
+/// the file and whether it had to be added.
+pub fn install(shell: Shell) -> Result<(PathBuf, bool)> {
+    let (line, file) = match shell {
+        Shell::Bash => ("eval \"$(llmwrap init bash)\"", home()?.join(".bashrc")),
+        Shell::Zsh => (
+            "eval \"$(llmwrap init zsh)\"",
+            dir_or_home("ZDOTDIR", "")?.join(".zshrc"),
+        ),
+        Shell::Fish => (
+            "llmwrap init fish | source",
+            dir_or_home("XDG_CONFIG_HOME", ".config")?.join("fish/config.fish"),
+        ),
+        Shell::Pwsh => anyhow::bail!(
+            "Add `llmwrap init powershell | Out-String | Invoke-Expression` to your $PROFILE"
+        ),
+        other => anyhow::bail!("`llmwrap init {}` is not supported yet", other),
+    };
+    let existing = match fs::read_to_string(&file) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => {
+            return Err(err).with_context(|| format!("Failed to read {}", file.display()));
+        }
+    };
+    if existing.contains(&format!("llmwrap init {}", shell)) {
+        return Ok((file, false));
+    }
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let separator = if existing.is_empty() || existing.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    };
+    cancel::shield(|| {
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&file)
+            .and_then(|mut out| {
+                writeln!(out, "{}\n# llmwrap: `llm` and Ctrl-G\n{}", separator, line)
+            })
+    })
+    .with_context(|| format!("Failed to write to {}", file.display()))?;
+    Ok((file, true))
+}
+
+/// The directory in `var` when set, otherwise `home_suffix` under $HOME.
+fn dir_or_home(var: &str, home_suffix: &str) -> Result<PathBuf> {
+    match std::env::var_os(var).filter(|dir| !dir.is_empty()) {
+        Some(dir) => Ok(PathBuf::from(dir)),
+        None => Ok(home()?.join(home_suffix)),
+    }
+}
+
+fn home() -> Result<PathBuf> {
+    std::env::var_os("HOME")
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+        .context("HOME is not set")
+}
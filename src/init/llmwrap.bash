@@ -0,0 +1,29 @@
+# llmwrap integration for bash. Load it from ~/.bashrc with:
+#   eval "$(llmwrap init bash)"
+
+# Generate a command for the description, confirm it, and run it in this
+# shell rather than a child of llmwrap, so cd, export and activating a
+# virtualenv stick. The command is added to the shell's history.
+llm() {
+    local command
+    command="$(command llmwrap --shell bash --eval "$@")" || return
+    [ -n "$command" ] || return 0
+    history -s -- "$command"
+    eval -- "$command"
+}
+
+# Ctrl-G replaces the command line with the command llmwrap generates for
+# it, ready to edit or run with Enter. Rebind with
+# `bind -x '"\C-x\C-g": __llmwrap_widget'` after loading.
+__llmwrap_widget() {
+    [ -n "$READLINE_LINE" ] || return 0
+    local command
+    command="$(command llmwrap --shell bash --print -- "$READLINE_LINE")" || return
+    [ -n "$command" ] || return 0
+    READLINE_LINE=$command
+    READLINE_POINT=${#READLINE_LINE}
+}
+
+if [[ $- == *i* ]]; then
+    bind -x '"\C-g": __llmwrap_widget'
+fi
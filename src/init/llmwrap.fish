@@ -0,0 +1,30 @@
+# llmwrap integration for fish. Load it from ~/.config/fish/config.fish with:
+#   llmwrap init fish | source
+
+# Generate a command for the description, confirm it, and run it in this
+# shell rather than a child of llmwrap, so cd, set -gx and activating a
+# virtualenv stick.
+function llm --description 'Generate a command with llmwrap and run it in this shell'
+    set -l command (command llmwrap --shell fish --eval $argv | string collect)
+    or return
+    test -n "$command"; or return 0
+    eval $command
+end
+
+# Ctrl-G replaces the command line with the command llmwrap generates for
+# it, ready to edit or run with Enter. Rebind with
+# `bind \cx\cg __llmwrap_widget` after loading.
+function __llmwrap_widget --description 'Replace the command line with the command llmwrap generates for it'
+    set -l line (commandline | string collect)
+    if test -n "$line"
+        set -l command (command llmwrap --shell fish --print -- $line | string collect)
+        and test -n "$command"
+        and commandline --replace -- $command
+    end
+    commandline --function repaint
+end
+
+if status is-interactive
+    bind \cg __llmwrap_widget
+    bind -M insert \cg __llmwrap_widget
+end
@@ -0,0 +1,42 @@
+# llmwrap integration for PowerShell. Load it from your $PROFILE with:
+#   llmwrap init powershell | Out-String | Invoke-Expression
+
+New-Module -Name LlmWrap -ScriptBlock {
+    # Generate a PowerShell command for the description and return it, without
+    # running it. Output is captured, so llmwrap stays in quiet print-only mode.
+    function Invoke-LlmWrap {
+        [CmdletBinding()]
+        param(
+            [Parameter(Mandatory, ValueFromRemainingArguments)]
+            [string[]] $Description
+        )
+        $command = (& llmwrap --shell pwsh -- @Description) -join "`n"
+        if ($LASTEXITCODE -eq 0 -and $command) {
+            $command
+        }
+    }
+
+    Set-Alias -Name llm -Value Invoke-LlmWrap
+
+    # Ctrl+g replaces the current line with the generated command, ready to edit
+    # or run with Enter. Rebind with Set-PSReadLineKeyHandler after loading.
+    if (Get-Command Set-PSReadLineKeyHandler -ErrorAction SilentlyContinue) {
+        Set-PSReadLineKeyHandler -Chord 'Ctrl+g' `
+            -BriefDescription 'llmwrap' `
+            -LongDescription 'Replace the line with the command llmwrap generates for it' `
+            -ScriptBlock {
+                $line = $null
+                $cursor = $null
+                [Microsoft.PowerShell.PSConsoleReadLine]::GetBufferState([ref] $line, [ref] $cursor)
+                if ([string]::IsNullOrWhiteSpace($line)) {
+                    return
+                }
+                $command = (& llmwrap --shell pwsh -- $line 2>$null) -join "`n"
+                if ($LASTEXITCODE -eq 0 -and $command) {
+                    [Microsoft.PowerShell.PSConsoleReadLine]::Replace(0, $line.Length, $command)
+                }
+            }
+    }
+
+    Export-ModuleMember -Function Invoke-LlmWrap -Alias llm
+} | Import-Module
@@ -0,0 +1,388 @@
+use crate::fuzzy;
+use std::collections::BTreeSet;
+
+/// A request answered from a built-in template instead of the model.
+pub struct IntentMatch {
+    pub name: &'static str,
+    pub command: String,
+}
+
+struct Intent {
+    name: &'static str,
+    /// The intent applies when every word of at least one trigger is present.
+    triggers: &'static [&'static [&'static str]],
+    /// Other words the prompt may contain; anything else defers to the model,
+    /// since it probably adds a constraint the template does not handle.
+    vocabulary: &'static [&'static str],
+    build: fn(&Request) -> Option<String>,
+}
+
+struct Request {
+    words: BTreeSet<String>,
+    paths: Vec<String>,
+}
+
+const FILLER: &[&str] = &[
+    "a",
+    "an",
+    "the",
+    "of",
+    "in",
+    "for",
+    "me",
+    "my",
+    "i",
+    "please",
+    "can",
+    "you",
+    "how",
+    "do",
+    "this",
+    "show",
+    "list",
+    "get",
+    "find",
+    "check",
+    "what",
+    "whats",
+    "which",
+    "is",
+    "are",
+    "here",
+    "all",
+    "current",
+    "directory",
+    "folder",
+    "dir",
+    "on",
+    "by",
+    "now",
+];
+
+const INTENTS: &[Intent] = &[
+    Intent {
+        name: "extract archive",
+        triggers: &[
+            &["extract"],
+            &["unpack"],
+            &["unzip"],
+            &["decompress"],
+            &["untar"],
+        ],
+        vocabulary: &["archive", "file", "tarball", "it", "content", "to", "into"],
+        build: extract_archive,
+    },
+    Intent {
+        name: "disk usage",
+        triggers: &[
+            &["disk", "usage"],
+            &["disk", "space"],
+            &["free", "space"],
+            &["space", "left"],
+            &["directory", "size"],
+            &["folder", "size"],
+        ],
+        vocabulary: &[
+            "much",
+            "used",
+            "taking",
+            "up",
+            "each",
+            "per",
+            "subdirectory",
+            "subfolder",
+            "size",
+            "available",
+            "remaining",
+            "left",
+            "free",
+            "filesystem",
+        ],
+        build: disk_usage,
+    },
+    Intent {
+        name: "find big files",
+        triggers: &[
+            &["big", "file"],
+            &["large", "file"],
+            &["huge", "file"],
+            &["largest", "file"],
+            &["biggest", "file"],
+        ],
+        vocabulary: &["top", "disk", "space", "taking", "up"],
+        build: big_files,
+    },
+    Intent {
+        name: "listening ports",
+        triggers: &[&["listening", "port"], &["open", "port"]],
+        vocabulary: &["process", "machine", "tcp", "udp"],
+        build: listening_ports,
+    },
+];
+
+/// Match `prompt` against the built-in intents, returning a vetted command
+/// only when the whole prompt is covered by one of them.
+pub fn classify(prompt: &str) -> Option<IntentMatch> {
+    let request = parse(prompt);
+    INTENTS.iter().find_map(|intent| {
+        let triggered = intent
+            .triggers
+            .iter()
+            .any(|trigger| trigger.iter().all(|w| request.words.contains(*w)));
+        let covered = request.words.iter().all(|word| {
+            intent.triggers.iter().any(|t| t.contains(&word.as_str()))
+                || intent.vocabulary.contains(&word.as_str())
+                || FILLER.contains(&word.as_str())
+        });
+        if !triggered || !covered {
+            return None;
+        }
+        (intent.build)(&request).map(|command| IntentMatch {
+            name: intent.name,
+            command,
+        })
+    })
+}
+
+fn parse(prompt: &str) -> Request {
+    let mut words = BTreeSet::new();
+    let mut paths = Vec::new();
+    let tokens = shlex::split(prompt)
+        .unwrap_or_else(|| prompt.split_whitespace().map(str::to_string).collect());
+    for token in &tokens {
+        let token = token.trim_end_matches(',');
+        if looks_like_path(token) {
+            paths.push(token.to_string());
+        } else if let Some(word) = fuzzy::canonical(token) {
+            words.insert(word);
+        }
+    }
+    Request { words, paths }
+}
+
+fn looks_like_path(token: &str) -> bool {
+    if token == "~" {
+        return true;
+    }
+    if token.starts_with('/') || token.starts_with('~') || token.starts_with('.') {
+        return token.len() > 1;
+    }
+    if token.contains('/') {
+        return true;
+    }
+    match token.rsplit_once('.') {
+        Some((stem, ext)) => {
+            !stem.is_empty() && !ext.is_empty() && ext.chars().all(|c| c.is_ascii_alphanumeric())
+        }
+        None => false,
+    }
+}
+
+/// Quote `path` for the shell. Quoting would stop the shell expanding a
+/// leading `~`, so it becomes `"$HOME"` instead; `~user` is left to the model.
+fn quote(path: &str) -> Option<String> {
+    let quoted = |path: &str| shlex::try_quote(path).ok().map(|q| q.into_owned());
+    match path.strip_prefix('~') {
+        None => quoted(path),
+        Some("") => Some("\"$HOME\"".to_string()),
+        Some(rest) => {
+            let rest = rest.strip_prefix('/')?;
+            if rest.is_empty() {
+                return Some("\"$HOME\"/".to_string());
+            }
+            Some(format!("\"$HOME\"/{}", quoted(rest)?))
+        }
+    }
+}
+
+fn extract_archive(request: &Request) -> Option<String> {
+    let (archive, dest) = match request.paths.as_slice() {
+        [archive] => (archive, None),
+        [archive, dest] => (archive, Some(quote(dest)?)),
+        _ => return None,
+    };
+    let name = archive.to_lowercase();
+    let file = quote(archive)?;
+    let tar = [
+        ".tar", ".tar.gz", ".tgz", ".tar.bz2", ".tbz2", ".tar.xz", ".txz", ".tar.zst",
+    ];
+
+    if tar.iter().any(|ext| name.ends_with(ext)) {
+        return Some(match dest {
+            Some(dest) => format!("mkdir -p {dest} && tar -xf {file} -C {dest}"),
+            None => format!("tar -xf {file}"),
+        });
+    }
+    if name.ends_with(".zip") {
+        return Some(match dest {
+            Some(dest) => format!("unzip {file} -d {dest}"),
+            None => format!("unzip {file}"),
+        });
+    }
+    if dest.is_some() {
+        return None;
+    }
+    let single_file = [
+        (".gz", "gunzip -k"),
+        (".bz2", "bunzip2 -k"),
+        (".xz", "unxz -k"),
+        (".zst", "zstd -d"),
+        (".7z", "7z x"),
+        (".rar", "unrar x"),
+    ];
+    single_file
+        .iter()
+        .find(|(ext, _)| name.ends_with(ext))
+        .map(|(_, tool)| format!("{tool} {file}"))
+}
+
+fn disk_usage(request: &Request) -> Option<String> {
+    let free_space = ["free", "left", "available", "remaining", "filesystem"]
+        .iter()
+        .any(|w| request.words.contains(*w));
+    match (request.paths.as_slice(), free_space) {
+        ([], true) => Some("df -h".to_string()),
+        ([path], true) => Some(format!("df -h {}", quote(path)?)),
+        ([], false) => Some("du -sh -- * | sort -h".to_string()),
+        ([path], false) => Some(format!("du -sh -- {}/* | sort -h", quote(path)?)),
+        _ => None,
+    }
+}
+
+fn big_files(request: &Request) -> Option<String> {
+    let dir = match request.paths.as_slice() {
+        [] => ".".to_string(),
+        [dir] => quote(dir)?,
+        _ => return None,
+    };
+    let ranked = ["largest", "biggest", "top"]
+        .iter()
+        .any(|w| request.words.contains(*w));
+    Some(if ranked {
+        format!("find {dir} -xdev -type f -exec du -h {{}} + | sort -rh | head -n 20")
+    } else {
+        format!("find {dir} -xdev -type f -size +100M -exec du -h {{}} + | sort -rh")
+    })
+}
+
+fn listening_ports(request: &Request) -> Option<String> {
+    if !request.paths.is_empty() {
+        return None;
+    }
+    Some(if cfg!(target_os = "linux") {
+        "ss -tulpn".to_string()
+    } else {
+        "lsof -nP -iTCP -sTCP:LISTEN".to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(prompt: &str) -> Option<String> {
+        classify(prompt).map(|intent| intent.command)
+    }
+
+    #[test]
+    fn extracts_archives_by_extension() {
+        assert_eq!(
+            command("extract backup.tar.gz").unwrap(),
+            "tar -xf backup.tar.gz"
+        );
+        assert_eq!(command("unzip photos.zip").unwrap(), "unzip photos.zip");
+        assert_eq!(command("decompress log.gz").unwrap(), "gunzip -k log.gz");
+        assert_eq!(
+            command("extract site.tar.gz into public/").unwrap(),
+            "mkdir -p public/ && tar -xf site.tar.gz -C public/"
+        );
+        // A bare word isn't taken for where to extract to.
+        assert!(command("unzip photos.zip to out").is_none());
+        assert_eq!(
+            command("unzip photos.zip to ./out").unwrap(),
+            "unzip photos.zip -d ./out"
+        );
+        // A single-file format has nowhere to extract to.
+        assert!(command("decompress log.gz into ./out").is_none());
+        assert!(command("extract notes.txt").is_none());
+    }
+
+    #[test]
+    fn reports_disk_usage() {
+        assert_eq!(command("how much disk space is left").unwrap(), "df -h");
+        assert_eq!(command("free space on /mnt").unwrap(), "df -h /mnt");
+        assert_eq!(command("directory size").unwrap(), "du -sh -- * | sort -h");
+        assert_eq!(
+            command("disk usage of each subfolder in /var/log").unwrap(),
+            "du -sh -- /var/log/* | sort -h"
+        );
+    }
+
+    #[test]
+    fn finds_big_files() {
+        assert_eq!(
+            command("find big files").unwrap(),
+            "find . -xdev -type f -size +100M -exec du -h {} + | sort -rh"
+        );
+        assert_eq!(
+            command("show the largest files in /srv").unwrap(),
+            "find /srv -xdev -type f -exec du -h {} + | sort -rh | head -n 20"
+        );
+    }
+
+    #[test]
+    fn lists_listening_ports() {
+        let expected = if cfg!(target_os = "linux") {
+            "ss -tulpn"
+        } else {
+            "lsof -nP -iTCP -sTCP:LISTEN"
+        };
+        assert_eq!(command("show listening ports").unwrap(), expected);
+        assert!(command("open ports on /etc").is_none());
+    }
+
+    #[test]
+    fn defers_words_outside_the_vocabulary() {
+        // Each adds a constraint the template would silently drop.
+        assert!(command("extract backup.tar.gz except the logs").is_none());
+        assert!(command("find big files modified yesterday").is_none());
+        assert!(command("disk usage sorted by owner").is_none());
+    }
+
+    #[test]
+    fn needs_a_trigger() {
+        assert!(command("backup.tar.gz").is_none());
+        assert!(command("files in /srv").is_none());
+        assert!(command("").is_none());
+    }
+
+    #[test]
+    fn quotes_paths_with_spaces() {
+        assert_eq!(
+            command("extract 'my backup.tar.gz'").unwrap(),
+            "tar -xf 'my backup.tar.gz'"
+        );
+        assert_eq!(
+            command("disk usage of \"/srv/big data\"").unwrap(),
+            "du -sh -- '/srv/big data'/* | sort -h"
+        );
+    }
+
+    #[test]
+    fn expands_a_leading_tilde() {
+        assert_eq!(
+            command("extract ~/Downloads/site.zip").unwrap(),
+            "unzip \"$HOME\"/Downloads/site.zip"
+        );
+        assert_eq!(
+            command("extract '~/my files/site.zip'").unwrap(),
+            "unzip \"$HOME\"/'my files/site.zip'"
+        );
+        assert_eq!(
+            command("find big files in ~").unwrap(),
+            "find \"$HOME\" -xdev -type f -size +100M -exec du -h {} + | sort -rh"
+        );
+        // Another user's home can't be spelled with $HOME.
+        assert!(command("extract ~bob/site.zip").is_none());
+    }
+}
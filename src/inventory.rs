@@ -0,0 +1,88 @@
+use crate::paths;
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+
+/// Groups of hosts kept in `inventory.toml` in the config directory, to run
+/// a command on all of them by the group's name.
+#[derive(Deserialize, Default)]
+pub struct Inventory {
+    #[serde(default)]
+    groups: BTreeMap<String, Group>,
+}
+
+#[derive(Deserialize)]
+pub struct Group {
+    /// Hosts as `--target ssh:` takes them: aliases or `[user@]host[:port]`.
+    pub hosts: Vec<String>,
+    /// Values set in the environment the command runs in on these hosts,
+    /// e.g. the name of a service that differs between groups.
+    #[serde(default)]
+    vars: BTreeMap<String, toml::Value>,
+}
+
+pub fn load() -> Result<Inventory> {
+    let Some(path) = paths::config_dir().map(|dir| dir.join("inventory.toml")) else {
+        return Ok(Inventory::default());
+    };
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Inventory::default());
+        }
+        Err(err) => {
+            return Err(err).with_context(|| format!("Failed to read {}", path.display()));
+        }
+    };
+    let inventory: Inventory = toml::from_str(&text)
+        .with_context(|| format!("Invalid inventory file {}", path.display()))?;
+    let name = Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap();
+    for (group, settings) in &inventory.groups {
+        if let Some(var) = settings.vars.keys().find(|var| !name.is_match(var)) {
+            anyhow::bail!(
+                "Invalid variable {} in group {} of {}: use letters, digits and underscores",
+                var,
+                group,
+                path.display()
+            );
+        }
+    }
+    Ok(inventory)
+}
+
+impl Inventory {
+    pub fn group(&self, name: &str) -> Option<&Group> {
+        self.groups.get(name)
+    }
+
+    /// Groups a description names, as in "restart the exporter on the
+    /// metrics group" or "on the db servers".
+    pub fn mentioned(&self, description: &str) -> Vec<&str> {
+        self.groups
+            .keys()
+            .filter(|name| {
+                let name = regex::escape(name);
+                Regex::new(&format!(
+                    r"(?i)\b{name}\s+(?:group|hosts|servers|machines|boxes)\b|\bgroup\s+{name}\b"
+                ))
+                .is_ok_and(|pattern| pattern.is_match(description))
+            })
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+impl Group {
+    /// The group's variables with their values as text.
+    pub fn vars(&self) -> impl Iterator<Item = (String, String)> + '_ {
+        self.vars.iter().map(|(name, value)| {
+            let value = match value {
+                toml::Value::String(text) => text.clone(),
+                other => other.to_string(),
+            };
+            (name.clone(), value)
+        })
+    }
+}
@@ -0,0 +1,168 @@
+use crate::api::{Client, Exchange, Turn};
+use crate::ui;
+use anyhow::Result;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// `[latency]` in `config.toml`: what to do when the model is slow to answer,
+/// and how long finding out about the machine may take.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// A model to ask as well when the first hasn't started answering in time.
+    pub fallback_model: Option<String>,
+    /// How long to wait for the first model to start streaming, in milliseconds.
+    pub fallback_after_ms: u64,
+    /// Send the same request again after this many milliseconds without an
+    /// answer starting. Off by default: it can double the cost of a request.
+    pub hedge_after_ms: Option<u64>,
+    /// How long each piece of context for the system prompt (the OS, the
+    /// tools installed, the project) may take before it is left out.
+    pub context_timeout_ms: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            fallback_model: None,
+            fallback_after_ms: 3000,
+            hedge_after_ms: None,
+            context_timeout_ms: 500,
+        }
+    }
+}
+
+enum Event {
+    /// The first piece of text arrived.
+    Started,
+    Done(usize, Result<(String, Exchange)>),
+}
+
+/// Ask `client` for a completion, streaming the answer to `on_text` as it
+/// arrives. With hedging or a fallback model configured, while nothing has
+/// arrived send the same request again to the same model (hedging) or to the
+/// fallback when their time comes, or as soon as the first model fails. The
+/// first to finish wins and the others are hung up on; their text is not
+/// passed on as it arrives, since it isn't known yet which will win.
+pub fn complete(
+    client: &Client,
+    settings: &Settings,
+    system_prompt: &str,
+    user_request: &str,
+    on_text: &mut dyn FnMut(&str),
+) -> Result<(String, Exchange)> {
+    // Requests to send if nothing has started by then, soonest first.
+    let mut pending: Vec<(Duration, Client)> = Vec::new();
+    if let Some(after) = settings.hedge_after_ms {
+        pending.push((Duration::from_millis(after), client.clone()));
+    }
+    if let Some(fallback) = &settings.fallback_model {
+        pending.push((
+            Duration::from_millis(settings.fallback_after_ms),
+            client.with_model(fallback),
+        ));
+    }
+    if pending.is_empty() {
+        return client.converse_stream(system_prompt, &[Turn::user(user_request)], on_text);
+    }
+    pending.sort_by_key(|(after, _)| *after);
+
+    let (sender, receiver) = mpsc::channel();
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut models: Vec<String> = Vec::new();
+    let launch = |client: Client, models: &mut Vec<String>| {
+        let index = models.len();
+        models.push(client.model().to_string());
+        let sender = sender.clone();
+        let stop = Arc::clone(&stop);
+        let system_prompt = system_prompt.to_string();
+        let user_request = user_request.to_string();
+        thread::spawn(move || {
+            let mut started = false;
+            let mut on_text = |_: &str| {
+                if !started {
+                    started = true;
+                    let _ = sender.send(Event::Started);
+                }
+            };
+            let result =
+                client.complete_stream_until(&system_prompt, &user_request, &mut on_text, &stop);
+            let _ = sender.send(Event::Done(index, result));
+        });
+    };
+
+    let began = Instant::now();
+    launch(client.clone(), &mut models);
+    let mut running = 1;
+    let mut started = false;
+    let mut first_error = None;
+    loop {
+        let next = pending.first().filter(|_| !started);
+        let event = match next {
+            Some((after, _)) => {
+                match receiver.recv_timeout(after.saturating_sub(began.elapsed())) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => {
+                        let (_, client) = pending.remove(0);
+                        launch(client, &mut models);
+                        running += 1;
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            None => match receiver.recv() {
+                Ok(event) => event,
+                Err(_) => break,
+            },
+        };
+        match event {
+            Event::Started => started = true,
+            Event::Done(index, Ok(answer)) => {
+                stop.store(true, Ordering::Relaxed);
+                if models[index] != client.model() {
+                    ui::say!(
+                        "{} was {} to answer; this command is from {}.",
+                        client.model(),
+                        if first_error.is_some() {
+                            "unable"
+                        } else {
+                            "slow"
+                        },
+                        models[index]
+                    );
+                }
+                return Ok(answer);
+            }
+            Event::Done(index, Err(err)) => {
+                running -= 1;
+                // Report the error from the request asked for first.
+                if index == 0 || first_error.is_none() {
+                    first_error = Some(err);
+                }
+                // Sending the request to the same model again would most
+                // likely fail the same way, but a fallback to another model is
+                // there for just this, so it goes right away.
+                let failed = models[index].clone();
+                pending.retain(|(_, other)| other.model() != failed);
+                if failed == client.model() {
+                    for (_, fallback) in pending.drain(..) {
+                        launch(fallback, &mut models);
+                        running += 1;
+                    }
+                }
+                if running == 0 {
+                    break;
+                }
+            }
+        }
+    }
+    match first_error {
+        Some(err) => Err(err),
+        None => anyhow::bail!("No answer from {}", client.model()),
+    }
+}
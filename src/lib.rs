@@ -0,0 +1,169 @@
+//! Turn a plain-English description into a shell command with a language
+//! model, the way the `llmwrap` binary does, for editors, TUIs and bots that
+//! want commands without shelling out to it.
+//!
+//! ```no_run
+//! use llmwrap::api::{Client, KeySource, Provider};
+//! use llmwrap::shell::Shell;
+//!
+//! let provider = Provider::Openai;
+//! let client = Client::new(
+//!     provider,
+//!     &KeySource::Provider,
+//!     provider.default_base(),
+//!     provider.default_model(),
+//! )?;
+//! let answer = llmwrap::generate_command(&client, "find files over 100MB here", Shell::Bash)?;
+//! let assessment = llmwrap::assess(Shell::Bash, &answer.command);
+//! println!("{} ({} risk)", answer.command, assessment.level);
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! [`api`] talks to the providers, [`risk`] classifies commands and [`shell`]
+//! knows the shells commands are written for. Everything else belongs to the
+//! binary and is private to the crate.
+
+mod analytics;
+mod ansible;
+pub mod answer;
+pub mod api;
+mod attach;
+mod bsd;
+mod busybox;
+mod cache;
+mod cancel;
+mod candidates;
+mod checksum;
+mod ci;
+mod cli;
+mod clipboard;
+mod cmdline;
+mod config;
+mod container;
+mod context;
+mod digest;
+mod dns;
+mod dotenv;
+mod dump;
+mod envchange;
+mod environment;
+mod exec;
+mod explain;
+#[cfg(feature = "fake-server")]
+pub mod fakeserver;
+mod fix;
+mod followup;
+mod functions;
+mod fuzzy;
+mod history;
+mod hooks;
+mod init;
+mod intents;
+mod inventory;
+mod latency;
+mod logpatterns;
+mod macos;
+mod makefile;
+mod net;
+mod novelty;
+mod patch;
+mod pathcheck;
+mod paths;
+mod postprocess;
+mod preflight;
+mod probes;
+mod project;
+mod quoting;
+mod readonly;
+mod remote;
+pub mod risk;
+mod safer;
+mod session;
+pub mod shell;
+mod snippets;
+mod ssh;
+mod store;
+mod suggest;
+mod summarize;
+mod targets;
+mod template;
+mod termux;
+mod timing;
+mod tty;
+mod tutor;
+mod ui;
+mod userland;
+mod winpath;
+mod workspace;
+mod wsl;
+
+use anyhow::Result;
+
+/// The entry point of the `llmwrap` binary.
+#[doc(hidden)]
+pub use cli::main;
+
+/// The system prompt commands are asked for with.
+pub const SYSTEM_PROMPT: &str = "You translate natural-language requests into a single shell command. \
+Respond with only the runnable command, no explanations, no code fences. \
+If the task truly needs several steps that don't fit on one line, respond with a short script \
+instead, one command per line, that stops at the first failure. \
+Prefer safe quoting for filenames. When downloading something to install or run, verify its \
+published checksum or signature before using it. If the request is impossible, reply with a brief reason.";
+
+/// Something that answers a system prompt and a request with text. An
+/// [`api::Client`] asks a provider; embedders can put their own transport or
+/// a canned answer in its place.
+pub trait Model {
+    fn complete(&self, system_prompt: &str, user_request: &str) -> Result<String>;
+}
+
+impl Model for api::Client {
+    fn complete(&self, system_prompt: &str, user_request: &str) -> Result<String> {
+        api::Client::complete(self, system_prompt, user_request)
+    }
+}
+
+/// Ask `model` for a command for `shell` doing what `description` says. The
+/// command is cleaned up as the binary does it but neither checked nor run;
+/// pass it to [`assess`] before running it.
+pub fn generate_command(
+    model: &dyn Model,
+    description: &str,
+    shell: shell::Shell,
+) -> Result<answer::Answer> {
+    if description.trim().is_empty() {
+        anyhow::bail!("The description is empty");
+    }
+    let system_prompt = match shell.prompt_note() {
+        Some(note) => format!("{} {}", SYSTEM_PROMPT, note),
+        None => SYSTEM_PROMPT.to_string(),
+    };
+    let raw = model.complete(&system_prompt, description)?;
+    Ok(answer::Answer::plain(sanitize_command(&raw)))
+}
+
+/// The command in the model's answer without code fences or backticks, or
+/// the whole script when the answer runs over several lines.
+pub fn sanitize_command(raw: &str) -> String {
+    let text = api::strip_code_fences(raw);
+    if is_script(&text) {
+        return text;
+    }
+    text.trim_matches(|c: char| c == '`' || c.is_whitespace())
+        .to_string()
+}
+
+/// Whether `command` is a script of several lines rather than one command.
+pub fn is_script(command: &str) -> bool {
+    command.trim().contains('\n')
+}
+
+/// How risky `command` is, read with the rules of the shell it is for.
+pub fn assess(shell: shell::Shell, command: &str) -> risk::Assessment {
+    match shell {
+        shell::Shell::Nu => risk::assess_nu(command),
+        shell::Shell::Cmd => risk::assess_cmd(command),
+        _ => risk::assess(command),
+    }
+}
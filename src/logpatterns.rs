@@ -0,0 +1,143 @@
+use crate::api::Client;
+use anyhow::Result;
+use regex::Regex;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+/// How many clusters the model is asked to name; the rest keep their pattern.
+const NAME_LIMIT: usize = 40;
+
+/// Words that mark a line as an error.
+static ERROR: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)\b(?:error|errors|err|fatal|panic|panicked|exception|traceback|failed|failure|critical|crit|emerg|alert|segfault|denied|refused|timed out|timeout)\b",
+    )
+    .unwrap()
+});
+
+/// Words that mark a line as a warning, for `--warnings`.
+static WARNING: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\b(?:warn|warning|warnings|deprecated)\b").unwrap());
+
+/// A timestamp at the start of a line: ISO 8601 as most programs write it,
+/// or the `Oct 14 09:55:43` of syslog and journalctl.
+static TIMESTAMP: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^\[?(\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}(?:[.,]\d+)?(?:Z|[+-]\d{2}:?\d{2})?|[A-Z][a-z]{2} [ \d]\d \d{2}:\d{2}:\d{2})\]?\s*",
+    )
+    .unwrap()
+});
+
+/// What varies between lines of one kind, most specific first, with what
+/// it is replaced by in the pattern.
+static VARIABLE: LazyLock<Vec<(Regex, &'static str)>> = LazyLock::new(|| {
+    [
+        (
+            r"\b[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}\b",
+            "<uuid>",
+        ),
+        (r"\b\d{1,3}(?:\.\d{1,3}){3}(?::\d+)?\b", "<ip>"),
+        (r"\b0x[0-9a-fA-F]+\b", "<hex>"),
+        (r"\b[0-9a-f]{8,}\b", "<hex>"),
+        (r#""[^"]*""#, "\"…\""),
+        (r"'[^']*'", "'…'"),
+        (r"\b\d+(?:\.\d+)?(?:ms|s|m|h|KB|MB|GB|kB|B|%)?\b", "<n>"),
+        (r"\s+", " "),
+    ]
+    .into_iter()
+    .map(|(pattern, with)| (Regex::new(pattern).unwrap(), with))
+    .collect()
+});
+
+/// Lines that differ only where numbers, ids, addresses and quoted values
+/// go, with how often they came up and when.
+pub struct Cluster {
+    /// The lines with what varies replaced, e.g. `connect to <ip>: refused`.
+    pub pattern: String,
+    /// The first line as it was, without its timestamp.
+    pub example: String,
+    pub count: usize,
+    pub first: Option<String>,
+    pub last: Option<String>,
+    /// A short name for the kind of error, from the model.
+    pub name: Option<String>,
+}
+
+/// Group the error lines in `log` (and warnings too with `warnings`), most
+/// frequent first.
+pub fn cluster(log: &str, warnings: bool) -> Vec<Cluster> {
+    let mut clusters: Vec<Cluster> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for line in log.lines() {
+        if !(ERROR.is_match(line) || warnings && WARNING.is_match(line)) {
+            continue;
+        }
+        let (timestamp, message) = match TIMESTAMP.captures(line) {
+            Some(caps) => (
+                caps.get(1).map(|m| m.as_str().to_string()),
+                &line[caps.get(0).map_or(0, |m| m.end())..],
+            ),
+            None => (None, line),
+        };
+        let pattern = VARIABLE
+            .iter()
+            .fold(message.trim().to_string(), |text, (regex, with)| {
+                regex.replace_all(&text, *with).into_owned()
+            });
+        match index.get(&pattern) {
+            Some(&at) => {
+                let cluster = &mut clusters[at];
+                cluster.count += 1;
+                if timestamp.is_some() {
+                    cluster.last = timestamp;
+                }
+            }
+            None => {
+                index.insert(pattern.clone(), clusters.len());
+                clusters.push(Cluster {
+                    pattern,
+                    example: message.trim().to_string(),
+                    count: 1,
+                    first: timestamp.clone(),
+                    last: timestamp,
+                    name: None,
+                });
+            }
+        }
+    }
+    // Stable, so clusters seen as often stay in the order they first came up.
+    clusters.sort_by_key(|cluster| Reverse(cluster.count));
+    clusters
+}
+
+/// Ask the model for a short name for each of the most frequent clusters.
+pub fn name(client: &Client, clusters: &mut [Cluster]) -> Result<()> {
+    let named = clusters.len().min(NAME_LIMIT);
+    if named == 0 {
+        return Ok(());
+    }
+    let system_prompt = "You name kinds of errors found in a log for someone skimming it. For \
+each numbered pattern, reply with exactly one line `NUMBER: name`, the name a few words saying \
+what went wrong, e.g. `3: Database connection refused`. No other text.";
+    let request = clusters[..named]
+        .iter()
+        .enumerate()
+        .map(|(i, cluster)| format!("{}: {}", i + 1, cluster.example))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let reply = client.complete(system_prompt, &request)?;
+    for line in reply.lines() {
+        let Some((number, name)) = line.trim().trim_start_matches("- ").split_once(':') else {
+            continue;
+        };
+        let name = name.trim().trim_matches('`');
+        if let Ok(number) = number.trim().parse::<usize>()
+            && (1..=named).contains(&number)
+            && !name.is_empty()
+        {
+            clusters[number - 1].name = Some(name.to_string());
+        }
+    }
+    Ok(())
+}
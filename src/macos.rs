@@ -0,0 +1,129 @@
+use crate::cmdline;
+use crate::safer;
+use std::path::Path;
+
+/// GNU tools Homebrew installs with a `g` prefix so they don't shadow the
+/// system's BSD ones.
+const GNU_PREFIXED: &[&str] = &[
+    "gsed",
+    "gdate",
+    "gfind",
+    "ggrep",
+    "gstat",
+    "gxargs",
+    "gawk",
+    "gtar",
+    "greadlink",
+    "gls",
+    "gdu",
+    "gcp",
+    "gtimeout",
+];
+
+/// Programs that change the files they are given.
+const WRITERS: &[&str] = &[
+    "rm", "mv", "cp", "ln", "touch", "mkdir", "rmdir", "chmod", "chown", "chflags", "tee",
+    "install", "sed",
+];
+
+/// What is worth telling the model about a Mac.
+pub struct Mac {
+    /// Where Homebrew lives, `/opt/homebrew` on Apple silicon and `/usr/local`
+    /// on Intel.
+    pub brew_prefix: Option<String>,
+    /// The `g`-prefixed GNU tools on the PATH.
+    pub gnu_tools: Vec<&'static str>,
+}
+
+/// The Mac this is running on, if it is one.
+pub fn detect() -> Option<Mac> {
+    if !cfg!(target_os = "macos") {
+        return None;
+    }
+    let brew_prefix = std::env::var("HOMEBREW_PREFIX").ok().or_else(|| {
+        ["/opt/homebrew", "/usr/local"]
+            .into_iter()
+            .find(|prefix| Path::new(prefix).join("bin/brew").is_file())
+            .map(str::to_string)
+    });
+    let gnu_tools = GNU_PREFIXED
+        .iter()
+        .copied()
+        .filter(|tool| safer::on_path(tool))
+        .collect();
+    Some(Mac {
+        brew_prefix,
+        gnu_tools,
+    })
+}
+
+impl Mac {
+    /// Sentences for the system prompt about Homebrew, the GNU tools and SIP.
+    pub fn prompt_note(&self) -> String {
+        let mut note = String::from("The system is macOS.");
+        match &self.brew_prefix {
+            Some(prefix) => note.push_str(&format!(
+                " Homebrew is installed in {}; install missing tools with `brew install`.",
+                prefix
+            )),
+            None => note.push_str(" Homebrew is not installed."),
+        }
+        if !self.gnu_tools.is_empty() {
+            note.push_str(&format!(
+                " GNU versions are installed with a g prefix ({}); use them where GNU behavior \
+is needed.",
+                self.gnu_tools.join(", ")
+            ));
+        }
+        note.push_str(
+            " System Integrity Protection makes /System, /usr (except /usr/local), /bin and /sbin \
+read-only even for root, so never write there.",
+        );
+        note
+    }
+
+    /// Parts of the command that would write where System Integrity
+    /// Protection forbids it.
+    pub fn sip_problems(&self, command: &str) -> Vec<String> {
+        let instead = match &self.brew_prefix {
+            Some(prefix) => format!("{}/bin or ~/bin", prefix),
+            None => "/usr/local/bin or ~/bin".to_string(),
+        };
+        let mut problems = Vec::new();
+        for segment in cmdline::segments(command) {
+            let args = segment.args_from_program();
+            let program = args
+                .first()
+                .map_or("", |p| p.rsplit('/').next().unwrap_or(p));
+            let mut targets: Vec<&str> = segment
+                .redirects
+                .iter()
+                .filter(|r| r.writes())
+                .map(|r| r.target.as_str())
+                .collect();
+            if WRITERS.contains(&program) {
+                targets.extend(
+                    args[1..]
+                        .iter()
+                        .filter(|a| !a.starts_with('-'))
+                        .map(String::as_str),
+                );
+            }
+            for target in targets {
+                if protected(target) {
+                    problems.push(format!(
+                        "`{}` is protected by System Integrity Protection, so even root cannot \
+change it; use {} instead",
+                        target, instead
+                    ));
+                }
+            }
+        }
+        problems
+    }
+}
+
+fn protected(path: &str) -> bool {
+    let under = |dir: &str| path == dir || path.starts_with(&format!("{}/", dir));
+    (under("/System") || under("/usr") || under("/bin") || under("/sbin")) && !under("/usr/local")
+}
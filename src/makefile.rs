@@ -0,0 +1,262 @@
+use crate::api;
+use crate::exec;
+use anyhow::{Context, Result};
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const JUSTFILE_NAMES: &[&str] = &["justfile", "Justfile", ".justfile"];
+const MAKEFILE_NAMES: &[&str] = &["GNUmakefile", "makefile", "Makefile"];
+
+/// Which task runner the build file is for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Make,
+    Just,
+}
+
+impl Kind {
+    fn from_path(path: &Path) -> Self {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        if JUSTFILE_NAMES.contains(&name) || name.ends_with(".just") {
+            Kind::Just
+        } else {
+            Kind::Make
+        }
+    }
+
+    pub fn runner(self) -> &'static str {
+        match self {
+            Kind::Make => "make",
+            Kind::Just => "just",
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Kind::Make => "Makefile",
+            Kind::Just => "justfile",
+        })
+    }
+}
+
+/// A generated target (make) or recipe (just).
+pub struct Target {
+    pub name: String,
+    pub text: String,
+}
+
+/// Pick the build file to edit: the one given, an existing justfile or
+/// Makefile in the current directory, or a new `Makefile`.
+pub fn locate(explicit: Option<&Path>) -> (Kind, PathBuf) {
+    if let Some(path) = explicit {
+        return (Kind::from_path(path), path.to_path_buf());
+    }
+    existing().unwrap_or_else(|| (Kind::Make, PathBuf::from("Makefile")))
+}
+
+/// The justfile or Makefile in the current directory, if there is one.
+pub fn existing() -> Option<(Kind, PathBuf)> {
+    let path = JUSTFILE_NAMES
+        .iter()
+        .chain(MAKEFILE_NAMES)
+        .map(PathBuf::from)
+        .find(|p| p.is_file())?;
+    Some((Kind::from_path(&path), path))
+}
+
+/// A target or recipe already defined in a build file.
+pub struct Rule {
+    pub name: String,
+    /// The recipe lines, without their indentation.
+    pub body: Vec<String>,
+}
+
+/// The targets or recipes defined in `text`, in order, leaving out ones named
+/// after files such as `build/app.o`.
+pub fn rules(kind: Kind, text: &str) -> Vec<Rule> {
+    let mut rules: Vec<Rule> = Vec::new();
+    let mut lines = text.lines().peekable();
+    while let Some(line) = lines.next() {
+        let Some(name) = rule_name(kind, line) else {
+            continue;
+        };
+        let mut body = Vec::new();
+        while let Some(next) = lines.next_if(|l| l.starts_with([' ', '\t'])) {
+            let next = next.trim();
+            if !next.is_empty() && !next.starts_with('#') {
+                body.push(next.to_string());
+            }
+        }
+        if !name.contains(['.', '/']) && !rules.iter().any(|r| r.name == name) {
+            rules.push(Rule { name, body });
+        }
+    }
+    rules
+}
+
+pub fn generate(
+    client: &api::Client,
+    kind: Kind,
+    existing: &str,
+    description: &str,
+) -> Result<Target> {
+    let rules = match kind {
+        Kind::Make => {
+            "Indent recipe lines with a tab. Declare targets that do not produce a file \
+in a `.PHONY:` line directly above the target."
+        }
+        Kind::Just => "Use a plain recipe name; add parameters only when the task needs them.",
+    };
+    let system_prompt = format!(
+        "You write {kind} entries. Respond with only the single new {noun} the user asks \
+for, optionally preceded by a one-line comment, no explanations, no code fences. Reuse \
+variables and recipes already defined in the existing file where it makes sense. {rules}",
+        noun = noun(kind),
+    );
+    let request = if existing.trim().is_empty() {
+        description.to_string()
+    } else {
+        format!("{}\n\nExisting {}:\n{}", description, kind, existing)
+    };
+
+    let raw = client.complete(&system_prompt, &request)?;
+    let mut text = api::strip_code_fences(&raw);
+    if kind == Kind::Make {
+        text = tab_indent(&text);
+    }
+    let name = text
+        .lines()
+        .find_map(|line| rule_name(kind, line))
+        .with_context(|| {
+            format!(
+                "The model did not return a {} {}:\n{}",
+                kind,
+                noun(kind),
+                text
+            )
+        })?;
+    Ok(Target { name, text })
+}
+
+/// The build file with `target` added, replacing any existing rule of the same name.
+pub fn merge(kind: Kind, existing: &str, target: &Target) -> String {
+    let lines: Vec<&str> = existing.lines().collect();
+    let start = lines
+        .iter()
+        .position(|line| rule_name(kind, line).as_deref() == Some(target.name.as_str()));
+
+    let mut result = String::new();
+    match start {
+        Some(start) => {
+            // Take the rule's comments and `.PHONY` line along with its body.
+            let mut first = start;
+            while first > 0 && is_preamble(kind, lines[first - 1], &target.name) {
+                first -= 1;
+            }
+            let mut end = start + 1;
+            while end < lines.len() && lines[end].starts_with([' ', '\t']) {
+                end += 1;
+            }
+            for line in &lines[..first] {
+                result.push_str(line);
+                result.push('\n');
+            }
+            result.push_str(&target.text);
+            result.push('\n');
+            for line in &lines[end..] {
+                result.push_str(line);
+                result.push('\n');
+            }
+        }
+        None => {
+            result.push_str(existing.trim_end());
+            if !result.is_empty() {
+                result.push_str("\n\n");
+            }
+            result.push_str(&target.text);
+            result.push('\n');
+        }
+    }
+    result
+}
+
+/// Ask the task runner to parse the updated file, when it is installed. Only
+/// just can do this without running anything, so Makefiles are not checked.
+pub fn check(kind: Kind, text: &str) -> Result<()> {
+    if kind != Kind::Just {
+        return Ok(());
+    }
+    let mut file = tempfile::Builder::new()
+        .prefix("llmwrap-")
+        .suffix(".just")
+        .tempfile()?;
+    file.write_all(text.as_bytes())?;
+    let output = Command::new("just")
+        .arg("--justfile")
+        .arg(file.path())
+        .arg("--summary")
+        .output();
+    drop(file);
+    let Ok(output) = output else {
+        return Ok(());
+    };
+    if !output.status.success() {
+        anyhow::bail!(
+            "just cannot parse the result: {}",
+            exec::decode_output(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+fn noun(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Make => "target",
+        Kind::Just => "recipe",
+    }
+}
+
+/// The target or recipe a rule line defines, e.g. `build` for `build: deps`.
+fn rule_name(kind: Kind, line: &str) -> Option<String> {
+    if line.starts_with([' ', '\t', '#', '.']) {
+        return None;
+    }
+    let (head, rest) = line.split_once(':')?;
+    // `NAME := value` and `NAME ::= value` are assignments, not rules.
+    if rest.starts_with('=') || head.contains('=') && kind == Kind::Make {
+        return None;
+    }
+    let name = head.split_whitespace().next()?.trim_start_matches('@');
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '/' | '.'));
+    valid.then(|| name.to_string())
+}
+
+fn is_preamble(kind: Kind, line: &str, name: &str) -> bool {
+    line.starts_with('#')
+        || kind == Kind::Make
+            && line
+                .strip_prefix(".PHONY:")
+                .is_some_and(|targets| targets.split_whitespace().eq([name]))
+}
+
+/// Make requires tabs before recipe lines; models often emit spaces instead.
+fn tab_indent(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let trimmed = line.trim_start_matches(' ');
+            if trimmed.len() != line.len() && !trimmed.is_empty() {
+                format!("\t{}", trimmed)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,173 @@
+use crate::api;
+use crate::cmdline;
+use crate::history::HistoryEntry;
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::process::{Command, Stdio};
+
+/// Explain at most this many new things per command; more would bury it.
+const LIMIT: usize = 5;
+/// Cut explanations taken from man pages to roughly one line.
+const LINE_LIMIT: usize = 100;
+
+/// A program, or a flag of a program, that no command you ran has used.
+pub struct Unfamiliar {
+    pub program: String,
+    pub flag: Option<String>,
+}
+
+impl fmt::Display for Unfamiliar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.flag {
+            Some(flag) => write!(f, "{} {}", self.program, flag),
+            None => f.write_str(&self.program),
+        }
+    }
+}
+
+/// Programs and flags in `command` that none of the commands run before used.
+pub fn find(command: &str, history: &[HistoryEntry]) -> Vec<Unfamiliar> {
+    let mut known: HashMap<String, HashSet<String>> = HashMap::new();
+    for entry in history.iter().filter(|h| h.executed) {
+        for (program, flags) in programs_and_flags(&entry.command) {
+            known.entry(program).or_default().extend(flags);
+        }
+    }
+    let mut found = Vec::new();
+    for (program, flags) in programs_and_flags(command) {
+        match known.get(&program) {
+            None => found.push(Unfamiliar {
+                program,
+                flag: None,
+            }),
+            Some(seen) => {
+                for flag in flags.into_iter().filter(|f| !seen.contains(f)) {
+                    found.push(Unfamiliar {
+                        program: program.clone(),
+                        flag: Some(flag),
+                    });
+                }
+            }
+        }
+    }
+    found.truncate(LIMIT);
+    found
+}
+
+/// Each segment's program with the flags given to it, without their values.
+fn programs_and_flags(command: &str) -> Vec<(String, Vec<String>)> {
+    cmdline::segments(command)
+        .iter()
+        .filter_map(|segment| {
+            let args = segment.args_from_program();
+            let program = args.first()?.rsplit('/').next()?.to_string();
+            let mut flags: Vec<String> = Vec::new();
+            for arg in &args[1..] {
+                if arg == "--" {
+                    break;
+                }
+                if !arg.starts_with('-') || arg == "-" {
+                    continue;
+                }
+                let flag = arg.split('=').next().unwrap_or(arg).to_string();
+                if !flags.contains(&flag) {
+                    flags.push(flag);
+                }
+            }
+            Some((program, flags))
+        })
+        .collect()
+}
+
+/// Explain what's unfamiliar in one line each, from the man pages where they
+/// are installed and from the model for the rest.
+pub fn explain(
+    client: &api::Client,
+    command: &str,
+    items: &[Unfamiliar],
+) -> Result<Vec<(String, String)>> {
+    let mut explained = Vec::new();
+    let mut missing = Vec::new();
+    for item in items {
+        match from_man_page(item) {
+            Some(text) => explained.push((item.to_string(), text)),
+            None => missing.push(item.to_string()),
+        }
+    }
+    if missing.is_empty() {
+        return Ok(explained);
+    }
+
+    let system_prompt = "You explain parts of shell commands to someone learning them. For each \
+item, reply with exactly one line `ITEM: explanation`, the explanation a short phrase saying what \
+the program or flag does in the given command. No other text.";
+    let request = format!("Command: {}\n\nItems:\n{}", command, missing.join("\n"));
+    let reply = client.complete(system_prompt, &request)?;
+    for line in reply.lines() {
+        let Some((item, text)) = line.trim().trim_start_matches("- ").split_once(": ") else {
+            continue;
+        };
+        let item = item.trim().trim_matches('`');
+        if missing.iter().any(|m| m == item) {
+            explained.push((item.to_string(), text.trim().to_string()));
+        }
+    }
+    Ok(explained)
+}
+
+/// The NAME line of the program's man page, or the description next to the
+/// flag in it.
+fn from_man_page(item: &Unfamiliar) -> Option<String> {
+    let output = Command::new("man")
+        .args(["-P", "cat", &item.program])
+        .env("MANWIDTH", "200")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // Strip the backspace overstrikes man uses for bold and underline.
+    let mut text = String::new();
+    for c in String::from_utf8_lossy(&output.stdout).chars() {
+        if c == '\u{8}' {
+            text.pop();
+        } else {
+            text.push(c);
+        }
+    }
+    let lines: Vec<&str> = text.lines().map(str::trim).collect();
+
+    let line = match &item.flag {
+        None => {
+            let name = lines.iter().position(|l| *l == "NAME")?;
+            let line = lines.get(name + 1)?;
+            line.split_once(" - ").map_or(*line, |(_, d)| d).to_string()
+        }
+        Some(flag) => {
+            let at = lines.iter().position(|l| {
+                l.strip_prefix(flag.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with([',', ' ', '=', '[']))
+                    || l.starts_with('-')
+                        && l.split(", ")
+                            .any(|part| part.split([' ', '=']).next() == Some(flag))
+            })?;
+            // The description either follows the flag on its line or starts on
+            // the next one.
+            let own = lines[at]
+                .split_once("  ")
+                .map(|(_, d)| d.trim())
+                .filter(|d| !d.is_empty());
+            match own {
+                Some(description) => description.to_string(),
+                None => lines.get(at + 1).filter(|l| !l.is_empty())?.to_string(),
+            }
+        }
+    };
+    Some(match line.char_indices().nth(LINE_LIMIT) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line,
+    })
+}
@@ -0,0 +1,35 @@
+use crate::cancel;
+use anyhow::{Context, Result};
+use similar::TextDiff;
+use std::fs;
+use std::path::Path;
+
+/// Read a file that llmwrap is about to edit; a missing file reads as empty.
+pub fn read_or_empty(path: &Path) -> Result<String> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(text),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+        Err(err) => Err(err).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+/// Write the new contents, creating parent directories as needed.
+pub fn write(path: &Path, text: &str) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    cancel::shield(|| fs::write(path, text))
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Print a unified diff of the proposed change to `path`.
+pub fn print_diff(path: &Path, old: &str, new: &str) {
+    let name = path.display().to_string();
+    let diff = TextDiff::from_lines(old, new);
+    print!(
+        "{}",
+        diff.unified_diff()
+            .context_radius(3)
+            .header(&format!("a/{}", name), &format!("b/{}", name))
+    );
+}
@@ -0,0 +1,350 @@
+use crate::cmdline::{self, Segment};
+use crate::fuzzy;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Suggest a replacement only when it is at least this similar to the missing path.
+const SUGGEST_THRESHOLD: f64 = 0.5;
+/// How far below the current directory to look for a suggestion.
+const SEARCH_DEPTH: usize = 3;
+const SEARCH_LIMIT: usize = 5_000;
+
+/// A path the command expects to exist but that does not.
+pub struct Missing {
+    pub path: String,
+    pub suggestion: Option<String>,
+}
+
+/// Programs whose operands are files that must already exist.
+struct Reader {
+    programs: &'static [&'static str],
+    /// Flags that consume the next word, e.g. `-n` in `head -n 5 file`.
+    value_flags: &'static [&'static str],
+    /// Leading operands that are not files, such as the pattern for grep.
+    skip: usize,
+    /// The last operand is a destination that may not exist yet.
+    last_is_destination: bool,
+}
+
+const READERS: &[Reader] = &[
+    Reader {
+        programs: &[
+            "cat",
+            "less",
+            "more",
+            "wc",
+            "file",
+            "stat",
+            "tac",
+            "nl",
+            "bat",
+            "md5sum",
+            "sha1sum",
+            "sha256sum",
+            "source",
+            ".",
+            "ls",
+            "rm",
+            "diff",
+            "cmp",
+            "uniq",
+            "gzip",
+            "gunzip",
+            "zcat",
+            "bzip2",
+            "xz",
+            "unxz",
+            "open",
+            "xdg-open",
+            "cd",
+            "du",
+        ],
+        value_flags: &["-d", "-S"],
+        skip: 0,
+        last_is_destination: false,
+    },
+    Reader {
+        programs: &["head", "tail"],
+        value_flags: &["-n", "-c"],
+        skip: 0,
+        last_is_destination: false,
+    },
+    Reader {
+        programs: &["sort", "cut"],
+        value_flags: &["-k", "-t", "-o", "-S", "-d", "-f", "-c", "-b"],
+        skip: 0,
+        last_is_destination: false,
+    },
+    Reader {
+        programs: &["chmod", "chown", "chgrp", "jq"],
+        value_flags: &[],
+        skip: 1,
+        last_is_destination: false,
+    },
+    Reader {
+        programs: &["grep", "egrep", "fgrep", "rg", "sed", "awk"],
+        value_flags: &["-m", "-A", "-B", "-C", "-e", "-f", "-t"],
+        skip: 1,
+        last_is_destination: false,
+    },
+    Reader {
+        programs: &["cp", "mv", "ln", "rsync", "scp", "convert", "magick"],
+        value_flags: &["-t"],
+        skip: 0,
+        last_is_destination: true,
+    },
+];
+
+/// Look up the files the command reads and report the ones that do not exist,
+/// with the closest existing path as a suggestion.
+pub fn check(command: &str) -> Vec<Missing> {
+    let mut missing: Vec<Missing> = Vec::new();
+    let mut pool = None;
+    for segment in cmdline::segments(command) {
+        for path in inputs(&segment) {
+            let checkable =
+                !path.contains(['$', '*', '?', '[', '`']) && !path.contains("://") && path != "-";
+            if !checkable || expand(&path).exists() || missing.iter().any(|m| m.path == path) {
+                continue;
+            }
+            let pool = pool.get_or_insert_with(candidates);
+            missing.push(Missing {
+                suggestion: suggest(&path, pool),
+                path,
+            });
+        }
+    }
+    missing
+}
+
+fn inputs(segment: &Segment) -> Vec<String> {
+    let args = segment.args_from_program();
+    let mut inputs: Vec<String> = segment
+        .redirects
+        .iter()
+        .filter(|r| r.op.ends_with('<'))
+        .map(|r| r.target.clone())
+        .collect();
+    let Some(program) = args.first().map(|p| p.rsplit('/').next().unwrap_or(p)) else {
+        return inputs;
+    };
+
+    match program {
+        "ffprobe" => inputs.extend(operands(&args[1..], &[]).into_iter().take(1)),
+        "ffmpeg" => {
+            inputs.extend(
+                args.windows(2)
+                    .filter(|pair| pair[0] == "-i")
+                    .map(|pair| pair[1].clone()),
+            );
+        }
+        "tar" => {
+            // Only an archive being extracted or listed has to exist already.
+            // A mode cluster such as `xzf` or `-tvf` comes first.
+            let mode = args.get(1).map(String::as_str).unwrap_or("");
+            let cluster = !mode.starts_with("--")
+                && mode
+                    .trim_start_matches('-')
+                    .chars()
+                    .all(|c| c.is_ascii_alphabetic());
+            let reading = args
+                .iter()
+                .any(|a| matches!(a.as_str(), "--extract" | "--list" | "-x" | "-t"))
+                || cluster && (mode.contains('x') || mode.contains('t'));
+            if reading {
+                let archive = args
+                    .iter()
+                    .position(|a| a == "-f" || a == "--file")
+                    .or_else(|| (cluster && mode.ends_with('f')).then_some(1));
+                inputs.extend(archive.and_then(|i| args.get(i + 1)).cloned());
+                inputs.extend(
+                    args.iter()
+                        .filter_map(|a| a.strip_prefix("--file="))
+                        .map(str::to_string),
+                );
+            }
+        }
+        _ => {
+            let Some(reader) = READERS.iter().find(|r| r.programs.contains(&program)) else {
+                return inputs;
+            };
+            let mut files = operands(&args[1..], reader.value_flags);
+            // `grep -e PATTERN file` has no positional pattern.
+            let explicit_pattern = args.iter().any(|a| a == "-e" || a == "-f");
+            let skip = if explicit_pattern { 0 } else { reader.skip };
+            files.drain(..skip.min(files.len()));
+            // With `-t DIR` the destination was a flag value.
+            if reader.last_is_destination && !args.iter().any(|a| a == "-t") {
+                files.pop();
+            }
+            inputs.extend(files);
+        }
+    }
+    inputs
+}
+
+/// Words that are not flags or flag values. Empty words, like the backup
+/// suffix in BSD `sed -i ''`, are never file names.
+fn operands(args: &[String], value_flags: &[&str]) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut iter = args.iter().filter(|a| !a.is_empty());
+    let mut after_dashes = false;
+    while let Some(arg) = iter.next() {
+        if after_dashes || !arg.starts_with('-') || arg == "-" {
+            result.push(arg.clone());
+        } else if arg == "--" {
+            after_dashes = true;
+        } else if value_flags.contains(&arg.as_str()) {
+            iter.next();
+        }
+    }
+    result
+}
+
+fn expand(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Relative paths under the current directory, skipping hidden directories.
+fn candidates() -> Vec<String> {
+    let mut found = Vec::new();
+    let mut pending = vec![(PathBuf::from("."), 0)];
+    while let Some((dir, depth)) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if found.len() >= SEARCH_LIMIT {
+                return found;
+            }
+            let path = entry.path();
+            let relative = path.strip_prefix(".").unwrap_or(&path).to_path_buf();
+            let Some(text) = relative.to_str() else {
+                continue;
+            };
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if path.is_dir() && !hidden && depth + 1 < SEARCH_DEPTH {
+                pending.push((path.clone(), depth + 1));
+            }
+            found.push(text.to_string());
+        }
+    }
+    found
+}
+
+fn suggest(missing: &str, pool: &[String]) -> Option<String> {
+    let missing_path = Path::new(missing);
+    // Absolute or parent-relative paths: look next to where the file was expected.
+    let nearby;
+    let pool: &[String] = if missing_path.is_absolute() || missing.starts_with("..") {
+        let parent = missing_path.parent()?;
+        nearby = fs::read_dir(parent)
+            .ok()?
+            .flatten()
+            .map(|e| e.path().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        &nearby
+    } else {
+        pool
+    };
+
+    let name = missing_path.file_name()?.to_string_lossy();
+    let extension = missing_path.extension();
+    let missing = missing.trim_start_matches("./");
+    pool.iter()
+        .map(|candidate| {
+            let path = Path::new(candidate);
+            let base = path
+                .file_name()
+                .map(|n| n.to_string_lossy())
+                .unwrap_or_default();
+            let score = fuzzy::edit_similarity(missing, candidate)
+                .max(fuzzy::edit_similarity(&name, &base));
+            let penalty = if path.extension() == extension {
+                1.0
+            } else {
+                0.8
+            };
+            (score * penalty, candidate)
+        })
+        .filter(|(score, _)| *score >= SUGGEST_THRESHOLD)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, candidate)| candidate.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(command: &str) -> Vec<String> {
+        cmdline::segments(command).iter().flat_map(inputs).collect()
+    }
+
+    #[test]
+    fn finds_the_files_a_command_reads() {
+        assert_eq!(read("cat a.txt b.txt"), ["a.txt", "b.txt"]);
+        assert_eq!(read("head -n 5 log.txt"), ["log.txt"]);
+        assert_eq!(read("sort -k 2 -t , data.csv"), ["data.csv"]);
+        assert_eq!(read("grep -n TODO src/main.rs"), ["src/main.rs"]);
+        assert_eq!(read("grep -e TODO -e FIXME notes.md"), ["notes.md"]);
+        assert_eq!(read("grep -v -F x.y app.log"), ["app.log"]);
+        assert_eq!(read("chmod 644 index.html"), ["index.html"]);
+        assert_eq!(read("sed -i '' s/a/b/ conf.ini"), ["conf.ini"]);
+        assert_eq!(read("cat -- -weird"), ["-weird"]);
+        assert_eq!(read("wc -l < input.txt"), ["input.txt"]);
+        assert_eq!(read("sudo /bin/cat /etc/shadow"), ["/etc/shadow"]);
+        assert!(read("make build").is_empty());
+    }
+
+    #[test]
+    fn destinations_may_not_exist_yet() {
+        assert_eq!(read("cp a.txt b.txt new/"), ["a.txt", "b.txt"]);
+        assert_eq!(read("mv -t dest old.log"), ["old.log"]);
+    }
+
+    #[test]
+    fn media_and_archives() {
+        assert_eq!(
+            read("ffmpeg -y -i in.mp4 -i audio.wav out.mp4"),
+            ["in.mp4", "audio.wav"]
+        );
+        assert_eq!(read("ffprobe -v quiet clip.mov"), ["quiet"]);
+        assert_eq!(read("tar xzf backup.tgz"), ["backup.tgz"]);
+        assert_eq!(read("tar -tvf backup.tar"), ["backup.tar"]);
+        assert_eq!(read("tar --extract --file=site.tar"), ["site.tar"]);
+        assert_eq!(read("tar -x -C out -f site.tar"), ["site.tar"]);
+        // Creating an archive writes it.
+        assert!(read("tar czf backup.tgz src").is_empty());
+    }
+
+    #[test]
+    fn suggests_the_closest_path() {
+        let pool: Vec<String> = ["src/main.rs", "src/lib.rs", "README.md", "Cargo.toml"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            suggest("src/mian.rs", &pool).as_deref(),
+            Some("src/main.rs")
+        );
+        assert_eq!(suggest("./REDME.md", &pool).as_deref(), Some("README.md"));
+        assert_eq!(suggest("docs/lib.rs", &pool).as_deref(), Some("src/lib.rs"));
+        assert_eq!(suggest("unrelated.py", &pool), None);
+    }
+
+    #[test]
+    fn reports_missing_files_once() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("config.yaml"), "").unwrap();
+        let there = dir.path().join("config.yaml").display().to_string();
+        let typo = dir.path().join("confg.yaml").display().to_string();
+        let missing = check(&format!("cat {there} {typo} && wc -l {typo}"));
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].path, typo);
+        assert_eq!(missing[0].suggestion, Some(there));
+        // Globs, variables, URLs and stdin can't be looked up.
+        assert!(check("cat $LOG *.nope https://example.com/x - /nonexistent-[0-9]").is_empty());
+    }
+}
@@ -0,0 +1,33 @@
+use std::path::PathBuf;
+
+/// Directory for user-edited files such as snippets.
+pub fn config_dir() -> Option<PathBuf> {
+    base_dir("XDG_CONFIG_HOME", ".config").map(|dir| dir.join("llmwrap"))
+}
+
+/// Directory for data worth keeping, such as command history.
+pub fn data_dir() -> Option<PathBuf> {
+    base_dir("XDG_DATA_HOME", ".local/share").map(|dir| dir.join("llmwrap"))
+}
+
+/// Directory for disposable data such as cached model responses.
+pub fn cache_dir() -> Option<PathBuf> {
+    base_dir("XDG_CACHE_HOME", ".cache").map(|dir| dir.join("llmwrap"))
+}
+
+/// Directory for state that only matters between recent runs, such as the last session.
+pub fn state_dir() -> Option<PathBuf> {
+    base_dir("XDG_STATE_HOME", ".local/state").map(|dir| dir.join("llmwrap"))
+}
+
+// Follow the XDG variable when set, otherwise fall back to the conventional
+// location under $HOME (or %LOCALAPPDATA% on Windows).
+fn base_dir(xdg_var: &str, home_suffix: &str) -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os(xdg_var).filter(|d| !d.is_empty()) {
+        return Some(PathBuf::from(dir));
+    }
+    if let Some(home) = std::env::var_os("HOME").filter(|h| !h.is_empty()) {
+        return Some(PathBuf::from(home).join(home_suffix));
+    }
+    std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+}
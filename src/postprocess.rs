@@ -0,0 +1,68 @@
+use crate::cmdline;
+use crate::shell::Shell;
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// One step from `[[postprocess]]` in `config.toml`, applied in order to every
+/// proposed command.
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum Step {
+    /// Replace matches of a regular expression; `with` may use `$1`-style groups.
+    Replace { replace: String, with: String },
+    /// Put text such as `time ` or `nice -n19 ` in front of the command.
+    Prefix { prefix: String },
+    /// Set environment variables for the command.
+    Env { env: BTreeMap<String, String> },
+}
+
+/// Run `command` through the configured steps.
+pub fn apply(steps: &[Step], shell: Shell, command: &str) -> Result<String> {
+    let mut command = command.to_string();
+    for step in steps {
+        command = match step {
+            Step::Replace { replace, with } => {
+                let pattern = Regex::new(replace)
+                    .with_context(|| format!("Invalid postprocess pattern {:?}", replace))?;
+                pattern.replace_all(&command, with.as_str()).into_owned()
+            }
+            Step::Prefix { prefix } => {
+                if command.starts_with(prefix.as_str()) {
+                    command
+                } else {
+                    format!("{}{}", prefix, command)
+                }
+            }
+            Step::Env { env } => with_env(shell, env, &command),
+        };
+    }
+    Ok(command)
+}
+
+fn with_env(shell: Shell, env: &BTreeMap<String, String>, command: &str) -> String {
+    let quote = |value: &str| {
+        shlex::try_quote(value).map_or_else(|_| value.to_string(), |q| q.into_owned())
+    };
+    let assignments: Vec<String> = env
+        .iter()
+        .map(|(name, value)| match shell {
+            Shell::Pwsh => format!("$env:{} = '{}';", name, value.replace('\'', "''")),
+            Shell::Nu => format!("$env.{} = {};", name, serde_json::json!(value)),
+            Shell::Cmd => format!("set \"{}={}\" &", name, value),
+            Shell::Fish => format!("set -lx {} {};", name, quote(value)),
+            Shell::Sh | Shell::Bash | Shell::Zsh => format!("{}={}", name, quote(value)),
+        })
+        .collect();
+    // `NAME=value cmd` only reaches the first command of a chain, so chains
+    // export the variables instead.
+    let prefix = if !shell.is_posix() {
+        assignments.join(" ")
+    } else if cmdline::segments(command).len() > 1 {
+        format!("export {};", assignments.join(" "))
+    } else {
+        assignments.join(" ")
+    };
+    format!("{} {}", prefix, command)
+}
@@ -0,0 +1,494 @@
+use crate::cmdline::{self, Segment};
+use crate::readonly;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+const PACKAGE_ACTIONS: &[&str] = &[
+    "install",
+    "remove",
+    "purge",
+    "upgrade",
+    "update",
+    "autoremove",
+    "add",
+    "del",
+    "-S",
+    "-R",
+    "-Syu",
+];
+
+const SERVICE_ACTIONS: &[&str] = &[
+    "start",
+    "stop",
+    "restart",
+    "reload",
+    "enable",
+    "disable",
+    "mask",
+    "daemon-reload",
+];
+
+/// Permission problems found before running a command.
+pub struct Report {
+    pub problems: Vec<String>,
+    /// The command rewritten to run the privileged segments through sudo, when
+    /// sudo is available to this user.
+    pub adapted: Option<String>,
+}
+
+/// Check whether `command` needs privileges the current user does not have.
+pub fn check(command: &str) -> Option<Report> {
+    if !cfg!(unix) || is_root() {
+        return None;
+    }
+
+    let segments = cmdline::segments(command);
+    let mut problems = Vec::new();
+    let mut privileged = Vec::new();
+    for segment in &segments {
+        if segment.elevated() {
+            continue;
+        }
+        let found = segment_problems(segment);
+        if !found.is_empty() {
+            problems.extend(found);
+            privileged.push(segment);
+        }
+    }
+    if problems.is_empty() {
+        return None;
+    }
+
+    let adapted = sudo_available().then(|| with_sudo(command, &privileged));
+    Some(Report { problems, adapted })
+}
+
+/// `command` with the `privileged` segments run through sudo.
+pub fn with_sudo(command: &str, privileged: &[&Segment]) -> String {
+    let mut adapted = command.to_string();
+    // Rewrite from the end so earlier spans stay valid.
+    for segment in privileged.iter().rev() {
+        let text = &command[segment.span.clone()];
+        let replacement = if segment.redirects.iter().any(|r| r.writes()) {
+            // sudo does not apply to the shell's own redirections.
+            format!("sudo sh -c {}", quote(text))
+        } else {
+            format!("sudo {}", text)
+        };
+        adapted.replace_range(segment.span.clone(), &replacement);
+    }
+    adapted
+}
+
+fn segment_problems(segment: &Segment) -> Vec<String> {
+    let mut problems = Vec::new();
+    let args = segment.args_from_program();
+    let Some(program) = segment.program().map(|p| p.rsplit('/').next().unwrap_or(p)) else {
+        return problems;
+    };
+    let has_word = |word: &str| args.iter().any(|a| a == word);
+
+    let needs_root = match program {
+        "apt" | "apt-get" | "dnf" | "yum" | "zypper" | "apk" | "pacman" => {
+            PACKAGE_ACTIONS.iter().any(|w| has_word(w))
+        }
+        "systemctl" => !has_word("--user") && SERVICE_ACTIONS.iter().any(|w| has_word(w)),
+        "mount" | "umount" => args.len() > 1,
+        "useradd" | "userdel" | "usermod" | "groupadd" | "groupdel" | "modprobe" | "rmmod"
+        | "iptables" | "nft" | "sysctl" | "shutdown" | "reboot" | "fdisk" | "parted" => true,
+        p => p.starts_with("mkfs"),
+    };
+    if needs_root {
+        problems.push(format!("`{}` normally requires root", args.join(" ")));
+    }
+
+    for path in written_paths(segment, program, args) {
+        if !writable(&path) {
+            problems.push(format!("{} is not writable by you", path.display()));
+        }
+    }
+    problems
+}
+
+/// Paths any segment of `command` would create, modify or remove, as written.
+pub fn written(command: &str) -> Vec<PathBuf> {
+    cmdline::segments(command)
+        .iter()
+        .flat_map(|segment| {
+            let args = segment.args_from_program();
+            let program = segment
+                .program()
+                .map_or("", |p| p.rsplit('/').next().unwrap_or(p));
+            written_paths(segment, program, args)
+        })
+        .collect()
+}
+
+/// Parts of `command` that may write files where no one can tell before it
+/// runs, such as `find -exec`, a script run by an interpreter or `xargs`, or a
+/// command substitution.
+pub fn unknown_writes(command: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    if readonly::substitutes(command) {
+        found.push("`$(...)`".to_string());
+    }
+    for segment in cmdline::segments(command) {
+        let args = segment.args_from_program();
+        let Some(program) = segment.program().map(|p| p.rsplit('/').next().unwrap_or(p)) else {
+            continue;
+        };
+        if let Some(script) = shell_script(program, args) {
+            found.extend(unknown_writes(script));
+        } else if program == "find" && args.iter().any(|a| FIND_RUNS.contains(&a.as_str())) {
+            found.push("`find -exec`".to_string());
+        } else if readonly::RUNNERS.contains(&program) && program != "ssh" {
+            found.push(format!("`{}`", program));
+        }
+    }
+    found
+}
+
+/// Arguments that make `find` run commands or write files of its choosing.
+const FIND_RUNS: &[&str] = &[
+    "-exec", "-execdir", "-ok", "-okdir", "-fprint", "-fprint0", "-fprintf", "-fls",
+];
+
+/// The script a shell is given with `-c`, as in `sudo sh -c 'echo x > /etc/motd'`.
+fn shell_script<'a>(program: &str, args: &'a [String]) -> Option<&'a str> {
+    if !matches!(
+        program,
+        "sh" | "bash" | "zsh" | "dash" | "ksh" | "mksh" | "ash"
+    ) {
+        return None;
+    }
+    let flag = args[1..]
+        .iter()
+        .position(|a| a.starts_with('-') && !a.starts_with("--") && a.contains('c'))?;
+    args.get(flag + 2).map(String::as_str)
+}
+
+/// The value of an option given as `-o VALUE`, `-oVALUE`, `--output VALUE` or
+/// `--output=VALUE`, or at the end of a cluster of short options such as
+/// `-sSLo VALUE`.
+fn option_value<'a>(args: &'a [String], short: char, long: &str) -> Option<&'a str> {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if let Some(rest) = arg.strip_prefix("--") {
+            if rest == long {
+                return iter.next().map(String::as_str);
+            }
+            if let Some(value) = rest.strip_prefix(long).and_then(|r| r.strip_prefix('=')) {
+                return Some(value);
+            }
+        } else if let Some(cluster) = arg.strip_prefix('-')
+            && let Some(at) = cluster.find(short)
+        {
+            let value = &cluster[at + short.len_utf8()..];
+            return if value.is_empty() {
+                iter.next().map(String::as_str)
+            } else {
+                Some(value)
+            };
+        }
+    }
+    None
+}
+
+/// Paths the segment would create, modify or remove.
+fn written_paths(segment: &Segment, program: &str, args: &[String]) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = segment
+        .redirects
+        .iter()
+        .filter(|r| r.writes() && r.target != "/dev/null")
+        .map(|r| PathBuf::from(&r.target))
+        .collect();
+    // Only assignments, as in `FOO=1 > out`, run no program.
+    if args.is_empty() {
+        return paths;
+    }
+
+    let operands: Vec<&String> = args[1..].iter().filter(|a| !a.starts_with('-')).collect();
+    let in_place = args
+        .iter()
+        .any(|a| a == "-i" || a.starts_with("--in-place"));
+    if let Some(script) = shell_script(program, args) {
+        paths.extend(written(script));
+    }
+    // `cp -t DIR a b` names the destination first.
+    let target_directory = option_value(&args[1..], 't', "target-directory");
+    match program {
+        "cp" | "mv" | "install" | "ln" if target_directory.is_some() => {
+            paths.extend(target_directory.map(PathBuf::from));
+            if program == "mv" {
+                paths.extend(
+                    operands
+                        .iter()
+                        .filter(|a| Some(a.as_str()) != target_directory)
+                        .map(PathBuf::from),
+                );
+            }
+        }
+        "tee" | "touch" | "mkdir" | "rm" | "rmdir" | "truncate" | "chmod" | "chown" => {
+            // chmod and chown take a mode or owner before the paths.
+            let skip = usize::from(matches!(program, "chmod" | "chown"));
+            paths.extend(operands.iter().skip(skip).map(PathBuf::from));
+        }
+        // Moving a file away removes it from where it was.
+        "mv" => paths.extend(operands.iter().map(PathBuf::from)),
+        "cp" | "install" | "ln" if operands.len() >= 2 => {
+            paths.extend(operands.last().map(PathBuf::from));
+        }
+        "sed" if in_place => {
+            paths.extend(operands.iter().skip(1).map(PathBuf::from));
+        }
+        "dd" => paths.extend(
+            args[1..]
+                .iter()
+                .filter_map(|a| a.strip_prefix("of="))
+                .map(PathBuf::from),
+        ),
+        // What find deletes is under the paths it starts from.
+        "find" if args.iter().any(|a| a == "-delete") => {
+            let roots: Vec<&String> = args[1..]
+                .iter()
+                .take_while(|a| !a.starts_with(['-', '(', '!']))
+                .collect();
+            if roots.is_empty() {
+                paths.push(PathBuf::from("."));
+            }
+            paths.extend(roots.into_iter().map(PathBuf::from));
+        }
+        "curl" => {
+            if let Some(output) = option_value(&args[1..], 'o', "output") {
+                paths.push(PathBuf::from(output));
+            } else if args[1..].iter().any(|a| {
+                a.starts_with("--remote-name")
+                    || (a.starts_with('-') && !a.starts_with("--") && a.contains('O'))
+            }) {
+                // Saved under the name in the URL, in the current directory.
+                paths.push(PathBuf::from("."));
+            }
+        }
+        "wget" => match option_value(&args[1..], 'O', "output-document") {
+            Some("-") => {}
+            Some(output) => paths.push(PathBuf::from(output)),
+            None => paths.push(PathBuf::from(
+                option_value(&args[1..], 'P', "directory-prefix").unwrap_or("."),
+            )),
+        },
+        "tar" if tar_extracts(args) => paths.push(PathBuf::from(
+            option_value(&args[1..], 'C', "directory").unwrap_or("."),
+        )),
+        "unzip" if !args.iter().any(|a| a == "-l") => {
+            paths.push(PathBuf::from(
+                option_value(&args[1..], 'd', "d").unwrap_or("."),
+            ));
+        }
+        "scp" | "rsync" if operands.len() >= 2 => {
+            paths.extend(
+                operands
+                    .last()
+                    .filter(|a| !a.contains(':'))
+                    .map(PathBuf::from),
+            );
+        }
+        _ => {}
+    }
+    paths
+}
+
+/// Whether `tar` is asked to extract, as in `tar -xf a.tar` or `tar xzf a.tgz`.
+fn tar_extracts(args: &[String]) -> bool {
+    let first = args
+        .get(1)
+        .is_some_and(|a| !a.starts_with('-') && a.contains('x'));
+    first
+        || args[1..].iter().any(|a| {
+            a == "--extract"
+                || a == "--get"
+                || (a.starts_with('-') && !a.starts_with("--") && a.contains('x'))
+        })
+}
+
+/// Whether the path (or, for a path that does not exist yet, its parent) can be
+/// written by the current user. Removing a file needs its directory to be writable.
+fn writable(path: &Path) -> bool {
+    let existing = if path.exists() {
+        path
+    } else {
+        match path.parent() {
+            Some(parent) if parent.as_os_str().is_empty() => Path::new("."),
+            Some(parent) => parent,
+            None => return true,
+        }
+    };
+    // Paths that do not exist at all are a different problem; don't guess.
+    if !existing.exists() {
+        return true;
+    }
+    access_writable(existing)
+}
+
+#[cfg(unix)]
+fn access_writable(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+    let Ok(c_path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return true;
+    };
+    // SAFETY: c_path is a valid NUL-terminated string for the duration of the call.
+    unsafe { libc::access(c_path.as_ptr(), libc::W_OK) == 0 }
+}
+
+#[cfg(not(unix))]
+fn access_writable(_path: &Path) -> bool {
+    true
+}
+
+#[cfg(unix)]
+fn is_root() -> bool {
+    // SAFETY: geteuid has no preconditions and cannot fail.
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_root() -> bool {
+    false
+}
+
+/// Whether this user may use sudo at all. `sudo -n -l` fails both when sudo is
+/// missing and when the user is not in sudoers; a required password is fine
+/// because sudo will prompt for it when the command runs.
+fn sudo_available() -> bool {
+    let Ok(output) = Command::new("sudo")
+        .args(["-n", "-l"])
+        .stdin(Stdio::null())
+        .output()
+    else {
+        return false;
+    };
+    output.status.success() || String::from_utf8_lossy(&output.stderr).contains("password")
+}
+
+fn quote(text: &str) -> String {
+    shlex::try_quote(text)
+        .map(|q| q.into_owned())
+        .unwrap_or_else(|_| format!("'{}'", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written_by(command: &str) -> Vec<String> {
+        written(command)
+            .into_iter()
+            .map(|path| path.display().to_string())
+            .collect()
+    }
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn sudo_goes_before_each_privileged_segment() {
+        let command = "apt-get update && apt-get install -y jq; jq --version";
+        let segments = cmdline::segments(command);
+        assert_eq!(
+            with_sudo(command, &[&segments[0], &segments[1]]),
+            "sudo apt-get update && sudo apt-get install -y jq; jq --version"
+        );
+    }
+
+    #[test]
+    fn redirects_run_in_a_root_shell() {
+        let command = "echo 'vm.swappiness=10' >> /etc/sysctl.conf && sysctl -p";
+        let segments = cmdline::segments(command);
+        assert_eq!(
+            with_sudo(command, &[&segments[0], &segments[1]]),
+            r#"sudo sh -c "echo 'vm.swappiness=10' >> /etc/sysctl.conf" && sudo sysctl -p"#
+        );
+    }
+
+    #[test]
+    fn paths_each_program_writes() {
+        assert_eq!(written_by("echo x > a >> b 2> /dev/null"), ["a", "b"]);
+        assert_eq!(written_by("touch a b && mkdir -p c"), ["a", "b", "c"]);
+        assert_eq!(written_by("chmod 644 a"), ["a"]);
+        assert_eq!(written_by("mv a b"), ["a", "b"]);
+        assert_eq!(written_by("cp -r a b c"), ["c"]);
+        assert_eq!(written_by("cp -t dir a b"), ["dir"]);
+        assert_eq!(written_by("mv --target-directory=dir a"), ["dir", "a"]);
+        assert_eq!(written_by("sed -i 's/a/b/' f"), ["f"]);
+        assert!(written_by("sed 's/a/b/' f").is_empty());
+        assert_eq!(written_by("dd if=a of=/dev/sdb"), ["/dev/sdb"]);
+        assert_eq!(written_by("find logs -name '*.gz' -delete"), ["logs"]);
+        assert_eq!(written_by("find -name '*.gz' -delete"), ["."]);
+        assert_eq!(written_by("rsync -a src/ host:dst/"), Vec::<String>::new());
+        assert_eq!(written_by("scp host:a b"), ["b"]);
+    }
+
+    #[test]
+    fn downloads_and_archives_write_where_they_are_told() {
+        assert_eq!(written_by("curl -sSLo out https://example.com"), ["out"]);
+        assert_eq!(written_by("curl --output=out https://example.com"), ["out"]);
+        assert_eq!(written_by("curl -O https://example.com/a"), ["."]);
+        assert!(written_by("curl https://example.com").is_empty());
+        assert!(written_by("wget -qO- https://example.com").is_empty());
+        assert_eq!(written_by("wget -P dl https://example.com/a"), ["dl"]);
+        assert_eq!(written_by("tar xzf a.tgz"), ["."]);
+        assert_eq!(
+            written_by("tar --extract -f a.tar --directory out"),
+            ["out"]
+        );
+        assert!(written_by("tar -tf a.tar").is_empty());
+        assert_eq!(written_by("unzip a.zip -d out"), ["out"]);
+        assert!(written_by("unzip -l a.zip").is_empty());
+    }
+
+    #[test]
+    fn shell_scripts_are_looked_into() {
+        assert_eq!(
+            written_by("sudo bash -c 'echo x > /etc/motd'"),
+            ["/etc/motd"]
+        );
+        assert_eq!(written_by("sh -ec 'rm -f a'"), ["a"]);
+        assert_eq!(
+            unknown_writes("sh -c 'find . -exec rm {} +'"),
+            ["`find -exec`"]
+        );
+    }
+
+    #[test]
+    fn writes_no_one_can_tell_before_running() {
+        assert_eq!(unknown_writes("rm $(cat list)"), ["`$(...)`"]);
+        assert_eq!(unknown_writes("ls | xargs rm"), ["`xargs`"]);
+        assert_eq!(unknown_writes("python3 fix.py"), ["`python3`"]);
+        assert!(unknown_writes("ssh host uptime").is_empty());
+        assert!(unknown_writes("find . -name '*.log' -print").is_empty());
+        assert!(unknown_writes("echo '$(not run)'").is_empty());
+    }
+
+    #[test]
+    fn only_assignments_write_nothing() {
+        assert!(written_by("FOO=1").is_empty());
+        assert_eq!(written_by("FOO=1 > out"), ["out"]);
+        assert!(unknown_writes("FOO=1").is_empty());
+    }
+
+    #[test]
+    fn option_values_in_every_spelling() {
+        let args = strings(&["-sSLo", "out", "url"]);
+        assert_eq!(option_value(&args, 'o', "output"), Some("out"));
+        let args = strings(&["-oout"]);
+        assert_eq!(option_value(&args, 'o', "output"), Some("out"));
+        let args = strings(&["--output", "out"]);
+        assert_eq!(option_value(&args, 'o', "output"), Some("out"));
+        let args = strings(&["--output=out"]);
+        assert_eq!(option_value(&args, 'o', "output"), Some("out"));
+        let args = strings(&["--outputs", "x", "url"]);
+        assert_eq!(option_value(&args, 'o', "output"), None);
+        let args = strings(&["-o"]);
+        assert_eq!(option_value(&args, 'o', "output"), None);
+    }
+}
@@ -0,0 +1,60 @@
+//! Finding out about the machine for the system prompt without keeping the
+//! user waiting: each source runs on a thread of its own, and one that is
+//! slow is left out rather than waited for.
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Something to find out about the machine for the system prompt, such as
+/// the OS or the project in the working directory, as a note for the model.
+pub struct Probe {
+    name: &'static str,
+    run: Box<dyn FnOnce() -> Option<String> + Send>,
+}
+
+impl Probe {
+    pub fn new(name: &'static str, run: impl FnOnce() -> Option<String> + Send + 'static) -> Self {
+        Probe {
+            name,
+            run: Box::new(run),
+        }
+    }
+}
+
+/// Run the probes side by side and return their notes in the order given,
+/// leaving out any not done within `timeout` with a warning. Those are left
+/// to finish on their own.
+pub fn run(probes: Vec<Probe>, timeout: Duration) -> Vec<String> {
+    let deadline = Instant::now() + timeout;
+    let (sender, receiver) = mpsc::channel();
+    let names: Vec<&'static str> = probes.iter().map(|probe| probe.name).collect();
+    for (index, probe) in probes.into_iter().enumerate() {
+        let sender = sender.clone();
+        thread::spawn(move || {
+            let _ = sender.send((index, (probe.run)()));
+        });
+    }
+    drop(sender);
+    let mut notes: Vec<Option<Option<String>>> = names.iter().map(|_| None).collect();
+    while notes.iter().any(Option::is_none) {
+        match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok((index, note)) => notes[index] = Some(note),
+            Err(_) => break,
+        }
+    }
+    let late: Vec<&str> = names
+        .iter()
+        .zip(&notes)
+        .filter(|(_, note)| note.is_none())
+        .map(|(name, _)| *name)
+        .collect();
+    if !late.is_empty() {
+        eprintln!(
+            "Warning: left out the {} context, which took over {} ms; `context_timeout_ms` under [latency] allows more",
+            late.join(" and "),
+            timeout.as_millis()
+        );
+    }
+    notes.into_iter().flatten().flatten().collect()
+}
@@ -0,0 +1,235 @@
+use crate::makefile;
+use std::fs;
+use std::path::Path;
+
+/// How many tasks to list at most; large monorepos define hundreds of scripts.
+const TASK_LIMIT: usize = 40;
+/// How much of what a task runs to show; the model only needs enough to tell
+/// whether it matches the request.
+const RUNS_LIMIT: usize = 100;
+
+/// What kind of project the current directory holds and the tasks it
+/// defines, so "run the tests" can become `npm test` or `make check`.
+pub struct Project {
+    /// One entry per marker found, e.g. "a Rust crate `llmwrap`".
+    pub kinds: Vec<String>,
+    pub tasks: Vec<Task>,
+}
+
+/// A script, alias or build target the project defines.
+pub struct Task {
+    /// How to invoke it, e.g. `npm run lint` or `make check`.
+    pub invocation: String,
+    /// What it runs, e.g. `eslint src`, when that is known.
+    pub runs: Option<String>,
+}
+
+impl Task {
+    fn new(invocation: String, runs: &str) -> Self {
+        let runs = runs.trim();
+        let runs = match runs.char_indices().nth(RUNS_LIMIT) {
+            Some((end, _)) => Some(format!("{}...", &runs[..end])),
+            None => Some(runs.to_string()).filter(|r| !r.is_empty()),
+        };
+        Task { invocation, runs }
+    }
+}
+
+impl Project {
+    /// Look for project markers in the current directory.
+    pub fn detect() -> Option<Self> {
+        let mut project = Project {
+            kinds: Vec::new(),
+            tasks: Vec::new(),
+        };
+        project.cargo();
+        project.node();
+        project.python();
+        project.go();
+        project.docker();
+        project.build_file();
+        project.tasks.truncate(TASK_LIMIT);
+        (!project.kinds.is_empty() || !project.tasks.is_empty()).then_some(project)
+    }
+
+    /// A note for the system prompt describing the project and its tasks.
+    pub fn prompt_note(&self) -> String {
+        let mut notes = Vec::new();
+        if !self.kinds.is_empty() {
+            notes.push(format!(
+                "The current directory holds {}.",
+                self.kinds.join(", ")
+            ));
+        }
+        if !self.tasks.is_empty() {
+            let tasks: Vec<String> = self
+                .tasks
+                .iter()
+                .map(|task| match &task.runs {
+                    Some(runs) => format!("`{}` (runs `{}`)", task.invocation, runs),
+                    None => format!("`{}`", task.invocation),
+                })
+                .collect();
+            notes.push(format!(
+                "The project defines these tasks: {}. When one of them already does what \
+is asked, respond with its invocation instead of the commands it runs, so the \
+project's own flags and setup are used.",
+                tasks.join(", ")
+            ));
+        }
+        notes.join(" ")
+    }
+
+    fn cargo(&mut self) {
+        let Some(manifest) = read_toml("Cargo.toml") else {
+            return;
+        };
+        match manifest
+            .get("package")
+            .and_then(|p| p.get("name"))
+            .and_then(toml::Value::as_str)
+        {
+            Some(name) => self.kinds.push(format!("a Rust crate `{}`", name)),
+            None => self.kinds.push("a Rust workspace".to_string()),
+        }
+        let aliases = read_toml(".cargo/config.toml").or_else(|| read_toml(".cargo/config"));
+        if let Some(aliases) = aliases
+            .as_ref()
+            .and_then(|c| c.get("alias"))
+            .and_then(toml::Value::as_table)
+        {
+            for (name, value) in aliases {
+                let runs = match value {
+                    toml::Value::String(runs) => runs.clone(),
+                    toml::Value::Array(words) => words
+                        .iter()
+                        .filter_map(toml::Value::as_str)
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    _ => String::new(),
+                };
+                let runs = if runs.is_empty() {
+                    runs
+                } else {
+                    format!("cargo {}", runs)
+                };
+                self.tasks.push(Task::new(format!("cargo {}", name), &runs));
+            }
+        }
+    }
+
+    fn node(&mut self) {
+        let Some(package) = fs::read_to_string("package.json")
+            .ok()
+            .and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok())
+        else {
+            return;
+        };
+        let runner = [
+            ("pnpm-lock.yaml", "pnpm"),
+            ("yarn.lock", "yarn"),
+            ("bun.lockb", "bun"),
+            ("bun.lock", "bun"),
+        ]
+        .into_iter()
+        .find(|(lockfile, _)| Path::new(lockfile).is_file())
+        .map_or("npm", |(_, runner)| runner);
+        match package.get("name").and_then(serde_json::Value::as_str) {
+            Some(name) => self.kinds.push(format!(
+                "a Node.js package `{}` managed with {}",
+                name, runner
+            )),
+            None => self
+                .kinds
+                .push(format!("a Node.js project managed with {}", runner)),
+        }
+        if let Some(scripts) = package
+            .get("scripts")
+            .and_then(serde_json::Value::as_object)
+        {
+            for (name, runs) in scripts {
+                let runs = runs.as_str().unwrap_or_default();
+                self.tasks
+                    .push(Task::new(format!("{} run {}", runner, name), runs));
+            }
+        }
+    }
+
+    fn python(&mut self) {
+        let Some(pyproject) = read_toml("pyproject.toml") else {
+            if Path::new("setup.py").is_file() || Path::new("requirements.txt").is_file() {
+                self.kinds.push("a Python project".to_string());
+            }
+            return;
+        };
+        let runner = if Path::new("uv.lock").is_file() {
+            " managed with uv"
+        } else if Path::new("poetry.lock").is_file() {
+            " managed with poetry"
+        } else {
+            ""
+        };
+        let name = pyproject
+            .get("project")
+            .or_else(|| pyproject.get("tool").and_then(|t| t.get("poetry")))
+            .and_then(|p| p.get("name"))
+            .and_then(toml::Value::as_str);
+        match name {
+            Some(name) => self
+                .kinds
+                .push(format!("a Python project `{}`{}", name, runner)),
+            None => self.kinds.push(format!("a Python project{}", runner)),
+        }
+    }
+
+    fn go(&mut self) {
+        let Ok(text) = fs::read_to_string("go.mod") else {
+            return;
+        };
+        match text.lines().find_map(|line| line.strip_prefix("module ")) {
+            Some(module) => self.kinds.push(format!("a Go module `{}`", module.trim())),
+            None => self.kinds.push("a Go module".to_string()),
+        }
+    }
+
+    fn docker(&mut self) {
+        if Path::new("Dockerfile").is_file() {
+            self.kinds.push("a Dockerfile".to_string());
+        }
+        let compose = [
+            "compose.yaml",
+            "compose.yml",
+            "docker-compose.yaml",
+            "docker-compose.yml",
+        ]
+        .into_iter()
+        .find(|name| Path::new(name).is_file());
+        if let Some(compose) = compose {
+            self.kinds
+                .push(format!("a Docker Compose file ({})", compose));
+        }
+    }
+
+    fn build_file(&mut self) {
+        let Some((kind, path)) = makefile::existing() else {
+            return;
+        };
+        let Ok(text) = fs::read_to_string(&path) else {
+            return;
+        };
+        for rule in makefile::rules(kind, &text) {
+            let runs = rule
+                .body
+                .iter()
+                .map(|line| line.trim_start_matches(['@', '-']))
+                .collect::<Vec<_>>()
+                .join("; ");
+            self.tasks
+                .push(Task::new(format!("{} {}", kind.runner(), rule.name), &runs));
+        }
+    }
+}
+
+fn read_toml(path: &str) -> Option<toml::Value> {
+    toml::from_str(&fs::read_to_string(path).ok()?).ok()
+}
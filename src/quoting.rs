@@ -0,0 +1,190 @@
+use crate::cmdline;
+use std::cmp::Reverse;
+use std::fs;
+use std::path::Path;
+
+/// Look at no more directory entries than this; big directories are rarely what
+/// a one-off command refers to by name.
+const SCAN_LIMIT: usize = 2_000;
+
+/// A command whose references to real files were re-quoted.
+pub struct Repair {
+    pub command: String,
+    /// The file names whose quoting was fixed.
+    pub names: Vec<String>,
+}
+
+/// Fix unquoted or badly escaped references to files that exist: entries of the
+/// current directory and paths mentioned in the request. Returns None when
+/// nothing needed fixing.
+pub fn repair(command: &str, prompt: &str) -> Option<Repair> {
+    let mut command = command.to_string();
+    let mut names = Vec::new();
+    for name in candidates(prompt) {
+        if references(&command, &name) {
+            continue;
+        }
+        let Some(span) = broken_span(&command, &name) else {
+            continue;
+        };
+        let mut fixed = command.clone();
+        fixed.replace_range(span, &quote(&name));
+        // Only keep the edit if the shell now sees the file name as one word.
+        if references(&fixed, &name) {
+            command = fixed;
+            names.push(name);
+        }
+    }
+    (!names.is_empty()).then_some(Repair { command, names })
+}
+
+/// Names relative to the current directory, one level of subdirectories deep,
+/// plus existing paths from the request, that need quoting on a command line.
+fn candidates(prompt: &str) -> Vec<String> {
+    let mut names = entries(Path::new("."), "");
+    let dirs: Vec<String> = names
+        .iter()
+        .filter(|n| Path::new(n).is_dir())
+        .cloned()
+        .collect();
+    for dir in dirs {
+        if names.len() >= SCAN_LIMIT {
+            break;
+        }
+        names.extend(entries(Path::new(&dir), &format!("{}/", dir)));
+    }
+    let mentioned = shlex::split(prompt).unwrap_or_default();
+    names.extend(
+        mentioned
+            .into_iter()
+            .filter(|word| word.contains('/') && Path::new(word).exists()),
+    );
+    names.retain(|name| quote(name) != *name);
+    names.sort();
+    names.dedup();
+    // Longest first, so `my file (1).txt` is repaired before `my file.txt`
+    // could match part of it.
+    names.sort_by_key(|name| Reverse(name.len()));
+    names
+}
+
+fn entries(dir: &Path, prefix: &str) -> Vec<String> {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .take(SCAN_LIMIT)
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .map(|name| format!("{}{}", prefix, name))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Whether a word of the command, as the shell will see it, is `name` or a path
+/// ending in it.
+fn references(command: &str, name: &str) -> bool {
+    let suffix = format!("/{}", name);
+    cmdline::segments(command).iter().any(|segment| {
+        segment
+            .words
+            .iter()
+            .chain(segment.redirects.iter().map(|r| &r.target))
+            .map(|word| word.strip_suffix('/').unwrap_or(word))
+            .any(|word| word == name || word.ends_with(&suffix))
+    })
+}
+
+/// Byte range of a mangled occurrence of `name`: its characters in order, with
+/// stray quotes and backslashes in between, starting and ending at a word boundary.
+fn broken_span(command: &str, name: &str) -> Option<std::ops::Range<usize>> {
+    let is_quote = |c: char| matches!(c, '\'' | '"' | '\\');
+    let starts_word = |i: usize| {
+        command[..i]
+            .chars()
+            .next_back()
+            .is_none_or(|c| c.is_whitespace() || matches!(c, '/' | '=' | '(' | '\'' | '"'))
+    };
+    let ends_word = |j: usize| {
+        command[j..].chars().next().is_none_or(|c| {
+            c.is_whitespace() || matches!(c, '/' | ';' | '|' | '&' | ')' | '>' | '<' | '\'' | '"')
+        })
+    };
+
+    for (start, _) in command.char_indices().filter(|&(i, _)| starts_word(i)) {
+        let mut expected = name.chars().peekable();
+        let mut end = start;
+        for (j, c) in command[start..].char_indices() {
+            match expected.peek() {
+                None => break,
+                Some(&e) if e == c => {
+                    expected.next();
+                }
+                Some(_) if is_quote(c) => {}
+                Some(_) => break,
+            }
+            end = start + j + c.len_utf8();
+        }
+        if expected.peek().is_some() || !ends_word(end) {
+            continue;
+        }
+        // Swallow a closing quote that matches one the span opened with.
+        let opener = command[start..].chars().next();
+        let closer = command[end..].chars().next();
+        if opener.is_some_and(is_quote) && opener == closer {
+            end += 1;
+        }
+        return Some(start..end);
+    }
+    None
+}
+
+fn quote(name: &str) -> String {
+    shlex::try_quote(name)
+        .map(|q| q.into_owned())
+        .unwrap_or_else(|_| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_word_that_is_the_name_or_ends_in_it_references_it() {
+        assert!(references("cat 'my file.txt'", "my file.txt"));
+        assert!(references(r"cat data/my\ file.txt", "my file.txt"));
+        assert!(references("ls \"my dir\"/", "my dir"));
+        assert!(references("wc -l < 'my file.txt'", "my file.txt"));
+        assert!(!references("cat my file.txt", "my file.txt"));
+        assert!(!references("cat 'not my file.txt'", "my file.txt"));
+    }
+
+    #[test]
+    fn mangled_names_are_found_between_word_boundaries() {
+        let command = "cp my file (1).txt backup/";
+        assert_eq!(broken_span(command, "my file (1).txt"), Some(3..18));
+        let command = r#"cat "my file.txt | wc -l"#;
+        assert_eq!(broken_span(command, "my file.txt"), Some(4..16));
+        let command = "cat 'my' 'file.txt' && ls";
+        assert_eq!(broken_span(command, "my file.txt"), Some(4..19));
+        assert_eq!(broken_span("cat amy file.txt", "my file.txt"), None);
+    }
+
+    #[test]
+    fn names_mentioned_in_the_request_are_requoted() {
+        let dir = std::env::temp_dir().join(format!("llmwrap-quoting-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("monthly report.pdf");
+        fs::write(&file, "").unwrap();
+        let path = file.display().to_string();
+
+        let command = format!("gzip -k {}", path);
+        let prompt = format!("compress '{}'", path);
+        let repaired = repair(&command, &prompt).unwrap();
+        assert_eq!(repaired.command, format!("gzip -k {}", quote(&path)));
+        assert!(repair(&repaired.command, &prompt).is_none());
+        assert_eq!(repaired.names, [path]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,133 @@
+use crate::cmdline::{self, Operator};
+use crate::net;
+use anyhow::Result;
+use regex::Regex;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+/// Programs that run whatever script they are fed.
+const INTERPRETERS: &[&str] = &[
+    "sh", "bash", "zsh", "dash", "ksh", "fish", "python", "python3", "perl", "ruby", "node",
+];
+/// Give up following a chain of redirects after this many hops.
+const MAX_REDIRECTS: usize = 10;
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `bash -c "$(curl ...)"`, `source <(wget ...)` and the like: a download
+/// substituted into an interpreter's command line. Substituted elsewhere, as in
+/// `sum=$(curl ...)`, it is only data.
+static SUBSTITUTED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"(?:\b(?:sh|bash|zsh|dash|ksh|fish|python3?|perl|ruby|node|eval|source)|(?:^|[;&|]\s*)\.)\s+(?:-\w+\s+)*["']?[$<]\(\s*(?:curl|wget)\b[^)]*?(https?://[^\s'")]+)"#,
+    )
+    .unwrap()
+});
+
+/// URLs in `command` whose content is downloaded and then executed, whether
+/// piped into an interpreter or substituted into its command line.
+pub fn executed_urls(command: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    let segments = cmdline::segments(command);
+    for pair in segments.windows(2) {
+        let [download, next] = pair else {
+            continue;
+        };
+        let downloads = download
+            .program()
+            .is_some_and(|p| matches!(p.rsplit('/').next(), Some("curl" | "wget")));
+        let runs = next
+            .program()
+            .is_some_and(|p| INTERPRETERS.contains(&p.rsplit('/').next().unwrap_or(p)));
+        if download.op == Some(Operator::Pipe) && downloads && runs {
+            urls.extend(
+                download
+                    .args_from_program()
+                    .iter()
+                    .filter(|a| a.starts_with("http://") || a.starts_with("https://"))
+                    .cloned(),
+            );
+        }
+    }
+    for captures in SUBSTITUTED.captures_iter(command) {
+        urls.push(captures[1].to_string());
+    }
+    urls.dedup();
+    urls
+}
+
+/// Where a URL ends up once its redirects are followed.
+pub struct Resolved {
+    /// Each URL that redirected, in the order they were visited.
+    pub redirects: Vec<String>,
+    pub final_url: String,
+    pub content_type: Option<String>,
+}
+
+impl Resolved {
+    /// Whether the content type says this is a web page rather than a script,
+    /// which is what an error or login page would look like.
+    pub fn looks_like_html(&self) -> bool {
+        self.content_type
+            .as_deref()
+            .is_some_and(|t| t.starts_with("text/html"))
+    }
+}
+
+/// Follow `url`'s redirects with HEAD requests, without downloading the body.
+pub fn resolve(url: &str) -> Result<Resolved> {
+    let agent = net::agent()?.redirects(0).timeout(RESOLVE_TIMEOUT).build();
+    let mut current = url.to_string();
+    let mut redirects = Vec::new();
+    for _ in 0..=MAX_REDIRECTS {
+        let response = match agent.head(&current).call() {
+            Ok(response) => response,
+            // Some servers reject HEAD; their answer still tells us where we are.
+            Err(ureq::Error::Status(_, response)) => response,
+            Err(err) => return Err(err.into()),
+        };
+        if (300..400).contains(&response.status())
+            && let Some(location) = response.header("location")
+        {
+            let next = join(&current, location);
+            redirects.push(std::mem::replace(&mut current, next));
+            continue;
+        }
+        return Ok(Resolved {
+            redirects,
+            final_url: current,
+            content_type: response.header("content-type").map(str::to_string),
+        });
+    }
+    anyhow::bail!("{} redirects more than {} times", url, MAX_REDIRECTS)
+}
+
+/// Resolve a `Location` header against the URL that sent it.
+fn join(base: &str, location: &str) -> String {
+    if location.contains("://") {
+        return location.to_string();
+    }
+    let (scheme, rest) = base.split_once("://").unwrap_or(("https", base));
+    if let Some(authority) = location.strip_prefix("//") {
+        return format!("{}://{}", scheme, authority);
+    }
+    let host = rest.split(['/', '?', '#']).next().unwrap_or(rest);
+    if location.starts_with('/') {
+        return format!("{}://{}{}", scheme, host, location);
+    }
+    let path = rest.split(['?', '#']).next().unwrap_or(rest);
+    let dir = path.rsplit_once('/').map_or(host, |(dir, _)| dir);
+    format!("{}://{}/{}", scheme, dir, location)
+}
+
+/// Whether `url` answers a HEAD request with success once redirects are
+/// followed.
+pub fn exists(url: &str) -> bool {
+    net::agent().is_ok_and(|agent| {
+        agent
+            .timeout(RESOLVE_TIMEOUT)
+            .build()
+            .head(url)
+            .call()
+            .is_ok()
+    })
+}
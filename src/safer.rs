@@ -0,0 +1,278 @@
+use crate::cmdline::{self, Operator, Word};
+use std::ops::Range;
+
+/// Shells that a downloaded script is commonly piped into.
+const SHELLS: &[&str] = &["sh", "bash", "zsh", "dash"];
+/// Octal and symbolic modes that let everyone write.
+const WORLD_WRITABLE: &[&str] = &[
+    "777", "0777", "666", "0666", "a+rwx", "ugo+rwx", "a+w", "o+w", "o+rwx",
+];
+
+/// A variant of a command that does the same job with less that can go wrong.
+pub struct Safer {
+    pub command: String,
+    /// What changed and why, one entry per rewrite.
+    pub changes: Vec<String>,
+}
+
+/// Rewrite the risky parts of `command`: `rm` that can't be undone, modes that
+/// open files to everyone, scripts piped straight from the network into a
+/// shell and force pushes.
+pub fn rewrite(command: &str) -> Option<Safer> {
+    let segments = cmdline::segments(command);
+    let mut changes = Vec::new();
+    let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+
+    for (i, segment) in segments.iter().enumerate() {
+        let words = segment.raw_words(command);
+        let skipped = segment.words.len() - segment.args_from_program().len();
+        let Some(args) = words.get(skipped..).filter(|a| !a.is_empty()) else {
+            continue;
+        };
+        let program = args[0].value.rsplit('/').next().unwrap_or("");
+        let flags = || args[1..].iter().filter(|w| w.value.starts_with('-'));
+
+        match program {
+            "rm" if !flags().any(|w| w.value == "-i" || w.value == "-I") => {
+                if let Some(trash) = ["trash", "trash-put"].into_iter().find(|p| on_path(p)) {
+                    // trash takes only the paths.
+                    edits.push((args[0].span.clone(), trash.to_string()));
+                    edits.extend(flags().map(|w| (with_space_before(command, w), String::new())));
+                    changes.push(format!(
+                        "`{}` moves files to the trash instead of deleting them",
+                        trash
+                    ));
+                } else {
+                    // -f would override -I, so drop it from the flags.
+                    for word in flags() {
+                        if word.value == "--force" {
+                            edits.push((with_space_before(command, word), String::new()));
+                        } else if !word.value.starts_with("--") && word.value.contains('f') {
+                            let kept: String = word.value.chars().filter(|&c| c != 'f').collect();
+                            if kept == "-" {
+                                edits.push((with_space_before(command, word), String::new()));
+                            } else {
+                                edits.push((word.span.clone(), kept));
+                            }
+                        }
+                    }
+                    edits.push((args[0].span.end..args[0].span.end, " -I".into()));
+                    changes.push(
+                        "`rm -I` asks once before removing more than three files or recursing"
+                            .into(),
+                    );
+                }
+            }
+            "chmod" => {
+                let recursive = flags().any(|w| w.value == "-R" || w.value == "--recursive");
+                let Some(mode) = args[1..].iter().find(|w| !w.value.starts_with('-')) else {
+                    continue;
+                };
+                if !WORLD_WRITABLE.contains(&mode.value.as_str()) {
+                    continue;
+                }
+                let scoped = if recursive {
+                    // X keeps execute on directories and files that already have it.
+                    "u=rwX,go=rX"
+                } else if mode.value.ends_with("666") {
+                    "644"
+                } else {
+                    "755"
+                };
+                edits.push((mode.span.clone(), scoped.into()));
+                changes.push(format!(
+                    "`chmod {}` lets only you write, not everyone",
+                    scoped
+                ));
+            }
+            "curl" | "wget" if segment.op == Some(Operator::Pipe) => {
+                let Some(next) = segments.get(i + 1) else {
+                    continue;
+                };
+                let Some(shell) = next.program().filter(|p| SHELLS.contains(p)) else {
+                    continue;
+                };
+                let Some(url) = args[1..].iter().find(|w| w.value.contains("://")) else {
+                    continue;
+                };
+                let file = url
+                    .value
+                    .rsplit('/')
+                    .next()
+                    .filter(|name| name.ends_with(".sh"))
+                    .unwrap_or("install.sh");
+                let download = if program == "curl" {
+                    format!("curl -fsSL -o {} {}", file, quote(&url.value))
+                } else {
+                    format!("wget -O {} {}", file, quote(&url.value))
+                };
+                // macOS has shasum but no sha256sum.
+                let checksum = if on_path("sha256sum") {
+                    format!("sha256sum {}", file)
+                } else {
+                    format!("shasum -a 256 {}", file)
+                };
+                // Run it with the shell part exactly as written, sudo and all,
+                // feeding it the file instead of the download.
+                let run = &command[next.span.clone()];
+                edits.push((
+                    segment.span.start..next.span.end,
+                    format!(
+                        "{} && {} && ${{PAGER:-less}} {} && printf 'Run {} with {}? [y/N] ' && read answer && [ \"$answer\" = y ] && {} < {}",
+                        download, checksum, file, file, shell, run, file
+                    ),
+                ));
+                changes.push(format!(
+                    "downloads {} and prints its SHA-256 so you can check and read it before {} runs it",
+                    file, shell
+                ));
+            }
+            "git" if args.get(1).is_some_and(|w| w.value == "push") => {
+                let Some(force) = flags().find(|w| w.value == "--force" || w.value == "-f") else {
+                    continue;
+                };
+                edits.push((force.span.clone(), "--force-with-lease".into()));
+                changes.push(
+                    "`--force-with-lease` refuses to overwrite commits you have not fetched".into(),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    if edits.is_empty() {
+        return None;
+    }
+    let mut safer = command.to_string();
+    edits.sort_by_key(|(span, _)| std::cmp::Reverse(span.start));
+    for (span, replacement) in edits {
+        safer.replace_range(span, &replacement);
+    }
+    Some(Safer {
+        command: safer,
+        changes,
+    })
+}
+
+/// The word's range with the whitespace before it, for removing it cleanly.
+fn with_space_before(command: &str, word: &Word) -> Range<usize> {
+    let start = command[..word.span.start].trim_end().len();
+    start..word.span.end
+}
+
+pub fn quote(text: &str) -> String {
+    shlex::try_quote(text)
+        .map(|q| q.into_owned())
+        .unwrap_or_else(|_| text.to_string())
+}
+
+/// Whether `program` is installed somewhere on `$PATH`.
+pub fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn safer(command: &str) -> Option<String> {
+        rewrite(command).map(|safer| safer.command)
+    }
+
+    fn trash() -> Option<&'static str> {
+        ["trash", "trash-put"].into_iter().find(|p| on_path(p))
+    }
+
+    #[test]
+    fn rm_asks_or_goes_to_the_trash() {
+        let cases = [
+            ("rm -rf build", "rm -I -r build", "build"),
+            ("rm -f a.txt b.txt", "rm -I a.txt b.txt", "a.txt b.txt"),
+            ("rm --force -v old", "rm -I -v old", "old"),
+            ("sudo rm -fr /opt/app", "sudo rm -I -r /opt/app", "/opt/app"),
+        ];
+        for (command, asks, paths) in cases {
+            let expected = match trash() {
+                Some(trash) => {
+                    command[..command.find("rm").unwrap()].to_string() + trash + " " + paths
+                }
+                None => asks.to_string(),
+            };
+            assert_eq!(safer(command).unwrap(), expected, "{}", command);
+        }
+        // Already asks.
+        assert!(rewrite("rm -i notes.txt").is_none());
+        assert!(rewrite("rm -I -r build").is_none());
+    }
+
+    #[test]
+    fn chmod_stops_at_the_owner() {
+        assert_eq!(safer("chmod 777 run.sh").unwrap(), "chmod 755 run.sh");
+        assert_eq!(safer("chmod 0666 notes").unwrap(), "chmod 644 notes");
+        assert_eq!(safer("chmod a+w notes").unwrap(), "chmod 755 notes");
+        assert_eq!(
+            safer("sudo chmod -R 777 /srv/www").unwrap(),
+            "sudo chmod -R u=rwX,go=rX /srv/www"
+        );
+        assert!(rewrite("chmod 755 run.sh").is_none());
+        assert!(rewrite("chmod u+x run.sh").is_none());
+    }
+
+    #[test]
+    fn piped_installers_are_downloaded_first() {
+        let checksum = if on_path("sha256sum") {
+            "sha256sum"
+        } else {
+            "shasum -a 256"
+        };
+        let safer =
+            rewrite("curl -fsSL https://example.com/get-tool.sh | sudo bash -s -- -y").unwrap();
+        assert_eq!(
+            safer.command,
+            format!(
+                "curl -fsSL -o get-tool.sh https://example.com/get-tool.sh && {} get-tool.sh && ${{PAGER:-less}} get-tool.sh && printf 'Run get-tool.sh with bash? [y/N] ' && read answer && [ \"$answer\" = y ] && sudo bash -s -- -y < get-tool.sh",
+                checksum
+            )
+        );
+        assert_eq!(
+            safer.changes,
+            [
+                "downloads get-tool.sh and prints its SHA-256 so you can check and read it before bash runs it"
+            ]
+        );
+        let safer = rewrite("wget -qO- 'https://example.com/install?v=2' | sh").unwrap();
+        assert!(
+            safer
+                .command
+                .starts_with("wget -O install.sh 'https://example.com/install?v=2' && "),
+            "{}",
+            safer.command
+        );
+        // Not into a shell, or not from the network.
+        assert!(rewrite("curl -s https://example.com/data.json | jq .").is_none());
+        assert!(rewrite("curl -s https://example.com/a.sh > a.sh").is_none());
+    }
+
+    #[test]
+    fn force_pushes_keep_unfetched_commits() {
+        assert_eq!(
+            safer("git push --force origin main").unwrap(),
+            "git push --force-with-lease origin main"
+        );
+        assert_eq!(safer("git push -f").unwrap(), "git push --force-with-lease");
+        assert!(rewrite("git push origin main").is_none());
+        assert!(rewrite("git push --force-with-lease").is_none());
+    }
+
+    #[test]
+    fn every_part_is_rewritten() {
+        let safer = rewrite("chmod 777 x && git push -f && echo done").unwrap();
+        assert_eq!(
+            safer.command,
+            "chmod 755 x && git push --force-with-lease && echo done"
+        );
+        assert_eq!(safer.changes.len(), 2);
+        assert!(rewrite("ls -la | grep rm").is_none());
+    }
+}
@@ -0,0 +1,221 @@
+use crate::api::Exchange;
+use crate::paths;
+use crate::risk::RiskLevel;
+use crate::store;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+/// The variants proposed for one request, kept as a tree: refining a command
+/// adds a child, regenerating adds a sibling. Saved after every change so
+/// `llmwrap back` can return to an earlier variant.
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    pub prompt: String,
+    pub nodes: Vec<Node>,
+    pub current: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Node {
+    pub command: String,
+    pub parent: Option<usize>,
+    /// The refinement that produced this variant, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// The API exchange that produced this variant; None when it came from the
+    /// cache, a snippet or a local rewrite.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exchange: Option<Exchange>,
+    /// What the model said the command does, when it answered in JSON.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<String>,
+    /// How risky the model rated the command, when it answered in JSON.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rated: Option<RiskLevel>,
+}
+
+impl Session {
+    pub fn new(prompt: &str, command: &str) -> Self {
+        Session {
+            prompt: prompt.trim().to_string(),
+            nodes: vec![Node {
+                command: command.to_string(),
+                parent: None,
+                note: None,
+                exchange: None,
+                explanation: None,
+                rated: None,
+            }],
+            current: 0,
+        }
+    }
+
+    /// The most recent session, if one was saved.
+    pub fn load() -> Option<Self> {
+        let text = fs::read_to_string(session_file()?).ok()?;
+        serde_json::from_str(&text).ok()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = session_file() else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create state directory {}", dir.display()))?;
+        }
+        let text = serde_json::to_string_pretty(self)?;
+        store::replace(&path, text.as_bytes())
+            .with_context(|| format!("Failed to write session file {}", path.display()))
+    }
+
+    pub fn command(&self) -> &str {
+        &self.nodes[self.current].command
+    }
+
+    /// The API exchange behind the current variant, if it came from the model.
+    pub fn exchange(&self) -> Option<&Exchange> {
+        self.nodes[self.current].exchange.as_ref()
+    }
+
+    /// Record the API exchange that produced the current variant.
+    pub fn set_exchange(&mut self, exchange: Exchange) {
+        self.nodes[self.current].exchange = Some(exchange);
+    }
+
+    /// What the model said about the current variant: what it does and how
+    /// risky it is.
+    pub fn describe(&mut self, explanation: Option<String>, rated: Option<RiskLevel>) {
+        let node = &mut self.nodes[self.current];
+        node.explanation = explanation;
+        node.rated = rated;
+    }
+
+    pub fn explanation(&self) -> Option<&str> {
+        self.nodes[self.current].explanation.as_deref()
+    }
+
+    pub fn rated(&self) -> Option<RiskLevel> {
+        self.nodes[self.current].rated
+    }
+
+    /// Add a refinement of the current variant and make it current.
+    pub fn refine(&mut self, command: &str, note: &str) {
+        self.push(command, Some(self.current), Some(note.to_string()));
+    }
+
+    /// Add an alternative to the current variant and make it current.
+    pub fn regenerate(&mut self, command: &str) {
+        let node = &self.nodes[self.current];
+        self.push(command, node.parent, node.note.clone());
+    }
+
+    /// Step back to the variant the current one was derived from. Returns false
+    /// at the first variant.
+    pub fn back(&mut self) -> bool {
+        match self.nodes[self.current].parent {
+            Some(parent) => {
+                self.current = parent;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn select(&mut self, index: usize) -> bool {
+        let valid = index < self.nodes.len();
+        if valid {
+            self.current = index;
+        }
+        valid
+    }
+
+    /// Commands sharing the current variant's parent, so a regeneration can
+    /// avoid repeating them.
+    pub fn siblings(&self) -> Vec<&str> {
+        let parent = self.nodes[self.current].parent;
+        self.nodes
+            .iter()
+            .filter(|n| n.parent == parent)
+            .map(|n| n.command.as_str())
+            .collect()
+    }
+
+    /// Lines showing every variant indented under the one it refines, with the
+    /// current one marked.
+    pub fn tree(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        self.walk(None, 0, &mut lines);
+        lines
+    }
+
+    fn walk(&self, parent: Option<usize>, depth: usize, lines: &mut Vec<String>) {
+        for (i, node) in self.nodes.iter().enumerate() {
+            if node.parent != parent {
+                continue;
+            }
+            let marker = if i == self.current { '*' } else { ' ' };
+            let note = node
+                .note
+                .as_ref()
+                .map(|n| format!("  (\"{}\")", n))
+                .unwrap_or_default();
+            lines.push(format!(
+                "{} [{}] {}{}{}",
+                marker,
+                i,
+                "  ".repeat(depth),
+                node.command,
+                note
+            ));
+            self.walk(Some(i), depth + 1, lines);
+        }
+    }
+
+    fn push(&mut self, command: &str, parent: Option<usize>, note: Option<String>) {
+        self.nodes.push(Node {
+            command: command.to_string(),
+            parent,
+            note,
+            exchange: None,
+            explanation: None,
+            rated: None,
+        });
+        self.current = self.nodes.len() - 1;
+    }
+}
+
+/// Keep what the last command printed next to the session it came from,
+/// returning where it was written.
+pub fn save_output(output: &str) -> Result<Option<PathBuf>> {
+    let Some(path) = paths::state_dir().map(|dir| dir.join("output.txt")) else {
+        return Ok(None);
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create state directory {}", dir.display()))?;
+    }
+    store::replace(&path, output.as_bytes())
+        .with_context(|| format!("Failed to write command output to {}", path.display()))?;
+    Ok(Some(path))
+}
+
+/// What the last command printed, if it was saved at or after `since`
+/// (seconds since the epoch); a run that wasn't captured leaves an older
+/// one behind.
+pub fn output_since(since: u64) -> Option<String> {
+    let path = paths::state_dir()?.join("output.txt");
+    fs::metadata(&path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .filter(|modified| modified.as_secs() >= since)
+        .and_then(|_| fs::read_to_string(&path).ok())
+}
+
+fn session_file() -> Option<PathBuf> {
+    paths::state_dir().map(|dir| dir.join("session.json"))
+}
@@ -0,0 +1,243 @@
+use crate::{safer, ssh, wsl};
+use std::borrow::Cow;
+use std::fmt;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// A shell that generated commands are written for and run with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Shell {
+    Sh,
+    Bash,
+    Zsh,
+    Fish,
+    Nu,
+    #[value(alias = "powershell")]
+    #[serde(alias = "powershell")]
+    Pwsh,
+    /// The Windows command prompt, cmd.exe.
+    Cmd,
+}
+
+impl Shell {
+    /// Guess the user's shell from `$SHELL`, defaulting to POSIX sh.
+    pub fn detect() -> Self {
+        let shell = std::env::var("SHELL").unwrap_or_default();
+        match shell.rsplit('/').next().unwrap_or_default() {
+            "bash" => Shell::Bash,
+            "zsh" => Shell::Zsh,
+            "fish" => Shell::Fish,
+            "nu" => Shell::Nu,
+            "pwsh" | "powershell" => Shell::Pwsh,
+            "cmd" | "cmd.exe" => Shell::Cmd,
+            _ => Shell::Sh,
+        }
+    }
+
+    pub fn binary(self) -> &'static str {
+        match self {
+            Shell::Sh => "sh",
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+            Shell::Nu => "nu",
+            Shell::Pwsh => "pwsh",
+            Shell::Cmd => "cmd",
+        }
+    }
+
+    /// Whether commands use POSIX shell syntax, which the local checks
+    /// (quoting, permissions, snippets) understand.
+    pub fn is_posix(self) -> bool {
+        matches!(self, Shell::Sh | Shell::Bash | Shell::Zsh)
+    }
+
+    /// Whether commands are written for a Windows shell, with Windows paths.
+    pub fn is_windows(self) -> bool {
+        matches!(self, Shell::Pwsh | Shell::Cmd)
+    }
+
+    /// What the system prompt should say about the target shell, if anything.
+    pub fn prompt_note(self) -> Option<&'static str> {
+        match self {
+            Shell::Sh => None,
+            Shell::Bash => Some("The command will run in bash."),
+            Shell::Zsh => Some("The command will run in zsh."),
+            Shell::Fish => Some("The command will run in fish; use fish syntax, not POSIX sh."),
+            Shell::Nu => Some(
+                "The command will run in nushell (nu): write one idiomatic nu pipeline using \
+nu built-ins and structured data (ls, where, sort-by, open, to json, ...), not POSIX sh syntax.",
+            ),
+            Shell::Pwsh => Some(
+                "The command will run in PowerShell: use PowerShell cmdlets and syntax, not POSIX sh.",
+            ),
+            Shell::Cmd => Some(
+                "The command will run in the Windows command prompt (cmd.exe): use cmd built-ins and \
+syntax (dir, copy, del, set, %VAR%, `&&`, `2>nul`) and Windows paths, not PowerShell or POSIX sh.",
+            ),
+        }
+    }
+
+    /// `command` as it is run: a script of several lines is changed to stop at
+    /// the first step that fails. fish and nu have no way to ask for that, so
+    /// their scripts run every step.
+    pub fn runnable(self, command: &str) -> Cow<'_, str> {
+        if !crate::is_script(command) {
+            return Cow::Borrowed(command);
+        }
+        match self {
+            Shell::Sh | Shell::Bash | Shell::Zsh => Cow::Owned(format!("set -e\n{}", command)),
+            // Native programs that fail stop the script only from PowerShell 7.3.
+            Shell::Pwsh => Cow::Owned(format!(
+                "$ErrorActionPreference = 'Stop'\n$PSNativeCommandUseErrorActionPreference = $true\n{}",
+                command
+            )),
+            // cmd runs only the first line it is given, so the steps go on one
+            // line, each after the one before succeeds. A comment would take
+            // the rest of the line with it, so comments are left out.
+            Shell::Cmd => Cow::Owned(
+                command
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !cmd_comment(line))
+                    .collect::<Vec<_>>()
+                    .join(" && "),
+            ),
+            Shell::Fish | Shell::Nu => Cow::Borrowed(command),
+        }
+    }
+
+    /// A process that runs the generated `command` with this shell where it is
+    /// meant to run: here, or on the host given with `--target ssh:`.
+    pub fn target_command(self, command: &str) -> Command {
+        match ssh::target() {
+            Some(host) => host.command(self, command),
+            None => self.command(command),
+        }
+    }
+
+    /// The program that runs commands for this shell here.
+    fn program(self) -> &'static str {
+        if self == Shell::Pwsh && wsl::windows_host() {
+            "powershell.exe"
+        } else {
+            self.binary()
+        }
+    }
+
+    /// Whether this shell can run commands on this machine.
+    pub fn installed(self) -> bool {
+        let program = self.program();
+        safer::on_path(program) || safer::on_path(&format!("{}.exe", program))
+    }
+
+    /// A process that runs `command` with this shell.
+    pub fn command(self, command: &str) -> Command {
+        let mut process = Command::new(self.program());
+        process.args(self.command_flags());
+        // cmd reads its command line as written rather than by the quoting
+        // rules other programs use, so it gets the command verbatim.
+        #[cfg(windows)]
+        if self == Shell::Cmd {
+            use std::os::windows::process::CommandExt;
+            process.raw_arg(format!("\"{}\"", command));
+            return process;
+        }
+        process.arg(command);
+        process
+    }
+
+    /// Flags that come before the command text when running it with this shell.
+    pub fn command_flags(self) -> &'static [&'static str] {
+        match self {
+            Shell::Pwsh => &["-NoProfile", "-Command"],
+            // No AutoRun commands, and strip only the outer quotes.
+            Shell::Cmd => &["/D", "/S", "/C"],
+            _ => &["-c"],
+        }
+    }
+
+    /// The startup file interactive sessions of this shell read; cmd has
+    /// none.
+    pub fn rc_file(self) -> Option<PathBuf> {
+        let home =
+            PathBuf::from(std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?);
+        Some(match self {
+            Shell::Sh => home.join(".profile"),
+            Shell::Bash => home.join(".bashrc"),
+            Shell::Zsh => std::env::var_os("ZDOTDIR")
+                .map(PathBuf::from)
+                .unwrap_or(home)
+                .join(".zshrc"),
+            Shell::Fish => std::env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| home.join(".config"))
+                .join("fish/config.fish"),
+            Shell::Nu => std::env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| home.join(".config"))
+                .join("nushell/config.nu"),
+            Shell::Pwsh if cfg!(windows) => {
+                home.join("Documents/PowerShell/Microsoft.PowerShell_profile.ps1")
+            }
+            Shell::Pwsh => std::env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| home.join(".config"))
+                .join("powershell/Microsoft.PowerShell_profile.ps1"),
+            Shell::Cmd => return None,
+        })
+    }
+}
+
+impl fmt::Display for Shell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.binary())
+    }
+}
+
+/// Whether `line` of a cmd script is a comment, `rem ...` or `:: ...`.
+fn cmd_comment(line: &str) -> bool {
+    let line = line.trim_start_matches('@');
+    let rem = line
+        .get(..3)
+        .is_some_and(|word| word.eq_ignore_ascii_case("rem"))
+        && line[3..].chars().next().is_none_or(char::is_whitespace);
+    rem || line.starts_with("::")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = "python3 -m venv .venv\n.venv/bin/pip install -r requirements.txt";
+
+    #[test]
+    fn posix_scripts_stop_at_the_first_failure() {
+        for shell in [Shell::Sh, Shell::Bash, Shell::Zsh] {
+            assert_eq!(shell.runnable(SCRIPT), format!("set -e\n{}", SCRIPT));
+        }
+    }
+
+    #[test]
+    fn single_commands_are_left_alone() {
+        assert_eq!(Shell::Sh.runnable("uptime"), "uptime");
+        assert_eq!(Shell::Cmd.runnable("dir /b"), "dir /b");
+    }
+
+    #[test]
+    fn powershell_scripts_stop_on_errors() {
+        let script = Shell::Pwsh.runnable(SCRIPT);
+        assert!(script.starts_with("$ErrorActionPreference = 'Stop'\n"));
+        assert!(script.ends_with(SCRIPT));
+    }
+
+    #[test]
+    fn cmd_scripts_go_on_one_line() {
+        let script = "@echo off\r\nrem make the env\r\npython -m venv .venv\r\n\r\n:: then install\r\nremove.bat\r\n.venv\\Scripts\\pip install -r requirements.txt";
+        assert_eq!(
+            Shell::Cmd.runnable(script),
+            "@echo off && python -m venv .venv && remove.bat && .venv\\Scripts\\pip install -r requirements.txt"
+        );
+    }
+}
@@ -0,0 +1,33 @@
+use crate::paths;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+
+/// A hand-written command kept in `snippets.toml` in the config directory.
+#[derive(Deserialize, Clone)]
+pub struct Snippet {
+    pub description: String,
+    pub command: String,
+}
+
+#[derive(Deserialize, Default)]
+struct SnippetFile {
+    #[serde(default)]
+    snippet: Vec<Snippet>,
+}
+
+pub fn load() -> Result<Vec<Snippet>> {
+    let Some(path) = paths::config_dir().map(|dir| dir.join("snippets.toml")) else {
+        return Ok(Vec::new());
+    };
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => {
+            return Err(err).with_context(|| format!("Failed to read {}", path.display()));
+        }
+    };
+    let file: SnippetFile = toml::from_str(&text)
+        .with_context(|| format!("Invalid snippets file {}", path.display()))?;
+    Ok(file.snippet)
+}
@@ -0,0 +1,787 @@
+use crate::cmdline;
+use crate::environment;
+use crate::inventory::Inventory;
+use crate::paths;
+use crate::safer;
+use crate::shell::Shell;
+use crate::store;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Name at most this many other hosts from ~/.ssh/config to the model.
+const ALIAS_LIMIT: usize = 30;
+
+/// How long what a host said it runs is trusted before asking again.
+const CONTEXT_TTL_SECS: u64 = 24 * 60 * 60;
+
+/// A machine commands run on over SSH, as `--target ssh:HOST` names it.
+pub struct Host {
+    /// What was given, e.g. `web01` or `deploy@10.0.0.5`: an alias from
+    /// ~/.ssh/config or an address, with any port taken off.
+    pub destination: String,
+    /// A port given with the host, overriding ~/.ssh/config.
+    port_override: Option<u16>,
+    /// Where ssh actually connects, with ~/.ssh/config applied.
+    pub hostname: String,
+    pub user: Option<String>,
+    pub port: u16,
+    /// Whether the host's key is in known_hosts, so connecting won't stop to
+    /// ask about it.
+    pub known: bool,
+    /// Variables from the inventory groups the host was named through, set
+    /// in the environment commands run in there.
+    pub vars: BTreeMap<String, String>,
+    fingerprint: OnceLock<Option<Fingerprint>>,
+}
+
+/// What the host runs, as it reports it.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Fingerprint {
+    /// The distribution, e.g. "Ubuntu 22.04.4 LTS", or the kernel name when
+    /// there is no os-release.
+    pub os: String,
+    pub arch: String,
+    pub login_shell: Option<String>,
+    /// Package managers installed, in the order they are usually preferred.
+    #[serde(default)]
+    pub package_managers: Vec<String>,
+    /// Which of the commonly requested tools are installed.
+    #[serde(default)]
+    pub tools: Vec<String>,
+}
+
+/// What hosts have said they run, by host, in the cache directory.
+#[derive(Serialize, Deserialize, Default)]
+struct Known {
+    hosts: BTreeMap<String, Remembered>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Remembered {
+    fingerprint: Fingerprint,
+    /// When the host was asked, in seconds since the epoch.
+    fetched: u64,
+}
+
+static TARGETS: OnceLock<Vec<Host>> = OnceLock::new();
+/// Which of `TARGETS` commands run on now.
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+/// Held while the host cache is read and written back, so hosts asked at
+/// the same time don't undo each other's entries.
+static KNOWN_LOCK: Mutex<()> = Mutex::new(());
+
+/// Remember the hosts commands go to; none when they run here.
+pub fn init(hosts: Vec<Host>) {
+    let _ = TARGETS.set(hosts);
+}
+
+/// Every host a command goes to, in the order given.
+pub fn targets() -> &'static [Host] {
+    TARGETS.get().map_or(&[], Vec::as_slice)
+}
+
+/// The host commands run on, when that is another machine. With several,
+/// the one `select` last picked, at first the first.
+pub fn target() -> Option<&'static Host> {
+    targets().get(CURRENT.load(Ordering::Relaxed))
+}
+
+/// Run commands on the `index`th of `targets()` from now on.
+pub fn select(index: usize) {
+    CURRENT.store(index, Ordering::Relaxed);
+}
+
+/// Ask every host what it runs at once rather than one after another.
+pub fn probe_all() {
+    thread::scope(|scope| {
+        for host in targets() {
+            scope.spawn(|| host.fingerprint());
+        }
+    });
+}
+
+/// A sentence for the system prompt when the same command goes to several
+/// hosts; the first is described in full by its own note.
+pub fn fan_out_note(hosts: &[Host]) -> Option<String> {
+    let rest = hosts.get(1..).filter(|rest| !rest.is_empty())?;
+    let others: Vec<String> = rest
+        .iter()
+        .map(|host| match host.fingerprint() {
+            Some(fingerprint) => format!(
+                "{} ({} {})",
+                host.destination, fingerprint.os, fingerprint.arch
+            ),
+            None => host.destination.clone(),
+        })
+        .collect();
+    Some(format!(
+        "The same command will also run on {}, so it must work unchanged on every one of \
+them; where they differ, use only what they all have.",
+        others.join(", ")
+    ))
+}
+
+/// A sentence for the system prompt on the inventory variables set where
+/// the command runs, when there are any.
+pub fn vars_note(hosts: &[Host]) -> Option<String> {
+    let first = hosts.first()?;
+    if hosts.iter().all(|host| host.vars.is_empty()) {
+        return None;
+    }
+    let list = |host: &Host| {
+        let vars: Vec<String> = host
+            .vars
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        vars.join(", ")
+    };
+    if hosts.iter().all(|host| host.vars == first.vars) {
+        return Some(format!(
+            "These variables from the inventory are set in the command's environment: {}. \
+Refer to them as environment variables where the request relies on them.",
+            list(first)
+        ));
+    }
+    let each: Vec<String> = hosts
+        .iter()
+        .map(|host| format!("{}: {}", host.destination, list(host)))
+        .collect();
+    Some(format!(
+        "Variables from the inventory are set in the command's environment, with each host's \
+own values ({}). Refer to them as environment variables rather than writing the values in.",
+        each.join("; ")
+    ))
+}
+
+/// The hosts `spec` names, separated by commas: hosts, groups from the
+/// inventory, or patterns like `web*` matched against the aliases in
+/// ~/.ssh/config, which groups can list too.
+pub fn resolve_all(spec: &str, inventory: &Inventory) -> Result<Vec<Host>> {
+    let mut hosts: Vec<Host> = Vec::new();
+    let mut add = |mut host: Host, vars: &BTreeMap<String, String>| {
+        // A host named twice, say through two groups, still runs it once.
+        let existing = hosts.iter_mut().find(|h| h.key() == host.key());
+        match existing {
+            Some(existing) => existing.vars.extend(vars.clone()),
+            None => {
+                host.vars.extend(vars.clone());
+                hosts.push(host);
+            }
+        }
+    };
+    for item in spec.split(',').map(str::trim) {
+        let (names, vars) = match inventory.group(item) {
+            Some(group) => (group.hosts.clone(), group.vars().collect()),
+            None => (vec![item.to_string()], BTreeMap::new()),
+        };
+        for name in names {
+            if !name.contains(['*', '?']) {
+                add(Host::resolve(&name)?, &vars);
+                continue;
+            }
+            let pattern = format!(
+                "^{}$",
+                regex::escape(&name)
+                    .replace(r"\*", ".*")
+                    .replace(r"\?", ".")
+            );
+            let pattern = regex::Regex::new(&pattern)?;
+            let matching: Vec<String> = aliases()
+                .into_iter()
+                .filter(|alias| pattern.is_match(alias))
+                .collect();
+            if matching.is_empty() {
+                anyhow::bail!("No host in ~/.ssh/config matches {}", name);
+            }
+            for alias in matching {
+                add(Host::resolve(&alias)?, &vars);
+            }
+        }
+    }
+    Ok(hosts)
+}
+
+impl Host {
+    /// Look up `spec`, `[user@]host[:port]`, the way ssh would, with
+    /// ~/.ssh/config applied.
+    pub fn resolve(spec: &str) -> Result<Host> {
+        let (destination, port_override) = match spec.rsplit_once(':') {
+            Some((host, port)) if !host.contains(':') => {
+                let port = port
+                    .parse()
+                    .with_context(|| format!("Invalid port in ssh:{}", spec))?;
+                (host.to_string(), Some(port))
+            }
+            _ => (spec.to_string(), None),
+        };
+        if destination.is_empty() || destination.starts_with('-') {
+            anyhow::bail!("Give a host to run on, e.g. `--target ssh:web01`");
+        }
+        let mut host = Host {
+            destination,
+            port_override,
+            hostname: String::new(),
+            user: None,
+            port: 22,
+            known: false,
+            vars: BTreeMap::new(),
+            fingerprint: OnceLock::new(),
+        };
+        let output = Command::new("ssh")
+            .arg("-G")
+            .args(host.connection_args())
+            .stdin(Stdio::null())
+            .output()
+            .context("Failed to run ssh; is OpenSSH installed?")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "ssh could not make sense of {}: {}",
+                spec,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let settings = settings(&String::from_utf8_lossy(&output.stdout));
+        host.hostname = settings
+            .get("hostname")
+            .cloned()
+            .unwrap_or_else(|| host.destination.clone());
+        host.user = settings.get("user").cloned();
+        host.port = settings
+            .get("port")
+            .and_then(|port| port.parse().ok())
+            .unwrap_or(22);
+        let known_hosts = settings
+            .get("userknownhostsfile")
+            .and_then(|files| files.split_whitespace().next())
+            .map(expand_home);
+        host.known = host.in_known_hosts(known_hosts);
+        Ok(host)
+    }
+
+    /// Arguments that make ssh connect to this host.
+    pub fn connection_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(port) = self.port_override {
+            args.extend(["-p".to_string(), port.to_string()]);
+        }
+        args.push(self.destination.clone());
+        args
+    }
+
+    /// A process that runs `command` with `shell` on the host, with a
+    /// terminal there when there is one here.
+    pub fn command(&self, shell: Shell, command: &str) -> Command {
+        let mut process = Command::new("ssh");
+        if std::io::stdin().is_terminal() {
+            process.arg("-t");
+        }
+        process
+            .args(self.connection_args())
+            .arg("--")
+            .arg(remote_command(shell, command, &self.vars));
+        process
+    }
+
+    /// What the host runs: remembered from the last day if it was asked,
+    /// otherwise asked now. None when it can't be reached without a password
+    /// or a host key check.
+    pub fn fingerprint(&self) -> Option<&Fingerprint> {
+        self.fingerprint
+            .get_or_init(|| {
+                if let Some(remembered) = Known::load().hosts.get(&self.key())
+                    && now().saturating_sub(remembered.fetched) < CONTEXT_TTL_SECS
+                {
+                    return Some(remembered.fingerprint.clone());
+                }
+                match self.probe() {
+                    Ok(fingerprint) => {
+                        remember(&self.key(), &fingerprint);
+                        Some(fingerprint)
+                    }
+                    Err(err) => {
+                        eprintln!("Warning: {:#}", err);
+                        None
+                    }
+                }
+            })
+            .as_ref()
+    }
+
+    /// Ask the host what it runs even if that is remembered, and remember
+    /// the answer.
+    pub fn refresh(&self) -> Result<Fingerprint> {
+        let fingerprint = self.probe()?;
+        remember(&self.key(), &fingerprint);
+        Ok(fingerprint)
+    }
+
+    /// How the host is remembered: as it was given, with any port.
+    fn key(&self) -> String {
+        match self.port_override {
+            Some(port) => format!("{}:{}", self.destination, port),
+            None => self.destination.clone(),
+        }
+    }
+
+    /// Which host and system a cached command was written for, so one
+    /// written for another machine, or for this one before it changed, is
+    /// not served.
+    pub fn cache_key(&self) -> String {
+        match self.fingerprint() {
+            Some(fingerprint) => format!(
+                "{} ({} {})",
+                self.destination, fingerprint.os, fingerprint.arch
+            ),
+            None => self.destination.clone(),
+        }
+    }
+
+    /// Ways this host differs from what `command` was written for, `reference`
+    /// being the host described to the model: another system, or tools the
+    /// command uses that aren't installed here.
+    pub fn mismatches(&self, reference: &Host, command: &str) -> Vec<String> {
+        let Some(fingerprint) = self.fingerprint() else {
+            return vec!["its system is unknown".to_string()];
+        };
+        let mut mismatches = Vec::new();
+        if let Some(expected) = reference
+            .fingerprint()
+            .filter(|_| reference.key() != self.key())
+        {
+            if (&fingerprint.os, &fingerprint.arch) != (&expected.os, &expected.arch) {
+                mismatches.push(format!(
+                    "runs {} ({}), not {} ({}) like {}",
+                    fingerprint.os,
+                    fingerprint.arch,
+                    expected.os,
+                    expected.arch,
+                    reference.destination
+                ));
+            }
+            if fingerprint.package_managers.first() != expected.package_managers.first()
+                && let Some(manager) = fingerprint.package_managers.first()
+            {
+                mismatches.push(format!("installs packages with {}", manager));
+            }
+        }
+        for segment in cmdline::segments(command) {
+            let Some(program) = segment.program() else {
+                continue;
+            };
+            let program = program.rsplit('/').next().unwrap_or(program);
+            let missing = format!("{} is not installed", program);
+            if environment::TOOLS
+                .iter()
+                .chain(environment::PACKAGE_MANAGERS)
+                .any(|tool| *tool == program)
+                && !fingerprint
+                    .tools
+                    .iter()
+                    .chain(&fingerprint.package_managers)
+                    .any(|tool| tool == program)
+                && !mismatches.contains(&missing)
+            {
+                mismatches.push(missing);
+            }
+        }
+        mismatches
+    }
+
+    /// Sentences for the system prompt on where the command runs and how to
+    /// reach the other hosts the user has set up.
+    pub fn prompt_note(&self) -> String {
+        let mut connection = self.hostname.clone();
+        if let Some(user) = &self.user {
+            connection = format!("{}@{}", user, connection);
+        }
+        if self.port != 22 {
+            connection.push_str(&format!(", port {}", self.port));
+        }
+        let mut note = format!(
+            "The command runs over SSH on {} ({}), not on this machine: use paths, tools and \
+services on that host.",
+            self.destination, connection
+        );
+        match self.fingerprint() {
+            Some(fingerprint) => {
+                note.push_str(&format!(
+                    " It runs {} ({})",
+                    fingerprint.os, fingerprint.arch
+                ));
+                if let Some(shell) = &fingerprint.login_shell {
+                    note.push_str(&format!(" and its login shell is {}", shell));
+                }
+                note.push('.');
+                if let Some(manager) = fingerprint.package_managers.first() {
+                    note.push_str(&format!(" Its package manager is {}.", manager));
+                }
+                let (installed, missing): (Vec<&str>, Vec<&str>) = environment::TOOLS
+                    .iter()
+                    .partition(|tool| fingerprint.tools.iter().any(|t| t == *tool));
+                if !installed.is_empty() {
+                    note.push_str(&format!(" Installed there: {}.", installed.join(", ")));
+                }
+                if !missing.is_empty() {
+                    note.push_str(&format!(" Not installed: {}.", missing.join(", ")));
+                }
+            }
+            None => note.push_str(" Its system is unknown, so stick to POSIX tools."),
+        }
+        let aliases: Vec<String> = aliases()
+            .into_iter()
+            .filter(|alias| *alias != self.destination)
+            .take(ALIAS_LIMIT)
+            .collect();
+        if !aliases.is_empty() {
+            note.push_str(&format!(
+                " Other hosts set up in ~/.ssh/config: {}. Refer to them by these names in ssh, \
+scp and rsync so their users, ports and keys apply.",
+                aliases.join(", ")
+            ));
+        }
+        note
+    }
+
+    /// Ask the host what it runs, without prompting for anything.
+    fn probe(&self) -> Result<Fingerprint> {
+        let programs: Vec<&str> = environment::PACKAGE_MANAGERS
+            .iter()
+            .chain(environment::TOOLS)
+            .copied()
+            .collect();
+        let probe = format!(
+            "uname -sm; cat /etc/os-release 2>/dev/null; echo \"SHELL=$SHELL\"; \
+for p in {}; do command -v \"$p\" >/dev/null 2>&1 && echo \"HAS=$p\"; done; true",
+            programs.join(" ")
+        );
+        let output = Command::new("ssh")
+            .args(["-o", "BatchMode=yes", "-o", "ConnectTimeout=10"])
+            .args(self.connection_args())
+            .arg("--")
+            .arg(remote_command(Shell::Sh, &probe, &BTreeMap::new()))
+            .stdin(Stdio::null())
+            .output()
+            .with_context(|| format!("Failed to run ssh to {}", self.destination))?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "Could not ask {} what it runs: {}",
+                self.destination,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        parse_fingerprint(&String::from_utf8_lossy(&output.stdout))
+            .with_context(|| format!("{} gave no answer to uname", self.destination))
+    }
+
+    /// Whether known_hosts has a key for the host, hashed or not.
+    fn in_known_hosts(&self, file: Option<PathBuf>) -> bool {
+        let name = if self.port == 22 {
+            self.hostname.clone()
+        } else {
+            format!("[{}]:{}", self.hostname, self.port)
+        };
+        let mut lookup = Command::new("ssh-keygen");
+        lookup.args(["-F", &name]);
+        if let Some(file) = file {
+            lookup.arg("-f").arg(file);
+        }
+        lookup
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    }
+}
+
+impl Known {
+    fn load() -> Known {
+        known_file()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = known_file() else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create cache directory {}", dir.display()))?;
+        }
+        let text = serde_json::to_string(self)?;
+        store::replace(&path, text.as_bytes())
+            .with_context(|| format!("Failed to write host cache {}", path.display()))
+    }
+}
+
+/// Keep `fingerprint` for `host`, warning rather than failing when it can't
+/// be saved: it only saves asking again.
+fn remember(host: &str, fingerprint: &Fingerprint) {
+    let _lock = KNOWN_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    // Other llmwraps may be asking other hosts at the same time.
+    let _shared = known_file().and_then(|path| {
+        fs::create_dir_all(path.parent()?).ok()?;
+        store::lock(&path).ok()
+    });
+    let mut known = Known::load();
+    known.hosts.insert(
+        host.to_string(),
+        Remembered {
+            fingerprint: fingerprint.clone(),
+            fetched: now(),
+        },
+    );
+    if let Err(err) = known.save() {
+        eprintln!("Warning: {:#}", err);
+    }
+}
+
+/// Forget what every host said it runs, so each is asked again next time.
+pub fn forget_all() -> Result<()> {
+    Known::default().save()
+}
+
+fn known_file() -> Option<PathBuf> {
+    paths::cache_dir().map(|dir| dir.join("hosts.json"))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// The text ssh hands the remote login shell: `command` run with `shell`
+/// and `vars` in its environment, quoted so the login shell passes it on
+/// untouched.
+fn remote_command(shell: Shell, command: &str, vars: &BTreeMap<String, String>) -> String {
+    let mut words: Vec<String> = Vec::new();
+    if !vars.is_empty() {
+        words.push("env".to_string());
+        words.extend(
+            vars.iter()
+                .map(|(name, value)| safer::quote(&format!("{}={}", name, value))),
+        );
+    }
+    words.push(shell.binary().to_string());
+    words.extend(shell.command_flags().iter().map(|flag| flag.to_string()));
+    words.push(safer::quote(command));
+    words.join(" ")
+}
+
+/// What the probe printed on a host, read into a fingerprint; None without
+/// the `uname -sm` line it starts with.
+fn parse_fingerprint(text: &str) -> Option<Fingerprint> {
+    let mut lines = text.lines();
+    let (kernel, arch) = lines
+        .next()
+        .and_then(|line| line.split_once(' '))
+        .map(|(kernel, arch)| (kernel.to_string(), arch.trim().to_string()))?;
+    let mut fingerprint = Fingerprint {
+        os: kernel,
+        arch,
+        login_shell: None,
+        package_managers: Vec::new(),
+        tools: Vec::new(),
+    };
+    for line in lines {
+        if let Some(name) = line.strip_prefix("PRETTY_NAME=") {
+            fingerprint.os = name.trim_matches('"').to_string();
+        } else if let Some(shell) = line.strip_prefix("SHELL=") {
+            fingerprint.login_shell = Some(shell.to_string()).filter(|s| !s.is_empty());
+        } else if let Some(program) = line.strip_prefix("HAS=") {
+            if environment::PACKAGE_MANAGERS.contains(&program) {
+                fingerprint.package_managers.push(program.to_string());
+            } else {
+                fingerprint.tools.push(program.to_string());
+            }
+        }
+    }
+    Some(fingerprint)
+}
+
+/// `ssh -G` output as a map of lowercase keys to values.
+fn settings(output: &str) -> HashMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Host names from the `Host` lines of ~/.ssh/config, leaving out patterns.
+fn aliases() -> Vec<String> {
+    let Some(config) = home().map(|home| home.join(".ssh").join("config")) else {
+        return Vec::new();
+    };
+    config_hosts(&fs::read_to_string(config).unwrap_or_default())
+}
+
+/// The names on `Host` lines in ssh config `text`, leaving out patterns.
+fn config_hosts(text: &str) -> Vec<String> {
+    let mut aliases: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let mut words = line.split_whitespace();
+        if !words
+            .next()
+            .is_some_and(|keyword| keyword.eq_ignore_ascii_case("host"))
+        {
+            continue;
+        }
+        for alias in words {
+            if !alias.contains(['*', '?', '!']) && !aliases.iter().any(|a| a == alias) {
+                aliases.push(alias.to_string());
+            }
+        }
+    }
+    aliases
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), home()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn home() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str =
+        "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIP4oaEyWTCCmB9XKQF2+X9gji9rAsRqelA8eD/6hcRL/";
+
+    fn host(hostname: &str, port: u16) -> Host {
+        Host {
+            destination: hostname.to_string(),
+            port_override: None,
+            hostname: hostname.to_string(),
+            user: None,
+            port,
+            known: false,
+            vars: BTreeMap::new(),
+            fingerprint: OnceLock::new(),
+        }
+    }
+
+    #[test]
+    fn config_hosts_leave_out_patterns() {
+        let config = "\
+# Work
+Host web01 web02
+    HostName 10.0.0.5
+host db
+Host *.internal !bastion jump?
+  Host web01
+Match host web03
+HostName ignored
+";
+        assert_eq!(config_hosts(config), ["web01", "web02", "db"]);
+        assert!(config_hosts("").is_empty());
+    }
+
+    #[test]
+    fn settings_are_split_at_the_first_space() {
+        let settings = settings(
+            "user deploy\nhostname 10.0.0.5\nport 2222\n\
+userknownhostsfile ~/.ssh/known_hosts ~/.ssh/known_hosts2\nbatchmode\n",
+        );
+        assert_eq!(settings["user"], "deploy");
+        assert_eq!(settings["port"], "2222");
+        assert_eq!(
+            settings["userknownhostsfile"],
+            "~/.ssh/known_hosts ~/.ssh/known_hosts2"
+        );
+        assert!(!settings.contains_key("batchmode"));
+    }
+
+    #[test]
+    fn known_hosts_are_matched_by_name_and_port() {
+        if !safer::on_path("ssh-keygen") {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("known_hosts");
+        fs::write(
+            &file,
+            format!(
+                "web01.example.com {KEY}\n[db.example.com]:2222 {KEY}\n\
+|1|H59yXLygLDDFoh0TPpDO3W45y30=|nEzoJT1aI0gaF06F3VyB3OpLnWk= {KEY}\n"
+            ),
+        )
+        .unwrap();
+        let known = |name, port| host(name, port).in_known_hosts(Some(file.clone()));
+        assert!(known("web01.example.com", 22));
+        assert!(known("db.example.com", 2222));
+        assert!(known("hashed.example.org", 22));
+        assert!(!known("db.example.com", 22));
+        assert!(!known("web01.example.com", 2222));
+        assert!(!known("other.example.com", 22));
+    }
+
+    #[test]
+    fn fingerprints_are_read_from_the_probe() {
+        let fingerprint = parse_fingerprint(
+            "Linux x86_64\nNAME=\"Ubuntu\"\nPRETTY_NAME=\"Ubuntu 22.04.4 LTS\"\n\
+SHELL=/bin/bash\nHAS=apt-get\nHAS=jq\nHAS=git\n",
+        )
+        .unwrap();
+        assert_eq!(fingerprint.os, "Ubuntu 22.04.4 LTS");
+        assert_eq!(fingerprint.arch, "x86_64");
+        assert_eq!(fingerprint.login_shell.as_deref(), Some("/bin/bash"));
+        assert_eq!(fingerprint.package_managers, ["apt-get"]);
+        assert_eq!(fingerprint.tools, ["jq", "git"]);
+        // Without os-release the kernel names the system.
+        let fingerprint = parse_fingerprint("Darwin arm64\nSHELL=\n").unwrap();
+        assert_eq!(fingerprint.os, "Darwin");
+        assert_eq!(fingerprint.login_shell, None);
+        assert!(parse_fingerprint("").is_none());
+    }
+
+    #[test]
+    fn remote_commands_are_quoted_once() {
+        assert_eq!(
+            remote_command(Shell::Bash, "ls -la ~", &BTreeMap::new()),
+            "bash -c 'ls -la ~'"
+        );
+        let vars = BTreeMap::from([("SERVICE".to_string(), "web api".to_string())]);
+        assert_eq!(
+            remote_command(Shell::Sh, "systemctl restart \"$SERVICE\"", &vars),
+            "env 'SERVICE=web api' sh -c 'systemctl restart \"$SERVICE\"'"
+        );
+    }
+
+    #[test]
+    fn specs_need_a_host_and_a_port_number() {
+        let error = |spec| Host::resolve(spec).err().unwrap().to_string();
+        assert_eq!(error("web01:ssh"), "Invalid port in ssh:web01:ssh");
+        assert!(error("").starts_with("Give a host to run on"));
+        assert!(error("-oProxyCommand=sh").starts_with("Give a host to run on"));
+        assert_eq!(host("web01", 22).key(), "web01");
+        let with_port = Host {
+            port_override: Some(2222),
+            ..host("web01", 22)
+        };
+        assert_eq!(with_port.key(), "web01:2222");
+        assert_eq!(with_port.connection_args(), ["-p", "2222", "web01"]);
+    }
+}
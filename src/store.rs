@@ -0,0 +1,105 @@
+//! Writing llmwrap's own files so they stay whole when several llmwraps run at
+//! once, as they do when shell widgets fire in quick succession, or when one
+//! is killed part way. A file is replaced by renaming a complete copy over it,
+//! changes that read the file first hold a lock on it throughout, and a line
+//! appended to history goes in whole.
+
+use crate::cancel;
+use anyhow::{Context, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// A lock on one of our files, released when dropped. It is taken on a file
+/// beside it, since replacing the file itself would leave the lock behind.
+pub struct Lock {
+    _file: File,
+}
+
+fn lock_file(path: &Path) -> io::Result<File> {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".lock");
+    OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(PathBuf::from(name))
+}
+
+/// Wait until no other llmwrap is changing `path`, and keep others out until
+/// the lock is dropped.
+pub fn lock(path: &Path) -> io::Result<Lock> {
+    let file = lock_file(path)?;
+    file.lock()?;
+    Ok(Lock { _file: file })
+}
+
+/// Lock `path` unless another llmwrap has it, returning None then.
+pub fn try_lock(path: &Path) -> io::Result<Option<Lock>> {
+    let file = lock_file(path)?;
+    match file.try_lock() {
+        Ok(()) => Ok(Some(Lock { _file: file })),
+        Err(fs::TryLockError::WouldBlock) => Ok(None),
+        Err(fs::TryLockError::Error(err)) => Err(err),
+    }
+}
+
+/// Replace `path` with `contents` in one step: they are written to a file
+/// beside it that is then renamed over it, so readers see the old contents or
+/// the new, never part of either.
+pub fn replace(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temporary = path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+    cancel::shield(|| {
+        let written = File::create(&temporary)
+            .and_then(|mut file| {
+                file.write_all(contents)?;
+                file.sync_all()
+            })
+            .and_then(|()| fs::rename(&temporary, path));
+        if written.is_err() {
+            let _ = fs::remove_file(&temporary);
+        }
+        written
+    })
+}
+
+/// Read `path` (None when it doesn't exist yet), let `change` work out the
+/// new contents and `replace` it, with no other llmwrap changing it between.
+pub fn update(path: &Path, change: impl FnOnce(Option<String>) -> Result<String>) -> Result<()> {
+    let _lock = lock(path).with_context(|| format!("Failed to lock {}", path.display()))?;
+    let current = match fs::read_to_string(path) {
+        Ok(text) => Some(text),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let text = change(current)?;
+    replace(path, text.as_bytes()).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Add `line` to the end of `path` in a single write while holding the lock,
+/// so lines from llmwraps running at once never interleave. When a crash cut
+/// the last line short, the new one still starts on a line of its own.
+pub fn append_line(path: &Path, line: &str) -> io::Result<()> {
+    let _lock = lock(path)?;
+    cancel::shield(|| {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+        let mut text = String::with_capacity(line.len() + 2);
+        if file.metadata()?.len() > 0 {
+            let mut last = [0u8];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                text.push('\n');
+            }
+        }
+        text.push_str(line.trim_end_matches('\n'));
+        text.push('\n');
+        file.write_all(text.as_bytes())?;
+        file.sync_data()
+    })
+}
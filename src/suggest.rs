@@ -0,0 +1,60 @@
+use crate::fuzzy;
+use crate::history::HistoryEntry;
+use crate::snippets::Snippet;
+
+/// Minimum similarity for a local entry to be offered before calling the API.
+pub const STRONG_MATCH: f64 = 0.7;
+
+const MAX_SUGGESTIONS: usize = 3;
+
+pub struct Suggestion {
+    pub command: String,
+    pub source: String,
+    pub score: f64,
+}
+
+/// Rank snippets and previously executed history entries against `prompt`.
+pub fn local_matches(
+    prompt: &str,
+    snippets: &[Snippet],
+    history: &[HistoryEntry],
+) -> Vec<Suggestion> {
+    let wanted = fuzzy::normalize(prompt);
+    let mut found: Vec<Suggestion> = Vec::new();
+
+    let snippet_candidates = snippets.iter().map(|s| {
+        (
+            &s.description,
+            &s.command,
+            format!("snippet: {}", s.description),
+        )
+    });
+    let history_candidates = history
+        .iter()
+        .rev()
+        .filter(|h| h.executed && h.exit_code.unwrap_or(0) == 0 && h.shell.is_none())
+        .map(|h| (&h.prompt, &h.command, format!("history: \"{}\"", h.prompt)));
+
+    for (text, command, source) in snippet_candidates.chain(history_candidates) {
+        let score = fuzzy::similarity(&wanted, &fuzzy::normalize(text));
+        if score < STRONG_MATCH {
+            continue;
+        }
+        match found.iter_mut().find(|s| &s.command == command) {
+            Some(existing) if existing.score >= score => {}
+            Some(existing) => {
+                existing.score = score;
+                existing.source = source;
+            }
+            None => found.push(Suggestion {
+                command: command.clone(),
+                source,
+                score,
+            }),
+        }
+    }
+
+    found.sort_by(|a, b| b.score.total_cmp(&a.score));
+    found.truncate(MAX_SUGGESTIONS);
+    found
+}
@@ -0,0 +1,202 @@
+use crate::api::Client;
+use crate::ui;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+const SYSTEM_PROMPT: &str = "You read the output of shell commands, such as build logs, \
+journals and test runs, for the person who ran them. Say briefly what happened and what went \
+wrong where, quoting the lines that matter exactly. Plain text, no preamble.";
+
+/// How often summaries of parts are summarized again before giving up on
+/// fitting them in one request.
+const MAX_ROUNDS: usize = 3;
+
+/// `[summarize]` in `config.toml`: how `llmwrap summarize` deals with output
+/// too long to send in one request.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// The model that summarizes each part; the provider's small model when
+    /// not set.
+    pub chunk_model: Option<String>,
+    /// The most text sent in one request, in characters (about four to a
+    /// token).
+    pub chunk_chars: usize,
+    /// How many parts are summarized at once.
+    pub parallel: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            chunk_model: None,
+            chunk_chars: 200_000,
+            parallel: 4,
+        }
+    }
+}
+
+/// What `output` says, answering `question` if there is one. Output longer
+/// than one request takes is cut into parts at line ends, the parts are
+/// summarized side by side with the small model, and `client`'s model writes
+/// the answer from those summaries.
+pub fn summarize(
+    client: &Client,
+    settings: &Settings,
+    output: &str,
+    question: Option<&str>,
+) -> Result<String> {
+    let limit = settings.chunk_chars.max(1000);
+    let ask = match question {
+        Some(question) => format!("Answer this about the output: {}", question),
+        None => "Summarize it.".to_string(),
+    };
+    if output.len() <= limit {
+        let progress = ui::Progress::start(client.model());
+        let answer = client.complete(
+            SYSTEM_PROMPT,
+            &format!("The output:\n{}\n\n{}", output, ask),
+        );
+        drop(progress);
+        return answer;
+    }
+
+    let small = client.with_model(
+        settings
+            .chunk_model
+            .as_deref()
+            .unwrap_or(client.provider().small_model()),
+    );
+    let mut parts: Vec<String> = split(output, limit)
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    let mut notes = Vec::new();
+    for round in 0..MAX_ROUNDS {
+        let request = if round == 0 {
+            format!(
+                "This is part {{part}} of {} of a long output. Summarize what it shows, keeping \
+errors, warnings and anything unusual with the lines they are on. {}",
+                parts.len(),
+                question.map_or(String::new(), |q| format!(
+                    "Keep what bears on this question: {}",
+                    q
+                ))
+            )
+        } else {
+            format!(
+                "These are summaries of consecutive parts of a long output, part {{part}} of {}. \
+Combine them into one shorter summary, keeping errors and anything unusual.",
+                parts.len()
+            )
+        };
+        ui::say!(
+            "Summarizing {} parts with {}...",
+            parts.len(),
+            small.model()
+        );
+        notes = map(&small, settings.parallel, &parts, &request)?;
+        let joined = join(&notes);
+        if joined.len() <= limit {
+            break;
+        }
+        parts = split(&joined, limit)
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+    }
+
+    let progress = ui::Progress::start(client.model());
+    let answer = client.complete(
+        SYSTEM_PROMPT,
+        &format!(
+            "The output was too long to send whole, so here are summaries of its parts in \
+order:\n\n{}\n\n{}",
+            join(&notes),
+            ask
+        ),
+    );
+    drop(progress);
+    answer
+}
+
+/// Send every part with `request`, its `{part}` replaced by the part's
+/// number, `parallel` at a time, returning the answers in order.
+fn map(client: &Client, parallel: usize, parts: &[String], request: &str) -> Result<Vec<String>> {
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let answers: Vec<Mutex<Option<Result<String>>>> =
+        parts.iter().map(|_| Mutex::new(None)).collect();
+    let progress = ui::Progress::start(client.model());
+    thread::scope(|scope| {
+        for _ in 0..parallel.clamp(1, parts.len()) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(part) = parts.get(index) else {
+                        break;
+                    };
+                    let request = request.replace("{part}", &(index + 1).to_string());
+                    let answer = client
+                        .complete(SYSTEM_PROMPT, &format!("{}\n\n{}", request, part))
+                        .with_context(|| {
+                            format!("Failed to summarize part {} of {}", index + 1, parts.len())
+                        });
+                    let failed = answer.is_err();
+                    *answers[index].lock().unwrap_or_else(|e| e.into_inner()) = Some(answer);
+                    let finished = done.fetch_add(1, Ordering::Relaxed) + 1;
+                    progress.push(&format!("\n{} of {} parts done", finished, parts.len()));
+                    // The rest would be wasted on a summary that can't be finished.
+                    if failed {
+                        next.store(parts.len(), Ordering::Relaxed);
+                    }
+                }
+            });
+        }
+    });
+    drop(progress);
+    answers
+        .into_iter()
+        .map(|answer| {
+            answer
+                .into_inner()
+                .unwrap_or_else(|e| e.into_inner())
+                .unwrap_or_else(|| Err(anyhow::anyhow!("A part was not summarized")))
+        })
+        .collect()
+}
+
+/// The summaries of consecutive parts, headed with their numbers.
+fn join(notes: &[String]) -> String {
+    notes
+        .iter()
+        .enumerate()
+        .map(|(i, note)| format!("Part {}:\n{}", i + 1, note.trim()))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// `text` in pieces of at most `limit` bytes, each ending at a line end
+/// unless a single line is longer than that.
+fn split(text: &str, limit: usize) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut rest = text;
+    while rest.len() > limit {
+        let mut end = limit;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        if let Some(newline) = rest[..end].rfind('\n') {
+            end = newline + 1;
+        }
+        parts.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    if !rest.trim().is_empty() || parts.is_empty() {
+        parts.push(rest);
+    }
+    parts
+}
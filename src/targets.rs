@@ -0,0 +1,124 @@
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+/// Offer a picker only up to this many candidates; past that a list is no help.
+pub const PICK_LIMIT: usize = 20;
+
+const DETERMINERS: &[&str] = &["the", "this", "that", "my"];
+
+/// Nouns a prompt uses for a kind of file, and the extensions of that kind.
+const KINDS: &[(&[&str], &[&str])] = &[
+    (
+        &["video", "movie", "clip", "recording"],
+        &["mp4", "mkv", "mov", "avi", "webm", "m4v", "flv", "wmv"],
+    ),
+    (
+        &["audio", "song", "track", "podcast"],
+        &["mp3", "wav", "flac", "ogg", "m4a", "aac", "opus"],
+    ),
+    (
+        &[
+            "image",
+            "picture",
+            "photo",
+            "screenshot",
+            "png",
+            "jpg",
+            "jpeg",
+        ],
+        &[
+            "png", "jpg", "jpeg", "gif", "webp", "bmp", "tiff", "heic", "svg",
+        ],
+    ),
+    (
+        &["archive", "tarball", "zip", "backup"],
+        &["zip", "tar", "gz", "tgz", "bz2", "xz", "7z", "rar", "zst"],
+    ),
+    (&["pdf", "document"], &["pdf", "docx", "odt"]),
+    (&["log", "logfile"], &["log"]),
+    (&["csv", "spreadsheet"], &["csv", "tsv", "xlsx", "ods"]),
+    (&["iso"], &["iso", "img"]),
+];
+
+/// A phrase such as "the video" that could mean several files here.
+pub struct Ambiguity {
+    /// Byte range of the phrase in the prompt.
+    pub phrase: Range<usize>,
+    pub noun: String,
+    /// Matching files in the current directory, sorted by name.
+    pub candidates: Vec<String>,
+}
+
+/// Find the first phrase in `prompt` that refers to a kind of file without
+/// naming one, along with the files in the current directory it could mean.
+pub fn find(prompt: &str) -> Option<Ambiguity> {
+    let words: Vec<(Range<usize>, &str)> = prompt
+        .split_whitespace()
+        .map(|word| {
+            let start = word.as_ptr() as usize - prompt.as_ptr() as usize;
+            (start..start + word.len(), word)
+        })
+        .collect();
+    // A file named in the prompt settles it.
+    if words.iter().any(|(_, word)| names_file(word)) {
+        return None;
+    }
+
+    for pair in words.windows(2) {
+        let [(det_span, det), (noun_span, noun)] = pair else {
+            continue;
+        };
+        if !DETERMINERS.contains(&det.to_lowercase().as_str()) {
+            continue;
+        }
+        let bare = noun.trim_end_matches([',', '.', '?', '!', ';', ':']);
+        let lower = bare.to_lowercase();
+        let Some((_, extensions)) = KINDS
+            .iter()
+            .find(|(nouns, _)| nouns.contains(&lower.as_str()))
+        else {
+            continue;
+        };
+        let candidates = files_with(extensions);
+        if candidates.is_empty() {
+            return None;
+        }
+        return Some(Ambiguity {
+            phrase: det_span.start..noun_span.start + bare.len(),
+            noun: lower,
+            candidates,
+        });
+    }
+    None
+}
+
+fn names_file(word: &str) -> bool {
+    let word = word.trim_matches(|c: char| "\"'`,.?!;:()".contains(c));
+    if word.is_empty() {
+        return false;
+    }
+    let known_extension = word.rsplit_once('.').is_some_and(|(stem, ext)| {
+        let ext = ext.to_lowercase();
+        !stem.is_empty() && KINDS.iter().any(|(_, exts)| exts.contains(&ext.as_str()))
+    });
+    known_extension || (word.contains(['.', '/']) && Path::new(word).exists())
+}
+
+fn files_with(extensions: &[&str]) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(".") else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter(|name| !name.starts_with('.'))
+        .filter(|name| {
+            name.rsplit_once('.')
+                .is_some_and(|(_, ext)| extensions.contains(&ext.to_lowercase().as_str()))
+        })
+        .collect();
+    names.sort();
+    names
+}
@@ -0,0 +1,93 @@
+//! Prompt templates picked with `--template NAME`: instructions for one kind
+//! of task, such as git or Docker, that take the place of the system prompt.
+//! `[templates.NAME]` tables in `config.toml` add more or replace the built-in
+//! ones, and placeholders such as `{shell}` are filled in when one is used.
+
+use crate::environment;
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// A `[templates.NAME]` table in `config.toml`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Template {
+    /// The system prompt, with placeholders. `{base}` stands for the one used
+    /// without a template, so a template can add to it rather than replace it.
+    pub prompt: String,
+}
+
+/// Templates that come with llmwrap, by name.
+const BUILT_IN: &[(&str, &str)] = &[
+    (
+        "docker",
+        "{base} The request is about Docker. Use `docker compose`, not the old \
+         `docker-compose`, refer to containers and images by name rather than by ID, \
+         and never remove volumes, or prune anything, unless asked to.",
+    ),
+    (
+        "git",
+        "{base} The request is about git, in the repository at {cwd}. Prefer commands \
+         that can be undone: `git switch` and `git restore` over `git checkout`, \
+         `git revert` over rewriting pushed history, and `--force-with-lease` over \
+         `--force`. Never discard uncommitted work unless asked to.",
+    ),
+];
+
+/// The prompt of the template named `name`, from `config.toml` if it defines
+/// one by that name, else built in.
+pub fn find<'a>(configured: &'a BTreeMap<String, Template>, name: &str) -> Result<&'a str> {
+    if let Some(template) = configured.get(name) {
+        return Ok(&template.prompt);
+    }
+    if let Some((_, prompt)) = BUILT_IN.iter().find(|(built_in, _)| *built_in == name) {
+        return Ok(prompt);
+    }
+    let mut known: Vec<&str> = BUILT_IN.iter().map(|(name, _)| *name).collect();
+    known.extend(configured.keys().map(String::as_str));
+    known.sort_unstable();
+    known.dedup();
+    anyhow::bail!("No template named {}; there are {}", name, known.join(", "))
+}
+
+/// Fill in `{base}` with `base` and `{shell}`, `{os}` and `{cwd}` with the
+/// shell commands are written for and this machine's OS and working directory.
+pub fn render(prompt: &str, base: &str, shell: &str) -> String {
+    let cwd = std::env::current_dir()
+        .map(|dir| dir.display().to_string())
+        .unwrap_or_default();
+    let os = environment::distribution().unwrap_or_else(|| std::env::consts::OS.to_string());
+    fill(
+        prompt,
+        &[("base", base), ("shell", shell), ("os", &os), ("cwd", &cwd)],
+    )
+}
+
+/// Replace each `{name}` in `text` with its value. Braces around anything
+/// else, as in `find -exec rm {} +` or `${HOME}`, are left as written.
+pub fn fill(text: &str, values: &[(&str, &str)]) -> String {
+    let mut filled = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest.find('}').and_then(|end| {
+            values
+                .iter()
+                .find(|(name, _)| *name == &rest[1..end])
+                .map(|(_, value)| (end, *value))
+        });
+        match value {
+            Some((end, value)) => {
+                filled.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                filled.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
@@ -0,0 +1,101 @@
+use regex::{Captures, Regex};
+use std::sync::{LazyLock, OnceLock};
+
+/// Termux, the Linux environment for Android, when llmwrap runs inside it.
+pub struct Termux {
+    /// Where Termux keeps what a Linux system has under `/usr`, usually
+    /// `/data/data/com.termux/files/usr`.
+    pub prefix: String,
+}
+
+/// A command with Linux paths and `sudo` that don't exist in Termux put right.
+pub struct Repair {
+    pub command: String,
+    /// What changed, e.g. "`/tmp` to `/data/data/com.termux/files/usr/tmp`".
+    pub changes: Vec<String>,
+}
+
+static DETECTED: OnceLock<Option<Termux>> = OnceLock::new();
+
+/// Directories of a Linux system and where Termux keeps them under its prefix.
+const MOVED: &[(&str, &str)] = &[
+    ("/usr/local/bin", "bin"),
+    ("/usr/bin", "bin"),
+    ("/usr/share", "share"),
+    ("/usr/lib", "lib"),
+    ("/bin", "bin"),
+    ("/etc", "etc"),
+    ("/tmp", "tmp"),
+    ("/var", "var"),
+];
+
+static SYSTEM_PATH: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"(^|[\s=(,:'"])(/usr/local/bin|/usr/bin|/usr/share|/usr/lib|/bin|/etc|/tmp|/var)(/|\b)"#,
+    )
+    .unwrap()
+});
+static SUDO: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(^|[;&|(]\s*|\n\s*)sudo\s+").unwrap());
+
+/// Termux, if this is running inside it.
+pub fn detect() -> Option<&'static Termux> {
+    DETECTED
+        .get_or_init(|| {
+            if !cfg!(any(target_os = "android", target_os = "linux")) {
+                return None;
+            }
+            let prefix = std::env::var("PREFIX").ok();
+            let termux = std::env::var_os("TERMUX_VERSION").is_some()
+                || prefix
+                    .as_deref()
+                    .is_some_and(|p| p.contains("/com.termux/"));
+            termux.then(|| Termux {
+                prefix: prefix.unwrap_or_else(|| "/data/data/com.termux/files/usr".to_string()),
+            })
+        })
+        .as_ref()
+}
+
+impl Termux {
+    /// What the system prompt should say about running in Termux.
+    pub fn prompt_note(&self) -> String {
+        format!(
+            "The command runs in Termux on Android. Install packages with `pkg install`, not \
+apt-get. There is no sudo and no root, and every file Termux can reach already belongs to the \
+user. There is no /usr, /bin or /tmp: programs are in {prefix}/bin, configuration in \
+{prefix}/etc and temporary files go in $TMPDIR ({prefix}/tmp). Home is $HOME, and the phone's \
+shared storage is ~/storage/shared once `termux-setup-storage` has been run. Use \
+termux-clipboard-set for the clipboard and termux-open to open files and URLs.",
+            prefix = self.prefix
+        )
+    }
+
+    /// Rewrite `command` for Termux: paths under `/usr`, `/bin`, `/etc`,
+    /// `/tmp` and `/var` move under the prefix and `sudo` is dropped. Returns
+    /// None when nothing needed changing.
+    pub fn repair(&self, command: &str) -> Option<Repair> {
+        let mut changes = Vec::new();
+        let command = SUDO.replace_all(command, |caps: &Captures| {
+            let change = "dropped `sudo`".to_string();
+            if !changes.contains(&change) {
+                changes.push(change);
+            }
+            caps[1].to_string()
+        });
+        let command = SYSTEM_PATH
+            .replace_all(&command, |caps: &Captures| {
+                let (_, moved) = MOVED
+                    .iter()
+                    .find(|(linux, _)| *linux == &caps[2])
+                    .expect("every matched directory is listed");
+                let path = format!("{}/{}", self.prefix, moved);
+                let change = format!("`{}` to `{}`", &caps[2], path);
+                if !changes.contains(&change) {
+                    changes.push(change);
+                }
+                format!("{}{}{}", &caps[1], path, &caps[3])
+            })
+            .into_owned();
+        (!changes.is_empty()).then_some(Repair { command, changes })
+    }
+}
@@ -0,0 +1,42 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The steps taken so far and how long each took, in the order first taken.
+static STEPS: Mutex<Vec<(&'static str, Duration)>> = Mutex::new(Vec::new());
+
+/// Run `f`, adding the time it takes to `step`'s.
+pub fn time<T>(step: &'static str, f: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let value = f();
+    record(step, started.elapsed());
+    value
+}
+
+/// Add `elapsed` to `step`'s time.
+pub fn record(step: &'static str, elapsed: Duration) {
+    let mut steps = STEPS.lock().unwrap_or_else(|e| e.into_inner());
+    match steps.iter_mut().find(|(name, _)| *name == step) {
+        Some((_, total)) => *total += elapsed,
+        None => steps.push((step, elapsed)),
+    }
+}
+
+/// A table of the steps for `--time-report`, with what they don't account
+/// for of the `total`: prompts, running the command and everything untimed.
+pub fn report(total: Duration) -> String {
+    let steps = STEPS.lock().unwrap_or_else(|e| e.into_inner());
+    let timed: Duration = steps.iter().map(|(_, elapsed)| *elapsed).sum();
+    let mut rows: Vec<(&str, Duration)> = steps.clone();
+    rows.push(("the rest", total.saturating_sub(timed)));
+    rows.push(("total", total));
+    let width = rows.iter().map(|(step, _)| step.len()).max().unwrap_or(0);
+    let mut report = String::from("Time report:\n");
+    for (step, elapsed) in rows {
+        report.push_str(&format!(
+            "  {:<width$}  {:>9.1} ms\n",
+            step,
+            elapsed.as_secs_f64() * 1000.0,
+        ));
+    }
+    report
+}
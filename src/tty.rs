@@ -0,0 +1,141 @@
+use crate::cmdline::{self, Operator, Segment};
+
+/// Programs that take over the terminal whatever their arguments.
+const FULL_SCREEN: &[&str] = &[
+    "vi", "vim", "nvim", "nano", "emacs", "micro", "less", "more", "most", "man", "top", "htop",
+    "btop", "atop", "iotop", "nmon", "watch", "tmux", "screen", "mc", "ranger", "nnn", "fzf",
+    "tig", "lazygit", "ncdu", "visudo", "vipw", "passwd", "su", "ftp", "sftp", "telnet",
+];
+
+/// Programs that are interactive depending on how they are called: shells,
+/// database clients and remote logins prompt unless given something to run.
+struct Prompting {
+    programs: &'static [&'static str],
+    /// Flags that consume the next word, so it is not counted as an operand.
+    value_flags: &'static [&'static str],
+    /// Flags that give the program something to run instead of prompting.
+    run_flags: &'static [&'static str],
+    /// Flags that ask for a terminal outright, such as `ssh -t`.
+    tty_flags: &'static [&'static str],
+    /// How many operands (a host or a database name) still leave it prompting;
+    /// None when only a tty flag makes it interactive.
+    operands: Option<usize>,
+}
+
+const PROMPTING: &[Prompting] = &[
+    Prompting {
+        programs: &["ssh", "mosh"],
+        value_flags: &[
+            "-p", "-i", "-l", "-o", "-F", "-J", "-L", "-R", "-D", "-b", "-c", "-E", "-e", "-I",
+            "-m", "-O", "-Q", "-S", "-W", "-w", "-B",
+        ],
+        run_flags: &["-N"],
+        tty_flags: &["-t", "-tt"],
+        operands: Some(1),
+    },
+    Prompting {
+        programs: &["mysql", "mariadb"],
+        value_flags: &["-u", "-h", "-P", "-D", "-S", "--user", "--host", "--port"],
+        run_flags: &["-e", "--execute"],
+        tty_flags: &[],
+        operands: Some(1),
+    },
+    Prompting {
+        programs: &["psql"],
+        value_flags: &[
+            "-U",
+            "-h",
+            "-p",
+            "-d",
+            "--username",
+            "--host",
+            "--port",
+            "--dbname",
+        ],
+        run_flags: &["-c", "--command", "-f", "--file", "-l", "--list"],
+        tty_flags: &[],
+        operands: Some(2),
+    },
+    Prompting {
+        programs: &["sqlite3", "mongosh", "mongo"],
+        value_flags: &["--host", "--port", "-u", "-p"],
+        run_flags: &["--eval", "-cmd", "-f", "--file"],
+        tty_flags: &[],
+        operands: Some(1),
+    },
+    Prompting {
+        programs: &["redis-cli"],
+        value_flags: &["-h", "-p", "-a", "-n", "-u"],
+        run_flags: &[],
+        tty_flags: &[],
+        operands: Some(0),
+    },
+    Prompting {
+        programs: &[
+            "python", "python3", "ipython", "node", "irb", "lua", "ghci", "R", "php", "bash", "sh",
+            "zsh", "fish",
+        ],
+        value_flags: &["-W", "-X"],
+        run_flags: &["-c", "-m", "-e", "-r", "-p", "--eval", "--print"],
+        tty_flags: &["-i"],
+        operands: Some(0),
+    },
+    Prompting {
+        programs: &["docker", "podman", "kubectl", "nerdctl"],
+        value_flags: &[],
+        run_flags: &[],
+        tty_flags: &["-it", "-ti", "-t", "--tty"],
+        operands: None,
+    },
+];
+
+/// The first program in `command` that needs the user at the terminal (an
+/// editor, a pager, a REPL or a remote shell), if any.
+pub fn interactive_program(command: &str) -> Option<String> {
+    let segments = cmdline::segments(command);
+    let mut stdin_piped = false;
+    for segment in &segments {
+        if let Some(program) = segment_program(segment, stdin_piped) {
+            return Some(program.to_string());
+        }
+        stdin_piped = segment.op == Some(Operator::Pipe);
+    }
+    None
+}
+
+fn segment_program(segment: &Segment, stdin_piped: bool) -> Option<&str> {
+    let args = segment.args_from_program();
+    let program = args.first()?.rsplit('/').next()?;
+    // Pagers and editors read keys from the terminal even when stdin is a pipe.
+    if FULL_SCREEN.contains(&program) {
+        return Some(program);
+    }
+    let prompting = PROMPTING.iter().find(|p| p.programs.contains(&program))?;
+    let rest = &args[1..];
+    if rest
+        .iter()
+        .any(|a| prompting.tty_flags.contains(&a.as_str()))
+    {
+        return Some(program);
+    }
+    let stdin_redirected = segment.redirects.iter().any(|r| r.op.contains('<'));
+    if stdin_piped
+        || stdin_redirected
+        || rest
+            .iter()
+            .any(|a| prompting.run_flags.contains(&a.as_str()))
+    {
+        return None;
+    }
+    let limit = prompting.operands?;
+    let mut operands = 0;
+    let mut words = rest.iter();
+    while let Some(word) = words.next() {
+        if prompting.value_flags.contains(&word.as_str()) {
+            words.next();
+        } else if !word.starts_with('-') {
+            operands += 1;
+        }
+    }
+    (operands <= limit).then_some(program)
+}
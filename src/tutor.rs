@@ -0,0 +1,82 @@
+use crate::api;
+use crate::paths;
+use crate::store;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// A command taken apart after it ran, with a question to check it sank in.
+#[derive(Deserialize)]
+pub struct Lesson {
+    pub parts: Vec<Part>,
+    pub question: String,
+    pub answer: String,
+}
+
+#[derive(Deserialize)]
+pub struct Part {
+    /// The piece of the command, e.g. `-czf`.
+    pub text: String,
+    pub explanation: String,
+    /// The general idea it shows, e.g. "tar compression flags".
+    pub concept: String,
+}
+
+/// Ask the model to take `command` apart for someone learning the shell.
+pub fn lesson(client: &api::Client, prompt: &str, command: &str) -> Result<Lesson> {
+    let system_prompt = "You teach the shell to someone who just ran a command they asked for. \
+Respond with only a JSON object, no code fences: {\"parts\": [{\"text\": piece of the command, \
+\"explanation\": what it did here in one sentence, \"concept\": the general idea in two to four \
+lowercase words}], \"question\": one short question testing the idea most worth remembering, \
+\"answer\": its answer in one sentence}. Cover every program, flag, operator and redirection \
+in order.";
+    let request = format!("They asked: {}\nThe command was: {}", prompt, command);
+    let raw = client.complete(system_prompt, &request)?;
+    let text = api::strip_code_fences(&raw);
+    serde_json::from_str(&text)
+        .with_context(|| format!("The model did not return a lesson:\n{}", text))
+}
+
+/// Concepts met in lessons so far, with how many times each came up.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Progress {
+    pub concepts: BTreeMap<String, u32>,
+}
+
+impl Progress {
+    pub fn load() -> Self {
+        progress_file()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = progress_file() else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create data directory {}", dir.display()))?;
+        }
+        let text = serde_json::to_string_pretty(self)?;
+        store::replace(&path, text.as_bytes())
+            .with_context(|| format!("Failed to write tutor progress {}", path.display()))
+    }
+
+    /// Count a concept as seen, returning whether this is the first time.
+    pub fn record(&mut self, concept: &str) -> bool {
+        let count = self
+            .concepts
+            .entry(concept.trim().to_lowercase())
+            .or_default();
+        *count += 1;
+        *count == 1
+    }
+}
+
+fn progress_file() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join("tutor.json"))
+}
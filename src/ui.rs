@@ -0,0 +1,435 @@
+use crate::cancel;
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+static INTERACTIVE: AtomicBool = AtomicBool::new(true);
+/// Where `divert_stdout` moved the original stdout to.
+static RESULT: OnceLock<Mutex<File>> = OnceLock::new();
+
+/// Print a status message: to stdout in a terminal session, to stderr otherwise
+/// so that only the result reaches the program reading our output.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if $crate::ui::interactive() {
+            println!($($arg)*)
+        } else {
+            eprintln!($($arg)*)
+        }
+    };
+}
+pub use say;
+
+/// Decide whether we may prompt: only when both stdin and stdout are terminals,
+/// unless the user chose explicitly.
+pub fn init(force: Option<bool>) {
+    let interactive =
+        force.unwrap_or_else(|| io::stdin().is_terminal() && io::stdout().is_terminal());
+    INTERACTIVE.store(interactive, Ordering::Relaxed);
+}
+
+pub fn interactive() -> bool {
+    INTERACTIVE.load(Ordering::Relaxed)
+}
+
+/// Send everything printed from now on to stderr, so prompts reach the
+/// terminal while a shell function captures our output, keeping the real
+/// stdout for what `emit` writes. Call before printing anything.
+#[cfg(unix)]
+pub fn divert_stdout() -> Result<()> {
+    use std::os::fd::FromRawFd;
+
+    // SAFETY: dup returns a new descriptor or -1, which is checked.
+    let saved = unsafe { libc::dup(1) };
+    if saved < 0 {
+        return Err(io::Error::last_os_error()).context("Failed to keep stdout");
+    }
+    // SAFETY: descriptors 1 and 2 are open for the life of the process.
+    if unsafe { libc::dup2(2, 1) } < 0 {
+        return Err(io::Error::last_os_error()).context("Failed to redirect stdout");
+    }
+    // SAFETY: `saved` is a descriptor we own and nothing else uses.
+    let _ = RESULT.set(Mutex::new(unsafe { File::from_raw_fd(saved) }));
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn divert_stdout() -> Result<()> {
+    anyhow::bail!("--eval needs a Unix shell; in PowerShell use `llmwrap init powershell`")
+}
+
+/// Write `text` to the stdout `divert_stdout` kept, or to stdout when it
+/// wasn't moved.
+pub fn emit(text: &str) -> Result<()> {
+    match RESULT.get() {
+        Some(result) => {
+            let mut file = result.lock().unwrap_or_else(|e| e.into_inner());
+            writeln!(file, "{}", text).context("Failed to write to stdout")
+        }
+        None => {
+            println!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Color {
+    Red,
+    Yellow,
+}
+
+/// `text` in bold `color` when status messages go to a terminal that may show
+/// it, i.e. `NO_COLOR` is not set.
+pub fn paint(text: &str, color: Color) -> String {
+    let terminal = if interactive() {
+        io::stdout().is_terminal()
+    } else {
+        io::stderr().is_terminal()
+    };
+    if !terminal || std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+        return text.to_string();
+    }
+    let code = match color {
+        Color::Red => 31,
+        Color::Yellow => 33,
+    };
+    format!("\x1b[1;{}m{}\x1b[0m", code, text)
+}
+
+const SPINNER: &[char] = &['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// A line on stderr while waiting for the model: a spinner until the answer
+/// starts, then the end of the answer so far as it streams in. The line is
+/// cleared when this is dropped. Nothing is shown when stderr is not a
+/// terminal.
+pub struct Progress {
+    shown: Option<Shown>,
+}
+
+struct Shown {
+    text: Arc<Mutex<String>>,
+    stop: Arc<AtomicBool>,
+    spinner: Option<JoinHandle<()>>,
+    /// Clears the line if the run is cancelled while waiting.
+    _cancelled: cancel::Hook,
+}
+
+impl Progress {
+    /// Start the spinner, saying what is being waited for.
+    pub fn start(waiting_for: &str) -> Progress {
+        if !io::stderr().is_terminal() {
+            return Progress { shown: None };
+        }
+        let text = Arc::new(Mutex::new(String::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let spinner = {
+            let text = Arc::clone(&text);
+            let stop = Arc::clone(&stop);
+            let label = format!("Waiting for {}", waiting_for);
+            thread::spawn(move || {
+                for frame in SPINNER.iter().cycle() {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    // Hold the lock while drawing so a delta can't interleave.
+                    let text = text.lock().unwrap_or_else(|e| e.into_inner());
+                    if text.is_empty() {
+                        draw(&format!("{} {}", frame, label));
+                    }
+                    drop(text);
+                    thread::sleep(Duration::from_millis(100));
+                }
+            })
+        };
+        let cancelled = {
+            let stop = Arc::clone(&stop);
+            cancel::on_cancel(move || {
+                stop.store(true, Ordering::Relaxed);
+                eprint!("\r\x1b[2K");
+            })
+        };
+        Progress {
+            shown: Some(Shown {
+                text,
+                stop,
+                spinner: Some(spinner),
+                _cancelled: cancelled,
+            }),
+        }
+    }
+
+    /// Show another piece of the answer.
+    pub fn push(&self, delta: &str) {
+        let Some(shown) = &self.shown else {
+            return;
+        };
+        let mut text = shown.text.lock().unwrap_or_else(|e| e.into_inner());
+        text.push_str(delta);
+        let line = text.trim_end().lines().last().unwrap_or("");
+        draw(line);
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        let Some(shown) = &mut self.shown else {
+            return;
+        };
+        shown.stop.store(true, Ordering::Relaxed);
+        if let Some(spinner) = shown.spinner.take() {
+            let _ = spinner.join();
+        }
+        eprint!("\r\x1b[2K");
+        let _ = io::stderr().flush();
+    }
+}
+
+/// Replace the current stderr line with `line`, cut to the terminal's width
+/// so it never wraps onto a line that can't be cleared.
+fn draw(line: &str) {
+    let width = columns().saturating_sub(1);
+    let line: String = line.chars().filter(|c| !c.is_control()).collect();
+    let cut: String = if line.chars().count() > width {
+        let tail: String = line
+            .chars()
+            .skip(line.chars().count() + 1 - width)
+            .collect();
+        format!("…{}", tail)
+    } else {
+        line
+    };
+    eprint!("\r\x1b[2K\x1b[2m{}\x1b[0m", cut);
+    let _ = io::stderr().flush();
+}
+
+#[cfg(unix)]
+fn columns() -> usize {
+    const STDERR: i32 = 2;
+    // SAFETY: winsize is plain data and TIOCGWINSZ fills it in; on failure
+    // it stays zeroed.
+    let size = unsafe {
+        let mut size: libc::winsize = std::mem::zeroed();
+        libc::ioctl(STDERR, libc::TIOCGWINSZ, &mut size);
+        size
+    };
+    if size.ws_col == 0 {
+        80
+    } else {
+        size.ws_col as usize
+    }
+}
+
+#[cfg(not(unix))]
+fn columns() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .unwrap_or(80)
+}
+
+/// Ask a question where Enter means yes.
+pub fn ask_yes_no(question: &str) -> Result<bool> {
+    let decision = read_line(question)?.to_lowercase();
+    Ok(decision.is_empty() || decision == "y" || decision == "yes")
+}
+
+/// Ask a question where Enter means no.
+pub fn ask_no_yes(question: &str) -> Result<bool> {
+    let decision = read_line(question)?.to_lowercase();
+    Ok(decision == "y" || decision == "yes")
+}
+
+/// Print `prompt` and read one trimmed line from stdin. Without a terminal the
+/// prompt is answered with an empty line, i.e. its default.
+pub fn read_line(prompt: &str) -> Result<String> {
+    if !interactive() {
+        return Ok(String::new());
+    }
+    print!("{}", prompt);
+    io::stdout().flush()?;
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(input.trim().to_string())
+}
+
+/// Print `prompt` and wait for a single keypress, returning it without the
+/// user having to press Enter; Enter itself comes back as `'\n'`. Where keys
+/// can't be read one at a time the first character of a line is used.
+pub fn read_key(prompt: &str) -> Result<char> {
+    print!("{}", prompt);
+    io::stdout().flush()?;
+    let key = match key_from_terminal()? {
+        Some(key) => key,
+        None => {
+            let mut input = String::new();
+            io::stdin().read_line(&mut input)?;
+            input.trim().chars().next().unwrap_or('\n')
+        }
+    };
+    println!();
+    Ok(if key == '\r' { '\n' } else { key })
+}
+
+/// Let the user choose one of `items` with the arrow keys (or `j`/`k`) and
+/// Enter, or by its number, returning its index; None when they press `q`.
+/// Each item shows as its first line. Where keys can't be read one at a time
+/// a number is read from a line instead.
+pub fn pick(items: &[String]) -> Result<Option<usize>> {
+    // Lines that wrap couldn't be redrawn in place.
+    let width = columns().saturating_sub(7).max(10);
+    let lines: Vec<String> = items
+        .iter()
+        .map(|item| {
+            let mut line = item.trim().lines().next().unwrap_or("").to_string();
+            if item.trim().contains('\n') {
+                line.push_str(" …");
+            }
+            if line.chars().count() > width {
+                line = line.chars().take(width - 1).collect::<String>() + "…";
+            }
+            line
+        })
+        .collect();
+    let mut selected = 0;
+    let mut drawn = false;
+    loop {
+        if drawn {
+            print!("\x1b[{}A\r\x1b[J", lines.len());
+        }
+        for (i, line) in lines.iter().enumerate() {
+            let marker = if i == selected { '>' } else { ' ' };
+            println!("{} {}. {}", marker, i + 1, line);
+        }
+        print!("Choose with Up/Down or a number, Enter to take it, q for none: ");
+        io::stdout().flush()?;
+        drawn = true;
+        let Some(key) = key_from_terminal()? else {
+            let mut input = String::new();
+            io::stdin().read_line(&mut input)?;
+            return Ok(match input.trim() {
+                "" => Some(0),
+                number => number
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|n| (1..=items.len()).contains(n))
+                    .map(|n| n - 1),
+            });
+        };
+        match key {
+            '\r' | '\n' => {
+                println!();
+                return Ok(Some(selected));
+            }
+            'q' => {
+                println!();
+                return Ok(None);
+            }
+            'k' => selected = selected.saturating_sub(1),
+            'j' => selected = (selected + 1).min(items.len() - 1),
+            // Arrow keys come as ESC [ A and ESC [ B.
+            '\x1b' => {
+                if key_from_terminal()? != Some('[') {
+                    println!();
+                    return Ok(None);
+                }
+                match key_from_terminal()? {
+                    Some('A') => selected = selected.saturating_sub(1),
+                    Some('B') => selected = (selected + 1).min(items.len() - 1),
+                    _ => {}
+                }
+            }
+            digit => {
+                if let Some(n) = digit.to_digit(10).map(|n| n as usize)
+                    && (1..=items.len()).contains(&n)
+                {
+                    println!();
+                    return Ok(Some(n - 1));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn key_from_terminal() -> Result<Option<char>> {
+    use std::io::Read;
+    use std::mem::MaybeUninit;
+
+    const STDIN: i32 = 0;
+    let mut saved = MaybeUninit::<libc::termios>::uninit();
+    // SAFETY: tcgetattr fills the termios on success, which is checked.
+    if unsafe { libc::tcgetattr(STDIN, saved.as_mut_ptr()) } != 0 {
+        return Ok(None);
+    }
+    // SAFETY: initialised by the successful tcgetattr above.
+    let saved = unsafe { saved.assume_init() };
+    let mut single = saved;
+    // Keep signals so Ctrl-C still interrupts.
+    single.c_lflag &= !(libc::ICANON | libc::ECHO);
+    single.c_cc[libc::VMIN] = 1;
+    single.c_cc[libc::VTIME] = 0;
+    // SAFETY: both termios values come from tcgetattr.
+    unsafe { libc::tcsetattr(STDIN, libc::TCSANOW, &single) };
+    // Ctrl-C while waiting for the key ends the run from another thread.
+    // SAFETY: restores the settings read above.
+    let restore = cancel::on_cancel(move || unsafe {
+        libc::tcsetattr(STDIN, libc::TCSANOW, &saved);
+    });
+    let mut byte = [0u8; 1];
+    let read = io::stdin().lock().read(&mut byte);
+    drop(restore);
+    // SAFETY: restores the settings read above.
+    unsafe { libc::tcsetattr(STDIN, libc::TCSANOW, &saved) };
+    Ok(match read? {
+        0 => Some('q'),
+        _ => Some(byte[0] as char),
+    })
+}
+
+#[cfg(not(unix))]
+fn key_from_terminal() -> Result<Option<char>> {
+    Ok(None)
+}
+
+/// Let the user change `text` in `$VISUAL` or `$EDITOR`, returning what they
+/// saved with surrounding whitespace trimmed.
+pub fn edit(text: &str) -> Result<String> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| if cfg!(windows) { "notepad" } else { "vi" }.to_string());
+    // A fresh file only we can read or write, so no one else sharing the
+    // temporary directory can swap its contents before the command is run.
+    let mut file = tempfile::Builder::new()
+        .prefix("llmwrap-")
+        .suffix(".sh")
+        .tempfile()
+        .context("Failed to create a file to edit the command in")?;
+    let path = file.path().to_path_buf();
+    writeln!(file, "{}", text).with_context(|| format!("Failed to write {}", path.display()))?;
+    // The editor may come with arguments, e.g. `code --wait`.
+    let mut words = shlex::split(&editor).unwrap_or_else(|| vec![editor.clone()]);
+    if words.is_empty() {
+        words.push(editor.clone());
+    }
+    let status = std::process::Command::new(&words[0])
+        .args(&words[1..])
+        .arg(&path)
+        .status()
+        .with_context(|| format!("Failed to start editor `{}`", editor));
+    let edited = std::fs::read_to_string(&path);
+    drop(file);
+    if !status?.success() {
+        anyhow::bail!("The editor exited with an error; keeping the command as it was");
+    }
+    Ok(edited
+        .with_context(|| format!("Failed to read {}", path.display()))?
+        .trim()
+        .to_string())
+}
@@ -0,0 +1,494 @@
+use crate::bsd;
+use crate::busybox;
+use crate::cmdline;
+use crate::safer;
+use std::fmt;
+use std::ops::Range;
+use std::process::{Command, Stdio};
+
+/// Which flavour of the core command-line tools this machine has.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Userland {
+    Gnu,
+    Bsd,
+    /// BusyBox applets, as in Alpine and other minimal containers.
+    Busybox,
+}
+
+impl Userland {
+    /// GNU tools announce themselves in `--version`; BSD ones reject the flag.
+    /// BusyBox claims to be "not GNU sed", so it is told apart by where `sed`
+    /// leads. Without sed at all, fall back to what the OS normally ships.
+    pub fn detect() -> Self {
+        if busybox::detect().is_some() {
+            return Userland::Busybox;
+        }
+        let output = Command::new("sed")
+            .arg("--version")
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output();
+        match output {
+            Ok(out) if String::from_utf8_lossy(&out.stdout).contains("GNU") => Userland::Gnu,
+            Ok(_) => Userland::Bsd,
+            Err(_)
+                if cfg!(any(
+                    target_os = "macos",
+                    target_os = "freebsd",
+                    target_os = "openbsd"
+                )) =>
+            {
+                Userland::Bsd
+            }
+            Err(_) => Userland::Gnu,
+        }
+    }
+
+    /// A sentence for the system prompt so the model picks the right flags.
+    pub fn prompt_note(self) -> &'static str {
+        match self {
+            Userland::Gnu => "The user's system has GNU coreutils, GNU sed, GNU find and GNU grep.",
+            // OpenBSD's `sed -i` takes its suffix attached, as GNU's does.
+            Userland::Bsd if cfg!(target_os = "openbsd") => {
+                "The user's system has BSD userland tools: OpenBSD sed (`sed -i` takes no \
+suffix argument), BSD date (no -d), BSD stat (-f, not -c), BSD find (no -printf) and BSD grep \
+(no -P)."
+            }
+            Userland::Bsd => {
+                "The user's system has BSD userland tools as on macOS: BSD sed (for in-place \
+edits use `sed -i ''`), BSD date (no -d), BSD stat (-f, not -c), BSD find (no -printf) \
+and BSD grep (no -P)."
+            }
+            Userland::Busybox => {
+                "The user's system has BusyBox applets instead of GNU coreutils, sed, find and \
+grep, as in minimal containers. They take far fewer options than GNU's: no find -printf, no \
+grep -P, and few long options such as --sort or --max-depth, so stick to POSIX options. The shell \
+is probably ash, not bash."
+            }
+        }
+    }
+}
+
+impl fmt::Display for Userland {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Userland::Gnu => "GNU",
+            Userland::Bsd => "BSD",
+            Userland::Busybox => "BusyBox",
+        })
+    }
+}
+
+/// Flags in a command that this userland does not support.
+pub struct Lint {
+    pub problems: Vec<String>,
+    /// The command with the problems that have a mechanical fix corrected.
+    pub corrected: Option<String>,
+}
+
+pub fn lint(command: &str, userland: Userland) -> Option<Lint> {
+    let mut problems = Vec::new();
+    let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+    let bsd = bsd::detect();
+
+    for segment in cmdline::segments(command) {
+        let words = segment.raw_words(command);
+        // Skip wrappers such as `sudo` the same way the segment does.
+        let skipped = segment.words.len() - segment.args_from_program().len();
+        let Some(args) = words.get(skipped..).filter(|a| !a.is_empty()) else {
+            continue;
+        };
+        let program = args[0].value.rsplit('/').next().unwrap_or("");
+        let has = |flag: &str| {
+            args[1..]
+                .iter()
+                .position(|w| w.value == flag)
+                .map(|i| i + 1)
+        };
+        let before = problems.len();
+        if let Some(bsd) = bsd
+            && bsd.escalate == Some("doas")
+            && let Some(sudo) = words[..skipped].iter().find(|w| w.value == "sudo")
+        {
+            problems.push(format!("{} has `doas`, not `sudo`", bsd));
+            edits.push((sudo.span.clone(), "doas".into()));
+        }
+        let mut problem = |text: String| problems.push(text);
+
+        if let Some(bsd) = bsd {
+            match program {
+                "apt" | "apt-get" | "yum" | "dnf" => {
+                    problem(format!(
+                        "`{}` is for Linux; {} installs packages with `{}`",
+                        program,
+                        bsd,
+                        bsd.install()
+                    ));
+                    if let Some(i) = has("install") {
+                        edits.push((args[0].span.start..args[i].span.end, bsd.install().into()));
+                        if bsd.flavor != bsd::Flavor::FreeBsd
+                            && let Some(y) = has("-y")
+                        {
+                            edits.push((args[y - 1].span.end..args[y].span.end, String::new()));
+                        }
+                    }
+                }
+                "systemctl" => {
+                    problem(format!("{} has no `systemctl`", bsd));
+                    if let [_, action, service] = args
+                        && matches!(
+                            action.value.as_str(),
+                            "start" | "stop" | "restart" | "status"
+                        )
+                    {
+                        edits.push((
+                            args[0].span.start..service.span.end,
+                            bsd.service(&action.value, &service.value),
+                        ));
+                    }
+                }
+                "ip" | "free" | "ss" => {
+                    let instead = match program {
+                        "ip" => "`ifconfig`, `route` or `netstat -rn`",
+                        "free" => "`top` or `sysctl hw.physmem`",
+                        _ => "`sockstat` or `netstat`",
+                    };
+                    problem(format!("{} has no `{}`; use {}", bsd, program, instead));
+                }
+                _ => {}
+            }
+        }
+
+        match (userland, program) {
+            (Userland::Bsd, "ps") if bsd.is_some() => {
+                if let Some(sort) = args.iter().position(|w| w.value.starts_with("--sort")) {
+                    problem(
+                        "BSD `ps` has no `--sort`; `-m` sorts by memory and `-r` by CPU".into(),
+                    );
+                    let key = if args[sort].value.contains('=') {
+                        sort
+                    } else {
+                        sort + 1
+                    };
+                    let key = args.get(key).map_or("", |w| w.value.as_str());
+                    let flag = if key.contains("cpu") {
+                        Some("-r")
+                    } else if key.contains("mem") || key.contains("rss") {
+                        Some("-m")
+                    } else {
+                        None
+                    };
+                    if let Some(flag) = flag {
+                        let end = args
+                            .get(sort + 1)
+                            .filter(|_| !args[sort].value.contains('='))
+                            .map_or(args[sort].span.end, |w| w.span.end);
+                        edits.push((args[sort].span.start..end, flag.into()));
+                    }
+                }
+                if let Some(i) = has("-ef") {
+                    problem(
+                        "BSD `ps -e` shows environments, not every process; use `ps aux`".into(),
+                    );
+                    edits.push((args[i].span.clone(), "aux".into()));
+                }
+            }
+            (Userland::Bsd, "sed") if !cfg!(target_os = "openbsd") => {
+                if let Some(i) = has("-i")
+                    && args.get(i + 1).is_none_or(|w| !w.value.is_empty())
+                {
+                    problem(
+                        "BSD `sed -i` takes a backup suffix; without one it eats the script".into(),
+                    );
+                    edits.push((args[i].span.end..args[i].span.end, " ''".into()));
+                }
+            }
+            (_, "sed") => {
+                if let Some(i) = has("-i")
+                    && args.get(i + 1).is_some_and(|w| w.value.is_empty())
+                {
+                    let sed = if cfg!(target_os = "openbsd") {
+                        "OpenBSD".to_string()
+                    } else {
+                        userland.to_string()
+                    };
+                    problem(format!(
+                        "{} `sed -i ''` reads the empty string as the script",
+                        sed
+                    ));
+                    edits.push((args[i].span.end..args[i + 1].span.end, String::new()));
+                }
+            }
+            (Userland::Bsd, "date") if has("-d").is_some() || has("--date").is_some() => {
+                problem("BSD `date` has no `-d`; use `date -j -f FORMAT DATE` or `date -v`".into());
+                if let Some(i) = has("-d")
+                    && let Some(epoch) = args.get(i + 1).and_then(|w| w.value.strip_prefix('@'))
+                {
+                    edits.push((
+                        args[i].span.start..args[i + 1].span.end,
+                        format!("-r {}", epoch),
+                    ));
+                } else if let Some(edit) = gnu_prefixed(program, &args[0].span) {
+                    edits.push(edit);
+                }
+            }
+            (Userland::Gnu, "date") if has("-v").is_some() || has("-j").is_some() => {
+                problem("GNU `date` has no `-v` or `-j`; use `date -d`".into());
+            }
+            (Userland::Bsd, "stat") if has("-c").is_some() || has("--format").is_some() => {
+                problem(
+                    "BSD `stat` formats with `-f`, using different placeholders than `-c`".into(),
+                );
+                edits.extend(gnu_prefixed(program, &args[0].span));
+            }
+            (Userland::Gnu, "stat") if has("-f").is_some() => {
+                problem("GNU `stat -f` reports on the file system, not the file; use `-c`".into());
+            }
+            (Userland::Bsd, "find") if has("-printf").is_some() => {
+                problem("BSD `find` has no `-printf`; pipe through `stat -f` instead".into());
+                edits.extend(gnu_prefixed(program, &args[0].span));
+            }
+            (Userland::Bsd, "grep") if args.iter().any(|w| w.value.starts_with("-P")) => {
+                problem("BSD `grep` has no `-P`; use `grep -E` or `perl -ne`".into());
+                edits.extend(gnu_prefixed(program, &args[0].span));
+            }
+            (Userland::Bsd, "xargs") => {
+                if let Some(i) = has("-r") {
+                    problem("BSD `xargs` has no `-r` (it already skips empty input)".into());
+                    edits.push((args[i - 1].span.end..args[i].span.end, String::new()));
+                }
+            }
+            (Userland::Bsd, "ls") => {
+                if let Some(i) = args.iter().position(|w| w.value.starts_with("--color")) {
+                    problem("BSD `ls` has no `--color`; use `-G`".into());
+                    edits.push((args[i].span.clone(), "-G".into()));
+                }
+            }
+            (Userland::Gnu, "ls") => {
+                if let Some(i) = has("-G") {
+                    problem("GNU `ls -G` hides groups instead of adding colour".into());
+                    edits.push((args[i].span.clone(), "--color=auto".into()));
+                }
+            }
+            (Userland::Bsd, "du") => {
+                if let Some(w) = args.iter().find(|w| w.value.starts_with("--max-depth=")) {
+                    problem("BSD `du` has no `--max-depth`; use `-d`".into());
+                    let depth = w.value.trim_start_matches("--max-depth=");
+                    edits.push((w.span.clone(), format!("-d {}", depth)));
+                }
+            }
+            (Userland::Bsd, "base64") => {
+                if let Some(i) = has("-w") {
+                    problem("BSD `base64` has no `-w`; it does not wrap by default".into());
+                    let end = args.get(i + 1).map_or(args[i].span.end, |w| w.span.end);
+                    edits.push((args[i - 1].span.end..end, String::new()));
+                }
+            }
+            (Userland::Bsd, "tac") => {
+                problem("`tac` is not installed on BSD; use `tail -r`".into());
+                edits.push((args[0].span.clone(), "tail -r".into()));
+            }
+            (Userland::Gnu, "tail") => {
+                if let Some(i) = has("-r") {
+                    problem("GNU `tail` has no `-r`; use `tac`".into());
+                    edits.push((args[0].span.start..args[i].span.end, "tac".into()));
+                }
+            }
+            (Userland::Bsd, "sha256sum") if !safer::on_path(program) => {
+                // Both print the sum first, then the file name.
+                let instead = if bsd.is_some() {
+                    "sha256 -r"
+                } else {
+                    "shasum -a 256"
+                };
+                problem(format!("`sha256sum` is not part of BSD; use `{}`", instead));
+                edits.push((args[0].span.clone(), instead.into()));
+            }
+            (Userland::Gnu | Userland::Busybox, "md5") => {
+                problem("`md5` is the BSD name; GNU has `md5sum`".into());
+                edits.push((args[0].span.clone(), "md5sum".into()));
+            }
+            (Userland::Gnu | Userland::Busybox, "open") => {
+                problem("`open` is macOS-only; use `xdg-open`".into());
+                edits.push((args[0].span.clone(), "xdg-open".into()));
+            }
+            (Userland::Gnu | Userland::Busybox, "pbcopy" | "pbpaste") => {
+                problem(format!(
+                    "`{}` is macOS-only; use `xclip` or `wl-copy`",
+                    program
+                ));
+            }
+            _ => {}
+        }
+
+        // Anything the checks above missed, against the applet's own help.
+        if userland == Userland::Busybox
+            && problems.len() == before
+            && program != "busybox"
+            && let Some(busybox) = busybox::detect()
+            && busybox.provides(&args[0].value)
+        {
+            let values: Vec<&str> = args[1..].iter().map(|w| w.value.as_str()).collect();
+            for flag in busybox.unsupported(program, &values) {
+                problems.push(format!("BusyBox `{}` has no `{}`", program, flag));
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        return None;
+    }
+    let corrected = (!edits.is_empty()).then(|| {
+        let mut fixed = command.to_string();
+        edits.sort_by_key(|(span, _)| std::cmp::Reverse(span.start));
+        for (span, replacement) in edits {
+            fixed.replace_range(span, &replacement);
+        }
+        fixed
+    });
+    Some(Lint {
+        problems,
+        corrected,
+    })
+}
+
+/// An edit swapping `program` for its GNU version when Homebrew installed one
+/// with a `g` prefix, e.g. `gdate`.
+fn gnu_prefixed(program: &str, span: &Range<usize>) -> Option<(Range<usize>, String)> {
+    let gnu = format!("g{}", program);
+    safer::on_path(&gnu).then(|| (span.clone(), gnu))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corrected(command: &str, userland: Userland) -> Option<String> {
+        lint(command, userland).and_then(|lint| lint.corrected)
+    }
+
+    fn problems(command: &str, userland: Userland) -> Vec<String> {
+        lint(command, userland).map_or(Vec::new(), |lint| lint.problems)
+    }
+
+    #[test]
+    fn commands_for_the_right_userland_pass() {
+        assert!(lint("ls -la --color=auto && sed -i 's/a/b/' f", Userland::Gnu).is_none());
+        assert!(lint("ls -G && sed -i '' 's/a/b/' f", Userland::Bsd).is_none());
+        assert!(lint("date -d yesterday +%F", Userland::Gnu).is_none());
+        assert!(lint("tail -r log | head", Userland::Bsd).is_none());
+    }
+
+    #[test]
+    #[cfg(not(target_os = "openbsd"))]
+    fn sed_in_place_suffix() {
+        assert_eq!(
+            corrected("sed -i 's/a/b/' f", Userland::Bsd).unwrap(),
+            "sed -i '' 's/a/b/' f"
+        );
+        assert_eq!(
+            corrected("sed -i '' 's/a/b/' f", Userland::Gnu).unwrap(),
+            "sed -i 's/a/b/' f"
+        );
+        assert_eq!(
+            problems("sed -i '' 's/a/b/' f", Userland::Gnu),
+            ["GNU `sed -i ''` reads the empty string as the script"]
+        );
+    }
+
+    #[test]
+    fn bsd_replacements() {
+        let cases = [
+            ("date -d @1700000000 +%F", "date -r 1700000000 +%F"),
+            ("ls --color=auto -l", "ls -G -l"),
+            ("du -h --max-depth=1 .", "du -h -d 1 ."),
+            ("base64 -w 0 key.pem", "base64 key.pem"),
+            ("tac access.log", "tail -r access.log"),
+            (
+                "find . -print0 | xargs -0 -r rm",
+                "find . -print0 | xargs -0 rm",
+            ),
+        ];
+        for (command, expected) in cases {
+            assert_eq!(
+                corrected(command, Userland::Bsd).as_deref(),
+                Some(expected),
+                "{}",
+                command
+            );
+        }
+    }
+
+    #[test]
+    fn gnu_replacements() {
+        let cases = [
+            ("ls -G", "ls --color=auto"),
+            ("tail -r notes.txt", "tac notes.txt"),
+            ("md5 file.iso", "md5sum file.iso"),
+            ("open report.pdf", "xdg-open report.pdf"),
+        ];
+        for (command, expected) in cases {
+            assert_eq!(
+                corrected(command, Userland::Gnu).as_deref(),
+                Some(expected),
+                "{}",
+                command
+            );
+        }
+        assert_eq!(
+            corrected("md5 file.iso", Userland::Busybox).unwrap(),
+            "md5sum file.iso"
+        );
+    }
+
+    #[test]
+    fn problems_without_a_mechanical_fix() {
+        let date = lint("date -j -f %s 1700000000", Userland::Gnu).unwrap();
+        assert_eq!(
+            date.problems,
+            ["GNU `date` has no `-v` or `-j`; use `date -d`"]
+        );
+        assert!(date.corrected.is_none());
+        let copy = lint("echo hi | pbcopy", Userland::Gnu).unwrap();
+        assert_eq!(
+            copy.problems,
+            ["`pbcopy` is macOS-only; use `xclip` or `wl-copy`"]
+        );
+        assert!(copy.corrected.is_none());
+        assert_eq!(
+            problems("stat -f %z file", Userland::Gnu),
+            ["GNU `stat -f` reports on the file system, not the file; use `-c`"]
+        );
+    }
+
+    #[test]
+    fn every_part_of_a_pipeline_is_checked() {
+        assert_eq!(
+            corrected("ls --color=always | tac > out.txt", Userland::Bsd).unwrap(),
+            "ls -G | tail -r > out.txt"
+        );
+        assert_eq!(problems("ls --color | tac", Userland::Bsd).len(), 2);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn wrappers_are_skipped() {
+        assert_eq!(
+            corrected("sudo sed -i '' 's/a/b/' /etc/hosts", Userland::Gnu).unwrap(),
+            "sudo sed -i 's/a/b/' /etc/hosts"
+        );
+        assert_eq!(
+            corrected("FOO=1 /usr/bin/tac log", Userland::Bsd).unwrap(),
+            "FOO=1 tail -r log"
+        );
+    }
+
+    #[test]
+    fn names_each_userland() {
+        assert_eq!(Userland::Gnu.to_string(), "GNU");
+        assert_eq!(Userland::Bsd.to_string(), "BSD");
+        assert_eq!(Userland::Busybox.to_string(), "BusyBox");
+        assert!(Userland::Busybox.prompt_note().contains("BusyBox"));
+        assert!(Userland::Gnu.prompt_note().contains("GNU sed"));
+    }
+}
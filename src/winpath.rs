@@ -0,0 +1,339 @@
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
+
+/// A PowerShell command with Unix or cmd conventions the model mixed in put
+/// right.
+pub struct Repair {
+    pub command: String,
+    /// What changed, e.g. "`%TEMP%` to `$env:TEMP`".
+    pub changes: Vec<String>,
+}
+
+/// PowerShell's own variables that look like environment variables in caps.
+const AUTOMATIC: &[&str] = &[
+    "ARGS",
+    "ERROR",
+    "EVENT",
+    "FALSE",
+    "FOREACH",
+    "HOME",
+    "HOST",
+    "INPUT",
+    "LASTEXITCODE",
+    "MATCHES",
+    "MYINVOCATION",
+    "NULL",
+    "OFS",
+    "PID",
+    "PROFILE",
+    "PSCOMMANDPATH",
+    "PSHOME",
+    "PSITEM",
+    "PSSCRIPTROOT",
+    "PSVERSIONTABLE",
+    "PWD",
+    "SHELLID",
+    "SWITCH",
+    "THIS",
+    "TRUE",
+];
+
+/// Unix variables whose Windows counterpart has another name.
+const RENAMED: &[(&str, &str)] = &[
+    ("USER", "USERNAME"),
+    ("TMPDIR", "TEMP"),
+    ("HOSTNAME", "COMPUTERNAME"),
+];
+
+static CMD_VARIABLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"%([A-Za-z_][A-Za-z0-9_]*)%").unwrap());
+static UNIX_VARIABLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\$(?:\{([A-Za-z_]\w*)\}|([A-Za-z_]\w*))(:?)").unwrap());
+static DEV_NULL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(&>|[0-9*]?>>?)\s*/dev/null\b").unwrap());
+static MOUNTED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(^|[\s=(,'"])/(?:mnt|cygdrive)/([a-zA-Z])((?:/[^\s"'|;&<>(),]*)?)"#).unwrap()
+});
+static SLASHED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(^|[\s=(,'"])([A-Za-z]):/([^/\s"'|;&<>(),][^\s"'|;&<>(),]*)?"#).unwrap()
+});
+/// A drive path whose directories have spaces in them, which only makes
+/// sense as one path that lost its quotes.
+static SPACED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"[A-Za-z]:\\(?:[^\\\s"'|;&<>():]+(?: [^\\\s"'|;&<>():-][^\\\s"'|;&<>():]*)*\\)+[^\\\s"'|;&<>():]*"#,
+    )
+    .unwrap()
+});
+static QUOTED: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"'[A-Za-z]:\\[^']*'").unwrap());
+
+/// Rewrite `command` for PowerShell: `%NAME%` and `$NAME` become `$env:NAME`,
+/// redirections to `/dev/null` go to `$null`, `/mnt/c/...` and `C:/...`
+/// become `C:\...`, and unquoted paths with spaces get quoted. Returns None
+/// when nothing needed changing.
+pub fn repair(command: &str) -> Option<Repair> {
+    let mut changes = Vec::new();
+    let mut command = command.to_string();
+
+    command = rewrite(&command, &CMD_VARIABLE, &mut changes, |caps, quote| {
+        (quote != Some('\'')).then(|| format!("$env:{}", &caps[1]))
+    });
+
+    let assigned = |name: &str| {
+        Regex::new(&format!(r"(?i)\${}\s*=[^=]|\(\s*\${}\s+in\b", name, name))
+            .is_ok_and(|pattern| pattern.is_match(&command))
+    };
+    let unix: Vec<String> = UNIX_VARIABLE
+        .captures_iter(&command)
+        .filter_map(|caps| caps.get(1).or(caps.get(2)))
+        .map(|name| name.as_str().to_string())
+        .filter(|name| {
+            // Environment variables are conventionally in caps; PowerShell's
+            // own variables rarely are.
+            name.len() > 1
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+                && !AUTOMATIC.contains(&name.as_str())
+                && !assigned(name)
+        })
+        .collect();
+    command = rewrite(&command, &UNIX_VARIABLE, &mut changes, |caps, quote| {
+        let (braced, name) = match (caps.get(1), caps.get(2)) {
+            (Some(name), _) => (true, name.as_str()),
+            (None, Some(name)) => (false, name.as_str()),
+            (None, None) => return None,
+        };
+        // `$ENV:PATH` is already right, and a scope like `$SCRIPT:x` is not ours.
+        if quote == Some('\'') || !caps[3].is_empty() || !unix.iter().any(|u| u == name) {
+            return None;
+        }
+        let name = RENAMED
+            .iter()
+            .find(|(unix, _)| *unix == name)
+            .map_or(name, |(_, windows)| windows);
+        Some(if braced {
+            format!("${{env:{}}}", name)
+        } else {
+            format!("$env:{}", name)
+        })
+    });
+
+    command = rewrite(&command, &DEV_NULL, &mut changes, |caps, quote| {
+        let operator = if &caps[1] == "&>" { "*>" } else { &caps[1] };
+        quote.is_none().then(|| format!("{}$null", operator))
+    });
+
+    command = rewrite(&command, &MOUNTED, &mut changes, |caps, _| {
+        Some(format!(
+            "{}{}:{}",
+            &caps[1],
+            caps[2].to_uppercase(),
+            if caps[3].is_empty() {
+                "\\".to_string()
+            } else {
+                caps[3].replace('/', "\\")
+            }
+        ))
+    });
+    command = rewrite(&command, &SLASHED, &mut changes, |caps, _| {
+        let rest = caps.get(3).map_or("", |m| m.as_str());
+        Some(format!(
+            "{}{}:\\{}",
+            &caps[1],
+            caps[2].to_uppercase(),
+            rest.replace('/', "\\")
+        ))
+    });
+
+    // A quoted path at the start of a command is just a string to PowerShell;
+    // `&` runs it.
+    let invoked = |command: &str, start: usize| {
+        let before = command[..start].trim_end();
+        before.is_empty() || before.ends_with([';', '|']) || before.ends_with("&&")
+    };
+    let unquoted = command.clone();
+    command = rewrite(&command, &SPACED, &mut changes, |caps, quote| {
+        let path = caps.get(0)?;
+        if quote.is_some() || !path.as_str().contains(' ') {
+            return None;
+        }
+        let quoted = format!("'{}'", path.as_str().replace('\'', "''"));
+        Some(if invoked(&unquoted, path.start()) {
+            format!("& {}", quoted)
+        } else {
+            quoted
+        })
+    });
+    let quoted = command.clone();
+    command = rewrite(&command, &QUOTED, &mut changes, |caps, quote| {
+        let path = caps.get(0)?;
+        (quote.is_none() && invoked(&quoted, path.start())).then(|| format!("& {}", path.as_str()))
+    });
+
+    (!changes.is_empty()).then_some(Repair { command, changes })
+}
+
+/// Replace each match of `pattern` that `fix` has a replacement for, given the
+/// quote it starts inside, noting what changed.
+fn rewrite(
+    command: &str,
+    pattern: &Regex,
+    changes: &mut Vec<String>,
+    fix: impl Fn(&Captures, Option<char>) -> Option<String>,
+) -> String {
+    pattern
+        .replace_all(command, |caps: &Captures| {
+            let whole = &caps[0];
+            let start = caps.get(0).map_or(0, |m| m.start());
+            match fix(caps, quote_at(command, start)) {
+                Some(fixed) if fixed != whole => {
+                    let change = format!("`{}` to `{}`", whole.trim(), fixed.trim());
+                    if !changes.contains(&change) {
+                        changes.push(change);
+                    }
+                    fixed
+                }
+                _ => whole.to_string(),
+            }
+        })
+        .into_owned()
+}
+
+/// The quote character PowerShell is inside at byte `index`, if any.
+fn quote_at(command: &str, index: usize) -> Option<char> {
+    let mut quote = None;
+    let mut chars = command[..index].chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, '`') | (Some('"'), '`') => {
+                chars.next();
+            }
+            (None, '\'' | '"') => quote = Some(c),
+            (Some('\''), '\'') if chars.peek() == Some(&'\'') => {
+                chars.next();
+            }
+            (Some(q), c) if c == q => quote = None,
+            _ => {}
+        }
+    }
+    quote
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repaired(command: &str) -> String {
+        repair(command).map_or_else(|| command.to_string(), |r| r.command)
+    }
+
+    #[test]
+    fn cmd_variables() {
+        assert_eq!(repaired("cd %TEMP%"), "cd $env:TEMP");
+        assert_eq!(
+            repaired("Copy-Item \"%USERPROFILE%\\a.txt\" ."),
+            "Copy-Item \"$env:USERPROFILE\\a.txt\" ."
+        );
+        // Single quotes keep it literal, as the model meant.
+        assert_eq!(repaired("echo '%PATH%'"), "echo '%PATH%'");
+    }
+
+    #[test]
+    fn unix_variables() {
+        assert_eq!(repaired("echo $HOME_DIR"), "echo $env:HOME_DIR");
+        assert_eq!(repaired("echo ${APPDATA}"), "echo ${env:APPDATA}");
+        assert_eq!(
+            repaired("echo $USER on $HOSTNAME"),
+            "echo $env:USERNAME on $env:COMPUTERNAME"
+        );
+        assert_eq!(repaired("cd $TMPDIR"), "cd $env:TEMP");
+    }
+
+    #[test]
+    fn powershell_variables_are_left_alone() {
+        for command in [
+            "echo $env:PATH",
+            "$ENV:PATH -split ';'",
+            "Get-ChildItem | ForEach-Object { $_.Name }",
+            "echo $PSVersionTable $PWD $HOME $LASTEXITCODE",
+            "$files = Get-ChildItem; $files.Count",
+            "$N = 3; 1..$N",
+            "foreach ($ITEM in $list) { $ITEM }",
+            "echo '$TEMP'",
+            "$script:COUNT += 1",
+            "echo $x",
+        ] {
+            assert!(repair(command).is_none(), "{}", command);
+        }
+    }
+
+    #[test]
+    fn dev_null() {
+        assert_eq!(repaired("git fetch > /dev/null"), "git fetch >$null");
+        assert_eq!(repaired("git fetch 2>/dev/null"), "git fetch 2>$null");
+        assert_eq!(repaired("make &> /dev/null"), "make *>$null");
+        assert_eq!(repaired("echo '> /dev/null'"), "echo '> /dev/null'");
+    }
+
+    #[test]
+    fn unix_style_drive_paths() {
+        assert_eq!(repaired("cd /mnt/c/Users/me"), "cd C:\\Users\\me");
+        assert_eq!(repaired("ls /cygdrive/d"), "ls D:\\");
+        assert_eq!(
+            repaired("cd c:/Windows/System32"),
+            "cd C:\\Windows\\System32"
+        );
+        assert_eq!(
+            repaired("Copy-Item a.txt -Destination \"d:/backup/\""),
+            "Copy-Item a.txt -Destination \"D:\\backup\\\""
+        );
+        // URLs aren't drive paths.
+        assert!(repair("Invoke-WebRequest https://example.com/a").is_none());
+    }
+
+    #[test]
+    fn paths_with_spaces_are_quoted() {
+        assert_eq!(
+            repaired("Get-ChildItem C:\\Program Files\\Git"),
+            "Get-ChildItem 'C:\\Program Files\\Git'"
+        );
+        assert_eq!(
+            repaired("C:\\Program Files\\Git\\bin\\git.exe --version"),
+            "& 'C:\\Program Files\\Git\\bin\\git.exe' --version"
+        );
+        assert!(repair("Get-ChildItem 'C:\\Program Files\\Git'").is_none());
+        assert!(repair("Get-ChildItem \"C:\\Program Files\\Git\"").is_none());
+    }
+
+    #[test]
+    fn quoted_programs_are_invoked() {
+        assert_eq!(
+            repaired("'C:\\Tools\\my tool.exe' -v"),
+            "& 'C:\\Tools\\my tool.exe' -v"
+        );
+        assert_eq!(
+            repaired("cd C:\\; 'C:\\Tools\\tool.exe'"),
+            "cd C:\\; & 'C:\\Tools\\tool.exe'"
+        );
+        assert!(repair("Write-Output 'C:\\Tools\\tool.exe'").is_none());
+    }
+
+    #[test]
+    fn changes_are_listed_once() {
+        let repair = repair("echo %TEMP% %TEMP% > /dev/null").unwrap();
+        assert_eq!(repair.command, "echo $env:TEMP $env:TEMP >$null");
+        assert_eq!(
+            repair.changes,
+            ["`%TEMP%` to `$env:TEMP`", "`> /dev/null` to `>$null`"]
+        );
+    }
+
+    #[test]
+    fn quotes_are_tracked() {
+        assert_eq!(quote_at("echo 'a", 7), Some('\''));
+        assert_eq!(quote_at("echo 'it''s", 11), Some('\''));
+        assert_eq!(quote_at("echo \"a`\"b", 10), Some('"'));
+        assert_eq!(quote_at("echo 'a' b", 10), None);
+    }
+}
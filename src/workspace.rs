@@ -0,0 +1,184 @@
+use crate::preflight;
+use std::path::{Component, Path, PathBuf};
+
+/// Paths `command` would modify that lie outside every tree in `allowed`.
+/// Paths that can't be worked out statically, such as ones built from
+/// variables, count as outside, and so do writes whose target can't be told
+/// at all, such as a script run by an interpreter.
+pub fn outside(command: &str, allowed: &[String]) -> Vec<String> {
+    let Ok(cwd) = std::env::current_dir() else {
+        return Vec::new();
+    };
+    let roots: Vec<PathBuf> = allowed
+        .iter()
+        .filter_map(|root| expand(root))
+        .map(|root| resolve(&cwd.join(root)))
+        .collect();
+    let mut found: Vec<String> = Vec::new();
+    for path in preflight::written(command) {
+        let text = path.to_string_lossy().into_owned();
+        let inside = expand(&text)
+            .map(|p| resolve(&cwd.join(p)))
+            .is_some_and(|p| roots.iter().any(|root| p.starts_with(root)));
+        if !inside && !found.contains(&text) {
+            found.push(text);
+        }
+    }
+    for unknown in preflight::unknown_writes(command) {
+        let text = format!("wherever {} writes", unknown);
+        if !found.contains(&text) {
+            found.push(text);
+        }
+    }
+    found
+}
+
+/// Expand `~` and `$HOME`, returning `None` for a path that uses any other
+/// variable or substitution.
+fn expand(path: &str) -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let (base, rest) = if path == "~" || path == "$HOME" {
+        (home?, "")
+    } else if let Some(rest) = path
+        .strip_prefix("~/")
+        .or_else(|| path.strip_prefix("$HOME/"))
+    {
+        (home?, rest)
+    } else {
+        (PathBuf::new(), path)
+    };
+    if rest.contains(['$', '`']) {
+        return None;
+    }
+    Some(base.join(rest))
+}
+
+/// Where `path` really is: its longest existing ancestor with symlinks
+/// followed, so a link inside an allowed tree can't lead out of it, and the
+/// rest, which may not exist yet, as written.
+fn resolve(path: &Path) -> PathBuf {
+    let path = normalize(path);
+    for ancestor in path.ancestors() {
+        if let Ok(real) = std::fs::canonicalize(ancestor) {
+            let rest = path.strip_prefix(ancestor).unwrap_or(Path::new(""));
+            return if rest.as_os_str().is_empty() {
+                real
+            } else {
+                real.join(rest)
+            };
+        }
+    }
+    path
+}
+
+/// Resolve `.` and `..` without touching the filesystem, since the path may
+/// not exist yet.
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                result.pop();
+            }
+            other => result.push(other),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmdline;
+
+    fn outside_tmp(command: &str) -> Vec<String> {
+        outside(command, &["/tmp".to_string()])
+    }
+
+    #[test]
+    fn paths_written_by_name() {
+        assert_eq!(outside_tmp("echo x > /etc/x"), ["/etc/x"]);
+        assert_eq!(outside_tmp("rm -rf /var/log/old"), ["/var/log/old"]);
+        assert!(outside_tmp("touch /tmp/x && echo y >> /tmp/y").is_empty());
+        assert_eq!(outside_tmp("touch /tmp/../etc/x"), ["/tmp/../etc/x"]);
+    }
+
+    #[test]
+    fn targets_given_as_options() {
+        assert_eq!(outside_tmp("find /etc -name '*.bak' -delete"), ["/etc"]);
+        assert_eq!(
+            outside_tmp("dd if=/dev/zero of=/etc/x bs=1M count=1"),
+            ["/etc/x"]
+        );
+        assert_eq!(outside_tmp("cp -t /etc a"), ["/etc"]);
+        assert_eq!(
+            outside_tmp("curl -sSLo /etc/x https://example.com/x"),
+            ["/etc/x"]
+        );
+        assert_eq!(
+            outside_tmp("wget -O /etc/x https://example.com/x"),
+            ["/etc/x"]
+        );
+        assert_eq!(outside_tmp("tar -xf a.tar -C /etc"), ["/etc"]);
+        assert!(outside_tmp("curl -o /tmp/x https://example.com/x").is_empty());
+        assert!(outside_tmp("tar -xf a.tar -C /tmp/out").is_empty());
+    }
+
+    #[test]
+    fn scripts_given_to_a_shell() {
+        assert_eq!(
+            outside_tmp("sudo sh -c 'echo x > /etc/motd'"),
+            ["/etc/motd"]
+        );
+        assert_eq!(outside_tmp("bash -ec 'rm -rf /opt/app'"), ["/opt/app"]);
+        assert!(outside_tmp("sh -c 'echo x > /tmp/x'").is_empty());
+    }
+
+    #[test]
+    fn writes_that_cant_be_told() {
+        assert_eq!(
+            outside_tmp("perl -pi -e 's/a/b/' /tmp/f"),
+            ["wherever `perl` writes"]
+        );
+        assert_eq!(
+            outside_tmp("find /tmp -exec rm {} +"),
+            ["wherever `find -exec` writes"]
+        );
+        assert_eq!(
+            outside_tmp("echo $(rm -rf x)"),
+            ["wherever `$(...)` writes"]
+        );
+        assert_eq!(outside_tmp("echo x > $DIR/x"), ["$DIR/x"]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn symlinks_are_followed() {
+        let outside_dir = tempfile::tempdir().unwrap();
+        let root = tempfile::tempdir().unwrap();
+        let link = root.path().join("x");
+        std::os::unix::fs::symlink(outside_dir.path(), &link).unwrap();
+        let allowed = [root.path().display().to_string()];
+
+        let through_link = format!("rm -rf {}/passwd", link.display());
+        assert_eq!(outside(&through_link, &allowed).len(), 1);
+        let new_file = format!("touch {}/new/file", root.path().display());
+        assert!(outside(&new_file, &allowed).is_empty());
+
+        // A root given through a link allows the tree it points at.
+        let allowed = [link.display().to_string()];
+        let real = format!("touch {}/file", outside_dir.path().display());
+        assert!(outside(&real, &allowed).is_empty());
+    }
+
+    #[test]
+    fn the_sudo_version_is_checked_too() {
+        let command = "echo x > /etc/motd";
+        let segments = cmdline::segments(command);
+        let adapted = preflight::with_sudo(command, &[&segments[0]]);
+        assert_eq!(adapted, "sudo sh -c 'echo x > /etc/motd'");
+        assert_eq!(outside_tmp(&adapted), ["/etc/motd"]);
+        assert!(!crate::readonly::violations(&adapted).is_empty());
+    }
+}
@@ -0,0 +1,132 @@
+use crate::safer;
+use regex::{Captures, Regex};
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, OnceLock};
+
+/// The Windows Subsystem for Linux, when llmwrap runs inside it.
+pub struct Wsl {
+    /// The distribution's name, e.g. `Ubuntu-24.04`.
+    pub distro: Option<String>,
+    /// Where Windows drives are mounted, `/mnt/` unless `/etc/wsl.conf` says
+    /// otherwise.
+    pub mount_root: String,
+}
+
+static DETECTED: OnceLock<Option<Wsl>> = OnceLock::new();
+static WINDOWS_HOST: AtomicBool = AtomicBool::new(false);
+
+static WINDOWS_PATH: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#""([A-Za-z]):[\\/]([^"]*)"|'([A-Za-z]):[\\/]([^']*)'|\b([A-Za-z]):\\(\S*)"#)
+        .unwrap()
+});
+
+/// Remember whether commands go to the Windows host.
+pub fn init(windows_host: bool) {
+    WINDOWS_HOST.store(windows_host, Ordering::Relaxed);
+}
+
+pub fn windows_host() -> bool {
+    WINDOWS_HOST.load(Ordering::Relaxed)
+}
+
+/// WSL, if this is running inside it.
+pub fn detect() -> Option<&'static Wsl> {
+    DETECTED
+        .get_or_init(|| {
+            if !cfg!(target_os = "linux") {
+                return None;
+            }
+            let interop = Path::new("/proc/sys/fs/binfmt_misc/WSLInterop").exists();
+            let kernel = fs::read_to_string("/proc/sys/kernel/osrelease")
+                .is_ok_and(|release| release.to_lowercase().contains("microsoft"));
+            let distro = std::env::var("WSL_DISTRO_NAME").ok();
+            if !interop && !kernel && distro.is_none() {
+                return None;
+            }
+            Some(Wsl {
+                distro,
+                mount_root: mount_root(),
+            })
+        })
+        .as_ref()
+}
+
+impl Wsl {
+    /// What the system prompt should say about running under WSL.
+    pub fn prompt_note(&self) -> String {
+        if windows_host() {
+            let share = match &self.distro {
+                Some(distro) => format!("\\\\wsl.localhost\\{}", distro),
+                None => "\\\\wsl.localhost\\<distro>".to_string(),
+            };
+            return format!(
+                "The command runs on the Windows side of WSL, in Windows PowerShell \
+(powershell.exe) started from the Linux side. Files in the Linux file system are under {}; \
+Windows drives are C:\\ and so on as usual.",
+                share
+            );
+        }
+        let c = format!("{}c", self.mount_root);
+        let opener = if safer::on_path("wslview") {
+            "wslview"
+        } else {
+            "explorer.exe"
+        };
+        format!(
+            "The command runs in Linux under WSL (Windows Subsystem for Linux). Windows drives are \
+mounted under {} (C:\\ is {}), so use those paths for Windows files. Windows programs can be run \
+by their .exe name: use clip.exe to copy to the clipboard, {} to open files and URLs in Windows, \
+and powershell.exe only for what needs Windows itself.",
+            self.mount_root, c, opener
+        )
+    }
+
+    /// `text` with Windows paths such as `C:\Users\me` written as the Linux
+    /// paths they are mounted at, e.g. `/mnt/c/Users/me`.
+    pub fn linux_paths(&self, text: &str) -> String {
+        WINDOWS_PATH
+            .replace_all(text, |caps: &Captures| {
+                let (drive, rest, quote) = if let Some(drive) = caps.get(1) {
+                    (drive.as_str(), &caps[2], "\"")
+                } else if let Some(drive) = caps.get(3) {
+                    (drive.as_str(), &caps[4], "'")
+                } else {
+                    (&caps[5], &caps[6], "")
+                };
+                format!(
+                    "{}{}{}/{}{}",
+                    quote,
+                    self.mount_root,
+                    drive.to_lowercase(),
+                    rest.replace('\\', "/"),
+                    quote
+                )
+            })
+            .into_owned()
+    }
+}
+
+/// The automount root from `/etc/wsl.conf`, with a trailing slash.
+fn mount_root() -> String {
+    let mut root = "/mnt/".to_string();
+    let Ok(text) = fs::read_to_string("/etc/wsl.conf") else {
+        return root;
+    };
+    let mut automount = false;
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            automount = line.eq_ignore_ascii_case("[automount]");
+        } else if automount
+            && let Some((key, value)) = line.split_once('=')
+            && key.trim() == "root"
+        {
+            root = value.trim().trim_matches('"').to_string();
+            if !root.ends_with('/') {
+                root.push('/');
+            }
+        }
+    }
+    root
+}
@@ -0,0 +1,488 @@
+//! Runs the llmwrap binary end to end against `llmwrap::fakeserver`, from the
+//! request it builds to what it prints, without the network.
+
+use llmwrap::fakeserver::FakeServer;
+use serde_json::Value;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Child, Command, Output, Stdio};
+
+/// A home directory of its own for one run of llmwrap, removed afterwards.
+struct Home(PathBuf);
+
+impl Home {
+    fn new(name: &str, config: &str) -> Home {
+        let home =
+            std::env::temp_dir().join(format!("llmwrap-cli-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&home);
+        fs::create_dir_all(home.join("config/llmwrap")).unwrap();
+        fs::write(home.join("config/llmwrap/config.toml"), config).unwrap();
+        Home(home)
+    }
+
+    /// Run llmwrap against `server` with `args`, piping in `stdin`.
+    fn run(&self, server: &FakeServer, args: &[&str], stdin: &str) -> Output {
+        let mut child = self.spawn(server, args);
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(stdin.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    /// Start llmwrap against `server` with `args`, with its stdin, stdout and
+    /// stderr piped.
+    fn spawn(&self, server: &FakeServer, args: &[&str]) -> Child {
+        Command::new(env!("CARGO_BIN_EXE_llmwrap"))
+            .args(["--provider", "openai", "--api-base", server.base_url()])
+            .args(args)
+            .env_clear()
+            .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+            .env("HOME", &self.0)
+            .env("XDG_CONFIG_HOME", self.0.join("config"))
+            .env("XDG_DATA_HOME", self.0.join("data"))
+            .env("XDG_CACHE_HOME", self.0.join("cache"))
+            .env("XDG_STATE_HOME", self.0.join("state"))
+            .env("LLMWRAP_OPENAI_API_KEY", "sk-fake")
+            .current_dir(&self.0)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap()
+    }
+}
+
+impl Drop for Home {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "llmwrap failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+#[test]
+fn streams_the_command() {
+    let server = FakeServer::start(&["find . -name '*.tmp' -delete"]).unwrap();
+    let home = Home::new("stream", "structured_output = false\n");
+    let output = home.run(&server, &["--print", "delete the tmp files"], "");
+    assert_eq!(stdout(&output), "find . -name '*.tmp' -delete");
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0]["stream"], Value::Bool(true));
+    assert!(requests[0].to_string().contains("delete the tmp files"));
+}
+
+#[test]
+fn reports_the_structured_answer() {
+    let answer = r#"{"command":"rm -rf build","explanation":"Deletes the build directory.","risk_level":"high"}"#;
+    let server = FakeServer::start(&[answer]).unwrap();
+    let home = Home::new("json", "");
+    let output = home.run(&server, &["--json", "remove the build dir"], "");
+    let printed: Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(printed["command"], "rm -rf build");
+    assert_eq!(printed["explanation"], "Deletes the build directory.");
+    assert_eq!(printed["risk"], "high");
+    assert_eq!(
+        server.requests()[0].pointer("/text/format/type"),
+        Some(&Value::from("json_schema"))
+    );
+}
+
+#[test]
+fn summarizes_long_output_in_parts() {
+    let server = FakeServer::start(&["part", "part", "part", "The build failed."]).unwrap();
+    let home = Home::new(
+        "summarize",
+        "[summarize]\nchunk_chars = 1000\nparallel = 1\n",
+    );
+    let log = "compiling crate one of many\n".repeat(100);
+    let output = home.run(&server, &["summarize"], &log);
+    assert_eq!(stdout(&output), "The build failed.");
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 4);
+    assert_eq!(requests[0]["model"], "gpt-5-mini");
+    assert_ne!(requests[0]["stream"], Value::Bool(true));
+    assert!(requests[3].to_string().contains("summaries of its parts"));
+}
+
+#[test]
+fn retries_when_rate_limited() {
+    let server = FakeServer::start(&["uptime"]).unwrap();
+    server.fail_next(429, 2);
+    let home = Home::new("retry", "structured_output = false\n");
+    let output = home.run(&server, &["--print", "how long has it been up"], "");
+    assert_eq!(stdout(&output), "uptime");
+    assert_eq!(server.requests().len(), 3);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("retrying in 0.0s (2 of 4)"), "{}", stderr);
+}
+
+#[test]
+fn gives_up_after_the_retries() {
+    let server = FakeServer::start(&["uptime"]).unwrap();
+    server.fail_next(503, 3);
+    let home = Home::new(
+        "give-up",
+        "structured_output = false\n[network]\nretries = 2\n",
+    );
+    let output = home.run(&server, &["--print", "how long has it been up"], "");
+    assert!(!output.status.success());
+    assert_eq!(server.requests().len(), 3);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Gave up after 2 retries"), "{}", stderr);
+    assert!(stderr.contains("returned 503"), "{}", stderr);
+}
+
+#[test]
+fn falls_back_to_the_next_model() {
+    let primary = FakeServer::start(&["unused"]).unwrap();
+    primary.fail_next(503, 1);
+    let fallback = FakeServer::start(&["uptime"]).unwrap();
+    let home = Home::new(
+        "fallback",
+        &format!(
+            "structured_output = false\n[network]\nretries = 0\n\n[[fallbacks]]\nmodel = \"backup\"\napi_base = \"{}\"\n",
+            fallback.base_url()
+        ),
+    );
+    let output = home.run(&primary, &["--print", "how long has it been up"], "");
+    assert_eq!(stdout(&output), "uptime");
+    assert_eq!(fallback.requests()[0]["model"], "backup");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("asking backup instead"), "{}", stderr);
+    assert!(stderr.contains("This answer is from backup."), "{}", stderr);
+}
+
+#[test]
+fn asks_the_fallback_model_at_once_when_the_first_fails() {
+    let server = FakeServer::start(&["uptime"]).unwrap();
+    server.fail_next(503, 1);
+    let home = Home::new(
+        "latency-fallback",
+        "structured_output = false\n[network]\nretries = 0\n\n[latency]\nfallback_model = \"backup\"\nfallback_after_ms = 60000\n",
+    );
+    let began = std::time::Instant::now();
+    let output = home.run(&server, &["--print", "how long has it been up"], "");
+    assert_eq!(stdout(&output), "uptime");
+    assert!(began.elapsed() < std::time::Duration::from_secs(30));
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1]["model"], "backup");
+    let printed = String::from_utf8_lossy(&output.stdout);
+    assert!(!printed.contains("slow to answer"), "{}", printed);
+}
+
+#[test]
+fn attaches_piped_input_and_files() {
+    let server = FakeServer::start(&["grep -c ' 500 ' access.log"]).unwrap();
+    let home = Home::new(
+        "attach",
+        "structured_output = false\n[attach]\nmax_bytes = 400\n",
+    );
+    fs::write(home.0.join("notes.txt"), "the API runs behind nginx\n").unwrap();
+    let log: String = (1..=100)
+        .map(|n| format!("GET /item/{} 200 ok\n", n))
+        .collect();
+    let output = home.run(
+        &server,
+        &["--print", "-f", "notes.txt", "count the failing requests"],
+        &log,
+    );
+    assert_eq!(stdout(&output), "grep -c ' 500 ' access.log");
+
+    let instructions = server.requests()[0]["input"][0].to_string();
+    assert!(instructions.contains("[notes.txt]"), "{}", instructions);
+    assert!(instructions.contains("the API runs behind nginx"));
+    assert!(instructions.contains("[stdin]"));
+    assert!(instructions.contains("GET /item/1 200 ok"));
+    assert!(instructions.contains("GET /item/100 200 ok"));
+    assert!(!instructions.contains("GET /item/50 200 ok"));
+    assert!(instructions.contains("lines left out"), "{}", instructions);
+}
+
+#[test]
+fn stops_the_command_on_timeout() {
+    let server = FakeServer::start(&["sleep 30"]).unwrap();
+    let home = Home::new("timeout", "structured_output = false\n");
+    let started = std::time::Instant::now();
+    let output = home.run(&server, &["--yes", "--timeout", "1", "wait a while"], "");
+    assert!(started.elapsed().as_secs() < 10);
+    assert_eq!(output.status.code(), Some(124));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Timed out after 1s"), "{}", stderr);
+    let history = fs::read_to_string(home.0.join("data/llmwrap/history.jsonl")).unwrap();
+    assert!(
+        history.contains(r#""command":"sleep 30","executed":true"#),
+        "{}",
+        history
+    );
+}
+
+#[test]
+fn runs_at_once_keep_every_entry() {
+    let server = FakeServer::start(&["uptime"]).unwrap();
+    let home = Home::new("at-once", "structured_output = false\n");
+    std::thread::scope(|scope| {
+        for n in 0..8 {
+            let (home, server) = (&home, &server);
+            scope.spawn(move || {
+                let request = format!("how long has host {} been up", n);
+                stdout(&home.run(server, &["--print", &request], ""));
+            });
+        }
+    });
+
+    let history = fs::read_to_string(home.0.join("data/llmwrap/history.jsonl")).unwrap();
+    assert_eq!(history.lines().count(), 8);
+    for line in history.lines() {
+        serde_json::from_str::<Value>(line).unwrap();
+    }
+    let cache = fs::read_to_string(home.0.join("cache/llmwrap/responses.json")).unwrap();
+    let cache: Value = serde_json::from_str(&cache).unwrap();
+    assert_eq!(cache["entries"].as_array().unwrap().len(), 8);
+}
+
+#[test]
+fn fills_in_the_prompt_template() {
+    let server = FakeServer::start(&["kubectl get pods"]).unwrap();
+    let home = Home::new(
+        "template",
+        "structured_output = false\nsystem_prompt = \"Answer for our hosts.\"\n\n[templates.k8s]\nprompt = \"{base} Use kubectl from {shell}, as in `find . -exec cat {} +` or ${KUBECONFIG}.\"\n",
+    );
+    let output = home.run(
+        &server,
+        &["--print", "--template", "k8s", "list the pods"],
+        "",
+    );
+    assert_eq!(stdout(&output), "kubectl get pods");
+    let instructions = server.requests()[0]["input"][0].to_string();
+    assert!(
+        instructions.contains(
+            "Answer for our hosts. Use kubectl from sh, as in `find . -exec cat {} +` or ${KUBECONFIG}."
+        ),
+        "{}",
+        instructions
+    );
+
+    let output = home.run(&server, &["--print", "--template", "helm", "list"], "");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("there are docker, git, k8s"), "{}", stderr);
+}
+
+#[test]
+fn repl_applies_config_changes() {
+    let server = FakeServer::start(&["uptime"]).unwrap();
+    let home = Home::new("reload", "structured_output = false\n");
+    let config = home.0.join("config/llmwrap/config.toml");
+    let mut child = home.spawn(&server, &["--interactive", "--repl"]);
+    let mut stdin = child.stdin.take().unwrap();
+    // Each request is skipped with `q` once its command is shown.
+    let mut ask = |request: &str, answered: usize| {
+        writeln!(stdin, "{}\nq", request).unwrap();
+        let started = std::time::Instant::now();
+        while server.requests().len() < answered {
+            assert!(
+                started.elapsed().as_secs() < 10,
+                "no request for {}",
+                request
+            );
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+    };
+    ask("how long has it been up", 1);
+    fs::write(
+        &config,
+        "structured_output = false\nsystem_prompt = \"Answer for our hosts.\"\n",
+    )
+    .unwrap();
+    ask("and now", 2);
+    fs::write(&config, "structured_output = maybe\n").unwrap();
+    ask("and again", 3);
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+
+    let requests = server.requests();
+    let prompts: Vec<String> = requests
+        .iter()
+        .map(|request| request["input"][0].to_string())
+        .collect();
+    assert!(!prompts[0].contains("Answer for our hosts."));
+    assert!(
+        prompts[1].contains("Answer for our hosts."),
+        "{}",
+        prompts[1]
+    );
+    assert!(
+        prompts[2].contains("Answer for our hosts."),
+        "{}",
+        prompts[2]
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Applied the changes to config.toml."),
+        "{}",
+        stdout
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("kept the settings from before: Invalid config file"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn repl_reload_keeps_the_command_line() {
+    let server = FakeServer::start(&["uptime"]).unwrap();
+    let home = Home::new(
+        "reload-profile",
+        "structured_output = false\n[profiles.work]\nmodel = \"model-one\"\n",
+    );
+    let config = home.0.join("config/llmwrap/config.toml");
+    let args = [
+        "--interactive",
+        "--repl",
+        "--profile",
+        "work",
+        "--system-prompt",
+        "Flag prompt.",
+    ];
+    let mut child = home.spawn(&server, &args);
+    let mut stdin = child.stdin.take().unwrap();
+    let mut ask = |request: &str, answered: usize| {
+        writeln!(stdin, "{}\nq", request).unwrap();
+        let started = std::time::Instant::now();
+        while server.requests().len() < answered {
+            assert!(
+                started.elapsed().as_secs() < 10,
+                "no request for {}",
+                request
+            );
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+    };
+    ask("how long has it been up", 1);
+    fs::write(
+        &config,
+        "structured_output = false\nsystem_prompt = \"Config prompt.\"\n[profiles.work]\nmodel = \"model-two\"\nshell = \"bash\"\n",
+    )
+    .unwrap();
+    ask("and now", 2);
+    drop(stdin);
+    child.wait_with_output().unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests[0]["model"], "model-one");
+    assert_eq!(requests[1]["model"], "model-two");
+    let prompt = requests[1]["input"][0].to_string();
+    assert!(prompt.contains("Flag prompt."), "{}", prompt);
+    assert!(!prompt.contains("Config prompt."), "{}", prompt);
+    assert!(
+        prompt.contains("The command will run in bash."),
+        "{}",
+        prompt
+    );
+}
+
+#[test]
+fn refuses_root_shell_writes_outside_the_workspace() {
+    let server = FakeServer::start(&["sudo sh -c 'echo hi > /etc/motd'"]).unwrap();
+    let home = Home::new(
+        "workspace",
+        "structured_output = false\nworkspace = [\"/tmp\"]\n",
+    );
+    let output = home.run(&server, &["--yes", "greet everyone at login"], "");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("outside the workspace"), "{}", stderr);
+
+    let output = home.run(
+        &server,
+        &["--print", "--read-only", "greet everyone at login"],
+        "",
+    );
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Read-only mode refuses"), "{}", stderr);
+}
+
+#[test]
+fn requests_can_start_with_a_subcommand_name() {
+    let server = FakeServer::start(&["ffmpeg -i video.mp4 video.gif"]).unwrap();
+    let home = Home::new("subcommand-words", "structured_output = false\n");
+    let output = home.run(
+        &server,
+        &["--print", "make", "a", "gif", "out", "of", "video.mp4"],
+        "",
+    );
+    assert_eq!(stdout(&output), "ffmpeg -i video.mp4 video.gif");
+    assert!(
+        server.requests()[0]
+            .to_string()
+            .contains("make a gif out of video.mp4")
+    );
+
+    let output = home.run(&server, &["--print", "history", "of", "git", "tags"], "");
+    stdout(&output);
+    assert!(
+        server.requests()[1]
+            .to_string()
+            .contains("history of git tags")
+    );
+
+    let output = home.run(&server, &["--print", "--", "explain", "the", "load"], "");
+    stdout(&output);
+    assert!(
+        server.requests()[2]
+            .to_string()
+            .contains("explain the load")
+    );
+
+    let output = home.run(&server, &["history", "--serch", "git"], "");
+    assert!(!output.status.success());
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[test]
+fn runs_only_exact_matches_without_a_terminal() {
+    let server = FakeServer::start(&["echo first", "echo second"]).unwrap();
+    let home = Home::new("similar", "structured_output = false\n");
+    let first = home.run(&server, &["--yes", "compress the log files"], "");
+    assert!(stdout(&first).contains("first"));
+    let second = home.run(&server, &["--yes", "compress log files"], "");
+    assert!(stdout(&second).contains("second"));
+    assert_eq!(server.requests().len(), 2);
+    let again = home.run(&server, &["--yes", "compress log files"], "");
+    assert!(stdout(&again).contains("second"));
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn scripts_stop_at_the_first_failure() {
+    let server = FakeServer::start(&["echo first\nfalse\necho reached"]).unwrap();
+    let home = Home::new("script", "structured_output = false\n");
+    let output = home.run(
+        &server,
+        &["--yes", "--shell", "sh", "set up the project"],
+        "",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    let printed = String::from_utf8_lossy(&output.stdout);
+    assert!(printed.contains("first"), "{}", printed);
+    assert!(!printed.contains("reached"), "{}", printed);
+}
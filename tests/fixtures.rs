@@ -0,0 +1,303 @@
+//! Replays recorded provider responses from `tests/fixtures` to the llmwrap
+//! binary through a local HTTP server, and checks the command it prints or
+//! the error it fails with. Each fixture is a real reply with ids and keys
+//! scrubbed; add one whenever a provider changes the shape of its answers.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::sync::mpsc;
+use std::thread;
+
+struct Fixture {
+    provider: &'static str,
+    file: &'static str,
+    status: u16,
+    /// Ask for a streamed answer instead of a single response.
+    stream: bool,
+    /// The command llmwrap should print.
+    expected: Option<&'static str>,
+    /// Text the error should contain when the request fails.
+    error: Option<&'static str>,
+}
+
+const fn command(provider: &'static str, file: &'static str, expected: &'static str) -> Fixture {
+    Fixture {
+        provider,
+        file,
+        status: 200,
+        stream: false,
+        expected: Some(expected),
+        error: None,
+    }
+}
+
+const fn streamed(provider: &'static str, file: &'static str, expected: &'static str) -> Fixture {
+    Fixture {
+        stream: true,
+        ..command(provider, file, expected)
+    }
+}
+
+const fn failure(
+    provider: &'static str,
+    file: &'static str,
+    status: u16,
+    error: &'static str,
+) -> Fixture {
+    Fixture {
+        provider,
+        file,
+        status,
+        stream: false,
+        expected: None,
+        error: Some(error),
+    }
+}
+
+const FIXTURES: &[Fixture] = &[
+    command(
+        "openai",
+        "openai/responses-reasoning.json",
+        "du -sh -- * | sort -h",
+    ),
+    command(
+        "openai",
+        "openai/responses-fenced.json",
+        "grep -rn --include='*.rs' 'TODO' .",
+    ),
+    command(
+        "openai",
+        "openai/responses-output-text.json",
+        "wc -l -- *.txt",
+    ),
+    command(
+        "openai",
+        "openai/responses-structured.json",
+        "git log --oneline -n 5",
+    ),
+    command(
+        "openai",
+        "openai/responses-incomplete.json",
+        "find . -name '*.log' -mtime +7",
+    ),
+    streamed(
+        "openai",
+        "openai/responses-stream.sse",
+        "tar -czf logs.tar.gz logs/",
+    ),
+    failure(
+        "openai",
+        "openai/error-invalid-key.json",
+        401,
+        "Incorrect API key provided",
+    ),
+    command(
+        "anthropic",
+        "anthropic/messages-basic.json",
+        "ls -lt | head -n 10",
+    ),
+    command(
+        "anthropic",
+        "anthropic/messages-thinking.json",
+        "find . -type f -printf '%s %p\\n' | sort -nr | head -n 5",
+    ),
+    streamed(
+        "anthropic",
+        "anthropic/messages-stream.sse",
+        "df -h --total",
+    ),
+    failure(
+        "anthropic",
+        "anthropic/error-overloaded.json",
+        529,
+        "Overloaded",
+    ),
+    command(
+        "ollama",
+        "ollama/chat-basic.json",
+        "ps aux --sort=-%mem | head -n 6",
+    ),
+    command("ollama", "ollama/chat-structured.json", "df -h /"),
+    streamed("ollama", "ollama/chat-stream.ndjson", "uptime && free -h"),
+    failure(
+        "ollama",
+        "ollama/error-model-not-found.json",
+        404,
+        "ollama pull",
+    ),
+];
+
+#[test]
+fn fixtures() {
+    let mut failures = Vec::new();
+    for fixture in FIXTURES {
+        if let Err(message) = check(fixture) {
+            failures.push(format!("{}: {}", fixture.file, message));
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+fn check(fixture: &Fixture) -> Result<(), String> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let body = fs::read(dir.join(fixture.file)).map_err(|err| err.to_string())?;
+    let content_type = match fixture.file.rsplit('.').next() {
+        Some("sse") => "text/event-stream",
+        Some("ndjson") => "application/x-ndjson",
+        _ => "application/json",
+    };
+    let (base, requests) = serve(vec![(fixture.status, content_type, body)]);
+    let output = run(fixture.provider, &base, fixture.file, fixture.stream)?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    let (path, _) = requests
+        .recv()
+        .map_err(|_| "no request was made".to_string())?;
+    let endpoint = match fixture.provider {
+        "anthropic" => "/messages",
+        "ollama" => "/api/chat",
+        _ => "/responses",
+    };
+    if !path.ends_with(endpoint) {
+        return Err(format!("requested {} instead of {}", path, endpoint));
+    }
+
+    match (fixture.expected, fixture.error) {
+        (Some(expected), _) => {
+            if !output.status.success() {
+                return Err(format!("failed: {}", stderr.trim()));
+            }
+            if stdout.trim() != expected {
+                return Err(format!(
+                    "printed {:?}, expected {:?}",
+                    stdout.trim(),
+                    expected
+                ));
+            }
+        }
+        (None, Some(error)) => {
+            if output.status.success() {
+                return Err(format!("succeeded with {:?}", stdout.trim()));
+            }
+            if !stderr.contains(error) {
+                return Err(format!("error {:?} lacks {:?}", stderr.trim(), error));
+            }
+        }
+        (None, None) => unreachable!(),
+    }
+    Ok(())
+}
+
+#[test]
+fn incomplete_stream_asks_the_same_model_for_more() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/openai");
+    let cut_off = fs::read(dir.join("responses-stream-incomplete.sse")).unwrap();
+    let answer = fs::read(dir.join("responses-output-text.json")).unwrap();
+    let (base, requests) = serve(vec![
+        (200, "text/event-stream", cut_off),
+        (200, "application/json", answer),
+    ]);
+    let output = run("openai", &base, "stream-incomplete", true).unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        "wc -l -- *.txt"
+    );
+
+    let bodies: Vec<serde_json::Value> = requests
+        .try_iter()
+        .map(|(_, body)| serde_json::from_str(&body).unwrap())
+        .collect();
+    assert_eq!(bodies.len(), 2, "{}", stderr);
+    assert_eq!(bodies[0]["stream"], true);
+    assert_eq!(bodies[1]["model"], bodies[0]["model"]);
+    // Twice the room the cut-off answer used, not another try at the same.
+    assert_eq!(bodies[1]["max_output_tokens"], 6000);
+    assert!(!stderr.contains("instead"), "{}", stderr);
+}
+
+/// Run llmwrap against the server at `base`, in a home of its own named
+/// after `name`, streaming the answer if `stream`.
+fn run(provider: &str, base: &str, name: &str, stream: bool) -> Result<Output, String> {
+    let home = std::env::temp_dir().join(format!(
+        "llmwrap-fixture-{}-{}",
+        std::process::id(),
+        name.replace('/', "-")
+    ));
+    let _ = fs::remove_dir_all(&home);
+    fs::create_dir_all(home.join("config/llmwrap")).map_err(|err| err.to_string())?;
+    // A recorded failure is the same every time, so retrying it only waits.
+    let mut config = String::from("[network]\nretries = 0\n");
+    if stream {
+        // Hedging never fires this late, but it makes llmwrap stream.
+        config.push_str("[latency]\nhedge_after_ms = 600000\n");
+    }
+    fs::write(home.join("config/llmwrap/config.toml"), config).map_err(|err| err.to_string())?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_llmwrap"))
+        .args(["--provider", provider, "--api-base", base])
+        .arg("show the fixture command")
+        .env_clear()
+        .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+        .env("HOME", &home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("XDG_DATA_HOME", home.join("data"))
+        .env("XDG_CACHE_HOME", home.join("cache"))
+        .env("XDG_STATE_HOME", home.join("state"))
+        .env("LLMWRAP_OPENAI_API_KEY", "sk-fixture")
+        .env("LLMWRAP_ANTHROPIC_API_KEY", "sk-ant-fixture")
+        .current_dir(&home)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|err| err.to_string())?;
+    let _ = fs::remove_dir_all(&home);
+    Ok(output)
+}
+
+/// Answer requests on a local port with `replies` in turn, the last one
+/// again for any more, sending back the path and body of each request made.
+/// Returns the base URL to point llmwrap at.
+fn serve(replies: Vec<(u16, &'static str, Vec<u8>)>) -> (String, mpsc::Receiver<(String, String)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for (i, stream) in listener.incoming().enumerate() {
+            let Ok(mut stream) = stream else { continue };
+            let mut reader = BufReader::new(&stream);
+            let mut request_line = String::new();
+            let _ = reader.read_line(&mut request_line);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 || line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':')
+                    && name.eq_ignore_ascii_case("content-length")
+                {
+                    length = value.trim().parse().unwrap_or(0);
+                }
+            }
+            let mut request = Vec::new();
+            let _ = reader.by_ref().take(length).read_to_end(&mut request);
+            let path = request_line.split(' ').nth(1).unwrap_or("").to_string();
+            let _ = sender.send((path, String::from_utf8_lossy(&request).into_owned()));
+            let (status, content_type, body) = &replies[i.min(replies.len() - 1)];
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {} Fixture\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                content_type,
+                body.len()
+            );
+            let _ = stream.write_all(body);
+        }
+    });
+    (base, receiver)
+}
@@ -0,0 +1,133 @@
+//! Properties of the code that turns a model's reply into a command, checked
+//! on generated input: cleaning up the answer, telling scripts from single
+//! commands, and reading the text out of responses and streams. The fuzz
+//! targets in `fuzz/` throw arbitrary bytes at the same functions.
+
+use llmwrap::api::{self, Provider, StreamParser};
+use llmwrap::{answer, is_script, sanitize_command};
+use proptest::prelude::*;
+use serde_json::json;
+
+const PROVIDERS: [Provider; 3] = [Provider::Openai, Provider::Anthropic, Provider::Ollama];
+
+/// A plausible one-line command: no newlines, backticks or surrounding space.
+fn command() -> impl Strategy<Value = String> {
+    "[a-z][a-zA-Z0-9 ./'\"|&;<>=$*_-]{0,60}".prop_map(|c| c.trim().to_string())
+}
+
+/// A model's reply: commands, fences, backticks and blank lines in any order.
+fn reply() -> impl Strategy<Value = String> {
+    prop::collection::vec(
+        prop_oneof![
+            command(),
+            Just("```".to_string()),
+            Just("```bash".to_string()),
+            Just("`".to_string()),
+            "`{1,3} ?```[a-z]{0,4}`{0,3}",
+            "[` ]{0,4}[a-z]{1,3}[` ]{0,4}",
+            Just(String::new()),
+            Just("  ".to_string()),
+            any::<String>(),
+        ],
+        0..8,
+    )
+    .prop_map(|lines| lines.join("\n"))
+}
+
+proptest! {
+    #[test]
+    fn sanitizing_twice_changes_nothing(raw in reply()) {
+        let once = sanitize_command(&raw);
+        prop_assert_eq!(sanitize_command(&once), once);
+    }
+
+    #[test]
+    fn sanitized_commands_have_no_fences(raw in reply()) {
+        let command = sanitize_command(&raw);
+        prop_assert_eq!(command.trim(), command.as_str());
+        prop_assert!(command.lines().all(|line| !line.trim_start().starts_with("```")));
+        prop_assert_eq!(is_script(&command), command.contains('\n'));
+    }
+
+    #[test]
+    fn fenced_and_quoted_commands_come_out_whole(command in command()) {
+        prop_assume!(!command.is_empty());
+        prop_assert_eq!(sanitize_command(&format!("```bash\n{}\n```", command)), command.clone());
+        prop_assert_eq!(sanitize_command(&format!("`{}`", command)), command.clone());
+        prop_assert_eq!(sanitize_command(&format!("\n  {}  \n", command)), command);
+    }
+
+    #[test]
+    fn scripts_keep_their_lines(lines in prop::collection::vec(command(), 2..6)) {
+        prop_assume!(lines.iter().all(|line| !line.is_empty()));
+        let script = lines.join("\n");
+        let fenced = format!("```sh\n{}\n```\n", script);
+        prop_assert_eq!(sanitize_command(&fenced), script);
+    }
+
+    #[test]
+    fn structured_answers_parse_back(
+        command in command(),
+        explanation in "[^\n]{0,80}",
+        risk in prop::sample::select(vec!["low", "medium", "high"]),
+    ) {
+        prop_assume!(!command.is_empty());
+        let raw = json!({"command": command, "explanation": explanation, "risk_level": risk});
+        let answer = answer::parse(&raw.to_string()).unwrap();
+        prop_assert_eq!(answer.command, command);
+        prop_assert_eq!(answer.rated.map(|r| r.to_string()), Some(risk.to_string()));
+    }
+
+    #[test]
+    fn response_text_is_found(text in ".{1,200}") {
+        let responses = [
+            json!({"output": [{"type": "message", "content": [{"type": "output_text", "text": text}]}]}),
+            json!({"content": [{"type": "thinking", "thinking": "hm"}, {"type": "text", "text": text}]}),
+            json!({"message": {"role": "assistant", "content": text}, "done": true}),
+        ];
+        for (provider, response) in PROVIDERS.into_iter().zip(responses) {
+            prop_assert_eq!(api::extract_text(provider, &response), Some(text.clone()));
+        }
+    }
+
+    #[test]
+    fn streamed_deltas_add_up(deltas in prop::collection::vec(".{0,20}", 0..10)) {
+        let text: String = deltas.concat();
+        let streams = [
+            deltas
+                .iter()
+                .map(|d| format!("data: {}", json!({"type": "response.output_text.delta", "delta": d})))
+                .collect::<Vec<_>>(),
+            deltas
+                .iter()
+                .map(|d| format!("data: {}", json!({"type": "content_block_delta", "delta": {"type": "text_delta", "text": d}})))
+                .collect(),
+            deltas
+                .iter()
+                .map(|d| json!({"message": {"role": "assistant", "content": d}, "done": false}).to_string())
+                .collect(),
+        ];
+        for (provider, lines) in PROVIDERS.into_iter().zip(streams) {
+            let mut parser = StreamParser::new(provider);
+            let mut seen = String::new();
+            for line in &lines {
+                seen.push_str(&parser.line(line).unwrap().unwrap_or_default());
+            }
+            let (_, streamed) = parser.finish();
+            prop_assert_eq!(&seen, &text);
+            prop_assert_eq!(&streamed, &text);
+        }
+    }
+
+    #[test]
+    fn any_stream_is_read_without_panicking(lines in prop::collection::vec(any::<String>(), 0..10)) {
+        for provider in PROVIDERS {
+            let mut parser = StreamParser::new(provider);
+            for line in &lines {
+                let _ = parser.line(line);
+                let _ = parser.line(&format!("data: {}", line));
+            }
+            parser.finish();
+        }
+    }
+}